@@ -8,18 +8,26 @@ use amethyst_core::{
 };
 use amethyst_error::Error;
 
-use crate::{output::Output, source::*, systems::AudioSystemDesc};
+use crate::{output::Output, source::*, systems::AudioSystemDesc, timeline::CueSheet};
 
 /// Audio bundle
 ///
-/// This will only add the audio system and the asset processor for `Source`.
+/// This will only add the audio system and the asset processors for `Source` and `CueSheet`.
 ///
-/// `DjSystem` must be added separately if you want to use our background music system.
+/// `DjSystem` or `MusicSystem` must be added separately if you want to use our background music
+/// systems, and `AudioDeviceSystem` to follow output devices being plugged in and removed.
 ///
 /// The generic N type should be the same as the one in `Transform`.
 #[derive(Default, Debug)]
 pub struct AudioBundle(Output);
 
+impl AudioBundle {
+    /// Creates a bundle playing on the given output, e.g. a recording output in tests.
+    pub fn with_output(output: Output) -> Self {
+        AudioBundle(output)
+    }
+}
+
 impl<'a, 'b> SystemBundle<'a, 'b> for AudioBundle {
     fn build(
         self,
@@ -32,6 +40,7 @@ impl<'a, 'b> SystemBundle<'a, 'b> for AudioBundle {
             &[],
         );
         builder.add(Processor::<Source>::new(), "source_processor", &[]);
+        builder.add(Processor::<CueSheet>::new(), "cue_sheet_processor", &[]);
         Ok(())
     }
 }
@@ -1,23 +1,57 @@
-use std::{
-    io::Cursor,
-    sync::{atomic::AtomicBool, Arc},
-};
+use std::time::Duration;
 
-use rodio::{Decoder, SpatialSink};
 use smallvec::SmallVec;
 
-use amethyst_core::ecs::{prelude::Component, storage::BTreeStorage};
+use amethyst_core::{
+    ecs::{prelude::Component, storage::BTreeStorage},
+    math::Point3,
+};
 
-use crate::{source::Source, DecoderError};
+use crate::{
+    effects::EffectChain,
+    environment::{Occlusion, Reverb},
+    mixer::Bus,
+    playback::{Playback, PlaybackId},
+    source::Source,
+    spatial::Attenuation,
+    timeline::{CueSheet, PlaybackPosition},
+    DecoderError,
+};
 
 /// An audio source, add this component to anything that emits sound.
+///
+/// Every sound played from the emitter is identified by a `PlaybackId`, which can be used to
+/// control it until it ends. Controlling a sound which already ended has no effect.
 /// TODO: This should get a proper Debug impl parsing the sinks and sound queue
 #[allow(missing_debug_implementations)]
-#[derive(Default)]
 pub struct AudioEmitter {
-    pub(crate) sinks: SmallVec<[(SpatialSink, Arc<AtomicBool>); 4]>,
-    pub(crate) sound_queue: SmallVec<[Decoder<Cursor<Source>>; 4]>,
+    pub(crate) playbacks: SmallVec<[Playback; 4]>,
     pub(crate) picker: Option<Box<dyn FnMut(&mut AudioEmitter) -> bool + Send + Sync>>,
+    pub(crate) bus: Bus,
+    pub(crate) attenuation: Option<Attenuation>,
+    pub(crate) effects: EffectChain,
+    /// Occlusion filter and reverb of the environment, applied after `effects`.
+    pub(crate) environment: EffectChain,
+    pub(crate) occlusion: Occlusion,
+    pub(crate) reverb: Reverb,
+    /// Position in the previous frame, used to compute the velocity for the Doppler effect.
+    pub(crate) last_position: Option<Point3<f32>>,
+}
+
+impl Default for AudioEmitter {
+    fn default() -> Self {
+        AudioEmitter {
+            playbacks: SmallVec::new(),
+            picker: None,
+            bus: Bus::Sfx,
+            attenuation: None,
+            effects: EffectChain::default(),
+            environment: EffectChain::default(),
+            occlusion: Occlusion::default(),
+            reverb: Reverb::default(),
+            last_position: None,
+        }
+    }
 }
 
 impl AudioEmitter {
@@ -28,11 +62,164 @@ impl AudioEmitter {
         Default::default()
     }
 
-    /// Plays an audio source from this emitter.
-    pub fn play(&mut self, source: &Source) -> Result<(), DecoderError> {
-        self.sound_queue
-            .push(Decoder::new(Cursor::new(source.clone())).map_err(|_| DecoderError)?);
-        Ok(())
+    /// Plays an audio source from this emitter, returning the id used to control the sound.
+    pub fn play(&mut self, source: &Source) -> Result<PlaybackId, DecoderError> {
+        Ok(self.push(Playback::new(source, None)?))
+    }
+
+    /// Plays an audio source with the markers and tempo of a `CueSheet`. The `AudioSystem` sends
+    /// a `CueEvent` whenever the sound reaches one of them.
+    pub fn play_with_cues(
+        &mut self,
+        source: &Source,
+        cues: &CueSheet,
+    ) -> Result<PlaybackId, DecoderError> {
+        Ok(self.push(Playback::new(source, Some(cues))?))
+    }
+
+    fn push(&mut self, playback: Playback) -> PlaybackId {
+        let id = playback.id;
+        self.playbacks.push(playback);
+        id
+    }
+
+    /// Returns the exact position of a sound, counting the samples which were played. The
+    /// position of a looping sound starts over at every loop.
+    pub fn position(&self, id: PlaybackId) -> Option<PlaybackPosition> {
+        self.playback(id).map(Playback::position)
+    }
+
+    /// Returns true if the sound is still playing or paused.
+    pub fn is_playing(&self, id: PlaybackId) -> bool {
+        self.playback(id).is_some_and(|p| !p.is_finished())
+    }
+
+    /// Returns the ids of all sounds which are playing or paused.
+    pub fn playbacks(&self) -> impl Iterator<Item = PlaybackId> + '_ {
+        self.playbacks
+            .iter()
+            .filter(|p| !p.is_finished())
+            .map(|p| p.id)
+    }
+
+    /// Returns the volume of a sound, without fades.
+    pub fn volume(&self, id: PlaybackId) -> Option<f32> {
+        self.playback(id).map(Playback::volume)
+    }
+
+    /// Sets the volume of a sound. A volume of 1.0 is unchanged, while 0.0 is silent.
+    pub fn set_volume(&mut self, id: PlaybackId, volume: f32) {
+        self.modify(id, |p| p.set_volume(volume));
+    }
+
+    /// Returns the playback speed of a sound.
+    pub fn speed(&self, id: PlaybackId) -> Option<f32> {
+        self.playback(id).map(Playback::speed)
+    }
+
+    /// Sets the playback speed of a sound. A speed of 2.0 plays the sound twice as fast and one
+    /// octave higher.
+    pub fn set_speed(&mut self, id: PlaybackId, speed: f32) {
+        self.modify(id, |p| p.set_speed(speed));
+    }
+
+    /// Returns true if a sound restarts when it reaches its end.
+    pub fn is_looping(&self, id: PlaybackId) -> bool {
+        self.playback(id).is_some_and(Playback::is_looping)
+    }
+
+    /// Makes a sound restart when it reaches its end.
+    pub fn set_looping(&mut self, id: PlaybackId, looping: bool) {
+        self.modify(id, |p| p.set_looping(looping));
+    }
+
+    /// Returns true if a sound is paused.
+    pub fn is_paused(&self, id: PlaybackId) -> bool {
+        self.playback(id).is_some_and(Playback::is_paused)
+    }
+
+    /// Pauses a sound, it can be resumed with `AudioEmitter::resume`.
+    pub fn pause(&mut self, id: PlaybackId) {
+        self.modify(id, |p| p.set_paused(true));
+    }
+
+    /// Resumes a paused sound.
+    pub fn resume(&mut self, id: PlaybackId) {
+        self.modify(id, |p| p.set_paused(false));
+    }
+
+    /// Stops a sound.
+    pub fn stop(&mut self, id: PlaybackId) {
+        self.modify(id, Playback::stop);
+    }
+
+    /// Stops all sounds of this emitter.
+    pub fn stop_all(&mut self) {
+        for playback in &mut self.playbacks {
+            playback.stop();
+        }
+    }
+
+    /// Fades a sound in from silence over `duration`. Call this right after `play`.
+    pub fn fade_in(&mut self, id: PlaybackId, duration: Duration) {
+        self.modify(id, |p| p.fade_in(duration));
+    }
+
+    /// Fades a sound out over `duration`, then stops it.
+    pub fn fade_out(&mut self, id: PlaybackId, duration: Duration) {
+        self.modify(id, |p| p.fade(0.0, duration, true));
+    }
+
+    /// Fades a sound to a fraction of its volume over `duration`, without stopping it.
+    pub fn fade_to(&mut self, id: PlaybackId, gain: f32, duration: Duration) {
+        self.modify(id, |p| p.fade(gain, duration, false));
+    }
+
+    fn playback(&self, id: PlaybackId) -> Option<&Playback> {
+        self.playbacks.iter().find(|p| p.id == id)
+    }
+
+    fn modify(&mut self, id: PlaybackId, f: impl FnOnce(&mut Playback)) {
+        if let Some(playback) = self.playbacks.iter_mut().find(|p| p.id == id) {
+            f(playback);
+        }
+    }
+
+    /// Returns the mixer bus the sounds of this emitter are routed to, `Bus::Sfx` by default.
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    /// Routes the sounds played from now on to the given mixer bus.
+    pub fn set_bus(&mut self, bus: Bus) {
+        self.bus = bus;
+    }
+
+    /// Returns the distance attenuation of this emitter.
+    pub fn attenuation(&self) -> Option<&Attenuation> {
+        self.attenuation.as_ref()
+    }
+
+    /// Sets the distance attenuation of this emitter. Without attenuation, the fixed falloff of
+    /// the audio backend is used and the emitter is never virtualized.
+    pub fn set_attenuation(&mut self, attenuation: Option<Attenuation>) {
+        self.attenuation = attenuation;
+    }
+
+    /// Returns the effects applied to the sounds of this emitter, before the effects of its bus.
+    /// Changes to the chain apply to the sounds which are already playing.
+    pub fn effects(&self) -> &EffectChain {
+        &self.effects
+    }
+
+    /// Replaces the effects applied to the sounds played from now on.
+    pub fn set_effects(&mut self, effects: EffectChain) {
+        self.effects = effects;
+    }
+
+    /// Returns true if a sound is suspended because the emitter is out of range of the listener.
+    pub fn is_virtual(&self, id: PlaybackId) -> bool {
+        self.playback(id).is_some_and(Playback::is_virtual)
     }
 
     /// An emitter's picker will be called by the AudioSystem whenever the emitter runs out of
@@ -58,7 +245,7 @@ impl Component for AudioEmitter {
 
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::Read, vec::Vec};
+    use std::{fs::File, io::Read, time::Duration, vec::Vec};
 
     use amethyst_utils::app_root_dir::application_root_dir;
 
@@ -135,6 +322,64 @@ mod tests {
         assert!(emitter_main.picker.is_none());
     }
 
+    #[test]
+    fn test_playback_control() {
+        let app_root = application_root_dir().unwrap();
+        let mut buffer = Vec::new();
+        File::open(app_root.join("tests/sound_test.wav"))
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        let src = Source { bytes: buffer };
+
+        let mut emitter = AudioEmitter::default();
+        let first = emitter.play(&src).unwrap();
+        let second = emitter.play(&src).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(emitter.playbacks().collect::<Vec<_>>(), vec![first, second]);
+
+        emitter.set_volume(first, 0.5);
+        emitter.set_speed(first, 2.0);
+        emitter.set_looping(first, true);
+        emitter.pause(first);
+        assert_eq!(emitter.volume(first), Some(0.5));
+        assert_eq!(emitter.speed(first), Some(2.0));
+        assert!(emitter.is_looping(first));
+        assert!(emitter.is_paused(first));
+        assert_eq!(emitter.volume(second), Some(1.0));
+
+        emitter.resume(first);
+        assert!(!emitter.is_paused(first));
+
+        emitter.stop(second);
+        assert!(emitter.is_playing(first));
+        assert!(!emitter.is_playing(second));
+    }
+
+    #[test]
+    fn test_fade_out_stops() {
+        let app_root = application_root_dir().unwrap();
+        let mut buffer = Vec::new();
+        File::open(app_root.join("tests/sound_test.wav"))
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        let src = Source { bytes: buffer };
+
+        let mut emitter = AudioEmitter::default();
+        let id = emitter.play(&src).unwrap();
+        emitter.fade_out(id, Duration::from_secs(1));
+        for playback in &mut emitter.playbacks {
+            playback.advance(Duration::from_millis(500));
+            assert_eq!(playback.gain(), 0.5);
+        }
+        assert!(emitter.is_playing(id));
+        for playback in &mut emitter.playbacks {
+            playback.advance(Duration::from_millis(500));
+        }
+        assert!(!emitter.is_playing(id));
+    }
+
     // use_audio_emitter is a fake test function to play an AudioEmitter
     fn use_audio_emitter(_emitter: &mut AudioEmitter) -> bool {
         true
@@ -0,0 +1,33 @@
+use amethyst_core::ecs::{prelude::Component, storage::DenseVecStorage};
+use serde::{Deserialize, Serialize};
+
+use crate::environment::AudioShape;
+
+/// An obstacle muffling the sounds whose line of sight to the `AudioListener` crosses it, such as
+/// a wall. The shape is placed with the `Transform` of the entity.
+///
+/// The sounds passing through several occluders are attenuated by each of them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AudioOccluder {
+    /// The volume of the obstacle.
+    pub shape: AudioShape,
+    /// Fraction of the volume of sounds which passes through, between 0.0 and 1.0.
+    pub transmission: f32,
+    /// Cutoff frequency in Hz of the low-pass filter applied to sounds passing through.
+    pub cutoff: f32,
+}
+
+impl AudioOccluder {
+    /// Creates an occluder letting through a third of the volume, below 1 kHz.
+    pub fn new(shape: AudioShape) -> Self {
+        AudioOccluder {
+            shape,
+            transmission: 0.3,
+            cutoff: 1000.0,
+        }
+    }
+}
+
+impl Component for AudioOccluder {
+    type Storage = DenseVecStorage<Self>;
+}
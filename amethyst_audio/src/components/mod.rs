@@ -1,6 +1,9 @@
 //! `amethyst` audio ecs components
 
-pub use self::{audio_emitter::AudioEmitter, audio_listener::AudioListener};
+pub use self::{
+    audio_emitter::AudioEmitter, audio_listener::AudioListener, audio_occluder::AudioOccluder,
+    reverb_zone::ReverbZone,
+};
 
 use amethyst_assets::PrefabData;
 use amethyst_core::{
@@ -11,10 +14,12 @@ use amethyst_error::Error;
 
 use serde::{Deserialize, Serialize};
 
-use crate::output::Output;
+use crate::{output::Output, spatial::Attenuation};
 
 mod audio_emitter;
 mod audio_listener;
+mod audio_occluder;
+mod reverb_zone;
 
 /// `PrefabData` for loading audio components
 ///
@@ -22,8 +27,12 @@ mod audio_listener;
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct AudioPrefab {
     emitter: bool,
+    /// Distance attenuation of the emitter.
+    attenuation: Option<Attenuation>,
     /// Left, Right
     listener: Option<(Point3<f32>, Point3<f32>)>,
+    occluder: Option<AudioOccluder>,
+    reverb_zone: Option<ReverbZone>,
 }
 
 impl<'a> PrefabData<'a> for AudioPrefab {
@@ -31,6 +40,8 @@ impl<'a> PrefabData<'a> for AudioPrefab {
         WriteStorage<'a, AudioEmitter>,
         WriteStorage<'a, AudioListener>,
         Option<Read<'a, Output>>,
+        WriteStorage<'a, AudioOccluder>,
+        WriteStorage<'a, ReverbZone>,
     );
     type Result = ();
 
@@ -42,7 +53,9 @@ impl<'a> PrefabData<'a> for AudioPrefab {
         _: &[Entity],
     ) -> Result<(), Error> {
         if self.emitter {
-            system_data.0.insert(entity, AudioEmitter::default())?;
+            let mut emitter = AudioEmitter::default();
+            emitter.set_attenuation(self.attenuation.clone());
+            system_data.0.insert(entity, emitter)?;
         }
         if let Some((left_ear, right_ear)) = self.listener {
             system_data.1.insert(
@@ -53,6 +66,12 @@ impl<'a> PrefabData<'a> for AudioPrefab {
                 },
             )?;
         }
+        if let Some(occluder) = &self.occluder {
+            system_data.3.insert(entity, occluder.clone())?;
+        }
+        if let Some(zone) = &self.reverb_zone {
+            system_data.4.insert(entity, zone.clone())?;
+        }
         Ok(())
     }
 }
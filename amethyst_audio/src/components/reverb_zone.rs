@@ -0,0 +1,44 @@
+use amethyst_core::ecs::{prelude::Component, storage::DenseVecStorage};
+use serde::{Deserialize, Serialize};
+
+use crate::environment::AudioShape;
+
+/// A volume of the world with its own acoustics, such as a cave or a hall. While the
+/// `AudioListener` is in the zone, the sounds of all `AudioEmitter`s reverberate with its
+/// settings. The shape is placed with the `Transform` of the entity.
+///
+/// Where zones overlap, the zone with the highest priority is used, so that a room can be placed
+/// inside a larger building.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReverbZone {
+    /// The volume of the zone.
+    pub shape: AudioShape,
+    /// Distance from the boundary of the zone over which the reverb fades in.
+    pub fade: f32,
+    /// Size of the room between 0.0 and 1.0, larger rooms reverberate longer.
+    pub room_size: f32,
+    /// Absorption of high frequencies between 0.0 and 1.0.
+    pub damping: f32,
+    /// Ratio of reverberated to original sound between 0.0 and 1.0.
+    pub mix: f32,
+    /// Zones with a higher priority are used over those they overlap.
+    pub priority: i32,
+}
+
+impl ReverbZone {
+    /// Creates a zone of a medium sized room.
+    pub fn new(shape: AudioShape) -> Self {
+        ReverbZone {
+            shape,
+            fade: 1.0,
+            room_size: 0.5,
+            damping: 0.5,
+            mix: 0.3,
+            priority: 0,
+        }
+    }
+}
+
+impl Component for ReverbZone {
+    type Storage = DenseVecStorage<Self>;
+}
@@ -0,0 +1,247 @@
+//! Tracking of the output devices which are plugged in and removed.
+
+use std::time::Duration;
+
+use amethyst_core::shrev::EventChannel;
+
+/// Sent through an `EventChannel` by the `AudioDeviceSystem` when the output devices change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AudioDeviceEvent {
+    /// A device was plugged in.
+    Added(String),
+    /// A device was removed.
+    Removed(String),
+    /// The default device of the system changed.
+    DefaultChanged(Option<String>),
+    /// The `Output` resource was moved to the device with the given name.
+    Switched(String),
+}
+
+/// The device an `Output` should be moved to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum SwitchTarget {
+    Device(String),
+    Default,
+}
+
+/// The output devices of the system, kept up to date by the `AudioDeviceSystem`.
+///
+/// When the device the `Output` resource plays on is removed, the output is moved to the default
+/// device so that audio keeps playing.
+#[derive(Debug)]
+pub struct AudioDevices {
+    devices: Vec<String>,
+    default: Option<String>,
+    poll_interval: Duration,
+    follow_default: bool,
+    requested: Option<SwitchTarget>,
+    /// Time since the devices were last enumerated, successfully or not, `None` before the
+    /// first enumeration.
+    since_poll: Option<Duration>,
+    /// Set once the devices were enumerated successfully.
+    enumerated: bool,
+}
+
+impl Default for AudioDevices {
+    fn default() -> Self {
+        AudioDevices {
+            devices: Vec::new(),
+            default: None,
+            poll_interval: Duration::from_secs(1),
+            follow_default: false,
+            requested: None,
+            since_poll: None,
+            enumerated: false,
+        }
+    }
+}
+
+impl AudioDevices {
+    /// Returns the names of the output devices found by the last enumeration.
+    pub fn devices(&self) -> &[String] {
+        &self.devices
+    }
+
+    /// Returns the name of the default output device of the system.
+    pub fn default_device(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// Returns how often the devices are enumerated.
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Sets how often the devices are enumerated, once per second by default. Enumerating
+    /// devices can take a few milliseconds on some systems.
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Returns true if the output moves to the default device whenever the default changes.
+    pub fn follows_default(&self) -> bool {
+        self.follow_default
+    }
+
+    /// Makes the output move to the default device whenever the default changes, e.g. when a
+    /// headset is plugged in and the system makes it the default.
+    pub fn set_follow_default(&mut self, follow: bool) {
+        self.follow_default = follow;
+    }
+
+    /// Moves the `Output` resource to the device with the given name in the next frame.
+    pub fn switch_to<N: Into<String>>(&mut self, name: N) {
+        self.requested = Some(SwitchTarget::Device(name.into()));
+    }
+
+    /// Moves the `Output` resource to the default device in the next frame.
+    pub fn switch_to_default(&mut self) {
+        self.requested = Some(SwitchTarget::Default);
+    }
+
+    /// Returns true if the devices should be enumerated after `delta` passed, in which case the
+    /// time until the next enumeration starts over.
+    pub(crate) fn should_poll(&mut self, delta: Duration) -> bool {
+        let poll = match self.since_poll {
+            Some(since) => since + delta >= self.poll_interval || self.requested.is_some(),
+            None => true,
+        };
+        self.since_poll = if poll {
+            Some(Duration::from_secs(0))
+        } else {
+            self.since_poll.map(|since| since + delta)
+        };
+        poll
+    }
+
+    /// Replaces the enumerated devices, sending events for the differences. Returns true if the
+    /// default device changed.
+    pub(crate) fn update(
+        &mut self,
+        devices: Vec<String>,
+        default: Option<String>,
+        events: &mut EventChannel<AudioDeviceEvent>,
+    ) -> bool {
+        let first = !self.enumerated;
+        self.enumerated = true;
+        if !first {
+            events.iter_write(
+                self.devices
+                    .iter()
+                    .filter(|name| !devices.contains(name))
+                    .map(|name| AudioDeviceEvent::Removed(name.clone()))
+                    .collect::<Vec<_>>(),
+            );
+            events.iter_write(
+                devices
+                    .iter()
+                    .filter(|name| !self.devices.contains(name))
+                    .map(|name| AudioDeviceEvent::Added(name.clone()))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        self.devices = devices;
+        let default_changed = !first && default != self.default;
+        if default_changed {
+            events.single_write(AudioDeviceEvent::DefaultChanged(default.clone()));
+        }
+        self.default = default;
+        default_changed
+    }
+
+    /// Returns the device the output should move to. `active` is the name of the device the
+    /// output plays on, if it plays on a device.
+    pub(crate) fn switch_target(
+        &mut self,
+        active: Option<&str>,
+        default_changed: bool,
+    ) -> Option<SwitchTarget> {
+        if let Some(requested) = self.requested.take() {
+            return Some(requested);
+        }
+        match active {
+            Some(active) if !self.devices.iter().any(|name| name == active) => {
+                Some(SwitchTarget::Default)
+            }
+            Some(_) if default_changed && self.follow_default => Some(SwitchTarget::Default),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn sends_device_changes() {
+        let mut devices = AudioDevices::default();
+        let mut events = EventChannel::new();
+        let mut reader = events.register_reader();
+        assert!(devices.should_poll(Duration::from_millis(10)));
+        assert!(!devices.update(
+            names(&["speakers", "headset"]),
+            Some("headset".to_string()),
+            &mut events
+        ));
+        assert_eq!(events.read(&mut reader).count(), 0);
+        assert!(!devices.should_poll(Duration::from_millis(500)));
+        assert!(devices.should_poll(Duration::from_millis(500)));
+
+        assert!(devices.update(
+            names(&["speakers", "hdmi"]),
+            Some("speakers".to_string()),
+            &mut events
+        ));
+        assert_eq!(
+            events.read(&mut reader).cloned().collect::<Vec<_>>(),
+            vec![
+                AudioDeviceEvent::Removed("headset".to_string()),
+                AudioDeviceEvent::Added("hdmi".to_string()),
+                AudioDeviceEvent::DefaultChanged(Some("speakers".to_string())),
+            ]
+        );
+        assert_eq!(devices.devices(), &names(&["speakers", "hdmi"])[..]);
+        assert_eq!(devices.default_device(), Some("speakers"));
+    }
+
+    #[test]
+    fn switches_away_from_removed_devices() {
+        let mut devices = AudioDevices::default();
+        let mut events = EventChannel::new();
+        devices.update(names(&["speakers"]), None, &mut events);
+        assert_eq!(devices.switch_target(Some("speakers"), false), None);
+        assert_eq!(devices.switch_target(None, false), None);
+        assert_eq!(
+            devices.switch_target(Some("headset"), false),
+            Some(SwitchTarget::Default)
+        );
+        assert_eq!(devices.switch_target(Some("speakers"), true), None);
+        devices.set_follow_default(true);
+        assert_eq!(
+            devices.switch_target(Some("speakers"), true),
+            Some(SwitchTarget::Default)
+        );
+
+        devices.switch_to("hdmi");
+        assert!(devices.should_poll(Duration::from_secs(0)));
+        assert_eq!(
+            devices.switch_target(Some("speakers"), false),
+            Some(SwitchTarget::Device("hdmi".to_string()))
+        );
+        assert_eq!(devices.switch_target(Some("speakers"), false), None);
+    }
+
+    #[test]
+    fn failed_enumeration_waits_for_the_interval() {
+        let mut devices = AudioDevices::default();
+        assert!(devices.should_poll(Duration::from_millis(10)));
+        // The enumeration failed, so `update` isn't called.
+        assert!(!devices.should_poll(Duration::from_millis(10)));
+        assert!(devices.should_poll(Duration::from_secs(1)));
+    }
+}
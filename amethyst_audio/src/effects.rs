@@ -0,0 +1,874 @@
+//! Effects applied to sounds as they play, such as filters, reverb and ducking.
+
+use std::{
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rodio::{Sample, Source};
+
+/// Longest time effects such as reverb and echo keep playing after the sound itself ended.
+const MAX_TAIL_SECONDS: u32 = 10;
+
+/// Level below which the tail of an effect is considered silent.
+const SILENCE: f32 = 1.0e-4;
+
+/// Release time of the envelope measured by `Effect::SidechainSend`, in seconds.
+const SEND_RELEASE: f32 = 0.1;
+
+/// A signal level shared between sounds, used to duck sounds while others play, e.g. music while
+/// dialogue plays.
+///
+/// Sounds with an `Effect::SidechainSend` write their level, sounds with an `Effect::Duck` read
+/// it. When several sounds send to the same sidechain, the level is the one of the sound which
+/// wrote it last. The level falls back to silence once the sending sound ends or stops sending.
+#[derive(Clone, Debug, Default)]
+pub struct Sidechain(Arc<AtomicU32>);
+
+impl Sidechain {
+    /// Creates a new silent sidechain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current level of the sidechain, 0.0 being silent.
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set_level(&self, level: f32) {
+        self.0.store(level.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Writes the level of a sound into a sidechain, and silences it once the sound is gone.
+#[derive(Debug)]
+struct SidechainSender(Sidechain);
+
+impl Drop for SidechainSender {
+    fn drop(&mut self) {
+        self.0.set_level(0.0);
+    }
+}
+
+impl PartialEq for Sidechain {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// An audio effect. Times are in seconds and levels are linear amplitudes, 1.0 being full scale.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect {
+    /// Removes frequencies above the cutoff, e.g. to muffle sounds heard underwater. A cutoff
+    /// above the frequencies the sound can hold lets it through unchanged.
+    LowPass {
+        /// Cutoff frequency in Hz.
+        cutoff: f32,
+    },
+    /// Removes frequencies below the cutoff.
+    HighPass {
+        /// Cutoff frequency in Hz.
+        cutoff: f32,
+    },
+    /// A simple room reverb.
+    Reverb {
+        /// Size of the room between 0.0 and 1.0, larger rooms reverberate longer.
+        room_size: f32,
+        /// Absorption of high frequencies between 0.0 and 1.0.
+        damping: f32,
+        /// Ratio of reverberated to original sound between 0.0 and 1.0.
+        mix: f32,
+    },
+    /// Repeats the sound after a delay.
+    Echo {
+        /// Time between repetitions.
+        delay: f32,
+        /// Volume of each repetition relative to the previous one, below 1.0.
+        feedback: f32,
+        /// Ratio of delayed to original sound between 0.0 and 1.0.
+        mix: f32,
+    },
+    /// Reduces the volume of loud sounds.
+    Compressor {
+        /// Level above which the sound is compressed.
+        threshold: f32,
+        /// How much the sound above the threshold is reduced, e.g. 4.0 for 4:1.
+        ratio: f32,
+        /// How fast the compression reacts to loud sounds.
+        attack: f32,
+        /// How fast the compression stops after loud sounds.
+        release: f32,
+    },
+    /// Measures the level of the sound into a sidechain, leaving the sound unchanged.
+    SidechainSend(Sidechain),
+    /// Reduces the volume of the sound while the level of a sidechain is above a threshold.
+    Duck {
+        /// The sidechain to listen to.
+        sidechain: Sidechain,
+        /// Level of the sidechain above which the sound is ducked.
+        threshold: f32,
+        /// Gain applied to the sound while it is ducked.
+        gain: f32,
+        /// How fast the sound is ducked.
+        attack: f32,
+        /// How fast the sound returns to full volume.
+        release: f32,
+    },
+}
+
+impl Effect {
+    /// Returns true if the effect keeps producing sound after its input ended.
+    fn has_tail(&self) -> bool {
+        matches!(self, Effect::Reverb { .. } | Effect::Echo { .. })
+    }
+}
+
+#[derive(Debug, Default)]
+struct SharedChain {
+    effects: Mutex<Vec<Effect>>,
+    version: AtomicU64,
+}
+
+/// A list of effects applied one after another, shared with the sounds playing through it.
+///
+/// Changes to the chain are applied to these sounds while they play, so parameters can be
+/// animated by modifying the chain every frame.
+#[derive(Clone, Debug, Default)]
+pub struct EffectChain(Arc<SharedChain>);
+
+impl EffectChain {
+    /// Creates a chain with the given effects.
+    pub fn new(effects: Vec<Effect>) -> Self {
+        let chain = Self::default();
+        chain.set_effects(effects);
+        chain
+    }
+
+    /// Returns a copy of the effects of the chain.
+    pub fn effects(&self) -> Vec<Effect> {
+        self.0.effects.lock().unwrap().clone()
+    }
+
+    /// Returns true if the chain has no effects.
+    pub fn is_empty(&self) -> bool {
+        self.0.effects.lock().unwrap().is_empty()
+    }
+
+    /// Replaces all effects of the chain.
+    pub fn set_effects(&self, effects: Vec<Effect>) {
+        self.modify(|e| *e = effects);
+    }
+
+    /// Appends an effect to the chain.
+    pub fn push(&self, effect: Effect) {
+        self.modify(|e| e.push(effect));
+    }
+
+    /// Replaces the effect at `index`, e.g. to change its parameters.
+    pub fn set(&self, index: usize, effect: Effect) {
+        self.modify(|e| {
+            if let Some(e) = e.get_mut(index) {
+                *e = effect;
+            }
+        });
+    }
+
+    /// Removes all effects from the chain.
+    pub fn clear(&self) {
+        self.modify(Vec::clear);
+    }
+
+    /// Modifies the effects of the chain.
+    pub fn modify(&self, f: impl FnOnce(&mut Vec<Effect>)) {
+        f(&mut self.0.effects.lock().unwrap());
+        self.0.version.fetch_add(1, Ordering::Release);
+    }
+
+    fn version(&self) -> u64 {
+        self.0.version.load(Ordering::Acquire)
+    }
+}
+
+/// Returns the coefficient of a one-pole smoothing filter with the given time constant.
+fn smoothing(seconds: f32, rate: f32) -> f32 {
+    if seconds <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / (seconds * rate)).exp()
+    }
+}
+
+/// A delay line of a single channel.
+#[derive(Debug)]
+struct DelayLine {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl DelayLine {
+    fn new(len: usize) -> Self {
+        DelayLine {
+            buffer: vec![0.0; len.max(1)],
+            position: 0,
+        }
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.buffer.resize(len.max(1), 0.0);
+        self.position %= self.buffer.len();
+    }
+
+    /// Returns the delayed sample and replaces it with `input`.
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input;
+        self.position = (self.position + 1) % self.buffer.len();
+        delayed
+    }
+
+    fn peek(&self) -> f32 {
+        self.buffer[self.position]
+    }
+}
+
+/// Delays of the comb and allpass filters of the reverb, in samples at 44.1 kHz.
+const COMB_DELAYS: [usize; 4] = [1116, 1188, 1277, 1356];
+const ALLPASS_DELAYS: [usize; 2] = [556, 441];
+/// Offset of the delays of odd channels, which makes the reverb sound wider.
+const STEREO_SPREAD: usize = 23;
+
+#[derive(Debug)]
+struct ReverbChannel {
+    combs: Vec<(DelayLine, f32)>,
+    allpasses: Vec<DelayLine>,
+}
+
+impl ReverbChannel {
+    fn new(sample_rate: u32, channel: usize) -> Self {
+        let scale = |delay: usize| {
+            let spread = if channel % 2 == 1 { STEREO_SPREAD } else { 0 };
+            (delay + spread) * sample_rate as usize / 44_100
+        };
+        ReverbChannel {
+            combs: COMB_DELAYS
+                .iter()
+                .map(|&d| (DelayLine::new(scale(d)), 0.0))
+                .collect(),
+            allpasses: ALLPASS_DELAYS
+                .iter()
+                .map(|&d| DelayLine::new(scale(d)))
+                .collect(),
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let input = input * 0.03;
+        let mut output = 0.0;
+        for (line, filtered) in &mut self.combs {
+            let delayed = line.peek();
+            *filtered = delayed * (1.0 - damping) + *filtered * damping;
+            line.process(input + *filtered * feedback);
+            output += delayed;
+        }
+        for line in &mut self.allpasses {
+            let delayed = line.peek();
+            line.process(output + delayed * 0.5);
+            output = delayed - output;
+        }
+        output
+    }
+}
+
+/// The state of an effect processing a sound.
+#[derive(Debug)]
+enum Processor {
+    Biquad {
+        coefficients: [f32; 5],
+        /// Previous two inputs and outputs of each channel.
+        history: Vec<[f32; 4]>,
+    },
+    Reverb {
+        channels: Vec<ReverbChannel>,
+        feedback: f32,
+        damping: f32,
+        mix: f32,
+    },
+    Echo {
+        lines: Vec<DelayLine>,
+        feedback: f32,
+        mix: f32,
+    },
+    Compressor {
+        envelope: f32,
+        threshold: f32,
+        ratio: f32,
+        attack: f32,
+        release: f32,
+    },
+    Send {
+        sidechain: SidechainSender,
+        envelope: f32,
+        release: f32,
+    },
+    Duck {
+        sidechain: Sidechain,
+        gain: f32,
+        threshold: f32,
+        ducked_gain: f32,
+        attack: f32,
+        release: f32,
+    },
+}
+
+fn biquad(effect: &Effect, sample_rate: u32) -> [f32; 5] {
+    let (cutoff, low_pass) = match effect {
+        Effect::LowPass { cutoff } => (*cutoff, true),
+        Effect::HighPass { cutoff } => (*cutoff, false),
+        _ => unreachable!(),
+    };
+    let max_cutoff = sample_rate as f32 * 0.49;
+    if low_pass && cutoff >= max_cutoff {
+        return [1.0, 0.0, 0.0, 0.0, 0.0];
+    }
+    let cutoff = cutoff.clamp(10.0, max_cutoff);
+    let w0 = 2.0 * PI * cutoff / sample_rate as f32;
+    let cos = w0.cos();
+    let alpha = w0.sin() / 2.0f32.sqrt();
+    let a0 = 1.0 + alpha;
+    let (b0, b1) = if low_pass {
+        ((1.0 - cos) / 2.0, 1.0 - cos)
+    } else {
+        ((1.0 + cos) / 2.0, -(1.0 + cos))
+    };
+    [
+        b0 / a0,
+        b1 / a0,
+        b0 / a0,
+        -2.0 * cos / a0,
+        (1.0 - alpha) / a0,
+    ]
+}
+
+impl Processor {
+    fn new(effect: &Effect, sample_rate: u32, channels: u16) -> Self {
+        let channels = usize::from(channels.max(1));
+        let mut processor = match effect {
+            Effect::LowPass { .. } | Effect::HighPass { .. } => Processor::Biquad {
+                coefficients: [0.0; 5],
+                history: vec![[0.0; 4]; channels],
+            },
+            Effect::Reverb { .. } => Processor::Reverb {
+                channels: (0..channels)
+                    .map(|c| ReverbChannel::new(sample_rate, c))
+                    .collect(),
+                feedback: 0.0,
+                damping: 0.0,
+                mix: 0.0,
+            },
+            Effect::Echo { .. } => Processor::Echo {
+                lines: (0..channels).map(|_| DelayLine::new(1)).collect(),
+                feedback: 0.0,
+                mix: 0.0,
+            },
+            Effect::Compressor { .. } => Processor::Compressor {
+                envelope: 0.0,
+                threshold: 1.0,
+                ratio: 1.0,
+                attack: 0.0,
+                release: 0.0,
+            },
+            Effect::SidechainSend(sidechain) => Processor::Send {
+                sidechain: SidechainSender(sidechain.clone()),
+                envelope: 0.0,
+                release: 0.0,
+            },
+            Effect::Duck { sidechain, .. } => Processor::Duck {
+                sidechain: sidechain.clone(),
+                gain: 1.0,
+                threshold: 0.0,
+                ducked_gain: 1.0,
+                attack: 0.0,
+                release: 0.0,
+            },
+        };
+        processor.configure(effect, sample_rate, channels as u16);
+        processor
+    }
+
+    /// Updates the parameters of the processor, keeping its state. Returns false if the effect
+    /// needs a new processor.
+    fn configure(&mut self, effect: &Effect, sample_rate: u32, channels: u16) -> bool {
+        // Envelopes are updated once per sample of every channel.
+        let rate = sample_rate as f32 * f32::from(channels.max(1));
+        match (self, effect) {
+            (Processor::Biquad { coefficients, .. }, Effect::LowPass { .. })
+            | (Processor::Biquad { coefficients, .. }, Effect::HighPass { .. }) => {
+                *coefficients = biquad(effect, sample_rate);
+            }
+            (
+                Processor::Reverb {
+                    feedback,
+                    damping,
+                    mix,
+                    ..
+                },
+                Effect::Reverb {
+                    room_size,
+                    damping: d,
+                    mix: m,
+                },
+            ) => {
+                *feedback = 0.7 + 0.28 * room_size.clamp(0.0, 1.0);
+                *damping = 0.4 * d.clamp(0.0, 1.0);
+                *mix = m.clamp(0.0, 1.0);
+            }
+            (
+                Processor::Echo {
+                    lines,
+                    feedback,
+                    mix,
+                },
+                Effect::Echo {
+                    delay,
+                    feedback: f,
+                    mix: m,
+                },
+            ) => {
+                let len = (delay.max(0.0) * sample_rate as f32) as usize;
+                for line in lines {
+                    line.resize(len);
+                }
+                *feedback = f.clamp(0.0, 0.99);
+                *mix = m.clamp(0.0, 1.0);
+            }
+            (
+                Processor::Compressor {
+                    threshold,
+                    ratio,
+                    attack,
+                    release,
+                    ..
+                },
+                Effect::Compressor {
+                    threshold: t,
+                    ratio: r,
+                    attack: a,
+                    release: rel,
+                },
+            ) => {
+                *threshold = t.max(f32::EPSILON);
+                *ratio = r.max(1.0);
+                *attack = smoothing(*a, rate);
+                *release = smoothing(*rel, rate);
+            }
+            (
+                Processor::Send {
+                    sidechain, release, ..
+                },
+                Effect::SidechainSend(s),
+            ) => {
+                if sidechain.0 != *s {
+                    *sidechain = SidechainSender(s.clone());
+                }
+                *release = smoothing(SEND_RELEASE, rate);
+            }
+            (
+                Processor::Duck {
+                    sidechain,
+                    threshold,
+                    ducked_gain,
+                    attack,
+                    release,
+                    ..
+                },
+                Effect::Duck {
+                    sidechain: s,
+                    threshold: t,
+                    gain,
+                    attack: a,
+                    release: r,
+                },
+            ) => {
+                *sidechain = s.clone();
+                *threshold = *t;
+                *ducked_gain = *gain;
+                *attack = smoothing(*a, rate);
+                *release = smoothing(*r, rate);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn process(&mut self, input: f32, channel: usize) -> f32 {
+        match self {
+            Processor::Biquad {
+                coefficients: [b0, b1, b2, a1, a2],
+                history,
+            } => {
+                let [x1, x2, y1, y2] = &mut history[channel];
+                let output = *b0 * input + *b1 * *x1 + *b2 * *x2 - *a1 * *y1 - *a2 * *y2;
+                *x2 = *x1;
+                *x1 = input;
+                *y2 = *y1;
+                *y1 = output;
+                output
+            }
+            Processor::Reverb {
+                channels,
+                feedback,
+                damping,
+                mix,
+            } => {
+                let wet = channels[channel].process(input, *feedback, *damping);
+                input * (1.0 - *mix) + wet * *mix
+            }
+            Processor::Echo {
+                lines,
+                feedback,
+                mix,
+            } => {
+                let line = &mut lines[channel];
+                let delayed = line.peek();
+                line.process(input + delayed * *feedback);
+                input * (1.0 - *mix) + delayed * *mix
+            }
+            Processor::Compressor {
+                envelope,
+                threshold,
+                ratio,
+                attack,
+                release,
+            } => {
+                let level = input.abs();
+                let coefficient = if level > *envelope { *attack } else { *release };
+                *envelope = level + (*envelope - level) * coefficient;
+                if *envelope > *threshold {
+                    let compressed = *threshold * (*envelope / *threshold).powf(1.0 / *ratio);
+                    input * compressed / *envelope
+                } else {
+                    input
+                }
+            }
+            Processor::Send {
+                sidechain,
+                envelope,
+                release,
+            } => {
+                let level = input.abs();
+                *envelope = if level > *envelope {
+                    level
+                } else {
+                    level + (*envelope - level) * *release
+                };
+                sidechain.0.set_level(*envelope);
+                input
+            }
+            Processor::Duck {
+                sidechain,
+                gain,
+                threshold,
+                ducked_gain,
+                attack,
+                release,
+            } => {
+                let (target, coefficient) = if sidechain.level() > *threshold {
+                    (*ducked_gain, *attack)
+                } else {
+                    (1.0, *release)
+                };
+                *gain = target + (*gain - target) * coefficient;
+                input * *gain
+            }
+        }
+    }
+}
+
+/// Wraps a source and applies the effects of an `EffectChain` to it.
+pub(crate) struct EffectSource<I> {
+    input: I,
+    chain: EffectChain,
+    version: Option<u64>,
+    sample_rate: u32,
+    channels: u16,
+    processors: Vec<Processor>,
+    /// Index of the channel of the next sample.
+    channel: usize,
+    ended: bool,
+    has_tail: bool,
+    /// Samples played since the input ended, and the number of those which were silent in a row.
+    tail: usize,
+    quiet: usize,
+}
+
+impl<I: Source> EffectSource<I>
+where
+    <I as Iterator>::Item: Sample,
+{
+    pub fn new(input: I, chain: EffectChain) -> EffectSource<I> {
+        EffectSource {
+            sample_rate: input.sample_rate(),
+            channels: input.channels(),
+            input,
+            chain,
+            version: None,
+            processors: Vec::new(),
+            channel: 0,
+            ended: false,
+            has_tail: false,
+            tail: 0,
+            quiet: 0,
+        }
+    }
+
+    /// Applies changes of the chain and of the format of the input.
+    fn update(&mut self) {
+        let version = self.chain.version();
+        let sample_rate = self.input.sample_rate();
+        let channels = self.input.channels();
+        if self.version == Some(version)
+            && sample_rate == self.sample_rate
+            && channels == self.channels
+        {
+            return;
+        }
+        if channels != self.channels {
+            self.processors.clear();
+        }
+        self.version = Some(version);
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        let effects = self.chain.effects();
+        self.processors.truncate(effects.len());
+        for (i, effect) in effects.iter().enumerate() {
+            match self.processors.get_mut(i) {
+                Some(processor) => {
+                    if !processor.configure(effect, sample_rate, channels) {
+                        *processor = Processor::new(effect, sample_rate, channels);
+                    }
+                }
+                None => self
+                    .processors
+                    .push(Processor::new(effect, sample_rate, channels)),
+            }
+        }
+        self.has_tail = effects.iter().any(Effect::has_tail);
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % usize::from(self.channels.max(1));
+        self.processors
+            .iter_mut()
+            .fold(sample, |sample, processor| {
+                processor.process(sample, channel)
+            })
+    }
+}
+
+impl<I: Source> Iterator for EffectSource<I>
+where
+    <I as Iterator>::Item: Sample,
+{
+    type Item = <I as Iterator>::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.ended {
+            if self.channel == 0 {
+                self.update();
+            }
+            match self.input.next() {
+                Some(sample) if self.processors.is_empty() => return Some(sample),
+                Some(sample) => {
+                    let output = self.process(cpal::Sample::to_f32(&sample));
+                    return Some(cpal::Sample::from(&output));
+                }
+                None => self.ended = true,
+            }
+        }
+        // Let reverb and echo ring out until they are silent for a tenth of a second.
+        let channels = usize::from(self.channels.max(1));
+        let rate = self.sample_rate as usize * channels;
+        if !self.has_tail
+            || self.quiet >= rate / 10
+            || self.tail >= rate * MAX_TAIL_SECONDS as usize
+        {
+            return None;
+        }
+        let output = self.process(0.0);
+        self.tail += 1;
+        if output.abs() < SILENCE {
+            self.quiet += 1;
+        } else {
+            self.quiet = 0;
+        }
+        Some(cpal::Sample::from(&output))
+    }
+}
+
+impl<I: Source> Source for EffectSource<I>
+where
+    <I as Iterator>::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.ended {
+            None
+        } else {
+            self.input.current_frame_len()
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        if self.ended {
+            self.channels
+        } else {
+            self.input.channels()
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        if self.ended {
+            self.sample_rate
+        } else {
+            self.input.sample_rate()
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.has_tail {
+            None
+        } else {
+            self.input.total_duration()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    fn process(effects: Vec<Effect>, samples: Vec<f32>) -> Vec<f32> {
+        let input = SamplesBuffer::new(1, 1000, samples);
+        EffectSource::new(input, EffectChain::new(effects)).collect()
+    }
+
+    fn sine(frequency: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * frequency * i as f32 / 1000.0).sin())
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, s| s.abs().max(peak))
+    }
+
+    #[test]
+    fn filters() {
+        let low = sine(10.0, 1000);
+        let high = sine(400.0, 1000);
+        let bypass = vec![Effect::LowPass {
+            cutoff: f32::INFINITY,
+        }];
+        assert_eq!(process(bypass, high.clone()), high);
+        let low_pass = vec![Effect::LowPass { cutoff: 50.0 }];
+        assert!(peak(&process(low_pass.clone(), low.clone())[500..]) > 0.9);
+        assert!(peak(&process(low_pass, high.clone())[500..]) < 0.1);
+        let high_pass = vec![Effect::HighPass { cutoff: 100.0 }];
+        assert!(peak(&process(high_pass.clone(), low)[500..]) < 0.1);
+        assert!(peak(&process(high_pass, high)[500..]) > 0.9);
+    }
+
+    #[test]
+    fn echo_rings_out() {
+        let effects = vec![Effect::Echo {
+            delay: 0.01,
+            feedback: 0.5,
+            mix: 0.5,
+        }];
+        let output = process(effects, vec![1.0]);
+        assert_eq!(output[0], 0.5);
+        assert_eq!(output[10], 0.5);
+        assert_eq!(output[20], 0.25);
+        assert!(output.len() > 100);
+        assert!(output.len() < 1000);
+    }
+
+    #[test]
+    fn reverb_has_tail() {
+        let effects = vec![Effect::Reverb {
+            room_size: 0.5,
+            damping: 0.5,
+            mix: 1.0,
+        }];
+        let output = process(effects, vec![1.0; 10]);
+        assert!(output.len() > 10);
+        assert!(peak(&output) > 0.0);
+    }
+
+    #[test]
+    fn compressor_reduces_loud_sounds() {
+        let effects = vec![Effect::Compressor {
+            threshold: 0.25,
+            ratio: 4.0,
+            attack: 0.0,
+            release: 0.1,
+        }];
+        let output = process(effects, vec![1.0, 0.1]);
+        assert!((output[0] - 0.25 * 4.0f32.powf(0.25)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sidechain_ducks() {
+        let sidechain = Sidechain::new();
+        let input = SamplesBuffer::new(1, 1000, vec![0.8f32]);
+        let chain = EffectChain::new(vec![Effect::SidechainSend(sidechain.clone())]);
+        let mut sender = EffectSource::new(input, chain);
+        sender.next();
+        assert_eq!(sidechain.level(), 0.8);
+        let effects = vec![Effect::Duck {
+            sidechain: sidechain.clone(),
+            threshold: 0.1,
+            gain: 0.25,
+            attack: 0.0,
+            release: 0.0,
+        }];
+        assert_eq!(process(effects.clone(), vec![1.0]), vec![0.25]);
+
+        // The sidechain is silent again once the sending sound is gone.
+        drop(sender);
+        assert_eq!(sidechain.level(), 0.0);
+        assert_eq!(process(effects, vec![1.0]), vec![1.0]);
+    }
+
+    #[test]
+    fn parameters_change_while_playing() {
+        let chain = EffectChain::new(vec![Effect::Compressor {
+            threshold: 1.0,
+            ratio: 1.0,
+            attack: 0.0,
+            release: 0.0,
+        }]);
+        let input = SamplesBuffer::new(1, 1000, vec![1.0f32; 4]);
+        let mut source = EffectSource::new(input, chain.clone());
+        assert_eq!(source.next(), Some(1.0));
+        chain.set(
+            0,
+            Effect::Compressor {
+                threshold: 0.5,
+                ratio: f32::INFINITY,
+                attack: 0.0,
+                release: 0.0,
+            },
+        );
+        assert_eq!(source.next(), Some(0.5));
+        chain.clear();
+        assert_eq!(source.next(), Some(1.0));
+    }
+}
@@ -0,0 +1,386 @@
+//! Occlusion and reverb zones, the effects of the environment on sounds heard by the listener.
+
+use std::time::Duration;
+
+use amethyst_core::math::{Matrix4, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{AudioOccluder, ReverbZone},
+    effects::Effect,
+};
+
+/// Cutoff frequency at which the occlusion filter is bypassed.
+const OPEN_CUTOFF: f32 = 20_000.0;
+
+/// Lowest cutoff frequency of an occluder, as the cutoff is interpolated in octaves.
+const MIN_CUTOFF: f32 = 10.0;
+
+/// Time constant with which occlusion and reverb follow the movements of the listener and
+/// emitters, so that sounds don't change abruptly when crossing a doorway.
+const SMOOTHING: f32 = 0.1;
+
+/// A volume in the space of an entity, scaled and rotated with its `Transform`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AudioShape {
+    /// A sphere centered on the entity.
+    Sphere {
+        /// Radius of the sphere.
+        radius: f32,
+    },
+    /// A box centered on the entity.
+    Box {
+        /// Half the size of the box along each axis.
+        half_extents: Vector3<f32>,
+    },
+}
+
+impl AudioShape {
+    /// Returns true if the segment between two points in the space of the shape crosses it.
+    pub(crate) fn intersects(&self, from: &Point3<f32>, to: &Point3<f32>) -> bool {
+        let direction = to - from;
+        match self {
+            AudioShape::Sphere { radius } => {
+                let length_sq = direction.norm_squared();
+                let t = if length_sq > f32::EPSILON {
+                    (-from.coords.dot(&direction) / length_sq).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                (from.coords + direction * t).norm() <= *radius
+            }
+            AudioShape::Box { half_extents } => {
+                let (mut enter, mut exit) = (0.0f32, 1.0f32);
+                for axis in 0..3 {
+                    let (start, delta, extent) = (from[axis], direction[axis], half_extents[axis]);
+                    if delta.abs() <= f32::EPSILON {
+                        if start.abs() > extent {
+                            return false;
+                        }
+                        continue;
+                    }
+                    let a = (-extent - start) / delta;
+                    let b = (extent - start) / delta;
+                    enter = enter.max(a.min(b));
+                    exit = exit.min(a.max(b));
+                    if enter > exit {
+                        return false;
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    /// Returns how far a point in the space of the shape is inside it, negative if it is outside.
+    pub(crate) fn depth(&self, point: &Point3<f32>) -> f32 {
+        match self {
+            AudioShape::Sphere { radius } => radius - point.coords.norm(),
+            AudioShape::Box { half_extents } => (0..3)
+                .map(|axis| half_extents[axis] - point[axis].abs())
+                .fold(f32::INFINITY, f32::min),
+        }
+    }
+}
+
+/// A shape placed in the world, with the matrix transforming world positions into its space.
+#[derive(Debug)]
+pub(crate) struct Placed<'a, T> {
+    pub(crate) inverse: Matrix4<f32>,
+    pub(crate) component: &'a T,
+}
+
+impl<'a, T> Placed<'a, T> {
+    /// Returns `None` if the global matrix can't be inverted, e.g. for a scale of 0.
+    pub(crate) fn new(global_matrix: &Matrix4<f32>, component: &'a T) -> Option<Self> {
+        global_matrix
+            .try_inverse()
+            .map(|inverse| Placed { inverse, component })
+    }
+
+    fn local(&self, point: &Point3<f32>) -> Point3<f32> {
+        self.inverse.transform_point(point)
+    }
+}
+
+/// The gain and low-pass cutoff applied to the sounds of an emitter by the occluders between it
+/// and the listener.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Occlusion {
+    pub(crate) gain: f32,
+    pub(crate) cutoff: f32,
+}
+
+impl Default for Occlusion {
+    fn default() -> Self {
+        Occlusion {
+            gain: 1.0,
+            cutoff: OPEN_CUTOFF,
+        }
+    }
+}
+
+impl Occlusion {
+    /// Returns the occlusion of the line of sight between a listener and an emitter.
+    pub(crate) fn between(
+        occluders: &[Placed<'_, AudioOccluder>],
+        listener: &Point3<f32>,
+        emitter: &Point3<f32>,
+    ) -> Self {
+        occluders
+            .iter()
+            .filter(|occluder| {
+                occluder
+                    .component
+                    .shape
+                    .intersects(&occluder.local(listener), &occluder.local(emitter))
+            })
+            .fold(Occlusion::default(), |occlusion, occluder| Occlusion {
+                gain: occlusion.gain * occluder.component.transmission.clamp(0.0, 1.0),
+                cutoff: occlusion
+                    .cutoff
+                    .min(occluder.component.cutoff.max(MIN_CUTOFF)),
+            })
+    }
+
+    /// Moves towards `target` as time passes.
+    pub(crate) fn approach(&mut self, target: Occlusion, delta: Duration) {
+        let t = smoothing(delta);
+        self.gain += (target.gain - self.gain) * t;
+        // Cutoffs are interpolated in octaves, as heard.
+        let (from, to) = (self.cutoff.log2(), target.cutoff.log2());
+        self.cutoff = (from + (to - from) * t).exp2();
+    }
+
+    fn is_open(&self) -> bool {
+        self.cutoff >= OPEN_CUTOFF * 0.95
+    }
+
+    /// Returns the occlusion filter, which lets everything through when nothing occludes.
+    fn filter(&self) -> Effect {
+        let cutoff = if self.is_open() {
+            f32::INFINITY
+        } else {
+            self.cutoff
+        };
+        Effect::LowPass { cutoff }
+    }
+}
+
+/// The reverb of the place the listener is in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Reverb {
+    room_size: f32,
+    damping: f32,
+    mix: f32,
+}
+
+impl Reverb {
+    /// Returns the reverb of the zone of highest priority the listener is in. The reverb fades in
+    /// from the boundary of the zone.
+    pub(crate) fn at(zones: &[Placed<'_, ReverbZone>], listener: &Point3<f32>) -> Self {
+        zones
+            .iter()
+            .filter_map(|zone| {
+                let depth = zone.component.shape.depth(&zone.local(listener));
+                let weight = if zone.component.fade > f32::EPSILON {
+                    depth / zone.component.fade
+                } else if depth >= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+                if weight > 0.0 {
+                    Some((zone.component, weight.min(1.0)))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(zone, _)| zone.priority)
+            .map(|(zone, weight)| Reverb {
+                room_size: zone.room_size,
+                damping: zone.damping,
+                mix: zone.mix * weight,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Moves towards `target` as time passes. The room changes at once while the reverb is
+    /// inaudible.
+    pub(crate) fn approach(&mut self, target: Reverb, delta: Duration) {
+        let t = smoothing(delta);
+        if self.mix <= f32::EPSILON {
+            self.room_size = target.room_size;
+            self.damping = target.damping;
+        } else {
+            self.room_size += (target.room_size - self.room_size) * t;
+            self.damping += (target.damping - self.damping) * t;
+        }
+        self.mix += (target.mix - self.mix) * t;
+        if self.mix < 1e-3 && target.mix == 0.0 {
+            self.mix = 0.0;
+        }
+    }
+
+    fn effect(&self) -> Effect {
+        Effect::Reverb {
+            room_size: self.room_size,
+            damping: self.damping,
+            mix: self.mix,
+        }
+    }
+}
+
+/// Returns the effects applied to the sounds of an emitter by its environment.
+///
+/// The filter and the reverb are both in the chain as long as either is needed, so that each
+/// keeps its place and its state, e.g. the tail of the reverb, while the other comes and goes.
+pub(crate) fn environment_effects(occlusion: &Occlusion, reverb: &Reverb) -> Vec<Effect> {
+    if occlusion.is_open() && reverb.mix <= 0.0 {
+        Vec::new()
+    } else {
+        vec![occlusion.filter(), reverb.effect()]
+    }
+}
+
+fn smoothing(delta: Duration) -> f32 {
+    1.0 - (-delta.as_secs_f32() / SMOOTHING).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst_core::math::Translation3;
+
+    use super::*;
+
+    fn point(x: f32, y: f32, z: f32) -> Point3<f32> {
+        Point3::new(x, y, z)
+    }
+
+    #[test]
+    fn sphere_intersection_and_depth() {
+        let sphere = AudioShape::Sphere { radius: 1.0 };
+        assert!(sphere.intersects(&point(-5.0, 0.5, 0.0), &point(5.0, 0.5, 0.0)));
+        assert!(!sphere.intersects(&point(-5.0, 1.5, 0.0), &point(5.0, 1.5, 0.0)));
+        assert!(!sphere.intersects(&point(2.0, 0.0, 0.0), &point(5.0, 0.0, 0.0)));
+        assert_eq!(sphere.depth(&point(0.5, 0.0, 0.0)), 0.5);
+        assert_eq!(sphere.depth(&point(2.0, 0.0, 0.0)), -1.0);
+    }
+
+    #[test]
+    fn box_intersection_and_depth() {
+        let wall = AudioShape::Box {
+            half_extents: Vector3::new(0.1, 2.0, 2.0),
+        };
+        assert!(wall.intersects(&point(-5.0, 1.0, 0.0), &point(5.0, -1.0, 0.0)));
+        assert!(!wall.intersects(&point(-5.0, 3.0, 0.0), &point(5.0, 3.0, 0.0)));
+        assert!(!wall.intersects(&point(-5.0, 0.0, 0.0), &point(-1.0, 0.0, 0.0)));
+        assert!(!wall.intersects(&point(-5.0, 0.0, 0.0), &point(5.0, 0.0, 9.0)));
+        assert!((wall.depth(&point(0.0, 1.5, 0.0)) - 0.1).abs() < 1e-6);
+        assert!(wall.depth(&point(1.0, 0.0, 0.0)) < 0.0);
+    }
+
+    #[test]
+    fn occluders_attenuate_the_line_of_sight() {
+        let occluder = AudioOccluder {
+            shape: AudioShape::Box {
+                half_extents: Vector3::new(0.1, 2.0, 2.0),
+            },
+            transmission: 0.5,
+            cutoff: 800.0,
+        };
+        let matrix = Translation3::new(10.0, 0.0, 0.0).to_homogeneous();
+        let occluders = vec![Placed::new(&matrix, &occluder).unwrap()];
+
+        let occlusion =
+            Occlusion::between(&occluders, &point(8.0, 0.0, 0.0), &point(12.0, 0.0, 0.0));
+        assert_eq!(
+            occlusion,
+            Occlusion {
+                gain: 0.5,
+                cutoff: 800.0
+            }
+        );
+        let clear = Occlusion::between(&occluders, &point(8.0, 0.0, 0.0), &point(8.0, 5.0, 0.0));
+        assert_eq!(clear, Occlusion::default());
+        assert!(environment_effects(&clear, &Reverb::default()).is_empty());
+
+        // The reverb keeps its place when the occlusion changes.
+        let reverb = Reverb {
+            room_size: 0.5,
+            damping: 0.5,
+            mix: 0.5,
+        };
+        let open = environment_effects(&clear, &reverb);
+        let occluded = environment_effects(&occlusion, &reverb);
+        assert_eq!(open.len(), 2);
+        assert_eq!(open[1], occluded[1]);
+        assert_eq!(
+            open[0],
+            Effect::LowPass {
+                cutoff: f32::INFINITY
+            }
+        );
+
+        let mut smoothed = Occlusion::default();
+        smoothed.approach(occlusion, Duration::from_secs(10));
+        assert!((smoothed.gain - 0.5).abs() < 1e-3);
+        assert!((smoothed.cutoff - 800.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn zero_cutoff_is_clamped() {
+        let occluder = AudioOccluder {
+            shape: AudioShape::Sphere { radius: 1.0 },
+            transmission: 0.5,
+            cutoff: 0.0,
+        };
+        let identity = Matrix4::identity();
+        let occluders = vec![Placed::new(&identity, &occluder).unwrap()];
+        let occlusion =
+            Occlusion::between(&occluders, &point(-2.0, 0.0, 0.0), &point(2.0, 0.0, 0.0));
+        assert_eq!(occlusion.cutoff, MIN_CUTOFF);
+        let mut smoothed = Occlusion::default();
+        smoothed.approach(occlusion, Duration::from_millis(100));
+        assert!(smoothed.cutoff.is_finite());
+    }
+
+    #[test]
+    fn reverb_fades_in_from_the_zone_boundary() {
+        let hall = ReverbZone {
+            shape: AudioShape::Sphere { radius: 10.0 },
+            fade: 2.0,
+            room_size: 0.9,
+            damping: 0.2,
+            mix: 0.6,
+            priority: 0,
+        };
+        let closet = ReverbZone {
+            shape: AudioShape::Sphere { radius: 1.0 },
+            fade: 0.0,
+            room_size: 0.1,
+            damping: 0.8,
+            mix: 0.3,
+            priority: 1,
+        };
+        let identity = Matrix4::identity();
+        let zones = vec![
+            Placed::new(&identity, &hall).unwrap(),
+            Placed::new(&identity, &closet).unwrap(),
+        ];
+        assert_eq!(
+            Reverb::at(&zones, &point(0.0, 12.0, 0.0)),
+            Reverb::default()
+        );
+        assert_eq!(Reverb::at(&zones, &point(0.0, 9.0, 0.0)).mix, 0.3);
+        assert_eq!(Reverb::at(&zones, &point(0.0, 5.0, 0.0)).mix, 0.6);
+        assert_eq!(
+            Reverb::at(&zones, &point(0.0, 0.5, 0.0)),
+            Reverb {
+                room_size: 0.1,
+                damping: 0.8,
+                mix: 0.3
+            }
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::io::{Result as IoResult, Write};
+
 use amethyst_assets::*;
 use amethyst_error::Error;
 
@@ -66,3 +68,31 @@ impl Format<AudioData> for Mp3Format {
         Ok(AudioData(bytes))
     }
 }
+
+/// Encodes interleaved samples between -1.0 and 1.0 as a 16 bit PCM WAV file.
+pub(crate) fn write_wav<W: Write>(
+    writer: &mut W,
+    channels: u16,
+    sample_rate: u32,
+    samples: &[f32],
+) -> IoResult<()> {
+    let data_len = samples.len() as u32 * 2;
+    let block_align = channels * 2;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * u32::from(block_align)).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
@@ -11,10 +11,22 @@
 pub use self::{
     bundle::AudioBundle,
     components::*,
+    devices::{AudioDeviceEvent, AudioDevices},
+    effects::{Effect, EffectChain, Sidechain},
+    environment::AudioShape,
     formats::{FlacFormat, Mp3Format, OggFormat, WavFormat},
+    mixer::{AudioMixer, Bus, BusGain, BusSettings},
+    music::{MusicPlayer, Playlist, PlaylistOrder, Repeat, Track},
+    playback::PlaybackId,
+    recording::{AudioRecording, RecordedSound, RECORDING_CHANNELS},
     sink::AudioSink,
     source::{Source, SourceHandle},
+    spatial::{Attenuation, DistanceModel, SpatialAudioConfig},
+    synth::{Envelope, Oscillator, SfxPreset, Synth, SynthFormat, Waveform, MAX_SYNTH_DURATION},
     systems::*,
+    timeline::{
+        Cue, CueEvent, CueKind, CueOrigin, CueSheet, CueSheetHandle, PlaybackPosition, Tempo,
+    },
 };
 
 use std::{
@@ -26,11 +38,22 @@ pub mod output;
 
 mod bundle;
 mod components;
+mod devices;
+mod effects;
 mod end_signal;
+mod environment;
 mod formats;
+mod mixer;
+mod music;
+mod playback;
+mod recording;
+mod route;
 mod sink;
 mod source;
+mod spatial;
+mod synth;
 mod systems;
+mod timeline;
 
 /// An error occurred while decoding the source.
 #[derive(Debug)]
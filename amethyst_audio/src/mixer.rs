@@ -0,0 +1,332 @@
+//! Mixer buses controlling the volume of categories of sounds.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Formatter, Result as FmtResult},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rodio::{Sample, Source};
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{EffectChain, EffectSource};
+
+/// A mixer bus. Every sound is routed to one bus, whose volume is applied on top of the volume
+/// the sound was played with.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Bus {
+    /// The bus all other buses are routed through.
+    Master,
+    /// Background music, used by `AudioSink` and `DjSystem`.
+    Music,
+    /// Sound effects, used by `AudioEmitter`.
+    Sfx,
+    /// Dialogue.
+    Voice,
+    /// User interface sounds, used by `UiSoundSystem`.
+    Ui,
+    /// A bus defined by the game.
+    Custom(String),
+}
+
+impl Display for Bus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Bus::Custom(name) => f.write_str(name),
+            bus => write!(f, "{:?}", bus),
+        }
+    }
+}
+
+/// The settings of a single bus.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BusSettings {
+    /// The volume of the bus. A volume of 1.0 is unchanged, while 0.0 is silent.
+    pub volume: f32,
+    /// Silences the bus.
+    pub muted: bool,
+    /// While any bus is soloed, all buses which aren't soloed are silent.
+    pub solo: bool,
+}
+
+impl Default for BusSettings {
+    fn default() -> Self {
+        BusSettings {
+            volume: 1.0,
+            muted: false,
+            solo: false,
+        }
+    }
+}
+
+/// The current gain and effects of a bus, shared with every sound playing on it, so that changes
+/// to the mixer are applied to sounds which are already playing.
+#[derive(Clone, Debug)]
+pub struct BusGain {
+    gain: Arc<AtomicU32>,
+    effects: EffectChain,
+}
+
+impl BusGain {
+    fn new(gain: f32, effects: EffectChain) -> Self {
+        BusGain {
+            gain: Arc::new(AtomicU32::new(gain.to_bits())),
+            effects,
+        }
+    }
+
+    /// Returns the current gain of the bus.
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+
+    /// Returns the effects of the bus.
+    pub fn effects(&self) -> &EffectChain {
+        &self.effects
+    }
+
+    fn set(&self, gain: f32) {
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl Default for BusGain {
+    /// A gain which isn't connected to any mixer, stays at 1.0 and has no effects.
+    fn default() -> Self {
+        BusGain::new(1.0, EffectChain::default())
+    }
+}
+
+/// Resource holding the settings of all mixer buses.
+///
+/// Buses which have never been configured use the default `BusSettings`. The resource can be
+/// serialized to store the settings of an options menu; use `AudioMixer::load_settings` to apply
+/// deserialized settings, as replacing the resource would disconnect the sounds already playing.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AudioMixer {
+    buses: BTreeMap<Bus, BusSettings>,
+    #[serde(skip)]
+    gains: HashMap<Bus, BusGain>,
+    #[serde(skip)]
+    effects: HashMap<Bus, EffectChain>,
+}
+
+impl AudioMixer {
+    /// Creates a new `AudioMixer` with all buses at full volume.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the settings of a bus.
+    pub fn bus(&self, bus: &Bus) -> BusSettings {
+        self.buses.get(bus).copied().unwrap_or_default()
+    }
+
+    /// Returns all buses which have been configured, along with their settings.
+    pub fn buses(&self) -> impl Iterator<Item = (&Bus, &BusSettings)> {
+        self.buses.iter()
+    }
+
+    /// Replaces the settings of a bus.
+    pub fn set_bus(&mut self, bus: Bus, settings: BusSettings) {
+        self.buses.insert(bus, settings);
+        self.update_gains();
+    }
+
+    /// Sets the volume of a bus. A volume of 1.0 is unchanged, while 0.0 is silent.
+    pub fn set_volume(&mut self, bus: Bus, volume: f32) {
+        self.modify(bus, |settings| settings.volume = volume);
+    }
+
+    /// Mutes or unmutes a bus.
+    pub fn set_muted(&mut self, bus: Bus, muted: bool) {
+        self.modify(bus, |settings| settings.muted = muted);
+    }
+
+    /// Solos or unsolos a bus.
+    pub fn set_solo(&mut self, bus: Bus, solo: bool) {
+        self.modify(bus, |settings| settings.solo = solo);
+    }
+
+    /// Replaces the settings of all buses with the ones of `settings`, e.g. after deserializing
+    /// them from a config file. Sounds which are already playing are updated.
+    pub fn load_settings(&mut self, settings: AudioMixer) {
+        self.buses = settings.buses;
+        self.update_gains();
+    }
+
+    /// Returns the effective gain of a bus, taking the master bus, mute and solo into account.
+    pub fn effective_volume(&self, bus: &Bus) -> f32 {
+        let master = self.bus(&Bus::Master);
+        if master.muted {
+            return 0.0;
+        }
+        if *bus == Bus::Master {
+            return master.volume;
+        }
+        let settings = self.bus(bus);
+        let any_solo = self
+            .buses
+            .iter()
+            .any(|(bus, settings)| *bus != Bus::Master && settings.solo);
+        if settings.muted || (any_solo && !settings.solo) {
+            0.0
+        } else {
+            master.volume * settings.volume
+        }
+    }
+
+    /// Returns the gain of a bus, to be applied to a sound routed to it.
+    pub fn gain(&mut self, bus: &Bus) -> BusGain {
+        if let Some(gain) = self.gains.get(bus) {
+            return gain.clone();
+        }
+        let gain = BusGain::new(self.effective_volume(bus), self.effects(bus));
+        self.gains.insert(bus.clone(), gain.clone());
+        gain
+    }
+
+    /// Returns the effects of a bus. Effects are applied to each sound routed to the bus, before
+    /// the gain of the bus; they don't apply to sounds routed to other buses through the master
+    /// bus.
+    pub fn effects(&mut self, bus: &Bus) -> EffectChain {
+        self.effects.entry(bus.clone()).or_default().clone()
+    }
+
+    fn modify(&mut self, bus: Bus, f: impl FnOnce(&mut BusSettings)) {
+        f(self.buses.entry(bus).or_default());
+        self.update_gains();
+    }
+
+    fn update_gains(&self) {
+        for (bus, gain) in &self.gains {
+            gain.set(self.effective_volume(bus));
+        }
+    }
+}
+
+/// Wraps a source, applies the effects of a bus and scales it by the current gain of the bus.
+pub(crate) struct BusSource<I> {
+    input: EffectSource<I>,
+    gain: BusGain,
+}
+
+impl<I: Source> BusSource<I>
+where
+    <I as Iterator>::Item: Sample,
+{
+    pub fn new(input: I, gain: BusGain) -> BusSource<I> {
+        BusSource {
+            input: EffectSource::new(input, gain.effects.clone()),
+            gain,
+        }
+    }
+}
+
+impl<I: Source> Iterator for BusSource<I>
+where
+    <I as Iterator>::Item: Sample,
+{
+    type Item = <I as Iterator>::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.input
+            .next()
+            .map(|sample| sample.amplify(self.gain.get()))
+    }
+}
+
+impl<I: Source> Source for BusSource<I>
+where
+    <I as Iterator>::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_includes_master() {
+        let mut mixer = AudioMixer::new();
+        mixer.set_volume(Bus::Master, 0.5);
+        mixer.set_volume(Bus::Music, 0.5);
+        assert_eq!(mixer.effective_volume(&Bus::Music), 0.25);
+        assert_eq!(mixer.effective_volume(&Bus::Sfx), 0.5);
+        assert_eq!(mixer.effective_volume(&Bus::Master), 0.5);
+    }
+
+    #[test]
+    fn mute_and_solo() {
+        let mut mixer = AudioMixer::new();
+        mixer.set_muted(Bus::Sfx, true);
+        assert_eq!(mixer.effective_volume(&Bus::Sfx), 0.0);
+
+        mixer.set_solo(Bus::Voice, true);
+        assert_eq!(mixer.effective_volume(&Bus::Voice), 1.0);
+        assert_eq!(mixer.effective_volume(&Bus::Music), 0.0);
+
+        mixer.set_muted(Bus::Master, true);
+        assert_eq!(mixer.effective_volume(&Bus::Voice), 0.0);
+    }
+
+    #[test]
+    fn gains_follow_settings() {
+        let mut mixer = AudioMixer::new();
+        let gain = mixer.gain(&Bus::Ui);
+        assert_eq!(gain.get(), 1.0);
+        mixer.set_volume(Bus::Ui, 0.3);
+        assert_eq!(gain.get(), 0.3);
+
+        let mut loaded = AudioMixer::new();
+        loaded.set_volume(Bus::Ui, 0.7);
+        mixer.load_settings(loaded);
+        assert_eq!(gain.get(), 0.7);
+    }
+
+    #[test]
+    fn bus_source_scales_samples() {
+        let mut mixer = AudioMixer::new();
+        mixer.set_volume(Bus::Sfx, 0.5);
+        let input = rodio::buffer::SamplesBuffer::new(1, 44100, vec![0.5f32, -1.0]);
+        let samples = BusSource::new(input, mixer.gain(&Bus::Sfx)).collect::<Vec<_>>();
+        assert_eq!(samples, vec![0.25, -0.5]);
+    }
+
+    #[test]
+    fn bus_effects_apply_to_playing_sounds() {
+        use crate::effects::Effect;
+
+        let mut mixer = AudioMixer::new();
+        let input = rodio::buffer::SamplesBuffer::new(1, 44100, vec![1.0f32; 2]);
+        let mut source = BusSource::new(input, mixer.gain(&Bus::Music));
+        assert_eq!(source.next(), Some(1.0));
+        mixer.effects(&Bus::Music).push(Effect::Compressor {
+            threshold: 0.5,
+            ratio: f32::INFINITY,
+            attack: 0.0,
+            release: 0.0,
+        });
+        assert_eq!(source.next(), Some(0.5));
+    }
+}
@@ -0,0 +1,550 @@
+//! Playlists, crossfades, stems and stingers for background music.
+
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rodio::Sink;
+
+use crate::source::SourceHandle;
+
+/// A piece of music made of one or more stems which play in sync. The volume of each stem can
+/// be mixed while the track plays, see `MusicPlayer::set_stem_volume`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track {
+    /// The layers of the track.
+    pub stems: Vec<SourceHandle>,
+}
+
+impl Track {
+    /// Creates a track made of a single source.
+    pub fn new(source: SourceHandle) -> Self {
+        Track {
+            stems: vec![source],
+        }
+    }
+
+    /// Creates a track made of several stems.
+    pub fn with_stems(stems: Vec<SourceHandle>) -> Self {
+        Track { stems }
+    }
+}
+
+/// The order in which the tracks of a playlist are played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaylistOrder {
+    /// In the order they were added.
+    Sequential,
+    /// In a random order, every track being played once before any track is repeated.
+    Shuffle,
+}
+
+/// What happens when a track or the playlist ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    /// The music stops after the last track.
+    Off,
+    /// The playlist starts over after the last track.
+    All,
+    /// The current track is repeated.
+    One,
+}
+
+/// A list of tracks played one after another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playlist {
+    /// The tracks of the playlist.
+    pub tracks: Vec<Track>,
+    /// The order the tracks are played in.
+    pub order: PlaylistOrder,
+    /// What happens when a track or the playlist ends.
+    pub repeat: Repeat,
+}
+
+impl Playlist {
+    /// Creates a sequential playlist which repeats once all tracks were played.
+    pub fn new(tracks: Vec<Track>) -> Self {
+        Playlist {
+            tracks,
+            order: PlaylistOrder::Sequential,
+            repeat: Repeat::All,
+        }
+    }
+
+    /// Sets the order the tracks are played in.
+    pub fn with_order(mut self, order: PlaylistOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Sets what happens when a track or the playlist ends.
+    pub fn with_repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+}
+
+/// A value moving linearly towards a target over time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Ramp {
+    value: f32,
+    target: f32,
+    /// Change per second.
+    rate: f32,
+}
+
+impl Ramp {
+    pub(crate) fn new(value: f32) -> Self {
+        Ramp {
+            value,
+            target: value,
+            rate: 0.0,
+        }
+    }
+
+    pub(crate) fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub(crate) fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Moves the value to `target` over `duration`, or immediately for a zero duration.
+    pub(crate) fn to(&mut self, target: f32, duration: Duration) {
+        self.target = target;
+        if duration == Duration::from_secs(0) {
+            self.value = target;
+            self.rate = 0.0;
+        } else {
+            self.rate = (target - self.value).abs() / duration.as_secs_f32();
+        }
+    }
+
+    pub(crate) fn advance(&mut self, delta: Duration) {
+        let step = self.rate * delta.as_secs_f32();
+        if (self.target - self.value).abs() <= step {
+            self.value = self.target;
+        } else if self.target > self.value {
+            self.value += step;
+        } else {
+            self.value -= step;
+        }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.value == self.target
+    }
+}
+
+/// A small xorshift generator used to shuffle playlists.
+#[derive(Clone, Debug)]
+struct Shuffler(u64);
+
+impl Shuffler {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Shuffler(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a random permutation of `0..len` which doesn't start with `avoid`, if possible.
+    fn permutation(&mut self, len: usize, avoid: Option<usize>) -> Vec<usize> {
+        let mut order = (0..len).collect::<Vec<_>>();
+        for i in (1..len).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            order.swap(i, j);
+        }
+        if len > 1 && order.first().copied() == avoid {
+            order.swap(0, len - 1);
+        }
+        order
+    }
+}
+
+/// A stem of a track which is playing.
+pub(crate) struct PlayingStem {
+    pub(crate) sink: Sink,
+    pub(crate) ended: Arc<AtomicBool>,
+}
+
+/// A track which is playing or waiting for its sources to load.
+pub(crate) struct PlayingTrack {
+    pub(crate) track: Track,
+    /// Index of the track in its playlist.
+    pub(crate) index: usize,
+    /// Empty until all stems are loaded.
+    pub(crate) stems: Vec<PlayingStem>,
+    pub(crate) fade: Ramp,
+    pub(crate) elapsed: Duration,
+    pub(crate) duration: Option<Duration>,
+    /// Set once the following track was started.
+    pub(crate) superseded: bool,
+}
+
+impl PlayingTrack {
+    pub(crate) fn new(track: Track, index: usize, fade_in: Duration) -> Self {
+        let mut fade = Ramp::new(0.0);
+        fade.to(1.0, fade_in);
+        PlayingTrack {
+            track,
+            index,
+            stems: Vec::new(),
+            fade,
+            elapsed: Duration::from_secs(0),
+            duration: None,
+            superseded: false,
+        }
+    }
+
+    pub(crate) fn is_started(&self) -> bool {
+        !self.stems.is_empty()
+    }
+
+    pub(crate) fn has_ended(&self) -> bool {
+        self.is_started()
+            && self
+                .stems
+                .iter()
+                .all(|stem| stem.ended.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn stop(&self) {
+        for stem in &self.stems {
+            stem.sink.stop();
+        }
+    }
+}
+
+/// A one-shot sound playing over the music.
+pub(crate) struct PlayingStinger {
+    pub(crate) source: SourceHandle,
+    pub(crate) volume: f32,
+    pub(crate) stem: Option<PlayingStem>,
+}
+
+/// Resource to control the background music played by the `MusicSystem`.
+///
+/// Playlists are registered under a name and selected with `MusicPlayer::play`, which is meant
+/// to be called when entering a `State`, e.g. in `on_start` and `on_resume`. Switching playlists
+/// or tracks crossfades between them.
+pub struct MusicPlayer {
+    pub(crate) playlists: HashMap<String, Playlist>,
+    pub(crate) current: Option<String>,
+    pub(crate) crossfade: Duration,
+    pub(crate) duck_volume: f32,
+    pub(crate) duck_fade: Duration,
+    pub(crate) stem_volumes: Vec<Ramp>,
+    pub(crate) duck: Ramp,
+    pub(crate) tracks: Vec<PlayingTrack>,
+    pub(crate) stingers: Vec<PlayingStinger>,
+    /// Index of the current track in the playlist and the remaining play order.
+    pub(crate) position: Option<usize>,
+    pub(crate) queue: Vec<usize>,
+    pub(crate) skip_requested: bool,
+    /// Tracks of the current playlist which failed to decode since a track last started.
+    pub(crate) failed: Vec<usize>,
+    shuffler: Shuffler,
+}
+
+impl Default for MusicPlayer {
+    fn default() -> Self {
+        MusicPlayer {
+            playlists: HashMap::new(),
+            current: None,
+            crossfade: Duration::from_secs(2),
+            duck_volume: 0.3,
+            duck_fade: Duration::from_millis(250),
+            stem_volumes: Vec::new(),
+            duck: Ramp::new(1.0),
+            tracks: Vec::new(),
+            stingers: Vec::new(),
+            position: None,
+            queue: Vec::new(),
+            skip_requested: false,
+            failed: Vec::new(),
+            shuffler: Shuffler::new(),
+        }
+    }
+}
+
+impl Debug for MusicPlayer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("MusicPlayer")
+            .field("playlists", &self.playlists.keys().collect::<Vec<_>>())
+            .field("current", &self.current)
+            .field("position", &self.position)
+            .field("crossfade", &self.crossfade)
+            .field("playing_tracks", &self.tracks.len())
+            .field("playing_stingers", &self.stingers.len())
+            .finish()
+    }
+}
+
+impl MusicPlayer {
+    /// Creates a new `MusicPlayer` with a crossfade of two seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long tracks are crossfaded when switching tracks or playlists.
+    pub fn set_crossfade(&mut self, crossfade: Duration) {
+        self.crossfade = crossfade;
+    }
+
+    /// Sets the volume the music is ducked to while a stinger plays, and how fast it is ducked.
+    pub fn set_ducking(&mut self, volume: f32, fade: Duration) {
+        self.duck_volume = volume;
+        self.duck_fade = fade;
+    }
+
+    /// Registers a playlist, replacing any playlist with the same name.
+    pub fn add_playlist<N: Into<String>>(&mut self, name: N, playlist: Playlist) {
+        self.playlists.insert(name.into(), playlist);
+    }
+
+    /// Returns the playlist with the given name.
+    pub fn playlist(&self, name: &str) -> Option<&Playlist> {
+        self.playlists.get(name)
+    }
+
+    /// Returns the name of the playlist currently playing.
+    pub fn current_playlist(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Returns the index of the current track in the current playlist.
+    pub fn current_track(&self) -> Option<usize> {
+        self.position
+    }
+
+    /// Crossfades to the playlist with the given name. Does nothing if the playlist is already
+    /// playing, so this can be called whenever a `State` becomes active.
+    pub fn play(&mut self, name: &str) {
+        if self.current.as_deref() == Some(name) || !self.playlists.contains_key(name) {
+            return;
+        }
+        self.current = Some(name.to_string());
+        self.position = None;
+        self.queue.clear();
+        self.failed.clear();
+        self.skip_requested = true;
+    }
+
+    /// Crossfades to the next track of the current playlist.
+    pub fn skip(&mut self) {
+        self.skip_requested = true;
+    }
+
+    /// Fades the music out and stops it.
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.position = None;
+        self.queue.clear();
+        self.failed.clear();
+        self.skip_requested = true;
+    }
+
+    /// Sets the volume of a stem of the tracks, fading to it over `fade`. The volume applies to
+    /// the stem with the same index in all tracks.
+    pub fn set_stem_volume(&mut self, stem: usize, volume: f32, fade: Duration) {
+        if self.stem_volumes.len() <= stem {
+            self.stem_volumes.resize(stem + 1, Ramp::new(1.0));
+        }
+        self.stem_volumes[stem].to(volume, fade);
+    }
+
+    /// Returns the volume of a stem, without the ongoing fade.
+    pub fn stem_volume(&self, stem: usize) -> f32 {
+        self.stem_volumes.get(stem).map_or(1.0, Ramp::target)
+    }
+
+    /// Plays a one-shot sound over the music, which is ducked while the stinger plays.
+    pub fn play_stinger(&mut self, source: SourceHandle, volume: f32) {
+        self.stingers.push(PlayingStinger {
+            source,
+            volume,
+            stem: None,
+        });
+    }
+
+    /// Returns true if a stinger is playing.
+    pub fn is_stinger_playing(&self) -> bool {
+        !self.stingers.is_empty()
+    }
+
+    /// Advances the playlist, returning the index of the track to play next and the track, if
+    /// any.
+    pub(crate) fn next_track(&mut self) -> Option<(usize, Track)> {
+        let playlist = self.playlists.get(self.current.as_ref()?)?;
+        let len = playlist.tracks.len();
+        if len == 0 {
+            return None;
+        }
+        let next = match (self.position, playlist.repeat) {
+            (Some(position), Repeat::One) if !self.skip_requested => position,
+            _ => {
+                if self.queue.is_empty() {
+                    if self.position.is_some() && playlist.repeat == Repeat::Off {
+                        return None;
+                    }
+                    self.queue = match playlist.order {
+                        PlaylistOrder::Sequential => (0..len).collect(),
+                        PlaylistOrder::Shuffle => self.shuffler.permutation(len, self.position),
+                    };
+                    self.queue.reverse();
+                }
+                self.queue.pop()?
+            }
+        };
+        self.position = Some(next);
+        Some((next, playlist.tracks[next].clone()))
+    }
+
+    /// Remembers that a track of the current playlist failed to decode. Returns false once
+    /// every track of the playlist failed, as there is nothing left to play.
+    pub(crate) fn track_failed(&mut self, index: usize) -> bool {
+        if !self.failed.contains(&index) {
+            self.failed.push(index);
+        }
+        let len = self
+            .current
+            .as_ref()
+            .and_then(|name| self.playlists.get(name))
+            .map_or(0, |playlist| playlist.tracks.len());
+        self.failed.len() < len
+    }
+
+    pub(crate) fn stem_volume_ramp(&self, stem: usize) -> f32 {
+        self.stem_volumes.get(stem).map_or(1.0, Ramp::value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst_assets::AssetStorage;
+
+    use super::*;
+    use crate::source::Source;
+
+    fn handles(count: usize) -> Vec<SourceHandle> {
+        let mut storage = AssetStorage::<Source>::new();
+        (0..count)
+            .map(|_| storage.insert(Source { bytes: Vec::new() }))
+            .collect()
+    }
+
+    fn player(playlist: Playlist) -> MusicPlayer {
+        let mut player = MusicPlayer::new();
+        player.add_playlist("level", playlist);
+        player.play("level");
+        player
+    }
+
+    fn tracks(handles: &[SourceHandle]) -> Vec<Track> {
+        handles.iter().cloned().map(Track::new).collect()
+    }
+
+    fn next_index(player: &mut MusicPlayer) -> Option<usize> {
+        let track = player.next_track();
+        player.skip_requested = false;
+        track.map(|_| player.current_track().unwrap())
+    }
+
+    #[test]
+    fn sequential_playlist() {
+        let handles = handles(3);
+        let mut player = player(Playlist::new(tracks(&handles)).with_repeat(Repeat::Off));
+        assert_eq!(next_index(&mut player), Some(0));
+        assert_eq!(next_index(&mut player), Some(1));
+        assert_eq!(next_index(&mut player), Some(2));
+        assert_eq!(next_index(&mut player), None);
+    }
+
+    #[test]
+    fn repeating_playlists() {
+        let handles = handles(2);
+        let mut player = player(Playlist::new(tracks(&handles)));
+        let order = (0..4)
+            .map(|_| next_index(&mut player).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![0, 1, 0, 1]);
+
+        let mut player = player_with_repeat_one(&handles);
+        assert_eq!(next_index(&mut player), Some(0));
+        assert_eq!(next_index(&mut player), Some(0));
+        player.skip();
+        assert_eq!(next_index(&mut player), Some(1));
+    }
+
+    fn player_with_repeat_one(handles: &[SourceHandle]) -> MusicPlayer {
+        player(Playlist::new(tracks(handles)).with_repeat(Repeat::One))
+    }
+
+    #[test]
+    fn shuffle_plays_every_track_once() {
+        let handles = handles(5);
+        let mut player = player(Playlist::new(tracks(&handles)).with_order(PlaylistOrder::Shuffle));
+        let mut order = (0..5)
+            .map(|_| next_index(&mut player).unwrap())
+            .collect::<Vec<_>>();
+        let last = *order.last().unwrap();
+        order.sort();
+        assert_eq!(order, vec![0, 1, 2, 3, 4]);
+        assert_ne!(next_index(&mut player), Some(last));
+    }
+
+    #[test]
+    fn play_same_playlist_is_ignored() {
+        let handles = handles(1);
+        let mut player = player(Playlist::new(tracks(&handles)));
+        next_index(&mut player);
+        player.play("level");
+        assert!(!player.skip_requested);
+        player.play("unknown");
+        assert_eq!(player.current_playlist(), Some("level"));
+    }
+
+    #[test]
+    fn failed_tracks_are_counted_until_all_failed() {
+        let handles = handles(3);
+        let mut player = player(Playlist::new(tracks(&handles)));
+        assert!(player.track_failed(0));
+        assert!(player.track_failed(0));
+        assert!(player.track_failed(2));
+        assert!(!player.track_failed(1));
+
+        // Another playlist gets its own chance.
+        player.add_playlist("menu", Playlist::new(tracks(&handles)));
+        player.play("menu");
+        assert!(player.track_failed(1));
+    }
+
+    #[test]
+    fn ramp() {
+        let mut ramp = Ramp::new(0.0);
+        ramp.to(1.0, Duration::from_secs(2));
+        ramp.advance(Duration::from_secs(1));
+        assert_eq!(ramp.value(), 0.5);
+        ramp.advance(Duration::from_secs(2));
+        assert_eq!(ramp.value(), 1.0);
+        assert!(ramp.is_done());
+        ramp.to(0.2, Duration::from_secs(0));
+        assert_eq!(ramp.value(), 0.2);
+    }
+}
@@ -4,26 +4,77 @@
 use std::{
     fmt::{Debug, Formatter, Result as FmtResult},
     io::Cursor,
-    sync::Arc,
+    sync::{Arc, Mutex, RwLock, Weak},
 };
 
 use cpal::traits::DeviceTrait;
 use log::error;
 use rodio::{
-    default_output_device, output_devices, Decoder, Device, Devices, OutputDevices, Sink,
+    default_output_device, output_devices, play_raw, Decoder, Device, Devices, OutputDevices, Sink,
     Source as RSource,
 };
 
 use amethyst_core::ecs::World;
 
-use crate::{sink::AudioSink, source::Source, DecoderError};
+use crate::{
+    mixer::{AudioMixer, Bus, BusGain, BusSource},
+    recording::{AudioRecording, SinkTag},
+    route::Route,
+    sink::AudioSink,
+    source::Source,
+    DecoderError,
+};
 
 /// A speaker(s) through which audio can be played.
 ///
-/// By convention, the default output is stored as a resource in the `World`.
+/// By convention, the default output is stored as a resource in the `World`. An output can also
+/// record what is played instead of playing it on a device, see `Output::new_recording`.
+///
+/// Clones of an output share its device, which can be changed while sounds play with
+/// `Output::switch_to`.
 #[derive(Clone)]
 pub struct Output {
-    pub(crate) device: Arc<Device>,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    backend: RwLock<Backend>,
+    /// The sinks created by this output, which are moved when switching to another device.
+    routes: Mutex<Vec<Weak<Route>>>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Device {
+        device: Arc<Device>,
+        /// Sample rate the sinks are mixed at before being played on the device.
+        sample_rate: u32,
+    },
+    Recording(AudioRecording),
+}
+
+impl Backend {
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Backend::Device { sample_rate, .. } => *sample_rate,
+            Backend::Recording(recording) => recording.sample_rate(),
+        }
+    }
+
+    /// Plays the queue of a sink on this backend.
+    fn attach(&self, route: &Arc<Route>) {
+        let relay = route.relay();
+        match self {
+            Backend::Device { device, .. } => {
+                route.tag().attach(None);
+                play_raw(device, relay);
+            }
+            Backend::Recording(recording) => {
+                let id = recording.add(relay);
+                route.tag().attach(Some((recording.clone(), id)));
+            }
+        }
+    }
 }
 
 /// Convenience method for opening the default output device.
@@ -33,21 +84,86 @@ pub struct Output {
 /// integrated audio chip.
 impl Default for Output {
     fn default() -> Self {
-        default_output_device()
-            .map(|device| Output {
-                device: Arc::new(device),
-            })
-            .expect("No default output device")
+        default_output().expect("No default output device")
     }
 }
 
 impl Output {
+    fn from_device(device: Device) -> Self {
+        let sample_rate = device
+            .default_output_format()
+            .map(|format| format.sample_rate.0)
+            .unwrap_or(44_100);
+        Output::from_backend(Backend::Device {
+            device: Arc::new(device),
+            sample_rate,
+        })
+    }
+
+    fn from_backend(backend: Backend) -> Self {
+        Output {
+            shared: Arc::new(Shared {
+                backend: RwLock::new(backend),
+                routes: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Creates an output which mixes everything played on it into an `AudioRecording` at the
+    /// given sample rate, instead of playing it on a device.
+    pub fn new_recording(sample_rate: u32) -> Self {
+        Output::from_backend(Backend::Recording(AudioRecording::new(sample_rate)))
+    }
+
+    /// Returns the recording of this output, if it plays on a recording created with
+    /// `Output::new_recording`.
+    pub fn recording(&self) -> Option<AudioRecording> {
+        match &*self.shared.backend.read().unwrap() {
+            Backend::Recording(recording) => Some(recording.clone()),
+            Backend::Device { .. } => None,
+        }
+    }
+
     /// Gets the name of the output
     pub fn name(&self) -> String {
-        self.device.name().unwrap_or_else(|e| {
-            error!("Failed to determine output device name: {}", e);
-            String::from("<unnamed_output_device>")
-        })
+        match &*self.shared.backend.read().unwrap() {
+            Backend::Device { device, .. } => device.name().unwrap_or_else(|e| {
+                error!("Failed to determine output device name: {}", e);
+                String::from("<unnamed_output_device>")
+            }),
+            Backend::Recording(_) => String::from("<recording>"),
+        }
+    }
+
+    /// Moves this output, and all its clones, to the device of `other`. The sounds which are
+    /// playing, including those of the `AudioSink` and of `AudioEmitter`s, continue on the new
+    /// device.
+    pub fn switch_to(&self, other: &Output) {
+        if Arc::ptr_eq(&self.shared, &other.shared) {
+            return;
+        }
+        let backend = other.shared.backend.read().unwrap().clone();
+        let mut current = self.shared.backend.write().unwrap();
+        let mut routes = self.shared.routes.lock().unwrap();
+        routes.retain(|route| route.strong_count() > 0);
+        for route in routes.iter().filter_map(Weak::upgrade) {
+            backend.attach(&route);
+        }
+        *current = backend;
+    }
+
+    /// Creates a sink playing on this output, along with the tag used to record the sources
+    /// appended to it.
+    pub(crate) fn sink(&self) -> (Sink, SinkTag) {
+        let (sink, queue) = Sink::new_idle();
+        let backend = self.shared.backend.read().unwrap();
+        let route = Route::new(queue, backend.sample_rate());
+        backend.attach(&route);
+        let tag = route.tag().clone();
+        let mut routes = self.shared.routes.lock().unwrap();
+        routes.retain(|route| route.strong_count() > 0);
+        routes.push(Arc::downgrade(&route));
+        (sink, tag)
     }
 
     /// Play a sound once.  A volume of 1.0 is unchanged, while 0.0 is silent.
@@ -82,13 +198,37 @@ impl Output {
         volume: f32,
         n: u16,
     ) -> Result<(), DecoderError> {
-        let sink = Sink::new(&self.device);
+        self.try_play_n_times_on_bus(source, volume, n, &BusGain::default())
+    }
+
+    /// Play a sound once on a mixer bus, whose gain is applied on top of `volume`.
+    ///
+    /// This may silently fail, in order to get error information use `try_play_n_times_on_bus`.
+    pub fn play_once_on_bus(&self, source: &Source, volume: f32, bus: &BusGain) {
+        if let Err(err) = self.try_play_n_times_on_bus(source, volume, 1, bus) {
+            error!("An error occurred while trying to play a sound: {:?}", err);
+        }
+    }
+
+    /// Play a sound n times on a mixer bus, whose gain is applied on top of `volume`.
+    ///
+    /// This will return an Error if the loaded audio file in source could not be decoded.
+    pub fn try_play_n_times_on_bus(
+        &self,
+        source: &Source,
+        volume: f32,
+        n: u16,
+        bus: &BusGain,
+    ) -> Result<(), DecoderError> {
+        let (sink, tag) = self.sink();
         for _ in 0..n {
-            sink.append(
+            tag.label(source);
+            sink.append(BusSource::new(
                 Decoder::new(Cursor::new(source.clone()))
                     .map_err(|_| DecoderError)?
                     .amplify(volume),
-            );
+                bus.clone(),
+            ));
         }
         sink.detach();
         Ok(())
@@ -113,17 +253,13 @@ impl Iterator for OutputIterator {
     type Item = Output;
 
     fn next(&mut self) -> Option<Output> {
-        self.devices.next().map(|device| Output {
-            device: Arc::new(device),
-        })
+        self.devices.next().map(Output::from_device)
     }
 }
 
 /// Get the default output, returns none if no outputs are available.
 pub fn default_output() -> Option<Output> {
-    default_output_device().map(|device| Output {
-        device: Arc::new(device),
-    })
+    default_output_device().map(Output::from_device)
 }
 
 /// Get a list of outputs available to the system.
@@ -134,11 +270,24 @@ pub fn outputs() -> OutputIterator {
 }
 
 /// Initialize default output
+///
+/// An `Output` which is already in the `World`, such as a recording output, is used instead of
+/// the default output. The `AudioSink` resource is routed to the `Bus::Music` bus of the
+/// `AudioMixer`.
 pub fn init_output(world: &mut World) {
-    if let Some(o) = default_output() {
-        world
-            .entry::<AudioSink>()
-            .or_insert_with(|| AudioSink::new(&o));
+    let output = world
+        .try_fetch::<Output>()
+        .map(|output| (*output).clone())
+        .or_else(default_output);
+    if let Some(o) = output {
+        if !world.has_value::<AudioSink>() {
+            let mut sink = AudioSink::new(&o);
+            sink.route(
+                &mut world.entry::<AudioMixer>().or_insert_with(AudioMixer::new),
+                Bus::Music,
+            );
+            world.insert(sink);
+        }
         world.entry::<Output>().or_insert_with(|| o);
     } else {
         error!("Failed finding a default audio output to hook AudioSink to, audio will not work!")
@@ -217,6 +366,43 @@ mod tests {
         check_result(result_try_play_n_times, should_pass);
     }
 
+    #[test]
+    fn switching_moves_playing_sinks() {
+        use crate::{output::Output, source::Source};
+        use rodio::buffer::SamplesBuffer;
+        use std::time::Duration;
+
+        let output = Output::new_recording(1000);
+        let first = output.recording().unwrap();
+        let second = Output::new_recording(1000);
+        let clone = output.clone();
+
+        let (sink, tag) = output.sink();
+        let source = Source { bytes: vec![1] };
+        tag.label(&source);
+        sink.append(SamplesBuffer::new(1, 1000, vec![0.5f32; 1000]));
+        sink.detach();
+        first.advance(Duration::from_millis(100));
+
+        clone.switch_to(&second);
+        assert!(output
+            .recording()
+            .unwrap()
+            .ptr_eq(&second.recording().unwrap()));
+        first.advance(Duration::from_millis(100));
+        assert!(first.sounds()[0].ended);
+        // The first output plays the rest of the chunk it took from the queue, 64 frames here.
+        assert!(first.sounds()[0].last_audible.unwrap() < Duration::from_millis(165));
+
+        let recording = second.recording().unwrap();
+        recording.advance(Duration::from_secs(1));
+        let sounds = recording.sounds_playing(&source);
+        assert_eq!(sounds.len(), 1);
+        assert!(sounds[0].first_audible.unwrap() < Duration::from_millis(10));
+        assert!(sounds[0].last_audible.unwrap() > Duration::from_millis(800));
+        assert!(sounds[0].ended);
+    }
+
     #[cfg(target_os = "linux")]
     fn check_result(result: Result<(), DecoderError>, should_pass: bool) {
         match result {
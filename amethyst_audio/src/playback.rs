@@ -0,0 +1,371 @@
+//! Control of single sounds played by an `AudioEmitter`.
+
+use std::{
+    io::Cursor,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rodio::{Decoder, Source as RSource};
+
+use crate::{
+    source::Source,
+    spatial::SpatialSink,
+    timeline::{Clock, CueKind, CueSheet, PlaybackPosition, Timeline},
+    DecoderError,
+};
+
+/// Largest number of samples between two points at which a change of playback speed takes effect.
+const SPEED_CHUNK_SAMPLES: usize = 1024;
+
+static NEXT_PLAYBACK_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a sound played by `AudioEmitter::play`, used to control it while it plays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PlaybackId(u64);
+
+impl PlaybackId {
+    fn next() -> Self {
+        PlaybackId(NEXT_PLAYBACK_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A change of volume over time.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Fade {
+    from: f32,
+    to: f32,
+    duration: Duration,
+    elapsed: Duration,
+    stop_when_done: bool,
+}
+
+impl Fade {
+    fn gain(&self) -> f32 {
+        if self.elapsed >= self.duration {
+            self.to
+        } else {
+            let t = self.elapsed.as_secs_f32() / self.duration.as_secs_f32();
+            self.from + (self.to - self.from) * t
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// State shared with the audio thread.
+#[derive(Debug)]
+struct SharedState {
+    speed: AtomicU32,
+    looping: AtomicBool,
+}
+
+/// A sound played by an `AudioEmitter`.
+pub(crate) struct Playback {
+    pub(crate) id: PlaybackId,
+    /// The source waiting to be appended to a sink by the `AudioSystem`.
+    pub(crate) pending: Option<ControlledSource>,
+    pub(crate) sink: Option<SpatialSink>,
+    /// Set when the sound has ended.
+    pub(crate) ended: Arc<AtomicBool>,
+    volume: f32,
+    speed: f32,
+    paused: bool,
+    virtualized: bool,
+    stopped: bool,
+    fade: Option<Fade>,
+    fade_gain: f32,
+    shared: Arc<SharedState>,
+    timeline: Timeline,
+}
+
+impl Playback {
+    pub(crate) fn new(source: &Source, cues: Option<&CueSheet>) -> Result<Self, DecoderError> {
+        let shared = Arc::new(SharedState {
+            speed: AtomicU32::new(1.0f32.to_bits()),
+            looping: AtomicBool::new(false),
+        });
+        let pending = ControlledSource::new(source.clone(), shared.clone())?;
+        let timeline = Timeline::new(pending.clock.clone(), cues);
+        Ok(Playback {
+            id: PlaybackId::next(),
+            pending: Some(pending),
+            sink: None,
+            ended: Arc::new(AtomicBool::new(false)),
+            volume: 1.0,
+            speed: 1.0,
+            paused: false,
+            virtualized: false,
+            stopped: false,
+            fade: None,
+            fade_gain: 1.0,
+            shared,
+            timeline,
+        })
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.stopped || self.ended.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub(crate) fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    pub(crate) fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub(crate) fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub(crate) fn is_looping(&self) -> bool {
+        self.shared.looping.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_looping(&mut self, looping: bool) {
+        self.shared.looping.store(looping, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub(crate) fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub(crate) fn is_virtual(&self) -> bool {
+        self.virtualized
+    }
+
+    /// Suspends a sound which can't be heard.
+    pub(crate) fn set_virtual(&mut self, virtualized: bool) {
+        self.virtualized = virtualized;
+    }
+
+    /// Returns the position in the sound, which starts over when the sound loops.
+    pub(crate) fn position(&self) -> PlaybackPosition {
+        self.timeline.position()
+    }
+
+    /// Calls `f` with the cues reached since the previous call.
+    pub(crate) fn poll_cues(&mut self, f: impl FnMut(PlaybackPosition, CueKind)) {
+        self.timeline.poll(f);
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.stopped = true;
+        if let Some(sink) = &self.sink {
+            sink.stop();
+        }
+    }
+
+    /// Starts fading from the current fade gain to `to`.
+    pub(crate) fn fade(&mut self, to: f32, duration: Duration, stop_when_done: bool) {
+        self.fade = Some(Fade {
+            from: self.fade_gain,
+            to,
+            duration,
+            elapsed: Duration::from_secs(0),
+            stop_when_done,
+        });
+    }
+
+    /// Starts the sound silent and fades it to full volume.
+    pub(crate) fn fade_in(&mut self, duration: Duration) {
+        self.fade_gain = 0.0;
+        self.fade(1.0, duration, false);
+    }
+
+    /// Advances fades by `delta`.
+    pub(crate) fn advance(&mut self, delta: Duration) {
+        if let Some(fade) = &mut self.fade {
+            fade.elapsed += delta;
+            self.fade_gain = fade.gain();
+            if fade.is_done() {
+                if fade.stop_when_done {
+                    self.stop();
+                }
+                self.fade = None;
+            }
+        }
+    }
+
+    /// Returns the gain to apply to the sink, i.e. the volume of the playback including fades.
+    pub(crate) fn gain(&self) -> f32 {
+        self.volume * self.fade_gain
+    }
+
+    /// Applies the playback settings to the sink. `speed_factor` scales the playback speed, e.g.
+    /// for the Doppler effect.
+    pub(crate) fn apply(&self, gain: f32, speed_factor: f32) {
+        self.shared
+            .speed
+            .store((self.speed * speed_factor).to_bits(), Ordering::Relaxed);
+        if let Some(sink) = &self.sink {
+            sink.set_volume(gain);
+            let paused = self.paused || self.virtualized;
+            if paused != sink.is_paused() {
+                if paused {
+                    sink.pause();
+                } else {
+                    sink.play();
+                }
+            }
+        }
+    }
+}
+
+/// Decodes a source, optionally looping it, at a playback speed which can be changed while it
+/// plays.
+pub(crate) struct ControlledSource {
+    source: Source,
+    input: Decoder<Cursor<Source>>,
+    shared: Arc<SharedState>,
+    /// Samples left until the sample rate may change.
+    remaining_in_chunk: usize,
+    clock: Clock,
+}
+
+impl ControlledSource {
+    fn new(source: Source, shared: Arc<SharedState>) -> Result<Self, DecoderError> {
+        let input = decode(&source)?;
+        let clock = Clock::new(input.channels(), input.sample_rate());
+        Ok(ControlledSource {
+            source,
+            input,
+            shared,
+            remaining_in_chunk: 0,
+            clock,
+        })
+    }
+
+    pub(crate) fn source(&self) -> &Source {
+        &self.source
+    }
+
+    fn next_chunk_len(&self) -> usize {
+        let channels = usize::from(self.input.channels().max(1));
+        let chunk = SPEED_CHUNK_SAMPLES - SPEED_CHUNK_SAMPLES % channels;
+        match self.input.current_frame_len() {
+            Some(len) if len > 0 => len.min(chunk),
+            _ => chunk,
+        }
+    }
+}
+
+fn decode(source: &Source) -> Result<Decoder<Cursor<Source>>, DecoderError> {
+    Decoder::new(Cursor::new(source.clone())).map_err(|_| DecoderError)
+}
+
+impl Iterator for ControlledSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.remaining_in_chunk == 0 {
+            self.remaining_in_chunk = self.next_chunk_len();
+        }
+        let mut sample = self.input.next();
+        if sample.is_none() && self.shared.looping.load(Ordering::Relaxed) {
+            if let Ok(input) = decode(&self.source) {
+                self.input = input;
+                self.clock.restart();
+                sample = self.input.next();
+            }
+        }
+        if sample.is_some() {
+            self.clock.tick();
+        }
+        self.remaining_in_chunk = self.remaining_in_chunk.saturating_sub(1);
+        sample
+    }
+}
+
+impl RSource for ControlledSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.remaining_in_chunk == 0 {
+            Some(self.next_chunk_len())
+        } else {
+            Some(self.remaining_in_chunk)
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        let speed = f32::from_bits(self.shared.speed.load(Ordering::Relaxed));
+        ((self.input.sample_rate() as f32 * speed) as u32).max(1)
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fade(from: f32, to: f32) -> Fade {
+        Fade {
+            from,
+            to,
+            duration: Duration::from_secs(2),
+            elapsed: Duration::from_secs(0),
+            stop_when_done: false,
+        }
+    }
+
+    #[test]
+    fn fade_interpolates_linearly() {
+        let mut fade = fade(0.0, 1.0);
+        assert_eq!(fade.gain(), 0.0);
+        fade.elapsed = Duration::from_secs(1);
+        assert_eq!(fade.gain(), 0.5);
+        fade.elapsed = Duration::from_secs(3);
+        assert_eq!(fade.gain(), 1.0);
+        assert!(fade.is_done());
+    }
+
+    fn test_source() -> Source {
+        let app_root = amethyst_utils::app_root_dir::application_root_dir().unwrap();
+        Source {
+            bytes: std::fs::read(app_root.join("tests/sound_test.wav")).unwrap(),
+        }
+    }
+
+    #[test]
+    fn controlled_source_speed_and_looping() {
+        let mut playback = Playback::new(&test_source(), None).unwrap();
+        let source = playback.pending.take().unwrap();
+        let sample_rate = source.sample_rate();
+
+        playback.set_speed(2.0);
+        playback.apply(1.0, 1.0);
+        assert_eq!(source.sample_rate(), sample_rate * 2);
+
+        let len = source.count();
+        let mut looping = Playback::new(&test_source(), None).unwrap();
+        looping.set_looping(true);
+        let source = looping.pending.take().unwrap();
+        assert_eq!(source.take(len * 2 + 1).count(), len * 2 + 1);
+    }
+
+    #[test]
+    fn playback_ids_are_unique() {
+        assert_ne!(PlaybackId::next(), PlaybackId::next());
+    }
+}
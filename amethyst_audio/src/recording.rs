@@ -0,0 +1,386 @@
+//! An audio output which records what is played instead of playing it on a device.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    fs::File,
+    io::{BufWriter, Result as IoResult, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::source::UniformSourceIterator;
+
+use crate::{formats::write_wav, route::Relay, source::Source};
+
+/// Number of channels of a recording: left and right.
+pub const RECORDING_CHANNELS: u16 = 2;
+
+/// Level below which a sample is considered silent.
+const SILENCE: f32 = 1.0e-4;
+
+/// A sound played on a recording `Output`, e.g. by an `AudioEmitter` or the `AudioSink`.
+#[derive(Clone, PartialEq)]
+pub struct RecordedSound {
+    /// The sources played by the sound, in the order they were played.
+    pub sources: Vec<Source>,
+    /// Time of the recording at which the sound was created.
+    pub created: Duration,
+    /// Time of the recording of the first audible sample of the sound.
+    pub first_audible: Option<Duration>,
+    /// Time of the recording of the last audible sample of the sound.
+    pub last_audible: Option<Duration>,
+    /// True once the sound has ended.
+    pub ended: bool,
+    /// Largest absolute value of the samples of the left and right channels.
+    pub peak: [f32; 2],
+    sum_squares: [f64; 2],
+}
+
+impl RecordedSound {
+    fn new(created: Duration) -> Self {
+        RecordedSound {
+            sources: Vec::new(),
+            created,
+            first_audible: None,
+            last_audible: None,
+            ended: false,
+            peak: [0.0; 2],
+            sum_squares: [0.0; 2],
+        }
+    }
+
+    /// Returns true if the sound played the given source.
+    pub fn played(&self, source: &Source) -> bool {
+        self.sources.contains(source)
+    }
+
+    /// Returns the largest absolute sample of the sound, i.e. the volume it was heard at for a
+    /// source at full scale.
+    pub fn volume(&self) -> f32 {
+        self.peak[0].max(self.peak[1])
+    }
+
+    /// Returns the left/right balance of the sound, from -1.0 for a sound heard only on the left
+    /// to 1.0 for a sound heard only on the right. Silent sounds are centered.
+    pub fn balance(&self) -> f32 {
+        let [left, right] = self.sum_squares;
+        let (left, right) = (left.sqrt(), right.sqrt());
+        if left + right <= 0.0 {
+            0.0
+        } else {
+            ((right - left) / (right + left)) as f32
+        }
+    }
+
+    fn record(&mut self, channel: usize, sample: f32, time: Duration) {
+        let level = sample.abs();
+        self.peak[channel] = self.peak[channel].max(level);
+        self.sum_squares[channel] += f64::from(sample) * f64::from(sample);
+        if level >= SILENCE {
+            self.first_audible.get_or_insert(time);
+            self.last_audible = Some(time);
+        }
+    }
+}
+
+impl Debug for RecordedSound {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("RecordedSound")
+            .field("sources", &self.sources.len())
+            .field("created", &self.created)
+            .field("first_audible", &self.first_audible)
+            .field("last_audible", &self.last_audible)
+            .field("ended", &self.ended)
+            .field("peak", &self.peak)
+            .field("balance", &self.balance())
+            .finish()
+    }
+}
+
+type Input = UniformSourceIterator<Relay, f32>;
+
+struct Recording {
+    sample_rate: u32,
+    /// Interleaved samples of the left and right channels.
+    samples: Vec<f32>,
+    /// Number of frames mixed so far.
+    frames: u64,
+    /// Time requested through `AudioRecording::advance`, which may not be a whole number of
+    /// frames.
+    time: Duration,
+    /// The sounds which are playing, by id.
+    playing: Vec<(usize, Input)>,
+    sounds: BTreeMap<usize, RecordedSound>,
+    next_id: usize,
+}
+
+impl Recording {
+    fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / f64::from(self.sample_rate))
+    }
+
+    fn mix_frame(&mut self) {
+        let time = self.position();
+        let mut frame = [0.0f32; 2];
+        let sounds = &mut self.sounds;
+        self.playing.retain_mut(|(id, input)| {
+            let sound = sounds.get_mut(id).expect("Playing sound without a record");
+            for (channel, mixed) in frame.iter_mut().enumerate() {
+                match input.next() {
+                    Some(sample) => {
+                        sound.record(channel, sample, time);
+                        *mixed += sample;
+                    }
+                    None => {
+                        sound.ended = true;
+                        return false;
+                    }
+                }
+            }
+            true
+        });
+        self.samples.extend_from_slice(&frame);
+        self.frames += 1;
+    }
+}
+
+/// A recording of everything played on an `Output` created with `Output::new_recording`.
+///
+/// Nothing is played on a device: sounds are mixed into an in-memory buffer at a fixed sample
+/// rate as time advances, which makes it possible to test audio code without a sound card. The
+/// `AudioSystem` advances the recording by the real time of each frame; the recording can also be
+/// advanced manually with `AudioRecording::advance`.
+#[derive(Clone)]
+pub struct AudioRecording(Arc<Mutex<Recording>>);
+
+impl AudioRecording {
+    /// Creates a new empty recording at the given sample rate.
+    pub fn new(sample_rate: u32) -> Self {
+        AudioRecording(Arc::new(Mutex::new(Recording {
+            sample_rate: sample_rate.max(1),
+            samples: Vec::new(),
+            frames: 0,
+            time: Duration::from_secs(0),
+            playing: Vec::new(),
+            sounds: BTreeMap::new(),
+            next_id: 0,
+        })))
+    }
+
+    /// Returns true if both handles refer to the same recording.
+    pub fn ptr_eq(&self, other: &AudioRecording) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Returns the sample rate of the recording.
+    pub fn sample_rate(&self) -> u32 {
+        self.0.lock().unwrap().sample_rate
+    }
+
+    /// Returns the length of the recording.
+    pub fn position(&self) -> Duration {
+        self.0.lock().unwrap().position()
+    }
+
+    /// Mixes the sounds which are playing for the given duration.
+    pub fn advance(&self, duration: Duration) {
+        let mut recording = self.0.lock().unwrap();
+        recording.time += duration;
+        let target = (recording.time.as_secs_f64() * f64::from(recording.sample_rate)) as u64;
+        while recording.frames < target {
+            recording.mix_frame();
+        }
+    }
+
+    /// Returns the interleaved samples of the left and right channels recorded so far.
+    pub fn samples(&self) -> Vec<f32> {
+        self.0.lock().unwrap().samples.clone()
+    }
+
+    /// Returns all sounds played since the recording started, in the order they were created.
+    pub fn sounds(&self) -> Vec<RecordedSound> {
+        self.0.lock().unwrap().sounds.values().cloned().collect()
+    }
+
+    /// Returns the sounds which played the given source.
+    pub fn sounds_playing(&self, source: &Source) -> Vec<RecordedSound> {
+        self.0
+            .lock()
+            .unwrap()
+            .sounds
+            .values()
+            .filter(|sound| sound.played(source))
+            .cloned()
+            .collect()
+    }
+
+    /// Discards the recorded samples and the sounds which have ended. The position of the
+    /// recording is kept.
+    pub fn clear(&self) {
+        let mut recording = self.0.lock().unwrap();
+        recording.samples.clear();
+        recording.sounds.retain(|_, sound| !sound.ended);
+    }
+
+    /// Writes the recorded samples as a 16 bit PCM WAV file.
+    pub fn write_wav<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        let recording = self.0.lock().unwrap();
+        write_wav(
+            &mut writer,
+            RECORDING_CHANNELS,
+            recording.sample_rate,
+            &recording.samples,
+        )?;
+        writer.flush()
+    }
+
+    /// Saves the recorded samples to a 16 bit PCM WAV file.
+    pub fn save_wav<P: AsRef<Path>>(&self, path: P) -> IoResult<()> {
+        self.write_wav(BufWriter::new(File::create(path)?))
+    }
+
+    /// Starts recording the sounds of a sink, returning the id of the recorded sound.
+    pub(crate) fn add(&self, relay: Relay) -> usize {
+        let mut recording = self.0.lock().unwrap();
+        let sample_rate = recording.sample_rate;
+        let created = recording.position();
+        let id = recording.next_id;
+        recording.next_id += 1;
+        recording.sounds.insert(id, RecordedSound::new(created));
+        recording.playing.push((
+            id,
+            UniformSourceIterator::new(relay, RECORDING_CHANNELS, sample_rate),
+        ));
+        id
+    }
+
+    /// Records that a source was appended to the sink of a recorded sound.
+    pub(crate) fn label(&self, id: usize, source: &Source) {
+        if let Some(sound) = self.0.lock().unwrap().sounds.get_mut(&id) {
+            sound.sources.push(source.clone());
+        }
+    }
+
+    /// Returns the sources appended to the sink of a recorded sound.
+    fn sources(&self, id: usize) -> Vec<Source> {
+        self.0
+            .lock()
+            .unwrap()
+            .sounds
+            .get(&id)
+            .map(|sound| sound.sources.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Debug for AudioRecording {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let recording = self.0.lock().unwrap();
+        f.debug_struct("AudioRecording")
+            .field("sample_rate", &recording.sample_rate)
+            .field("position", &recording.position())
+            .field("sounds", &recording.sounds.len())
+            .finish()
+    }
+}
+
+/// Identifies the recorded sound of a sink, if the sink plays on a recording `Output`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SinkTag(Arc<Mutex<Option<(AudioRecording, usize)>>>);
+
+impl SinkTag {
+    /// Records the sink as a sound of a recording, or stops recording it. The sources appended
+    /// so far are copied to the new recorded sound.
+    pub(crate) fn attach(&self, recorded: Option<(AudioRecording, usize)>) {
+        let previous = self.0.lock().unwrap().take();
+        if let (Some((previous, previous_id)), Some((recording, id))) = (previous, &recorded) {
+            for source in previous.sources(previous_id) {
+                recording.label(*id, &source);
+            }
+        }
+        *self.0.lock().unwrap() = recorded;
+    }
+
+    /// Records that a source was appended to the sink.
+    pub(crate) fn label(&self, source: &Source) {
+        if let Some((recording, id)) = &*self.0.lock().unwrap() {
+            recording.label(*id, source);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{output::Output, spatial::SpatialSink};
+
+    /// A mono sine wave at full scale, as the test files are silent.
+    fn test_source() -> Source {
+        let samples = (0..800)
+            .map(|i| ((i as f32 * 0.1).sin() * f32::from(i16::MAX)) as i16)
+            .collect::<Vec<_>>();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + samples.len() as u32 * 2).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        for field in &[16u32, 1 | 1 << 16, 8000, 16000, 2 | 16 << 16] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(samples.len() as u32 * 2).to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        Source { bytes }
+    }
+
+    #[test]
+    fn records_sounds() {
+        let output = Output::new_recording(44_100);
+        let recording = output.recording().unwrap();
+        let source = test_source();
+        output.try_play_once(&source, 0.5).unwrap();
+        recording.advance(Duration::from_millis(10));
+        assert_eq!(recording.position(), Duration::from_millis(10));
+        assert_eq!(recording.samples().len(), 441 * 2);
+        recording.advance(Duration::from_secs(1));
+
+        let sounds = recording.sounds_playing(&source);
+        assert_eq!(sounds.len(), 1);
+        let sound = &sounds[0];
+        assert!(sound.ended);
+        assert_eq!(sound.created, Duration::from_secs(0));
+        assert!(sound.first_audible.is_some());
+        assert!(sound.volume() > 0.45 && sound.volume() <= 0.5);
+        assert!(sound.first_audible.unwrap() < Duration::from_millis(20));
+        assert!(sound.last_audible.unwrap() < Duration::from_millis(120));
+        assert!(sound.balance().abs() < 1e-3);
+    }
+
+    #[test]
+    fn records_balance() {
+        let output = Output::new_recording(8_000);
+        let recording = output.recording().unwrap();
+        let (sink, tag) = output.sink();
+        let sink = SpatialSink::new(sink, [1.0, 0.0, 0.0], [-0.1, 0.0, 0.0], [0.1, 0.0, 0.0]);
+        let source = test_source();
+        tag.label(&source);
+        sink.append(rodio::Decoder::new(std::io::Cursor::new(source)).unwrap());
+        recording.advance(Duration::from_millis(500));
+        assert!(recording.sounds()[0].balance() > 0.0);
+    }
+
+    #[test]
+    fn writes_wav() {
+        let recording = AudioRecording::new(1000);
+        recording.advance(Duration::from_millis(10));
+        let mut bytes = Vec::new();
+        recording.write_wav(&mut bytes).unwrap();
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes.len(), 44 + 10 * 2 * 2);
+        recording.clear();
+        assert!(recording.samples().is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+//! The connection of a sink to an output, which can be moved to another output while it plays.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::{queue::SourcesQueueOutput, source::UniformSourceIterator, Source};
+
+use crate::recording::SinkTag;
+
+/// Number of channels of the samples relayed to the output, whose frames are tracked as pairs.
+const ROUTE_CHANNELS: u16 = 2;
+
+/// Number of samples a relay takes from the queue at once, a whole number of frames, so that the
+/// queue is only locked once per chunk by the audio thread.
+const CHUNK_SAMPLES: usize = 128;
+
+/// The queue of a sink, relayed to the output it is attached to.
+pub(crate) struct Route {
+    state: Mutex<RouteState>,
+    tag: SinkTag,
+}
+
+struct RouteState {
+    input: UniformSourceIterator<SourcesQueueOutput<f32>, f32>,
+    /// Generation of the relay of the output the sink was last attached to.
+    generation: u64,
+}
+
+impl Route {
+    pub(crate) fn new(queue: SourcesQueueOutput<f32>, sample_rate: u32) -> Arc<Self> {
+        Arc::new(Route {
+            state: Mutex::new(RouteState {
+                input: UniformSourceIterator::new(queue, ROUTE_CHANNELS, sample_rate.max(1)),
+                generation: 0,
+            }),
+            tag: SinkTag::default(),
+        })
+    }
+
+    /// Returns the tag identifying the sink in a recording.
+    pub(crate) fn tag(&self) -> &SinkTag {
+        &self.tag
+    }
+
+    /// Creates the source playing the sink on a new output. Relays created before stop playing.
+    pub(crate) fn relay(self: &Arc<Self>) -> Relay {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        Relay {
+            route: self.clone(),
+            generation: state.generation,
+            sample_rate: state.input.sample_rate(),
+            chunk: VecDeque::with_capacity(CHUNK_SAMPLES),
+            ended: false,
+        }
+    }
+}
+
+/// Plays the queue of a `Route` until the sink is attached to another output.
+///
+/// The queue is taken in chunks of whole frames so that channels aren't swapped when the sink is
+/// handed over to another relay. A relay which was replaced still plays the rest of its chunk.
+pub(crate) struct Relay {
+    route: Arc<Route>,
+    generation: u64,
+    sample_rate: u32,
+    /// Samples taken from the queue which weren't played yet.
+    chunk: VecDeque<f32>,
+    ended: bool,
+}
+
+impl Iterator for Relay {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(sample) = self.chunk.pop_front() {
+            return Some(sample);
+        }
+        if self.ended {
+            return None;
+        }
+        {
+            let mut state = self.route.state.lock().unwrap();
+            if self.generation == state.generation {
+                self.chunk.extend(state.input.by_ref().take(CHUNK_SAMPLES));
+            }
+        }
+        let sample = self.chunk.pop_front();
+        self.ended = sample.is_none();
+        sample
+    }
+}
+
+impl Source for Relay {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        ROUTE_CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::{buffer::SamplesBuffer, Sink};
+
+    use super::*;
+
+    /// A sink whose queue plays the samples once as mono, which are duplicated to both channels.
+    fn route(samples: Vec<f32>) -> Arc<Route> {
+        let (sink, queue) = Sink::new_idle();
+        sink.append(SamplesBuffer::new(1, 44_100, samples));
+        sink.detach();
+        Route::new(queue, 44_100)
+    }
+
+    #[test]
+    fn relays_hand_over_whole_frames() {
+        let route = route((0..CHUNK_SAMPLES).map(|i| i as f32).collect());
+        let mut first = route.relay();
+        assert_eq!(first.next(), Some(0.0));
+        let second = route.relay();
+        // The first relay plays the rest of its chunk while the second one plays the queue.
+        let first = first.collect::<Vec<_>>();
+        let second = second.collect::<Vec<_>>();
+        assert_eq!(first.len() + 1, CHUNK_SAMPLES);
+        assert!(!second.is_empty());
+        // The queue may be resampled, but both channels stay in sync and continue in order.
+        assert!(first[1..].chunks(2).all(|frame| frame[0] == frame[1]));
+        assert!(second.chunks(2).all(|frame| frame[0] == frame[1]));
+        assert!(second[0] > first[first.len() - 1]);
+    }
+
+    #[test]
+    fn replaced_relay_stops() {
+        let route = route(vec![1.0, 2.0]);
+        let mut first = route.relay();
+        let second = route.relay();
+        assert_eq!(first.next(), None);
+        assert_eq!(second.collect::<Vec<_>>(), vec![1.0, 1.0, 2.0, 2.0]);
+    }
+}
@@ -1,8 +1,22 @@
-use std::io::Cursor;
+use std::{
+    collections::VecDeque,
+    io::Cursor,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
 
 use rodio::{Decoder, Sink};
 
-use crate::{output::Output, source::Source, DecoderError};
+use crate::{
+    mixer::{AudioMixer, Bus, BusGain, BusSource},
+    output::Output,
+    recording::SinkTag,
+    source::Source,
+    timeline::{ClockSource, CueKind, CueSheet, PlaybackPosition, Timeline},
+    DecoderError,
+};
 
 /// This structure provides a way to programmatically pick and play music.
 // TODO: This needs a proper debug implementeation. This should probably propigate up to a TODO
@@ -10,23 +24,96 @@ use crate::{output::Output, source::Source, DecoderError};
 #[allow(missing_debug_implementations)]
 pub struct AudioSink {
     sink: Sink,
+    tag: SinkTag,
+    bus: Option<(Bus, BusGain)>,
+    /// Timelines of the queued sources, in the order they play.
+    timelines: Mutex<VecDeque<Timeline>>,
+    /// Whether the cues of the sink are polled, which is only the case for the `AudioSink`
+    /// resource.
+    polled: AtomicBool,
 }
 
 impl AudioSink {
-    /// Creates a new `AudioSink` using the given audio output.
+    /// Creates a new `AudioSink` using the given audio output. The sink isn't routed to any mixer
+    /// bus, see `AudioSink::route`.
     pub fn new(output: &Output) -> AudioSink {
+        let (sink, tag) = output.sink();
         AudioSink {
-            sink: Sink::new(&output.device),
+            sink,
+            tag,
+            bus: None,
+            timelines: Mutex::new(VecDeque::new()),
+            polled: AtomicBool::new(false),
         }
     }
 
+    /// Routes the sources appended from now on to the given mixer bus.
+    pub fn route(&mut self, mixer: &mut AudioMixer, bus: Bus) {
+        let gain = mixer.gain(&bus);
+        self.bus = Some((bus, gain));
+    }
+
+    /// Returns the mixer bus this sink is routed to, if any.
+    pub fn bus(&self) -> Option<&Bus> {
+        self.bus.as_ref().map(|(bus, _)| bus)
+    }
+
     /// Adds a source to the sink's queue of music to play.
     pub fn append(&self, source: &Source) -> Result<(), DecoderError> {
-        self.sink
-            .append(Decoder::new(Cursor::new(source.clone())).map_err(|_| DecoderError)?);
+        self.append_timeline(source, None)
+    }
+
+    /// Adds a source to the sink's queue along with its markers and tempo. The `AudioSystem`
+    /// sends a `CueEvent` whenever the source reaches one of them, as long as this sink is the
+    /// `AudioSink` resource.
+    pub fn append_with_cues(&self, source: &Source, cues: &CueSheet) -> Result<(), DecoderError> {
+        self.append_timeline(source, Some(cues))
+    }
+
+    fn append_timeline(
+        &self,
+        source: &Source,
+        cues: Option<&CueSheet>,
+    ) -> Result<(), DecoderError> {
+        let decoder = Decoder::new(Cursor::new(source.clone())).map_err(|_| DecoderError)?;
+        let decoder = ClockSource::new(decoder);
+        {
+            let mut timelines = self.timelines.lock().unwrap();
+            // Ended sources are otherwise only removed when their cues are polled.
+            let polled = self.polled.load(Ordering::Relaxed);
+            timelines.retain(|timeline| !timeline.is_ended() || (polled && timeline.has_cues()));
+            timelines.push_back(Timeline::new(decoder.clock().clone(), cues));
+        }
+        self.tag.label(source);
+        match &self.bus {
+            Some((_, gain)) => self.sink.append(BusSource::new(decoder, gain.clone())),
+            None => self.sink.append(decoder),
+        }
         Ok(())
     }
 
+    /// Returns the exact position in the source which is playing, counting the samples which
+    /// were played, or `None` if the sink is empty.
+    pub fn position(&self) -> Option<PlaybackPosition> {
+        self.timelines
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|timeline| !timeline.is_ended())
+            .map(Timeline::position)
+    }
+
+    /// Calls `f` with the cues reached since the previous call.
+    pub(crate) fn poll_cues(&self, mut f: impl FnMut(PlaybackPosition, CueKind)) {
+        self.polled.store(true, Ordering::Relaxed);
+        self.timelines.lock().unwrap().retain_mut(|timeline| {
+            // Checked first so that the cues reached right before the end are still sent.
+            let ended = timeline.is_ended();
+            timeline.poll(&mut f);
+            !ended
+        });
+    }
+
     /// Returns true if the sink has no more music to play.
     pub fn empty(&self) -> bool {
         self.sink.empty()
@@ -134,4 +221,75 @@ mod tests {
     fn test_append_fake() {
         test_append("tests/sound_test.fake", false);
     }
+
+    #[test]
+    fn test_position_and_cues() {
+        use crate::timeline::{Cue, CueKind, CueSheet, PlaybackPosition};
+        use std::time::Duration;
+
+        let app_root = amethyst_utils::app_root_dir::application_root_dir().unwrap();
+        let src = crate::Source {
+            bytes: std::fs::read(app_root.join("tests/sound_test.wav")).unwrap(),
+        };
+        let cues = CueSheet {
+            cues: vec![Cue {
+                name: "start".to_string(),
+                time: 0.0,
+            }],
+            tempo: None,
+        };
+
+        let output = crate::output::Output::new_recording(44_100);
+        let sink = crate::AudioSink::new(&output);
+        sink.append_with_cues(&src, &cues).unwrap();
+        assert_eq!(sink.position().map(|p| p.frame), Some(0));
+
+        let mut reached = Vec::new();
+        sink.poll_cues(|position, kind| reached.push((position, kind)));
+        assert!(reached.is_empty());
+
+        output
+            .recording()
+            .unwrap()
+            .advance(Duration::from_millis(100));
+        let position = sink.position().unwrap();
+        assert!(position.frame > 0);
+        sink.poll_cues(|position, kind| reached.push((position, kind)));
+        assert_eq!(
+            reached,
+            vec![(
+                PlaybackPosition::new(0, position.sample_rate),
+                CueKind::Marker("start".to_string())
+            )]
+        );
+
+        sink.stop();
+        output
+            .recording()
+            .unwrap()
+            .advance(Duration::from_millis(100));
+        assert_eq!(sink.position(), None);
+    }
+
+    #[test]
+    fn ended_sources_are_removed_without_polling() {
+        use std::time::Duration;
+
+        let app_root = amethyst_utils::app_root_dir::application_root_dir().unwrap();
+        let src = crate::Source {
+            bytes: std::fs::read(app_root.join("tests/sound_test.wav")).unwrap(),
+        };
+        let output = crate::output::Output::new_recording(44_100);
+        let sink = crate::AudioSink::new(&output);
+        for _ in 0..3 {
+            sink.append_with_cues(&src, &Default::default()).unwrap();
+            sink.stop();
+            output
+                .recording()
+                .unwrap()
+                .advance(Duration::from_millis(100));
+        }
+        sink.append(&src).unwrap();
+        assert_eq!(sink.timelines.lock().unwrap().len(), 1);
+    }
 }
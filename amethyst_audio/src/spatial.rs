@@ -0,0 +1,369 @@
+//! Distance attenuation and Doppler effect for 3D audio.
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use amethyst_core::math::{Point3, Vector3};
+use rodio::{source::ChannelVolume, Sample, Sink, Source};
+use serde::{Deserialize, Serialize};
+
+/// How the volume of an `AudioEmitter` decreases with its distance to the `AudioListener`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DistanceModel {
+    /// The volume decreases linearly from full volume at the minimum distance to silence at the
+    /// maximum distance.
+    Linear,
+    /// The volume is `min / (min + rolloff * (distance - min))`, which is physically accurate
+    /// for a rolloff of 1.0.
+    Inverse {
+        /// How fast the volume decreases.
+        rolloff: f32,
+    },
+    /// The volume is `(distance / min) ^ -rolloff`.
+    Exponential {
+        /// How fast the volume decreases.
+        rolloff: f32,
+    },
+    /// A curve of `(distance, volume)` points, interpolated linearly. Distances are relative to
+    /// the attenuation range: 0.0 is the minimum and 1.0 the maximum distance.
+    Custom(Vec<(f32, f32)>),
+}
+
+/// Distance attenuation of an `AudioEmitter`.
+///
+/// Emitters without attenuation keep the fixed falloff built into the audio backend.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attenuation {
+    /// The attenuation curve.
+    pub model: DistanceModel,
+    /// Up to this distance the sound plays at full volume.
+    pub min_distance: f32,
+    /// Beyond this distance the sound is inaudible.
+    pub max_distance: f32,
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Attenuation {
+            model: DistanceModel::Inverse { rolloff: 1.0 },
+            min_distance: 1.0,
+            max_distance: 100.0,
+        }
+    }
+}
+
+impl Attenuation {
+    /// Creates a new `Attenuation`.
+    pub fn new(model: DistanceModel, min_distance: f32, max_distance: f32) -> Self {
+        Attenuation {
+            model,
+            min_distance,
+            max_distance,
+        }
+    }
+
+    /// Returns true if a sound at the given distance can't be heard.
+    pub fn is_out_of_range(&self, distance: f32) -> bool {
+        distance > self.max_distance
+    }
+
+    /// Returns the gain of a sound at the given distance, between 0.0 and 1.0.
+    pub fn gain(&self, distance: f32) -> f32 {
+        if self.is_out_of_range(distance) {
+            return 0.0;
+        }
+        let min = self.min_distance.max(f32::EPSILON);
+        let distance = distance.max(min);
+        let gain = match &self.model {
+            DistanceModel::Linear => {
+                let range = self.max_distance - min;
+                if range <= 0.0 {
+                    1.0
+                } else {
+                    1.0 - (distance - min) / range
+                }
+            }
+            DistanceModel::Inverse { rolloff } => min / (min + rolloff * (distance - min)),
+            DistanceModel::Exponential { rolloff } => (distance / min).powf(-rolloff),
+            DistanceModel::Custom(points) => {
+                let range = self.max_distance - min;
+                let t = if range <= 0.0 {
+                    0.0
+                } else {
+                    (distance - min) / range
+                };
+                interpolate(points, t)
+            }
+        };
+        gain.clamp(0.0, 1.0)
+    }
+}
+
+fn interpolate(points: &[(f32, f32)], t: f32) -> f32 {
+    match points.iter().position(|&(x, _)| x >= t) {
+        None => points.last().map_or(1.0, |&(_, y)| y),
+        Some(0) => points[0].1,
+        Some(i) => {
+            let (x0, y0) = points[i - 1];
+            let (x1, y1) = points[i];
+            if x1 <= x0 {
+                y1
+            } else {
+                y0 + (y1 - y0) * (t - x0) / (x1 - x0)
+            }
+        }
+    }
+}
+
+/// Global settings of the 3D audio simulation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpatialAudioConfig {
+    /// Speed of sound in world units per second, used for the Doppler effect.
+    pub speed_of_sound: f32,
+    /// Scales the Doppler effect, 0.0 disables it and 1.0 is physically accurate.
+    pub doppler_factor: f32,
+    /// Stops decoding sounds of emitters beyond their maximum distance. Looping sounds are
+    /// suspended until the listener is in range again, other sounds are stopped.
+    pub virtualization: bool,
+}
+
+impl Default for SpatialAudioConfig {
+    fn default() -> Self {
+        SpatialAudioConfig {
+            speed_of_sound: 343.0,
+            doppler_factor: 0.0,
+            virtualization: true,
+        }
+    }
+}
+
+impl SpatialAudioConfig {
+    /// Returns the factor by which the pitch of a sound changes due to the Doppler effect.
+    ///
+    /// Velocities are in world units per second.
+    pub fn doppler_shift(
+        &self,
+        listener: &Point3<f32>,
+        listener_velocity: &Vector3<f32>,
+        emitter: &Point3<f32>,
+        emitter_velocity: &Vector3<f32>,
+    ) -> f32 {
+        let to_listener = listener - emitter;
+        let distance = to_listener.norm();
+        if self.doppler_factor <= 0.0 || distance <= f32::EPSILON {
+            return 1.0;
+        }
+        let direction = to_listener / distance;
+        // Velocities along the line from emitter to listener, limited below the speed of sound.
+        let limit = self.speed_of_sound / self.doppler_factor * 0.99;
+        let listener_speed = listener_velocity.dot(&direction).min(limit);
+        let emitter_speed = emitter_velocity.dot(&direction).min(limit);
+        (self.speed_of_sound - self.doppler_factor * listener_speed)
+            / (self.speed_of_sound - self.doppler_factor * emitter_speed)
+    }
+}
+
+/// Scales the positions of the ears and the emitter around the listener so that all of them are
+/// within unit distance of each other. This keeps the stereo panning of the audio backend while
+/// disabling its own distance falloff.
+pub(crate) fn panning_positions(
+    left_ear: Point3<f32>,
+    right_ear: Point3<f32>,
+    emitter: Point3<f32>,
+) -> ([f32; 3], [f32; 3], [f32; 3]) {
+    let center = Point3::from((left_ear.coords + right_ear.coords) / 2.0);
+    let extent = (emitter - center).norm() + (left_ear - center).norm();
+    let scale = if extent > f32::EPSILON {
+        0.5 / extent
+    } else {
+        1.0
+    };
+    let scaled = |p: Point3<f32>| {
+        let p = center + (p - center) * scale;
+        [p.x, p.y, p.z]
+    };
+    (scaled(left_ear), scaled(right_ear), scaled(emitter))
+}
+
+/// Positions of the emitter, left ear and right ear of a `SpatialSink`.
+type SoundPositions = [[f32; 3]; 3];
+
+/// Returns the volumes of the left and right channels of a sound heard from the given positions.
+///
+/// Each channel is panned between half and full volume, the ear closer to the emitter being
+/// louder, then divided by the squared distance from its ear to the emitter when it is over 1.
+fn ear_volumes([emitter, left_ear, right_ear]: &SoundPositions) -> [f32; 2] {
+    let distance_sq =
+        |a: &[f32; 3], b: &[f32; 3]| (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum::<f32>();
+    let left_sq = distance_sq(left_ear, emitter);
+    let right_sq = distance_sq(right_ear, emitter);
+    let ears = distance_sq(left_ear, right_ear).sqrt().max(f32::EPSILON);
+    let difference = ((right_sq.sqrt() - left_sq.sqrt()) / ears).clamp(-1.0, 1.0);
+    let left = ((difference + 1.0) / 4.0 + 0.5) * (1.0 / left_sq).min(1.0);
+    let right = ((1.0 - difference) / 4.0 + 0.5) * (1.0 / right_sq).min(1.0);
+    [left, right]
+}
+
+/// A sink playing sounds at a position in space, which works with any `Output` backend.
+pub(crate) struct SpatialSink {
+    sink: Sink,
+    positions: Arc<Mutex<SoundPositions>>,
+}
+
+impl SpatialSink {
+    pub(crate) fn new(
+        sink: Sink,
+        emitter: [f32; 3],
+        left_ear: [f32; 3],
+        right_ear: [f32; 3],
+    ) -> Self {
+        SpatialSink {
+            sink,
+            positions: Arc::new(Mutex::new([emitter, left_ear, right_ear])),
+        }
+    }
+
+    pub(crate) fn set_emitter_position(&self, position: [f32; 3]) {
+        self.positions.lock().unwrap()[0] = position;
+    }
+
+    pub(crate) fn set_left_ear_position(&self, position: [f32; 3]) {
+        self.positions.lock().unwrap()[1] = position;
+    }
+
+    pub(crate) fn set_right_ear_position(&self, position: [f32; 3]) {
+        self.positions.lock().unwrap()[2] = position;
+    }
+
+    pub(crate) fn append<S>(&self, source: S)
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send + Debug,
+    {
+        let positions = self.positions.clone();
+        let volumes = ear_volumes(&self.positions.lock().unwrap());
+        let source = ChannelVolume::new(source, volumes.to_vec()).periodic_access(
+            Duration::from_millis(10),
+            move |source| {
+                let [left, right] = ear_volumes(&positions.lock().unwrap());
+                source.set_volume(0, left);
+                source.set_volume(1, right);
+            },
+        );
+        self.sink.append(source);
+    }
+
+    pub(crate) fn set_volume(&self, volume: f32) {
+        self.sink.set_volume(volume);
+    }
+
+    pub(crate) fn play(&self) {
+        self.sink.play();
+    }
+
+    pub(crate) fn pause(&self) {
+        self.sink.pause();
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    pub(crate) fn stop(&self) {
+        self.sink.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attenuation(model: DistanceModel) -> Attenuation {
+        Attenuation::new(model, 2.0, 10.0)
+    }
+
+    #[test]
+    fn linear() {
+        let a = attenuation(DistanceModel::Linear);
+        assert_eq!(a.gain(1.0), 1.0);
+        assert_eq!(a.gain(6.0), 0.5);
+        assert_eq!(a.gain(10.0), 0.0);
+        assert_eq!(a.gain(11.0), 0.0);
+    }
+
+    #[test]
+    fn inverse_and_exponential() {
+        let a = attenuation(DistanceModel::Inverse { rolloff: 1.0 });
+        assert_eq!(a.gain(2.0), 1.0);
+        assert_eq!(a.gain(4.0), 0.5);
+        let a = attenuation(DistanceModel::Exponential { rolloff: 2.0 });
+        assert_eq!(a.gain(4.0), 0.25);
+        assert!(a.is_out_of_range(10.5));
+    }
+
+    #[test]
+    fn custom_curve() {
+        let a = attenuation(DistanceModel::Custom(vec![
+            (0.0, 1.0),
+            (0.5, 0.8),
+            (1.0, 0.0),
+        ]));
+        assert_eq!(a.gain(2.0), 1.0);
+        assert!((a.gain(4.0) - 0.9).abs() < 1e-6);
+        assert!((a.gain(8.0) - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn doppler_shift() {
+        let config = SpatialAudioConfig {
+            speed_of_sound: 100.0,
+            doppler_factor: 1.0,
+            ..Default::default()
+        };
+        let listener = Point3::origin();
+        let emitter = Point3::new(10.0, 0.0, 0.0);
+        let still = Vector3::zeros();
+        // An approaching emitter sounds higher, a receding one lower.
+        let approaching =
+            config.doppler_shift(&listener, &still, &emitter, &(Vector3::x() * -50.0));
+        assert!((approaching - 2.0).abs() < 1e-6);
+        let receding = config.doppler_shift(&listener, &still, &emitter, &(Vector3::x() * 50.0));
+        assert!((receding - 100.0 / 150.0).abs() < 1e-6);
+        let moving_listener =
+            config.doppler_shift(&listener, &(Vector3::x() * 50.0), &emitter, &still);
+        assert!((moving_listener - 1.5).abs() < 1e-6);
+        let disabled = SpatialAudioConfig::default();
+        assert_eq!(
+            disabled.doppler_shift(&listener, &still, &emitter, &(Vector3::x() * 50.0)),
+            1.0
+        );
+    }
+
+    #[test]
+    fn ear_volumes_pan_towards_the_emitter() {
+        let ears = [[-0.1, 0.0, 0.0], [0.1, 0.0, 0.0]];
+        let [left, right] = ear_volumes(&[[0.5, 0.0, 0.0], ears[0], ears[1]]);
+        assert!(right > left);
+        let [left, right] = ear_volumes(&[[-0.5, 0.0, 0.0], ears[0], ears[1]]);
+        assert!(left > right);
+        let [left, right] = ear_volumes(&[[0.0, 0.0, 0.5], ears[0], ears[1]]);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn panning_positions_are_within_unit_distance() {
+        let (left, right, emitter) = panning_positions(
+            Point3::new(-1.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(100.0, 0.0, 0.0),
+        );
+        let distance = |a: [f32; 3], b: [f32; 3]| Point3::from(a) - Point3::from(b);
+        assert!(distance(left, emitter).norm() <= 1.0);
+        assert!(distance(right, emitter).norm() <= 1.0);
+        assert!(distance(right, emitter).norm() < distance(left, emitter).norm());
+    }
+}
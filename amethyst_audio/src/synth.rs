@@ -0,0 +1,546 @@
+//! Procedural sounds synthesized from parameters, e.g. retro sound effects.
+
+use std::f32::consts::PI;
+
+use amethyst_assets::Format;
+use amethyst_error::{format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    formats::{write_wav, AudioData},
+    source::Source,
+};
+
+/// Longest sound a `Synth` renders, in seconds; longer envelopes are cut off.
+pub const MAX_SYNTH_DURATION: f32 = 60.0;
+
+/// The shape of the wave produced by an `Oscillator`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Waveform {
+    /// A pure tone.
+    Sine,
+    /// A square wave, `duty` being the fraction of each period spent high.
+    Square {
+        /// Fraction of each period spent high, 0.5 for a symmetric wave.
+        duty: f32,
+    },
+    /// A triangle wave.
+    Triangle,
+    /// A sawtooth wave.
+    Sawtooth,
+    /// White noise, changing value 32 times per period so that the frequency sets its pitch.
+    Noise,
+}
+
+/// Volume of a sound over time: it rises during `attack`, falls to the `sustain` level during
+/// `decay`, stays there for `sustain_time`, then fades out during `release`. Times are in
+/// seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Envelope {
+    /// Time to rise from silence to full volume.
+    pub attack: f32,
+    /// Time to fall from full volume to the sustain level.
+    pub decay: f32,
+    /// Level held after the decay, between 0.0 and 1.0.
+    pub sustain: f32,
+    /// Time the sustain level is held.
+    pub sustain_time: f32,
+    /// Time to fade from the sustain level to silence.
+    pub release: f32,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope {
+            attack: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            sustain_time: 0.2,
+            release: 0.1,
+        }
+    }
+}
+
+impl Envelope {
+    /// Returns the length of the sound shaped by the envelope.
+    pub fn duration(&self) -> f32 {
+        self.attack + self.decay + self.sustain_time + self.release
+    }
+
+    /// Returns the level of the envelope `time` seconds after the sound started.
+    pub fn level(&self, time: f32) -> f32 {
+        let mut t = time;
+        if t < self.attack {
+            return t / self.attack;
+        }
+        t -= self.attack;
+        if t < self.decay {
+            return 1.0 - (1.0 - self.sustain) * t / self.decay;
+        }
+        t -= self.decay;
+        if t < self.sustain_time {
+            return self.sustain;
+        }
+        t -= self.sustain_time;
+        if t < self.release {
+            return self.sustain * (1.0 - t / self.release);
+        }
+        0.0
+    }
+}
+
+/// A tone generator, whose frequency can change over time like in sfxr.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Oscillator {
+    /// The shape of the wave.
+    pub waveform: Waveform,
+    /// Initial frequency in Hz.
+    pub frequency: f32,
+    /// Change of the frequency in Hz per second.
+    pub slide: f32,
+    /// The oscillator stops once its frequency falls below this value.
+    pub min_frequency: f32,
+    /// Change of the duty of square waves per second.
+    pub duty_sweep: f32,
+    /// Depth of the vibrato, as a fraction of the frequency.
+    pub vibrato_depth: f32,
+    /// Frequency of the vibrato in Hz.
+    pub vibrato_speed: f32,
+    /// After the given time in seconds, the frequency is multiplied by the given factor.
+    pub arpeggio: Option<(f32, f32)>,
+    /// Volume of the oscillator, between 0.0 and 1.0.
+    pub volume: f32,
+}
+
+impl Default for Oscillator {
+    fn default() -> Self {
+        Oscillator {
+            waveform: Waveform::Square { duty: 0.5 },
+            frequency: 440.0,
+            slide: 0.0,
+            min_frequency: 0.0,
+            duty_sweep: 0.0,
+            vibrato_depth: 0.0,
+            vibrato_speed: 0.0,
+            arpeggio: None,
+            volume: 1.0,
+        }
+    }
+}
+
+impl Oscillator {
+    /// Creates an oscillator with the given waveform and frequency.
+    pub fn new(waveform: Waveform, frequency: f32) -> Self {
+        Oscillator {
+            waveform,
+            frequency,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the frequency at the given time, or `None` once the oscillator stopped.
+    fn frequency_at(&self, time: f32) -> Option<f32> {
+        let mut frequency = self.frequency + self.slide * time;
+        if let Some((start, factor)) = self.arpeggio {
+            if time >= start {
+                frequency *= factor;
+            }
+        }
+        frequency *= 1.0 + self.vibrato_depth * (2.0 * PI * self.vibrato_speed * time).sin();
+        if frequency <= 0.0 || frequency < self.min_frequency {
+            None
+        } else {
+            Some(frequency)
+        }
+    }
+}
+
+/// Built-in sound effects in the style of sfxr. The generated `Synth` can be serialized and
+/// tweaked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SfxPreset {
+    /// Picking up a coin.
+    Coin,
+    /// Firing a laser.
+    Laser,
+    /// An explosion.
+    Explosion,
+    /// Picking up a power up.
+    PowerUp,
+    /// Getting hit.
+    Hit,
+    /// Jumping.
+    Jump,
+    /// A short user interface blip.
+    Blip,
+}
+
+/// A procedural sound made of oscillators shaped by an envelope.
+///
+/// Synths are loaded from RON files with `SynthFormat`, which renders them to a `Source` so that
+/// they can be played like any other sound:
+///
+/// ```ron
+/// (
+///     volume: 0.5,
+///     envelope: (sustain_time: 0.05, release: 0.2),
+///     oscillators: [
+///         (waveform: Square(duty: 0.5), frequency: 1000.0, arpeggio: Some((0.06, 1.5))),
+///     ],
+/// )
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Synth {
+    /// Sample rate of the rendered sound.
+    pub sample_rate: u32,
+    /// Volume of the sound, between 0.0 and 1.0.
+    pub volume: f32,
+    /// The volume of the sound over time, which also sets its length.
+    pub envelope: Envelope,
+    /// The oscillators, which are mixed together.
+    pub oscillators: Vec<Oscillator>,
+    /// Seed of the noise generator, so that noise sounds the same every time it is rendered.
+    pub seed: u64,
+}
+
+impl Default for Synth {
+    fn default() -> Self {
+        Synth {
+            sample_rate: 44_100,
+            volume: 0.5,
+            envelope: Envelope::default(),
+            oscillators: Vec::new(),
+            seed: 0,
+        }
+    }
+}
+
+impl Synth {
+    /// Creates a synth with the given envelope and oscillators.
+    pub fn new(envelope: Envelope, oscillators: Vec<Oscillator>) -> Self {
+        Synth {
+            envelope,
+            oscillators,
+            ..Default::default()
+        }
+    }
+
+    /// Generates a random variation of a preset. The same seed always gives the same sound.
+    pub fn preset(preset: SfxPreset, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut range = |min: f32, max: f32| min + (max - min) * rng.next_f32();
+        let square = Waveform::Square { duty: 0.5 };
+        let (envelope, oscillator) = match preset {
+            SfxPreset::Coin => (
+                envelope(0.0, range(0.03, 0.08), range(0.15, 0.3)),
+                Oscillator {
+                    arpeggio: Some((range(0.04, 0.08), range(1.3, 1.6))),
+                    ..Oscillator::new(square, range(800.0, 1500.0))
+                },
+            ),
+            SfxPreset::Laser => (
+                envelope(0.0, range(0.05, 0.15), range(0.05, 0.2)),
+                Oscillator {
+                    slide: -range(2000.0, 4000.0),
+                    min_frequency: range(80.0, 150.0),
+                    duty_sweep: range(-0.5, 0.5),
+                    ..Oscillator::new(
+                        Waveform::Square {
+                            duty: range(0.2, 0.5),
+                        },
+                        range(600.0, 1200.0),
+                    )
+                },
+            ),
+            SfxPreset::Explosion => (
+                envelope(0.0, range(0.05, 0.2), range(0.3, 0.6)),
+                Oscillator {
+                    slide: -range(50.0, 150.0),
+                    ..Oscillator::new(Waveform::Noise, range(100.0, 300.0))
+                },
+            ),
+            SfxPreset::PowerUp => (
+                envelope(0.0, range(0.2, 0.4), range(0.1, 0.3)),
+                Oscillator {
+                    slide: range(600.0, 1200.0),
+                    vibrato_depth: range(0.0, 0.1),
+                    vibrato_speed: range(10.0, 20.0),
+                    ..Oscillator::new(square, range(300.0, 500.0))
+                },
+            ),
+            SfxPreset::Hit => (
+                envelope(0.0, range(0.02, 0.05), range(0.05, 0.15)),
+                Oscillator {
+                    slide: -range(500.0, 1000.0),
+                    ..Oscillator::new(Waveform::Sawtooth, range(200.0, 500.0))
+                },
+            ),
+            SfxPreset::Jump => (
+                envelope(0.0, range(0.05, 0.1), range(0.1, 0.2)),
+                Oscillator {
+                    slide: range(400.0, 800.0),
+                    ..Oscillator::new(square, range(250.0, 450.0))
+                },
+            ),
+            SfxPreset::Blip => (
+                envelope(0.0, range(0.03, 0.06), range(0.02, 0.05)),
+                Oscillator::new(square, range(800.0, 1200.0)),
+            ),
+        };
+        Synth {
+            seed,
+            ..Synth::new(envelope, vec![oscillator])
+        }
+    }
+
+    /// Renders the sound as mono samples between -1.0 and 1.0, stopping after
+    /// `MAX_SYNTH_DURATION` seconds.
+    pub fn render(&self) -> Vec<f32> {
+        let sample_rate = self.sample_rate.max(1) as f32;
+        let duration = self.envelope.duration().min(MAX_SYNTH_DURATION);
+        let len = (duration * sample_rate) as usize;
+        let mut voices = self
+            .oscillators
+            .iter()
+            .enumerate()
+            .map(|(i, _)| Voice::new(self.seed.wrapping_add(i as u64)))
+            .collect::<Vec<_>>();
+        (0..len)
+            .map(|i| {
+                let time = i as f32 / sample_rate;
+                let mixed = self
+                    .oscillators
+                    .iter()
+                    .zip(&mut voices)
+                    .map(|(oscillator, voice)| voice.next(oscillator, time, sample_rate))
+                    .sum::<f32>();
+                (mixed * self.volume * self.envelope.level(time)).clamp(-1.0, 1.0)
+            })
+            .collect()
+    }
+
+    /// Renders the sound as a 16 bit PCM WAV file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_wav(&mut bytes, 1, self.sample_rate.max(1), &self.render())
+            .expect("Writing to a Vec can't fail");
+        bytes
+    }
+
+    /// Renders the sound to a `Source` which can be played.
+    pub fn to_source(&self) -> Source {
+        Source {
+            bytes: self.to_wav(),
+        }
+    }
+}
+
+fn envelope(attack: f32, sustain_time: f32, release: f32) -> Envelope {
+    Envelope {
+        attack,
+        decay: 0.0,
+        sustain: 1.0,
+        sustain_time,
+        release,
+    }
+}
+
+/// A small xorshift generator for noise and presets.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Spread the bits of small seeds, as xorshift can't start from zero.
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// The state of an oscillator while rendering.
+#[derive(Debug)]
+struct Voice {
+    phase: f32,
+    stopped: bool,
+    noise: Rng,
+    noise_value: f32,
+    noise_step: u32,
+}
+
+impl Voice {
+    fn new(seed: u64) -> Self {
+        Voice {
+            phase: 0.0,
+            stopped: false,
+            noise: Rng::new(seed),
+            noise_value: 0.0,
+            noise_step: u32::MAX,
+        }
+    }
+
+    fn next(&mut self, oscillator: &Oscillator, time: f32, sample_rate: f32) -> f32 {
+        if self.stopped {
+            return 0.0;
+        }
+        let frequency = match oscillator.frequency_at(time) {
+            Some(frequency) => frequency,
+            None => {
+                self.stopped = true;
+                return 0.0;
+            }
+        };
+        let phase = self.phase;
+        self.phase = (self.phase + frequency / sample_rate).fract();
+        let value = match oscillator.waveform {
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Square { duty } => {
+                let duty = (duty + oscillator.duty_sweep * time).clamp(0.0, 1.0);
+                if phase < duty {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Noise => {
+                let step = (phase * 32.0) as u32;
+                if step != self.noise_step {
+                    self.noise_step = step;
+                    self.noise_value = self.noise.next_f32() * 2.0 - 1.0;
+                }
+                self.noise_value
+            }
+        };
+        value * oscillator.volume
+    }
+}
+
+/// Loads a `Synth` from a RON file and renders it.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct SynthFormat;
+
+amethyst_assets::register_format!("SYNTH", SynthFormat as AudioData);
+impl Format<AudioData> for SynthFormat {
+    fn name(&self) -> &'static str {
+        "SYNTH"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<AudioData, Error> {
+        let synth: Synth = ron::de::from_bytes(&bytes).map_err(Error::new)?;
+        if synth.envelope.duration() > MAX_SYNTH_DURATION {
+            return Err(format_err!(
+                "Synthesized sound lasts {} seconds, longer than the maximum of {}",
+                synth.envelope.duration(),
+                MAX_SYNTH_DURATION
+            ));
+        }
+        Ok(AudioData(synth.to_wav()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rodio::{Decoder, Source as RSource};
+
+    use super::*;
+
+    #[test]
+    fn envelope_levels() {
+        let envelope = Envelope {
+            attack: 1.0,
+            decay: 1.0,
+            sustain: 0.5,
+            sustain_time: 1.0,
+            release: 1.0,
+        };
+        assert_eq!(envelope.duration(), 4.0);
+        assert_eq!(envelope.level(0.5), 0.5);
+        assert_eq!(envelope.level(1.5), 0.75);
+        assert_eq!(envelope.level(2.5), 0.5);
+        assert_eq!(envelope.level(3.5), 0.25);
+        assert_eq!(envelope.level(4.5), 0.0);
+    }
+
+    #[test]
+    fn square_wave() {
+        let synth = Synth {
+            sample_rate: 8,
+            volume: 1.0,
+            envelope: envelope(0.0, 1.0, 0.0),
+            ..Synth::new(
+                Envelope::default(),
+                vec![Oscillator::new(Waveform::Square { duty: 0.5 }, 2.0)],
+            )
+        };
+        assert_eq!(
+            synth.render(),
+            vec![1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn slide_stops_below_min_frequency() {
+        let oscillator = Oscillator {
+            slide: -100.0,
+            min_frequency: 50.0,
+            ..Oscillator::new(Waveform::Sine, 100.0)
+        };
+        assert_eq!(oscillator.frequency_at(0.25), Some(75.0));
+        assert_eq!(oscillator.frequency_at(0.75), None);
+    }
+
+    #[test]
+    fn presets_are_deterministic() {
+        assert_eq!(
+            Synth::preset(SfxPreset::Explosion, 7).render(),
+            Synth::preset(SfxPreset::Explosion, 7).render()
+        );
+        assert_ne!(
+            Synth::preset(SfxPreset::Coin, 1),
+            Synth::preset(SfxPreset::Coin, 2)
+        );
+    }
+
+    #[test]
+    fn load_from_ron() {
+        let ron = r#"(
+            sample_rate: 8000,
+            envelope: (sustain_time: 0.05, release: 0.05),
+            oscillators: [(waveform: Triangle, frequency: 440.0)],
+        )"#;
+        let AudioData(bytes) = SynthFormat.import_simple(ron.as_bytes().to_vec()).unwrap();
+        let decoder = Decoder::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(decoder.sample_rate(), 8000);
+        let samples = decoder.collect::<Vec<i16>>();
+        assert_eq!(samples.len(), 800);
+        assert!(samples.iter().any(|&s| s != 0));
+    }
+
+    #[test]
+    fn long_sounds_are_capped() {
+        let synth = Synth {
+            sample_rate: 8,
+            envelope: envelope(0.0, 1.0e9, 0.0),
+            ..Synth::new(
+                Envelope::default(),
+                vec![Oscillator::new(Waveform::Sine, 1.0)],
+            )
+        };
+        assert_eq!(synth.render().len(), 8 * MAX_SYNTH_DURATION as usize);
+
+        let ron = r#"(envelope: (sustain_time: 1000000000.0))"#;
+        assert!(SynthFormat.import_simple(ron.as_bytes().to_vec()).is_err());
+    }
+}
@@ -1,31 +1,31 @@
-use std::{
-    iter::Iterator,
-    mem::replace,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-};
+use std::{iter::Iterator, mem::replace, sync::atomic::Ordering};
 
 use derive_new::new;
-use rodio::SpatialSink;
 
 #[cfg(feature = "profiler")]
 use thread_profiler::profile_scope;
 
 use amethyst_core::{
     ecs::prelude::{
-        Entities, Entity, Join, Read, ReadStorage, System, SystemData, World, WriteStorage,
+        Entities, Entity, Join, Read, ReadStorage, System, SystemData, World, Write, WriteStorage,
     },
-    math::convert,
+    math::{convert, Point3, Vector3},
+    shrev::EventChannel,
+    timing::Time,
     transform::Transform,
     SystemDesc,
 };
 
 use crate::{
-    components::{AudioEmitter, AudioListener},
+    components::{AudioEmitter, AudioListener, AudioOccluder, ReverbZone},
+    effects::EffectSource,
     end_signal::EndSignalSource,
+    environment::{environment_effects, Occlusion, Placed, Reverb},
+    mixer::{AudioMixer, BusSource},
     output::Output,
+    sink::AudioSink,
+    spatial::{panning_positions, SpatialAudioConfig, SpatialSink},
+    timeline::{CueEvent, CueOrigin},
 };
 
 /// Builds an `AudioSystem`.
@@ -46,8 +46,22 @@ impl<'a, 'b> SystemDesc<'a, 'b, AudioSystem> for AudioSystemDesc {
 }
 
 /// Syncs 3D transform data with the audio engine to provide 3D audio.
+///
+/// Emitters with an `Attenuation` are attenuated with distance and virtualized according to the
+/// `SpatialAudioConfig` resource.
+///
+/// Sounds are muffled by the `AudioOccluder`s between their emitter and the listener, and
+/// reverberate with the settings of the `ReverbZone` the listener is in.
+///
+/// A `CueEvent` is sent when a sound played with a `CueSheet`, by an emitter or the `AudioSink`
+/// resource, reaches one of its cues.
 #[derive(Debug, Default, new)]
-pub struct AudioSystem(Output);
+pub struct AudioSystem(
+    Output,
+    /// Position of the listener in the previous frame, used to compute its velocity.
+    #[new(default)]
+    Option<Point3<f32>>,
+);
 
 /// Add this structure to world as a resource with ID 0 to select an entity whose AudioListener
 /// component will be used.  If this resource isn't found then the system will arbitrarily select
@@ -63,14 +77,44 @@ impl<'a> System<'a> for AudioSystem {
         ReadStorage<'a, Transform>,
         ReadStorage<'a, AudioListener>,
         WriteStorage<'a, AudioEmitter>,
+        Write<'a, AudioMixer>,
+        Read<'a, Time>,
+        Read<'a, SpatialAudioConfig>,
+        Option<Read<'a, AudioSink>>,
+        Write<'a, EventChannel<CueEvent>>,
+        ReadStorage<'a, AudioOccluder>,
+        ReadStorage<'a, ReverbZone>,
     );
 
     fn run(
         &mut self,
-        (output, select_listener, entities, transform, listener, mut audio_emitter): Self::SystemData,
+        (
+            output,
+            select_listener,
+            entities,
+            transform,
+            listener,
+            mut audio_emitter,
+            mut mixer,
+            time,
+            config,
+            sink,
+            mut cue_events,
+            occluders,
+            reverb_zones,
+        ): Self::SystemData,
     ) {
         #[cfg(feature = "profiler")]
         profile_scope!("audio_system");
+        // Fades keep running while the game is paused through `Time::set_time_scale`.
+        let delta = time.delta_real_time();
+        let velocity = |position: Point3<f32>, previous: Option<Point3<f32>>| {
+            let seconds = time.delta_seconds();
+            match previous {
+                Some(previous) if seconds > 0.0 => (position - previous) / seconds,
+                _ => Vector3::zeros(),
+            }
+        };
         // Process emitters and listener.
         if let Some((listener, entity)) = select_listener
             .as_ref()
@@ -83,59 +127,193 @@ impl<'a> System<'a> for AudioSystem {
                 .or_else(|| transform.get(entity))
             {
                 let listener_transform = listener_transform.global_matrix();
-                let left_ear_position: [f32; 3] = {
-                    let pos = listener_transform
-                        .transform_point(&listener.left_ear)
-                        .to_homogeneous()
-                        .xyz();
-                    [convert(pos.x), convert(pos.y), convert(pos.z)]
-                };
-                let right_ear_position: [f32; 3] = {
-                    let pos = listener_transform
-                        .transform_point(&listener.right_ear)
-                        .to_homogeneous()
-                        .xyz();
-                    [convert(pos.x), convert(pos.y), convert(pos.z)]
-                };
-                for (transform, mut audio_emitter) in (&transform, &mut audio_emitter).join() {
-                    let emitter_position: [f32; 3] = {
+                let left_ear = listener_transform.transform_point(&listener.left_ear);
+                let right_ear = listener_transform.transform_point(&listener.right_ear);
+                let left_ear: Point3<f32> = Point3::new(
+                    convert(left_ear.x),
+                    convert(left_ear.y),
+                    convert(left_ear.z),
+                );
+                let right_ear: Point3<f32> = Point3::new(
+                    convert(right_ear.x),
+                    convert(right_ear.y),
+                    convert(right_ear.z),
+                );
+                let listener_position = Point3::from((left_ear.coords + right_ear.coords) / 2.0);
+                let listener_velocity = velocity(listener_position, self.1);
+                self.1 = Some(listener_position);
+
+                let occluders = (&transform, &occluders)
+                    .join()
+                    .filter_map(|(transform, occluder)| {
+                        Placed::new(transform.global_matrix(), occluder)
+                    })
+                    .collect::<Vec<_>>();
+                let reverb_zones = (&transform, &reverb_zones)
+                    .join()
+                    .filter_map(|(transform, zone)| Placed::new(transform.global_matrix(), zone))
+                    .collect::<Vec<_>>();
+                let reverb = Reverb::at(&reverb_zones, &listener_position);
+
+                for (entity, transform, mut audio_emitter) in
+                    (&*entities, &transform, &mut audio_emitter).join()
+                {
+                    let emitter_position: Point3<f32> = {
                         let x = transform.global_matrix()[(0, 3)];
                         let y = transform.global_matrix()[(1, 3)];
                         let z = transform.global_matrix()[(2, 3)];
-                        [convert(x), convert(y), convert(z)]
+                        Point3::new(convert(x), convert(y), convert(z))
                     };
-                    // Remove all sinks whose sounds have ended.
-                    audio_emitter.sinks.retain(|s| !s.1.load(Ordering::Relaxed));
-                    for &mut (ref mut sink, _) in &mut audio_emitter.sinks {
-                        sink.set_emitter_position(emitter_position);
-                        sink.set_left_ear_position(left_ear_position);
-                        sink.set_right_ear_position(right_ear_position);
+                    let emitter_velocity = velocity(emitter_position, audio_emitter.last_position);
+                    let occlusion =
+                        Occlusion::between(&occluders, &listener_position, &emitter_position);
+                    if audio_emitter.last_position.is_some() {
+                        audio_emitter.occlusion.approach(occlusion, delta);
+                        audio_emitter.reverb.approach(reverb, delta);
+                    } else {
+                        audio_emitter.occlusion = occlusion;
+                        audio_emitter.reverb = reverb;
+                    }
+                    let environment =
+                        environment_effects(&audio_emitter.occlusion, &audio_emitter.reverb);
+                    if environment != audio_emitter.environment.effects() {
+                        audio_emitter.environment.set_effects(environment);
                     }
-                    if audio_emitter.sinks.is_empty() {
+                    let occlusion_gain = audio_emitter.occlusion.gain;
+                    audio_emitter.last_position = Some(emitter_position);
+
+                    let distance = (emitter_position - listener_position).norm();
+                    let (distance_gain, out_of_range, (left, right, emitter)) =
+                        match &audio_emitter.attenuation {
+                            Some(attenuation) => (
+                                attenuation.gain(distance),
+                                config.virtualization && attenuation.is_out_of_range(distance),
+                                panning_positions(left_ear, right_ear, emitter_position),
+                            ),
+                            None => (
+                                1.0,
+                                false,
+                                (array(left_ear), array(right_ear), array(emitter_position)),
+                            ),
+                        };
+                    let doppler = config.doppler_shift(
+                        &listener_position,
+                        &listener_velocity,
+                        &emitter_position,
+                        &emitter_velocity,
+                    );
+
+                    // Remove all sounds which have ended.
+                    remove_finished(audio_emitter, entity, &mut cue_events);
+                    for playback in &mut audio_emitter.playbacks {
+                        playback.advance(delta);
+                        // Sounds which won't be heard again before they end are culled.
+                        if out_of_range && !playback.is_looping() {
+                            playback.stop();
+                        }
+                        playback.set_virtual(out_of_range);
+                        if let Some(sink) = &playback.sink {
+                            sink.set_emitter_position(emitter);
+                            sink.set_left_ear_position(left);
+                            sink.set_right_ear_position(right);
+                        }
+                        playback.apply(playback.gain() * distance_gain * occlusion_gain, doppler);
+                    }
+                    remove_finished(audio_emitter, entity, &mut cue_events);
+                    if audio_emitter.playbacks.is_empty() {
                         if let Some(mut picker) = replace(&mut audio_emitter.picker, None) {
                             if picker(&mut audio_emitter) {
                                 audio_emitter.picker = Some(picker);
                             }
                         }
                     }
-                    while let Some(source) = audio_emitter.sound_queue.pop() {
-                        if let Some(output) = &output {
-                            let sink = SpatialSink::new(
-                                &output.device,
-                                emitter_position,
-                                left_ear_position,
-                                right_ear_position,
-                            );
-                            let atomic_bool = Arc::new(AtomicBool::new(false));
-                            let clone = atomic_bool.clone();
-                            sink.append(EndSignalSource::new(source, move || {
-                                clone.store(true, Ordering::Relaxed);
-                            }));
-                            audio_emitter.sinks.push((sink, atomic_bool));
+                    if let Some(output) = &output {
+                        let gain = mixer.gain(&audio_emitter.bus);
+                        for playback in &mut audio_emitter.playbacks {
+                            if playback.is_virtual() {
+                                continue;
+                            }
+                            if let Some(source) = playback.pending.take() {
+                                let (sink, tag) = output.sink();
+                                tag.label(source.source());
+                                let sink = SpatialSink::new(sink, emitter, left, right);
+                                let ended = playback.ended.clone();
+                                sink.append(EndSignalSource::new(
+                                    BusSource::new(
+                                        EffectSource::new(
+                                            EffectSource::new(
+                                                source,
+                                                audio_emitter.effects.clone(),
+                                            ),
+                                            audio_emitter.environment.clone(),
+                                        ),
+                                        gain.clone(),
+                                    ),
+                                    move || {
+                                        ended.store(true, Ordering::Relaxed);
+                                    },
+                                ));
+                                playback.sink = Some(sink);
+                                playback.apply(
+                                    playback.gain() * distance_gain * occlusion_gain,
+                                    doppler,
+                                );
+                            }
+                        }
+                    } else {
+                        // Without an output the sounds can never start, so they end right away
+                        // instead of piling up in the emitter.
+                        for playback in &mut audio_emitter.playbacks {
+                            if playback.pending.take().is_some() {
+                                playback.stop();
+                            }
                         }
                     }
                 }
             }
         }
+
+        if let Some(sink) = &sink {
+            sink.poll_cues(|position, kind| {
+                cue_events.single_write(CueEvent {
+                    origin: CueOrigin::Sink,
+                    kind,
+                    position,
+                })
+            });
+        }
+
+        // Mix what was played this frame into the recording, if the output records.
+        if let Some(recording) = output.as_ref().and_then(|output| output.recording()) {
+            recording.advance(delta);
+        }
     }
 }
+
+/// Sends the cues reached by the sounds of an emitter and removes the sounds which have ended.
+fn remove_finished(
+    emitter: &mut AudioEmitter,
+    entity: Entity,
+    cue_events: &mut EventChannel<CueEvent>,
+) {
+    emitter.playbacks.retain(|playback| {
+        // Checked first so that the cues reached right before the end are still sent.
+        let finished = playback.is_finished();
+        let origin = CueOrigin::Emitter {
+            entity,
+            playback: playback.id,
+        };
+        playback.poll_cues(|position, kind| {
+            cue_events.single_write(CueEvent {
+                origin,
+                kind,
+                position,
+            })
+        });
+        !finished
+    });
+}
+
+fn array(point: Point3<f32>) -> [f32; 3] {
+    [point.x, point.y, point.z]
+}
@@ -0,0 +1,94 @@
+use cpal::traits::DeviceTrait;
+use derive_new::new;
+use log::{error, warn};
+use rodio::{default_output_device, output_devices};
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+use amethyst_core::{
+    ecs::prelude::{Read, System, SystemData, World, Write},
+    shrev::EventChannel,
+    timing::Time,
+    SystemDesc,
+};
+
+use crate::{
+    devices::{AudioDeviceEvent, AudioDevices, SwitchTarget},
+    output::{default_output, outputs, Output},
+};
+
+/// Builds an `AudioDeviceSystem`.
+#[derive(Default, Debug)]
+pub struct AudioDeviceSystemDesc;
+
+impl<'a, 'b> SystemDesc<'a, 'b, AudioDeviceSystem> for AudioDeviceSystemDesc {
+    fn build(self, world: &mut World) -> AudioDeviceSystem {
+        <AudioDeviceSystem as System<'_>>::SystemData::setup(world);
+
+        AudioDeviceSystem::new()
+    }
+}
+
+/// Enumerates the output devices at the interval set in the `AudioDevices` resource, sending an
+/// `AudioDeviceEvent` for each change.
+///
+/// The `Output` resource is moved to another device when requested through `AudioDevices`, or to
+/// the default device when its device is removed. Sounds which are playing continue on the new
+/// device.
+#[derive(Debug, Default, new)]
+pub struct AudioDeviceSystem;
+
+impl<'a> System<'a> for AudioDeviceSystem {
+    type SystemData = (
+        Option<Read<'a, Output>>,
+        Read<'a, Time>,
+        Write<'a, AudioDevices>,
+        Write<'a, EventChannel<AudioDeviceEvent>>,
+    );
+
+    fn run(&mut self, (output, time, mut devices, mut events): Self::SystemData) {
+        #[cfg(feature = "profiler")]
+        profile_scope!("audio_device_system");
+
+        if !devices.should_poll(time.delta_real_time()) {
+            return;
+        }
+        let names = match output_devices() {
+            Ok(found) => found
+                .filter_map(|device| device.name().ok())
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                error!("Failed to enumerate audio output devices: {}", e);
+                return;
+            }
+        };
+        let default = default_output_device().and_then(|device| device.name().ok());
+        let default_changed = devices.update(names, default, &mut events);
+
+        let output = match output {
+            Some(output) => output,
+            None => return,
+        };
+        let active = output
+            .recording()
+            .map_or_else(|| Some(output.name()), |_| None);
+        let target = match devices.switch_target(active.as_deref(), default_changed) {
+            Some(SwitchTarget::Device(name)) => {
+                let target = outputs().find(|output| output.name() == name);
+                if target.is_none() {
+                    warn!("Cannot switch to audio output device `{}`: not found", name);
+                }
+                target
+            }
+            Some(SwitchTarget::Default) => default_output(),
+            None => None,
+        };
+        if let Some(target) = target {
+            let name = target.name();
+            if active.as_ref() != Some(&name) {
+                output.switch_to(&target);
+                events.single_write(AudioDeviceEvent::Switched(name));
+            }
+        }
+    }
+}
@@ -43,6 +43,8 @@ where
 }
 
 /// Calls a closure if the `AudioSink` is empty.
+///
+/// The music plays on the `Bus::Music` mixer bus, unless the `AudioSink` was routed elsewhere.
 #[derive(Debug, new)]
 pub struct DjSystem<F, R> {
     f: F,
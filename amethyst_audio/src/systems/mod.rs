@@ -2,8 +2,12 @@
 
 pub use self::{
     audio::{AudioSystem, AudioSystemDesc},
+    devices::{AudioDeviceSystem, AudioDeviceSystemDesc},
     dj::{DjSystem, DjSystemDesc},
+    music::{MusicSystem, MusicSystemDesc},
 };
 
 mod audio;
+mod devices;
 mod dj;
+mod music;
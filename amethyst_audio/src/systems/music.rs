@@ -0,0 +1,257 @@
+use std::{
+    io::Cursor,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use derive_new::new;
+use log::error;
+use rodio::{Decoder, Source as RSource};
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+use amethyst_assets::AssetStorage;
+use amethyst_core::{
+    ecs::prelude::{Read, System, SystemData, World, Write},
+    timing::Time,
+    SystemDesc,
+};
+
+use crate::{
+    end_signal::EndSignalSource,
+    mixer::{AudioMixer, Bus, BusGain, BusSource},
+    music::{MusicPlayer, PlayingStem, PlayingTrack},
+    output::{default_output, Output},
+    source::{Source, SourceHandle},
+    DecoderError,
+};
+
+/// Builds a `MusicSystem`.
+#[derive(Default, Debug)]
+pub struct MusicSystemDesc;
+
+impl<'a, 'b> SystemDesc<'a, 'b, MusicSystem> for MusicSystemDesc {
+    fn build(self, world: &mut World) -> MusicSystem {
+        <MusicSystem as System<'_>>::SystemData::setup(world);
+
+        if !world.has_value::<Output>() {
+            if let Some(output) = default_output() {
+                world.insert(output);
+            }
+        }
+
+        MusicSystem::new()
+    }
+}
+
+/// Plays the music selected through the `MusicPlayer` resource on the `Bus::Music` mixer bus.
+///
+/// Tracks are crossfaded when the playlist changes and, if the length of the current track is
+/// known, before it ends. The music is ducked while stingers play.
+#[derive(Debug, Default, new)]
+pub struct MusicSystem;
+
+impl<'a> System<'a> for MusicSystem {
+    type SystemData = (
+        Option<Read<'a, Output>>,
+        Read<'a, AssetStorage<Source>>,
+        Read<'a, Time>,
+        Write<'a, AudioMixer>,
+        Write<'a, MusicPlayer>,
+    );
+
+    fn run(&mut self, (output, storage, time, mut mixer, mut player): Self::SystemData) {
+        #[cfg(feature = "profiler")]
+        profile_scope!("music_system");
+
+        let output = match output {
+            Some(output) => output,
+            None => return,
+        };
+        let delta = time.delta_real_time();
+        let gain = mixer.gain(&Bus::Music);
+        let player = &mut *player;
+
+        if player.skip_requested {
+            let was_playing = player.tracks.iter().any(|t| !t.superseded);
+            supersede(player);
+            start_next(
+                player,
+                if was_playing {
+                    player.crossfade
+                } else {
+                    Duration::from_secs(0)
+                },
+            );
+        } else if let Some(current) = player.tracks.iter().rev().find(|t| !t.superseded) {
+            let ended = current.has_ended();
+            let near_end = current.duration.is_some_and(|duration| {
+                current.is_started() && current.elapsed + player.crossfade >= duration
+            });
+            if ended || near_end {
+                supersede(player);
+                let fade_in = if ended {
+                    Duration::from_secs(0)
+                } else {
+                    player.crossfade
+                };
+                start_next(player, fade_in);
+            }
+        }
+
+        let mut failed = Vec::new();
+        for track in player.tracks.iter_mut().filter(|t| !t.is_started()) {
+            if let Some(sources) = loaded(&storage, &track.track.stems) {
+                match start_track(&output, &gain, &sources) {
+                    Ok((stems, duration)) => {
+                        track.stems = stems;
+                        track.duration = duration;
+                        player.failed.clear();
+                    }
+                    Err(e) => {
+                        error!("Cannot play music track. {}", e);
+                        // Tracks of a playlist which was switched away from are not replaced.
+                        if !track.superseded {
+                            failed.push(track.index);
+                        }
+                        track.superseded = true;
+                        track.fade.to(0.0, Duration::from_secs(0));
+                    }
+                }
+            }
+        }
+        for index in failed {
+            if player.track_failed(index) {
+                // Skipping also moves past a track repeated with `Repeat::One`.
+                player.skip_requested = true;
+                start_next(player, Duration::from_secs(0));
+            } else {
+                error!("No track of the music playlist can be played, stopping the music.");
+                player.stop();
+            }
+        }
+
+        player.stingers.retain_mut(|stinger| match &stinger.stem {
+            Some(stem) => !stem.ended.load(Ordering::Relaxed),
+            None => match storage.get(&stinger.source) {
+                Some(source) => match play(&output, &gain, source) {
+                    Ok((stem, _)) => {
+                        stem.sink.set_volume(stinger.volume);
+                        stem.sink.play();
+                        stinger.stem = Some(stem);
+                        true
+                    }
+                    Err(e) => {
+                        error!("Cannot play stinger. {}", e);
+                        false
+                    }
+                },
+                None => true,
+            },
+        });
+
+        let duck = if player.stingers.is_empty() {
+            1.0
+        } else {
+            player.duck_volume
+        };
+        if player.duck.target() != duck {
+            let fade = player.duck_fade;
+            player.duck.to(duck, fade);
+        }
+        player.duck.advance(delta);
+        for stem in &mut player.stem_volumes {
+            stem.advance(delta);
+        }
+
+        for track in &mut player.tracks {
+            track.fade.advance(delta);
+            if track.is_started() {
+                track.elapsed += delta;
+            }
+        }
+        player.tracks.retain(|track| {
+            let faded_out = track.superseded && track.fade.is_done() && track.fade.value() == 0.0;
+            let finished = faded_out || track.has_ended();
+            if finished {
+                track.stop();
+            }
+            !finished
+        });
+
+        for track in &player.tracks {
+            for (i, stem) in track.stems.iter().enumerate() {
+                stem.sink.set_volume(
+                    track.fade.value() * player.stem_volume_ramp(i) * player.duck.value(),
+                );
+            }
+        }
+    }
+}
+
+/// Fades out all playing tracks.
+fn supersede(player: &mut MusicPlayer) {
+    let crossfade = player.crossfade;
+    for track in player.tracks.iter_mut().filter(|t| !t.superseded) {
+        track.superseded = true;
+        track.fade.to(0.0, crossfade);
+    }
+}
+
+fn start_next(player: &mut MusicPlayer, fade_in: Duration) {
+    if let Some((index, track)) = player.next_track() {
+        player.tracks.push(PlayingTrack::new(track, index, fade_in));
+    }
+    player.skip_requested = false;
+}
+
+fn loaded<'s>(
+    storage: &'s AssetStorage<Source>,
+    handles: &[SourceHandle],
+) -> Option<Vec<&'s Source>> {
+    handles.iter().map(|handle| storage.get(handle)).collect()
+}
+
+/// Starts all stems of a track at the same time, returning them along with the length of the
+/// longest stem, if known.
+fn start_track(
+    output: &Output,
+    gain: &BusGain,
+    sources: &[&Source],
+) -> Result<(Vec<PlayingStem>, Option<Duration>), DecoderError> {
+    let mut stems = Vec::with_capacity(sources.len());
+    let mut duration = Some(Duration::from_secs(0));
+    for source in sources {
+        let (stem, length) = play(output, gain, source)?;
+        duration = duration.and_then(|d| length.map(|l| d.max(l)));
+        stems.push(stem);
+    }
+    for stem in &stems {
+        stem.sink.set_volume(0.0);
+        stem.sink.play();
+    }
+    Ok((stems, duration))
+}
+
+/// Appends a source to a new paused sink on the music bus.
+fn play(
+    output: &Output,
+    gain: &BusGain,
+    source: &Source,
+) -> Result<(PlayingStem, Option<Duration>), DecoderError> {
+    let decoder = Decoder::new(Cursor::new(source.clone())).map_err(|_| DecoderError)?;
+    let duration = decoder.total_duration();
+    let ended = Arc::new(AtomicBool::new(false));
+    let signal = ended.clone();
+    let (sink, tag) = output.sink();
+    tag.label(source);
+    sink.pause();
+    sink.append(EndSignalSource::new(
+        BusSource::new(decoder, gain.clone()),
+        move || signal.store(true, Ordering::Relaxed),
+    ));
+    Ok((PlayingStem { sink, ended }, duration))
+}
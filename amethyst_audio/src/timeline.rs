@@ -0,0 +1,503 @@
+//! Playback positions and cues sent when a sound reaches marked times or beats.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rodio::{Sample, Source as RSource};
+use serde::{Deserialize, Serialize};
+
+use amethyst_assets::{Asset, Handle};
+use amethyst_core::ecs::prelude::{Entity, VecStorage};
+
+use crate::playback::PlaybackId;
+
+/// A position in a playing sound, counted in frames of the decoded source. A frame holds one
+/// sample per channel.
+///
+/// The position doesn't depend on the playback speed, so it stays in sync with the cues of the
+/// sound when it is slowed down or sped up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PlaybackPosition {
+    /// Number of frames played.
+    pub frame: u64,
+    /// Sample rate of the source.
+    pub sample_rate: u32,
+}
+
+impl PlaybackPosition {
+    /// Creates a position from a number of frames.
+    pub fn new(frame: u64, sample_rate: u32) -> Self {
+        PlaybackPosition { frame, sample_rate }
+    }
+
+    /// Returns the frame at the given time, rounded to the nearest frame.
+    pub fn from_secs_f64(secs: f64, sample_rate: u32) -> Self {
+        let frame = (secs * f64::from(sample_rate)).round().max(0.0) as u64;
+        PlaybackPosition { frame, sample_rate }
+    }
+
+    /// Returns the position in seconds.
+    pub fn as_secs_f64(&self) -> f64 {
+        self.frame as f64 / f64::from(self.sample_rate.max(1))
+    }
+
+    /// Returns the position as a duration.
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.as_secs_f64())
+    }
+}
+
+/// A named marker in a sound.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cue {
+    /// Name sent with the `CueEvent`.
+    pub name: String,
+    /// Time of the marker in seconds from the start of the sound.
+    pub time: f64,
+}
+
+/// The tempo of a piece of music, used to send a `CueEvent` on every beat.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tempo {
+    /// Beats per minute.
+    pub bpm: f64,
+    /// Number of beats in a bar.
+    #[serde(default = "default_beats_per_bar")]
+    pub beats_per_bar: u32,
+    /// Time of the first beat in seconds from the start of the sound.
+    #[serde(default)]
+    pub offset: f64,
+}
+
+fn default_beats_per_bar() -> u32 {
+    4
+}
+
+/// Markers and tempo of a sound, usually loaded with `RonFormat`:
+///
+/// ```ron
+/// (
+///     cues: [(name: "drop", time: 32.0)],
+///     tempo: Some((bpm: 120.0, beats_per_bar: 4, offset: 0.05)),
+/// )
+/// ```
+///
+/// Sheets are attached to a sound when playing it with `AudioEmitter::play_with_cues` or
+/// `AudioSink::append_with_cues`, and the `AudioSystem` then sends a `CueEvent` for each marker
+/// and beat in the frame it is reached.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CueSheet {
+    /// The markers.
+    pub cues: Vec<Cue>,
+    /// The tempo, to send beat events.
+    pub tempo: Option<Tempo>,
+}
+
+/// A handle to a cue sheet asset.
+pub type CueSheetHandle = Handle<CueSheet>;
+
+impl Asset for CueSheet {
+    const NAME: &'static str = "audio::CueSheet";
+    type Data = Self;
+    type HandleStorage = VecStorage<CueSheetHandle>;
+}
+
+impl CueSheet {
+    /// Returns the cues in the frames `from..to`, in the order they are reached.
+    pub(crate) fn cues_between(
+        &self,
+        from: u64,
+        to: u64,
+        sample_rate: u32,
+    ) -> Vec<(PlaybackPosition, CueKind)> {
+        let mut reached = self
+            .cues
+            .iter()
+            .map(|cue| {
+                (
+                    PlaybackPosition::from_secs_f64(cue.time, sample_rate),
+                    CueKind::Marker(cue.name.clone()),
+                )
+            })
+            .filter(|(position, _)| position.frame >= from && position.frame < to)
+            .collect::<Vec<_>>();
+        if let Some(tempo) = self.tempo.as_ref().filter(|tempo| tempo.bpm > 0.0) {
+            let beat_length = 60.0 / tempo.bpm;
+            let beats_per_bar = u64::from(tempo.beats_per_bar.max(1));
+            let from_secs = from as f64 / f64::from(sample_rate.max(1));
+            let mut beat = ((from_secs - tempo.offset) / beat_length).floor().max(0.0) as u64;
+            loop {
+                let position = PlaybackPosition::from_secs_f64(
+                    tempo.offset + beat as f64 * beat_length,
+                    sample_rate,
+                );
+                if position.frame >= to {
+                    break;
+                }
+                if position.frame >= from {
+                    reached.push((
+                        position,
+                        CueKind::Beat {
+                            beat,
+                            bar: beat / beats_per_bar,
+                            beat_in_bar: (beat % beats_per_bar) as u32,
+                        },
+                    ));
+                }
+                beat += 1;
+            }
+        }
+        reached.sort_by_key(|(position, _)| position.frame);
+        reached
+    }
+}
+
+/// What was reached by a sound.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CueKind {
+    /// A marker of the `CueSheet`, with its name.
+    Marker(String),
+    /// A beat of the `Tempo` of the `CueSheet`.
+    Beat {
+        /// Index of the beat, from 0.
+        beat: u64,
+        /// Index of the bar, from 0.
+        bar: u64,
+        /// Index of the beat in its bar, 0 being the first beat of the bar.
+        beat_in_bar: u32,
+    },
+}
+
+/// The sound which reached a cue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CueOrigin {
+    /// A sound played by the `AudioEmitter` of an entity.
+    Emitter {
+        /// The entity of the emitter.
+        entity: Entity,
+        /// The sound.
+        playback: PlaybackId,
+    },
+    /// A sound appended to the `AudioSink` resource.
+    Sink,
+}
+
+/// Sent through an `EventChannel` by the `AudioSystem` when a sound reaches a cue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CueEvent {
+    /// The sound which reached the cue.
+    pub origin: CueOrigin,
+    /// The cue.
+    pub kind: CueKind,
+    /// The exact position of the cue in the sound.
+    pub position: PlaybackPosition,
+}
+
+/// Counts the samples played by the audio thread.
+#[derive(Clone, Debug)]
+pub(crate) struct Clock(Arc<ClockState>);
+
+#[derive(Debug)]
+struct ClockState {
+    channels: u64,
+    sample_rate: u32,
+    samples: AtomicU64,
+    loops: AtomicU64,
+    loop_samples: AtomicU64,
+    ended: AtomicBool,
+}
+
+impl Clock {
+    pub(crate) fn new(channels: u16, sample_rate: u32) -> Self {
+        Clock(Arc::new(ClockState {
+            channels: u64::from(channels.max(1)),
+            sample_rate,
+            samples: AtomicU64::new(0),
+            loops: AtomicU64::new(0),
+            loop_samples: AtomicU64::new(0),
+            ended: AtomicBool::new(false),
+        }))
+    }
+
+    /// Counts one played sample.
+    pub(crate) fn tick(&self) {
+        self.0.samples.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Starts counting from the start of the sound again.
+    pub(crate) fn restart(&self) {
+        let samples = self.0.samples.load(Ordering::SeqCst);
+        self.0.loop_samples.store(samples, Ordering::SeqCst);
+        self.0.samples.store(0, Ordering::SeqCst);
+        self.0.loops.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn end(&self) {
+        self.0.ended.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_ended(&self) -> bool {
+        self.0.ended.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn position(&self) -> PlaybackPosition {
+        self.read().1
+    }
+
+    /// Returns the number of times the sound looped and the position in the current loop.
+    fn read(&self) -> (u64, PlaybackPosition) {
+        loop {
+            let loops = self.0.loops.load(Ordering::SeqCst);
+            let samples = self.0.samples.load(Ordering::SeqCst);
+            if loops == self.0.loops.load(Ordering::SeqCst) {
+                return (loops, self.frames(samples));
+            }
+        }
+    }
+
+    fn frames(&self, samples: u64) -> PlaybackPosition {
+        PlaybackPosition::new(samples / self.0.channels, self.0.sample_rate)
+    }
+}
+
+/// The position of a sound along with the cues not reached yet.
+#[derive(Debug)]
+pub(crate) struct Timeline {
+    clock: Clock,
+    cues: Option<Arc<CueSheet>>,
+    polled: (u64, u64),
+}
+
+impl Timeline {
+    pub(crate) fn new(clock: Clock, cues: Option<&CueSheet>) -> Self {
+        Timeline {
+            clock,
+            cues: cues.cloned().map(Arc::new),
+            polled: (0, 0),
+        }
+    }
+
+    pub(crate) fn position(&self) -> PlaybackPosition {
+        self.clock.position()
+    }
+
+    pub(crate) fn is_ended(&self) -> bool {
+        self.clock.is_ended()
+    }
+
+    /// Returns true if the timeline has cues to send.
+    pub(crate) fn has_cues(&self) -> bool {
+        self.cues.is_some()
+    }
+
+    /// Calls `f` with the cues reached since the previous poll.
+    ///
+    /// Once the sound ended, the cues on its very last frame are sent as well.
+    pub(crate) fn poll(&mut self, mut f: impl FnMut(PlaybackPosition, CueKind)) {
+        // Read before the position so that the position is final if the sound ended.
+        let ended = self.clock.is_ended();
+        let (loops, position) = self.clock.read();
+        let end = if ended {
+            position.frame + 1
+        } else {
+            position.frame
+        };
+        let (polled_loops, mut polled_frame) = self.polled;
+        self.polled = (loops, end);
+        let cues = match &self.cues {
+            Some(cues) => cues,
+            None => return,
+        };
+        let sample_rate = position.sample_rate;
+        let mut reached = Vec::new();
+        if loops > polled_loops {
+            // Loops skipped entirely within a single frame are ignored.
+            let loop_end = self
+                .clock
+                .frames(self.clock.0.loop_samples.load(Ordering::SeqCst))
+                .frame;
+            reached.extend(cues.cues_between(polled_frame, loop_end + 1, sample_rate));
+            polled_frame = 0;
+        }
+        reached.extend(cues.cues_between(polled_frame, end, sample_rate));
+        for (position, kind) in reached {
+            f(position, kind);
+        }
+    }
+}
+
+/// Counts the samples played from the input with a `Clock`, ending the clock when dropped.
+pub(crate) struct ClockSource<I> {
+    input: I,
+    clock: Clock,
+}
+
+impl<I: RSource> ClockSource<I>
+where
+    I::Item: Sample,
+{
+    pub(crate) fn new(input: I) -> Self {
+        let clock = Clock::new(input.channels(), input.sample_rate());
+        ClockSource { input, clock }
+    }
+
+    pub(crate) fn clock(&self) -> &Clock {
+        &self.clock
+    }
+}
+
+impl<I> Drop for ClockSource<I> {
+    fn drop(&mut self) {
+        self.clock.end();
+    }
+}
+
+impl<I: RSource> Iterator for ClockSource<I>
+where
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let sample = self.input.next();
+        match sample {
+            Some(_) => self.clock.tick(),
+            None => self.clock.end(),
+        }
+        sample
+    }
+}
+
+impl<I: RSource> RSource for ClockSource<I>
+where
+    I::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    fn sheet() -> CueSheet {
+        CueSheet {
+            cues: vec![Cue {
+                name: "drop".to_string(),
+                time: 0.25,
+            }],
+            tempo: Some(Tempo {
+                bpm: 120.0,
+                beats_per_bar: 2,
+                offset: 0.0,
+            }),
+        }
+    }
+
+    #[test]
+    fn cues_between_frames() {
+        let cues = sheet().cues_between(0, 1000, 1000);
+        assert_eq!(
+            cues,
+            vec![
+                (
+                    PlaybackPosition::new(0, 1000),
+                    CueKind::Beat {
+                        beat: 0,
+                        bar: 0,
+                        beat_in_bar: 0
+                    }
+                ),
+                (
+                    PlaybackPosition::new(250, 1000),
+                    CueKind::Marker("drop".to_string())
+                ),
+                (
+                    PlaybackPosition::new(500, 1000),
+                    CueKind::Beat {
+                        beat: 1,
+                        bar: 0,
+                        beat_in_bar: 1
+                    }
+                ),
+            ]
+        );
+        let cues = sheet().cues_between(1000, 1501, 1000);
+        assert_eq!(cues.len(), 2);
+        assert_eq!(
+            cues[1].1,
+            CueKind::Beat {
+                beat: 3,
+                bar: 1,
+                beat_in_bar: 1
+            }
+        );
+        assert!(sheet().cues_between(1, 250, 1000).is_empty());
+    }
+
+    #[test]
+    fn clock_counts_frames_of_the_source() {
+        let mut source = ClockSource::new(SamplesBuffer::new(2, 100, vec![0i16; 40]));
+        let clock = source.clock().clone();
+        let mut timeline = Timeline::new(clock.clone(), Some(&sheet()));
+        source.by_ref().take(10).for_each(drop);
+        assert_eq!(clock.position(), PlaybackPosition::new(5, 100));
+        assert!(!clock.is_ended());
+
+        let mut reached = Vec::new();
+        timeline.poll(|position, _| reached.push(position.frame));
+        assert_eq!(reached, vec![0]);
+        source.for_each(drop);
+        assert!(clock.is_ended());
+        assert_eq!(clock.position().as_duration(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn timeline_wraps_on_loop() {
+        let clock = Clock::new(1, 1000);
+        let mut timeline = Timeline::new(clock.clone(), Some(&sheet()));
+        (0..900).for_each(|_| clock.tick());
+        let mut reached = Vec::new();
+        timeline.poll(|position, _| reached.push(position.frame));
+        clock.restart();
+        (0..300).for_each(|_| clock.tick());
+        timeline.poll(|position, _| reached.push(position.frame));
+        assert_eq!(reached, vec![0, 250, 500, 0, 250]);
+        assert_eq!(timeline.position(), PlaybackPosition::new(300, 1000));
+    }
+
+    #[test]
+    fn cue_on_the_last_frame_is_sent() {
+        let clock = Clock::new(1, 1000);
+        let mut timeline = Timeline::new(clock.clone(), Some(&sheet()));
+        (0..500).for_each(|_| clock.tick());
+        let mut reached = Vec::new();
+        timeline.poll(|position, _| reached.push(position.frame));
+        assert_eq!(reached, vec![0, 250]);
+        clock.end();
+        timeline.poll(|position, _| reached.push(position.frame));
+        timeline.poll(|position, _| reached.push(position.frame));
+        assert_eq!(reached, vec![0, 250, 500]);
+    }
+}
@@ -0,0 +1,281 @@
+//! Presses and releases of actions over time, used for edge queries, buffering and combos.
+
+use std::{borrow::Borrow, collections::VecDeque, hash::Hash, time::Duration};
+
+use derivative::Derivative;
+use fnv::FnvHashMap as HashMap;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use super::BindingTypes;
+
+/// A sequence of actions which performs another action when pressed in order within a time
+/// window, such as down, down-forward, forward and punch for a special move.
+///
+/// Presses made in the same frame can match the steps in any order, so that a step can be a
+/// diagonal bound to a combination of buttons pressed together with another step.
+#[derive(Derivative, Serialize, Deserialize)]
+#[derivative(Debug(bound = ""), Clone(bound = ""))]
+#[serde(bound(
+    serialize = "T::Action: Serialize",
+    deserialize = "T::Action: Deserialize<'de>",
+))]
+pub struct Combo<T: BindingTypes> {
+    /// The action performed by the combo, sent with `InputEvent::ComboPerformed`.
+    pub action: T::Action,
+    /// The actions to press in order. The combo is performed when the last one is pressed.
+    pub sequence: Vec<T::Action>,
+    /// Largest time between the presses of the first and the last actions of the sequence.
+    pub window: Duration,
+}
+
+impl<T: BindingTypes> Combo<T> {
+    /// Creates a combo.
+    pub fn new(action: T::Action, sequence: Vec<T::Action>, window: Duration) -> Self {
+        Combo {
+            action,
+            sequence,
+            window,
+        }
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+struct Press<T: BindingTypes> {
+    id: u64,
+    action: T::Action,
+    frame: u64,
+    time: Duration,
+    consumed: bool,
+}
+
+/// The history of the presses and releases of actions.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+pub(crate) struct ActionHistory<T: BindingTypes> {
+    frame: u64,
+    time: Duration,
+    pub(crate) buffer_frames: u64,
+    held_since: HashMap<T::Action, Duration>,
+    just_pressed: SmallVec<[T::Action; 4]>,
+    just_released: SmallVec<[T::Action; 4]>,
+    presses: VecDeque<Press<T>>,
+    next_press: u64,
+    /// Combos with the id of the last press of their last performance.
+    combos: Vec<(Combo<T>, Option<u64>)>,
+    performed: SmallVec<[T::Action; 2]>,
+}
+
+impl<T: BindingTypes> Default for ActionHistory<T> {
+    fn default() -> Self {
+        ActionHistory {
+            frame: 0,
+            time: Duration::from_secs(0),
+            buffer_frames: 6,
+            held_since: HashMap::default(),
+            just_pressed: SmallVec::new(),
+            just_released: SmallVec::new(),
+            presses: VecDeque::new(),
+            next_press: 0,
+            combos: Vec::new(),
+            performed: SmallVec::new(),
+        }
+    }
+}
+
+impl<T: BindingTypes> ActionHistory<T> {
+    pub(crate) fn frame_begin(&mut self) {
+        self.frame += 1;
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.performed.clear();
+        let longest_combo = self
+            .combos
+            .iter()
+            .map(|(combo, _)| combo.window)
+            .max()
+            .unwrap_or_default();
+        let (frame, time, buffer_frames) = (self.frame, self.time, self.buffer_frames);
+        self.presses.retain(|press| {
+            press.frame + buffer_frames >= frame || press.time + longest_combo >= time
+        });
+    }
+
+    pub(crate) fn set_time(&mut self, time: Duration) {
+        self.time = time;
+    }
+
+    pub(crate) fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// Records a press of the action, returning the actions of the combos it completes.
+    pub(crate) fn press(&mut self, action: &T::Action) -> SmallVec<[T::Action; 2]> {
+        let time = self.time;
+        self.held_since.entry(action.clone()).or_insert(time);
+        self.just_pressed.push(action.clone());
+        self.presses.push_back(Press {
+            id: self.next_press,
+            action: action.clone(),
+            frame: self.frame,
+            time,
+            consumed: false,
+        });
+        self.next_press += 1;
+
+        let mut performed = SmallVec::new();
+        for index in 0..self.combos.len() {
+            if self.combos[index].0.sequence.last() != Some(action) {
+                continue;
+            }
+            if let Some(last) = self.match_combo(index) {
+                self.combos[index].1 = Some(last);
+                performed.push(self.combos[index].0.action.clone());
+            }
+        }
+        self.performed.extend(performed.iter().cloned());
+        performed
+    }
+
+    /// Records a release of the action, `down` telling if another binding still holds it.
+    pub(crate) fn release(&mut self, action: &T::Action, down: bool) {
+        if !down {
+            self.held_since.remove(action);
+        }
+        self.just_released.push(action.clone());
+    }
+
+    /// Returns the actions which are held.
+    pub(crate) fn held(&self) -> impl Iterator<Item = &T::Action> {
+        self.held_since.keys()
+    }
+
+    /// Forgets the held actions, e.g. when the window loses focus.
+    pub(crate) fn release_all(&mut self) {
+        self.held_since.clear();
+    }
+
+    pub(crate) fn just_pressed<A>(&self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.just_pressed.iter().any(|a| a.borrow() == action)
+    }
+
+    pub(crate) fn just_released<A>(&self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.just_released.iter().any(|a| a.borrow() == action)
+    }
+
+    pub(crate) fn held_for<A>(&self, action: &A) -> Option<Duration>
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.held_since
+            .get(action)
+            .and_then(|since| self.time.checked_sub(*since))
+    }
+
+    pub(crate) fn buffered<A>(&self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.buffered_presses(action).next().is_some()
+    }
+
+    pub(crate) fn consume_buffered<A>(&mut self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        let id = match self.buffered_presses(action).last() {
+            Some(press) => press.id,
+            None => return false,
+        };
+        for press in self.presses.iter_mut() {
+            if press.action.borrow() == action && press.id <= id {
+                press.consumed = true;
+            }
+        }
+        true
+    }
+
+    pub(crate) fn performed<A>(&self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.performed.iter().any(|a| a.borrow() == action)
+    }
+
+    pub(crate) fn add_combo(&mut self, combo: Combo<T>) {
+        self.combos.push((combo, None));
+    }
+
+    pub(crate) fn remove_combo<A>(&mut self, action: &A) -> Option<Combo<T>>
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        let index = self
+            .combos
+            .iter()
+            .position(|(combo, _)| combo.action.borrow() == action)?;
+        Some(self.combos.remove(index).0)
+    }
+
+    fn buffered_presses<'a, A>(&'a self, action: &'a A) -> impl Iterator<Item = &'a Press<T>>
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        let (frame, buffer_frames) = (self.frame, self.buffer_frames);
+        self.presses.iter().filter(move |press| {
+            !press.consumed
+                && press.frame + buffer_frames >= frame
+                && press.action.borrow() == action
+        })
+    }
+
+    /// Returns the id of the last press of the combo if the presses, ending with the last one,
+    /// perform it.
+    fn match_combo(&self, index: usize) -> Option<u64> {
+        let (combo, performed) = &self.combos[index];
+        let last = self.presses.back()?;
+        // Match the steps from the last one, each to the latest press not after the next step.
+        let mut used = SmallVec::<[usize; 8]>::new();
+        used.push(self.presses.len() - 1);
+        let mut frame = last.frame;
+        let mut first = last;
+        for step in combo.sequence.iter().rev().skip(1) {
+            let (position, press) =
+                self.presses
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find(|(position, press)| {
+                        press.frame <= frame && press.action == *step && !used.contains(position)
+                    })?;
+            used.push(position);
+            frame = press.frame;
+            first = press;
+        }
+        let in_window = first.time + combo.window >= last.time;
+        let after_performance = match performed {
+            Some(id) => first.id > *id,
+            None => true,
+        };
+        if in_window && after_performance {
+            Some(last.id)
+        } else {
+            None
+        }
+    }
+}
@@ -1,6 +1,11 @@
+use amethyst_core::math::Vector2;
+use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 
-use super::{Button, ControllerAxis, MouseAxis};
+use super::{Button, ControllerAxis, ControllerStick, MouseAxis};
+
+/// Largest number of frames the movement of the mouse can be averaged over.
+pub(crate) const MAX_MOUSE_SMOOTHING: usize = 16;
 
 /// Represents any input represented by a float value from -1 to 1.
 /// Retrieve the value of this with [axis_value](struct.InputHandler.html#method.axis_value).
@@ -25,6 +30,16 @@ pub enum Axis {
         /// Treat input values from -dead_zone to dead_zone as 0,
         /// linearly interpolate remaining ranges.
         dead_zone: f64,
+        /// How the dead zone applies to the stick this axis belongs to. Defaults to `Axial`.
+        #[serde(default)]
+        dead_zone_shape: DeadZoneShape,
+        /// Curve applied to the distance of the axis from its dead zone. Defaults to `Linear`.
+        #[serde(default)]
+        response: ResponseCurve,
+        /// Multiplier applied to the value after the response curve, which is then clamped to
+        /// [-1..1]. Defaults to 1.
+        #[serde(default = "one")]
+        sensitivity: f32,
     },
     /// Represents a mouse as a 2D input device
     Mouse {
@@ -34,6 +49,18 @@ pub enum Axis {
         over_extendable: bool,
         /// Zone to which the movement is relative
         radius: f32,
+        /// Multiplier applied to the movement. Defaults to 1.
+        #[serde(default = "one")]
+        sensitivity: f32,
+        /// Number of frames the movement is averaged over, up to 16, to smooth it. Defaults to
+        /// 1, which doesn't smooth the movement.
+        #[serde(default = "one_frame")]
+        smoothing: u32,
+        /// Increases the movement with its speed: the movement is multiplied by
+        /// `1 + acceleration * speed`, the speed being the distance the mouse moved in the frame.
+        /// Defaults to 0.
+        #[serde(default)]
+        acceleration: f32,
     },
     /// Represents the wheel on a PC mouse.
     MouseWheel {
@@ -43,3 +70,377 @@ pub enum Axis {
         horizontal: bool,
     },
 }
+
+/// Represents an input with two dimensions, such as the direction of a movement, whose value is
+/// a vector of length up to 1.
+/// Retrieve the value of this with [axis2_value](struct.InputHandler.html#method.axis2_value).
+///
+/// Unlike two separate axes, the value is normalized, so that moving diagonally isn't faster than
+/// moving straight.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub enum Axis2 {
+    /// Represents a direction emulated with four digital inputs, like the W, A, S and D keyboard
+    /// buttons or the dpad of a controller. The X value goes from `left` to `right`, and the Y
+    /// value from `down` to `up`.
+    Emulated {
+        /// Button pointing up, towards positive Y values.
+        up: Button,
+        /// Button pointing down, towards negative Y values.
+        down: Button,
+        /// Button pointing left, towards negative X values.
+        left: Button,
+        /// Button pointing right, towards positive X values.
+        right: Button,
+    },
+    /// Represents a stick of a controller. Its axes have the values of the equivalent
+    /// `Axis::Controller`s, except that the Y value is positive upwards like that of the other
+    /// 2D axes, while the Y axes of sticks are positive downwards.
+    Controller {
+        /// The controller id, as assigned by the `InputHandler`.
+        controller_id: u32,
+        /// The stick.
+        stick: ControllerStick,
+        /// Whether or not to multiply the X value by -1.
+        invert_x: bool,
+        /// Whether or not to multiply the Y value by -1.
+        invert_y: bool,
+        /// Size of the dead zone of the stick.
+        dead_zone: f64,
+        /// How the dead zone applies to the stick. Defaults to `ScaledRadial`.
+        #[serde(default = "scaled_radial")]
+        dead_zone_shape: DeadZoneShape,
+        /// Curve applied to the distance of the stick from its dead zone. Defaults to `Linear`.
+        #[serde(default)]
+        response: ResponseCurve,
+        /// Multiplier applied to the value after the response curve. Defaults to 1.
+        #[serde(default = "one")]
+        sensitivity: f32,
+    },
+    /// Represents the movement of the mouse. Its axes have the values of the equivalent
+    /// `Axis::Mouse`es.
+    Mouse {
+        /// If false, the length of the value is limited to 1.
+        over_extendable: bool,
+        /// Zone to which the movement is relative
+        radius: f32,
+        /// Multiplier applied to the movement. Defaults to 1.
+        #[serde(default = "one")]
+        sensitivity: f32,
+        /// Number of frames the movement is averaged over, up to 16. Defaults to 1.
+        #[serde(default = "one_frame")]
+        smoothing: u32,
+        /// Increases the movement with its speed, like the `acceleration` of `Axis::Mouse`.
+        /// Defaults to 0.
+        #[serde(default)]
+        acceleration: f32,
+    },
+}
+
+impl Axis2 {
+    /// Returns the X and Y axes the value is made of.
+    pub fn axes(&self) -> (Axis, Axis) {
+        match *self {
+            Axis2::Emulated {
+                up,
+                down,
+                left,
+                right,
+            } => (
+                Axis::Emulated {
+                    pos: right,
+                    neg: left,
+                },
+                Axis::Emulated { pos: up, neg: down },
+            ),
+            Axis2::Controller {
+                controller_id,
+                stick,
+                invert_x,
+                invert_y,
+                dead_zone,
+                dead_zone_shape,
+                ref response,
+                sensitivity,
+            } => {
+                let (x, y) = stick.axes();
+                let axis = |axis, invert| Axis::Controller {
+                    controller_id,
+                    axis,
+                    invert,
+                    dead_zone,
+                    dead_zone_shape,
+                    response: response.clone(),
+                    sensitivity,
+                };
+                (axis(x, invert_x), axis(y, invert_y))
+            }
+            Axis2::Mouse {
+                over_extendable,
+                radius,
+                sensitivity,
+                smoothing,
+                acceleration,
+            } => {
+                let axis = |axis| Axis::Mouse {
+                    axis,
+                    over_extendable,
+                    radius,
+                    sensitivity,
+                    smoothing,
+                    acceleration,
+                };
+                (axis(MouseAxis::X), axis(MouseAxis::Y))
+            }
+        }
+    }
+
+    /// Returns the value of the input from the values of its X and Y axes.
+    pub(crate) fn value(&self, x: f32, y: f32) -> Vector2<f32> {
+        let y = match self {
+            Axis2::Controller { .. } => -y,
+            _ => y,
+        };
+        let value = Vector2::new(x, y);
+        match *self {
+            Axis2::Mouse {
+                over_extendable: true,
+                ..
+            } => value,
+            _ => {
+                let length = value.norm();
+                if length > 1.0 {
+                    value / length
+                } else {
+                    value
+                }
+            }
+        }
+    }
+}
+
+/// How the dead zone of a controller axis applies to the stick it belongs to.
+///
+/// Triggers don't belong to a stick, so their dead zone is always `Axial`.
+#[derive(Derivative, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[derivative(Default)]
+pub enum DeadZoneShape {
+    /// Each axis of the stick has its own dead zone, which makes the dead zone a cross and the
+    /// movement of the stick feel square.
+    #[derivative(Default)]
+    Axial,
+    /// The stick is in the dead zone when its distance from the center is within the dead zone.
+    /// Values past the dead zone are not rescaled, so they start from the size of the dead zone.
+    Radial,
+    /// Like `Radial`, but the distance of the stick from the dead zone is rescaled to start
+    /// from 0, so that small movements past the dead zone give small values.
+    ScaledRadial,
+}
+
+/// How the position of a controller axis past its dead zone maps to its value.
+///
+/// The curve applies to the distance from the center, between 0 and 1, and the sign of the
+/// value is kept.
+#[derive(Derivative, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[derivative(Default)]
+pub enum ResponseCurve {
+    /// The value is the position.
+    #[derivative(Default)]
+    Linear,
+    /// The value is the position raised to the given power. Powers above 1 give finer control
+    /// near the center.
+    Exponential(f32),
+    /// The value is interpolated between the given points of (position, value), sorted by
+    /// position. The curve starts at (0, 0) and ends at (1, 1) unless points are given there.
+    Custom(Vec<(f32, f32)>),
+}
+
+impl ResponseCurve {
+    /// Returns the value of the curve at a distance from the center between 0 and 1.
+    pub fn apply(&self, position: f32) -> f32 {
+        match *self {
+            ResponseCurve::Linear => position,
+            ResponseCurve::Exponential(power) => position.powf(power),
+            ResponseCurve::Custom(ref points) => {
+                let start = match points.first() {
+                    Some(&(x, _)) if x <= 0.0 => None,
+                    _ => Some((0.0, 0.0)),
+                };
+                let end = match points.last() {
+                    Some(&(x, _)) if x >= 1.0 => None,
+                    _ => Some((1.0, 1.0)),
+                };
+                let points = start
+                    .into_iter()
+                    .chain(points.iter().cloned())
+                    .chain(end)
+                    .collect::<Vec<_>>();
+                let after = points
+                    .iter()
+                    .position(|&(x, _)| x >= position)
+                    .unwrap_or(points.len() - 1);
+                let (x1, y1) = points[after];
+                if after == 0 || x1 <= position {
+                    return y1;
+                }
+                let (x0, y0) = points[after - 1];
+                y0 + (y1 - y0) * (position - x0) / (x1 - x0)
+            }
+        }
+    }
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn one_frame() -> u32 {
+    1
+}
+
+fn scaled_radial() -> DeadZoneShape {
+    DeadZoneShape::ScaledRadial
+}
+
+/// Returns the other axis of the stick the axis belongs to.
+fn paired_axis(axis: ControllerAxis) -> Option<ControllerAxis> {
+    match axis {
+        ControllerAxis::LeftX => Some(ControllerAxis::LeftY),
+        ControllerAxis::LeftY => Some(ControllerAxis::LeftX),
+        ControllerAxis::RightX => Some(ControllerAxis::RightY),
+        ControllerAxis::RightY => Some(ControllerAxis::RightX),
+        ControllerAxis::LeftTrigger | ControllerAxis::RightTrigger => None,
+    }
+}
+
+/// Rescales a distance from the center so that the edge of the dead zone is 0.
+fn past_dead_zone(distance: f32, dead_zone: f32) -> f32 {
+    if distance <= dead_zone || dead_zone >= 1.0 {
+        0.0
+    } else {
+        ((distance - dead_zone) / (1.0 - dead_zone)).min(1.0)
+    }
+}
+
+/// The settings of an `Axis::Controller` shaping the position of the axis into its value.
+#[derive(Debug)]
+pub(crate) struct ControllerResponse<'a> {
+    pub(crate) axis: ControllerAxis,
+    pub(crate) invert: bool,
+    pub(crate) dead_zone: f64,
+    pub(crate) dead_zone_shape: DeadZoneShape,
+    pub(crate) response: &'a ResponseCurve,
+    pub(crate) sensitivity: f32,
+}
+
+impl<'a> ControllerResponse<'a> {
+    /// Returns the other axis of the stick, used by radial dead zones.
+    pub(crate) fn paired_axis(&self) -> Option<ControllerAxis> {
+        match self.dead_zone_shape {
+            DeadZoneShape::Axial => None,
+            DeadZoneShape::Radial | DeadZoneShape::ScaledRadial => paired_axis(self.axis),
+        }
+    }
+
+    /// Returns the value of the axis from its position and that of the other axis of its stick.
+    pub(crate) fn value(&self, position: f32, paired: Option<f32>) -> f32 {
+        let position = if self.invert { -position } else { position };
+        let dead_zone = self.dead_zone as f32;
+        let value = match (self.dead_zone_shape, paired) {
+            (DeadZoneShape::Radial, Some(paired)) => {
+                let distance = position.hypot(paired);
+                if distance <= dead_zone {
+                    0.0
+                } else {
+                    position * self.response.apply(distance.min(1.0)) / distance
+                }
+            }
+            (DeadZoneShape::ScaledRadial, Some(paired)) => {
+                let distance = position.hypot(paired);
+                if distance <= dead_zone {
+                    0.0
+                } else {
+                    let scaled = past_dead_zone(distance, dead_zone);
+                    position * self.response.apply(scaled) / distance
+                }
+            }
+            _ => {
+                let distance = position.abs();
+                // Checked before the curve, which may not be 0 at rest.
+                if distance <= dead_zone || dead_zone >= 1.0 {
+                    0.0
+                } else {
+                    let scaled = past_dead_zone(distance, dead_zone);
+                    position.signum() * self.response.apply(scaled)
+                }
+            }
+        };
+        (value * self.sensitivity).clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::assert_ulps_eq;
+
+    fn response(shape: DeadZoneShape, curve: &ResponseCurve) -> ControllerResponse<'_> {
+        ControllerResponse {
+            axis: ControllerAxis::LeftX,
+            invert: false,
+            dead_zone: 0.2,
+            dead_zone_shape: shape,
+            response: curve,
+            sensitivity: 1.0,
+        }
+    }
+
+    #[test]
+    fn dead_zone_shapes() {
+        let linear = ResponseCurve::Linear;
+        let axial = response(DeadZoneShape::Axial, &linear);
+        assert_eq!(axial.paired_axis(), None);
+        assert_ulps_eq!(axial.value(0.1, None), 0.0);
+        assert_ulps_eq!(axial.value(-0.6, None), -0.5);
+
+        let radial = response(DeadZoneShape::Radial, &linear);
+        assert_eq!(radial.paired_axis(), Some(ControllerAxis::LeftY));
+        // Diagonal movements within the dead zone of each axis leave the dead zone.
+        assert_ulps_eq!(radial.value(0.15, Some(0.0)), 0.0);
+        assert_ulps_eq!(radial.value(0.15, Some(0.15)), 0.15);
+
+        let scaled = response(DeadZoneShape::ScaledRadial, &linear);
+        assert_ulps_eq!(scaled.value(0.0, Some(0.6)), 0.0);
+        assert_ulps_eq!(scaled.value(0.6, Some(0.0)), 0.5);
+        assert_ulps_eq!(scaled.value(-0.36, Some(0.48)), -0.36 * 0.5 / 0.6);
+    }
+
+    #[test]
+    fn response_curves_and_sensitivity() {
+        let exponential = ResponseCurve::Exponential(2.0);
+        let mut axis = response(DeadZoneShape::Axial, &exponential);
+        assert_ulps_eq!(axis.value(-0.6, None), -0.25);
+        axis.sensitivity = 3.0;
+        axis.invert = true;
+        assert_ulps_eq!(axis.value(-0.6, None), 0.75);
+        assert_ulps_eq!(axis.value(-1.0, None), 1.0);
+
+        let custom = ResponseCurve::Custom(vec![(0.5, 0.2)]);
+        assert_ulps_eq!(custom.apply(0.25), 0.1);
+        assert_ulps_eq!(custom.apply(0.75), 0.6);
+        assert_ulps_eq!(custom.apply(1.0), 1.0);
+        assert_ulps_eq!(ResponseCurve::Custom(vec![(0.0, 0.5)]).apply(0.0), 0.5);
+
+        // The value at rest is 0 whatever the curve.
+        let offset = ResponseCurve::Custom(vec![(0.0, 0.5)]);
+        assert_ulps_eq!(
+            response(DeadZoneShape::Axial, &offset).value(0.0, None),
+            0.0
+        );
+        let flat = ResponseCurve::Exponential(0.0);
+        let mut axis = response(DeadZoneShape::Axial, &flat);
+        axis.dead_zone = 0.0;
+        assert_ulps_eq!(axis.value(0.0, None), 0.0);
+        assert_ulps_eq!(axis.value(0.5, None), 1.0);
+    }
+}
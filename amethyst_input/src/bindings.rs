@@ -5,14 +5,18 @@ use std::{
     error::Error,
     fmt::{Debug, Display, Formatter, Result as FmtResult},
     hash::Hash,
+    iter,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
+use amethyst_config::Config;
 use derivative::Derivative;
 use fnv::FnvHashMap as HashMap;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 
-use super::{Axis, Button};
+use super::{Axis, Axis2, BindingsFileError, Button};
 
 /// Define a set of types used for bindings configuration.
 /// Usually defaulted to `StringBindings`, which uses `String`s.
@@ -100,6 +104,9 @@ impl BindingTypes for StringBindings {
 ///             neg: Key(Left)
 ///         )
 ///     },
+///     axes2: {
+///         "move": Emulated(up: Key(W), down: Key(S), left: Key(A), right: Key(D)),
+///     },
 ///     actions: {
 ///         "fire": [ [Mouse(Left)], [Key(X)] ], // Multiple bindings for one action
 ///         "reload": [ [Key(LControl), Key(R)] ] // Combinations of multiple bindings possible
@@ -114,11 +121,27 @@ impl BindingTypes for StringBindings {
 ))]
 pub struct Bindings<T: BindingTypes> {
     pub(super) axes: HashMap<T::Axis, Axis>,
+    /// Ids of 2D axes are separate from those of axes, so an axis and a 2D axis can have the same.
+    #[serde(default)]
+    pub(super) axes2: HashMap<T::Axis, Axis2>,
     /// The inner array here is for button combinations, the other is for different possibilities.
     ///
     /// So for example if you want to quit by either "Esc" or "Ctrl+q" you would have
     /// `[[Esc], [Ctrl, Q]]`.
     pub(super) actions: HashMap<T::Action, SmallVec<[SmallVec<[Button; 2]>; 4]>>,
+    /// Changes whenever the bindings change, so that the `InputHandler` knows when to look up the
+    /// actions hidden by its input contexts again.
+    #[serde(skip, default = "next_revision")]
+    #[derivative(Debug = "ignore", Default(value = "next_revision()"))]
+    revision: u64,
+}
+
+/// Last revision given to bindings, so that two bindings never have the same revision unless one
+/// is a clone of the other.
+static LAST_REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    LAST_REVISION.fetch_add(1, Ordering::Relaxed) + 1
 }
 
 /// An enum of possible errors that can occur when binding an action or axis.
@@ -224,6 +247,32 @@ where
 {
 }
 
+/// A binding which conflicts with the binding of an action or axis, returned by
+/// [action_conflicts](struct.Bindings.html#method.action_conflicts) and
+/// [axis_conflicts](struct.Bindings.html#method.axis_conflicts).
+#[derive(Clone, Derivative)]
+#[derivative(Debug(bound = ""))]
+pub enum BindingConflict<T: BindingTypes> {
+    /// The binding can't be inserted, it would fail with this error.
+    Invalid(BindingError<T>),
+    /// The button is part of a binding of another action, so that both actions are triggered
+    /// together.
+    Action(T::Action, Button),
+    /// The button is used by an emulated axis, which moves along with the action.
+    Axis(T::Axis, Button),
+}
+
+impl<T: BindingTypes> PartialEq for BindingConflict<T> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BindingConflict::Invalid(a), BindingConflict::Invalid(x)) => a == x,
+            (BindingConflict::Action(a, b), BindingConflict::Action(x, y)) => a == x && b == y,
+            (BindingConflict::Axis(a, b), BindingConflict::Axis(x, y)) => a == x && b == y,
+            (_, _) => false,
+        }
+    }
+}
+
 /// An enum of possible errors that can occur when removing an action binding.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ActionRemovedError {
@@ -270,6 +319,7 @@ impl<T: BindingTypes> Bindings<T> {
     ) -> Result<Option<Axis>, BindingError<T>> {
         let id = id.into();
         self.check_axis_invariants(&id, &axis)?;
+        self.revision = next_revision();
         Ok(self.axes.insert(id, axis))
     }
 
@@ -279,6 +329,7 @@ impl<T: BindingTypes> Bindings<T> {
         T::Axis: Borrow<A>,
         A: Hash + Eq + ?Sized,
     {
+        self.revision = next_revision();
         self.axes.remove(id)
     }
 
@@ -291,11 +342,71 @@ impl<T: BindingTypes> Bindings<T> {
         self.axes.get(id)
     }
 
+    /// Returns a number which changes whenever the bindings change.
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+
     /// Gets a list of all axes
     pub fn axes(&self) -> impl Iterator<Item = &T::Axis> {
         self.axes.keys()
     }
 
+    /// Assign a 2D axis to an ID value
+    ///
+    /// This will insert a new 2D axis if no entry for this id exists.
+    /// If one does exist this will replace the 2D axis at that id and return it.
+    pub fn insert_axis2<A: Into<T::Axis>>(
+        &mut self,
+        id: A,
+        axis: Axis2,
+    ) -> Result<Option<Axis2>, BindingError<T>> {
+        let id = id.into();
+        self.check_axis2_invariants(&id, &axis)?;
+        self.revision = next_revision();
+        Ok(self.axes2.insert(id, axis))
+    }
+
+    /// Removes a 2D axis, this will return the removed 2D axis if successful.
+    pub fn remove_axis2<A>(&mut self, id: &A) -> Option<Axis2>
+    where
+        T::Axis: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.revision = next_revision();
+        self.axes2.remove(id)
+    }
+
+    /// Returns a reference to a 2D axis.
+    pub fn axis2<A>(&self, id: &A) -> Option<&Axis2>
+    where
+        T::Axis: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.axes2.get(id)
+    }
+
+    /// Gets a list of all 2D axes
+    pub fn axes2(&self) -> impl Iterator<Item = &T::Axis> {
+        self.axes2.keys()
+    }
+
+    /// Returns the X and Y axes of the 2D axes, with the ids of their 2D axis.
+    pub(crate) fn axis2_components(&self) -> impl Iterator<Item = (&T::Axis, Axis)> {
+        self.axes2.iter().flat_map(|(k, a)| {
+            let (x, y) = a.axes();
+            iter::once((k, x)).chain(iter::once((k, y)))
+        })
+    }
+
+    /// Returns the axes and the X and Y axes of the 2D axes, with their ids.
+    pub(crate) fn bound_axes(&self) -> impl Iterator<Item = (&T::Axis, Axis)> {
+        self.axes
+            .iter()
+            .map(|(k, a)| (k, a.clone()))
+            .chain(self.axis2_components())
+    }
+
     /// Add a button or button combination to an action.
     ///
     /// This will attempt to insert a new binding between this action and the button(s).
@@ -306,6 +417,7 @@ impl<T: BindingTypes> Bindings<T> {
     ) -> Result<(), BindingError<T>> {
         let bind: SmallVec<[Button; 2]> = binding.into_iter().collect();
         self.check_action_invariants(&id, bind.as_slice())?;
+        self.revision = next_revision();
         let mut make_new = false;
         match self.actions.get_mut(&id) {
             Some(action_bindings) => {
@@ -340,6 +452,7 @@ impl<T: BindingTypes> Bindings<T> {
                 }
             }
         }
+        self.revision = next_revision();
         let kill_it;
         if let Some(action_bindings) = self.actions.get_mut(id) {
             let index = action_bindings.iter().position(|b| {
@@ -382,6 +495,79 @@ impl<T: BindingTypes> Bindings<T> {
         self.actions.keys()
     }
 
+    /// Returns the conflicts of a new binding for an action with the existing bindings.
+    ///
+    /// Besides the `BindingError` inserting it would return, this reports the other actions and
+    /// axes sharing one of its buttons. Bindings of the action itself are ignored, so that they can
+    /// be replaced after the new binding is inserted.
+    pub fn action_conflicts(&self, id: &T::Action, binding: &[Button]) -> Vec<BindingConflict<T>> {
+        let mut conflicts = Vec::new();
+        if let Err(e) = self.check_action_invariants(id, binding) {
+            conflicts.push(BindingConflict::Invalid(e));
+        }
+        for (k, a) in self.actions.iter().filter(|(k, _a)| *k != id) {
+            for c in a.iter().filter(|c| !same_combo(c, binding)) {
+                for button in binding.iter().filter(|b| c.contains(b)) {
+                    let conflict = BindingConflict::Action(k.clone(), *button);
+                    if !conflicts.contains(&conflict) {
+                        conflicts.push(conflict);
+                    }
+                }
+            }
+        }
+        // A single button bound to an axis is already an invalid binding.
+        if binding.len() > 1 {
+            for (k, a) in self.bound_axes() {
+                if let Axis::Emulated { pos, neg } = a {
+                    for button in binding.iter().filter(|b| **b == pos || **b == neg) {
+                        conflicts.push(BindingConflict::Axis(k.clone(), *button));
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Returns the conflicts of a new binding for an axis with the existing bindings.
+    ///
+    /// Besides the `BindingError` inserting it would return, this reports the actions bound to
+    /// a combination containing one of the buttons of an emulated axis. The current binding of the
+    /// axis itself is ignored, as inserting the new binding replaces it.
+    pub fn axis_conflicts(&self, id: &T::Axis, axis: &Axis) -> Vec<BindingConflict<T>> {
+        let mut conflicts = Vec::new();
+        if let Err(e) = self.check_axis_invariants(id, axis) {
+            conflicts.push(BindingConflict::Invalid(e));
+        }
+        if let Axis::Emulated { pos, neg } = axis {
+            for (k, a) in self.actions.iter() {
+                // Combos of length one are already invalid.
+                for c in a.iter().filter(|c| c.len() > 1) {
+                    for button in [pos, neg].iter().filter(|b| c.contains(b)) {
+                        let conflict = BindingConflict::Action(k.clone(), **button);
+                        if !conflicts.contains(&conflict) {
+                            conflicts.push(conflict);
+                        }
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Writes the bindings to a RON file, which can be loaded again with
+    /// [with_bindings_from_file](struct.InputBundle.html#method.with_bindings_from_file).
+    ///
+    /// The bindings are checked before being written, so that a file which would fail to load
+    /// isn't written.
+    pub fn save<P: AsRef<Path>>(&self, file: P) -> Result<(), BindingsFileError<T>>
+    where
+        Self: Config,
+    {
+        self.clone().check_invariants()?;
+        self.write(file)?;
+        Ok(())
+    }
+
     /// Check that this structure upholds its guarantees. Should only be necessary when serializing or deserializing the bindings.
     pub fn check_invariants(&mut self) -> Result<(), BindingError<T>> {
         // The easiest way to do this is to use the existing code that checks for invariants when adding bindings.
@@ -408,6 +594,15 @@ impl<T: BindingTypes> Bindings<T> {
             self.remove_axis(&k);
             self.insert_axis(k, a)?;
         }
+        let axis2_bindings = self
+            .axes2
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        for (k, a) in axis2_bindings {
+            self.remove_axis2(&k);
+            self.insert_axis2(k, a)?;
+        }
         Ok(())
     }
 
@@ -425,10 +620,10 @@ impl<T: BindingTypes> Bindings<T> {
             }
         }
         if bind.len() == 1 {
-            for (k, a) in self.axes.iter() {
+            for (k, a) in self.bound_axes() {
                 if let Axis::Emulated { pos, neg } = a {
-                    if bind[0] == *pos || bind[0] == *neg {
-                        return Err(BindingError::ButtonBoundToAxis(k.clone(), a.clone()));
+                    if bind[0] == pos || bind[0] == neg {
+                        return Err(BindingError::ButtonBoundToAxis(k.clone(), a));
                     }
                 }
             }
@@ -444,19 +639,54 @@ impl<T: BindingTypes> Bindings<T> {
     }
 
     fn check_axis_invariants(&self, id: &T::Axis, axis: &Axis) -> Result<(), BindingError<T>> {
+        let others = self
+            .axes
+            .iter()
+            .filter(|(k, _a)| *k != id)
+            .map(|(k, a)| (k, a.clone()))
+            .chain(self.axis2_components());
+        self.check_axis_against(axis, others)
+    }
+
+    fn check_axis2_invariants(&self, id: &T::Axis, axis: &Axis2) -> Result<(), BindingError<T>> {
+        let (x, y) = axis.axes();
+        // The X and Y axes can't share a button.
+        self.check_axis_against(&y, iter::once((id, x.clone())))?;
+        for component in [x, y].iter() {
+            let others = self.axes.iter().map(|(k, a)| (k, a.clone())).chain(
+                self.axes2
+                    .iter()
+                    .filter(|(k, _a)| *k != id)
+                    .flat_map(|(k, a)| {
+                        let (x, y) = a.axes();
+                        iter::once((k, x)).chain(iter::once((k, y)))
+                    }),
+            );
+            self.check_axis_against(component, others)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the axis doesn't use the input of one of the other axes, nor a button bound to
+    /// an action on its own.
+    fn check_axis_against<'a>(
+        &'a self,
+        axis: &Axis,
+        others: impl Iterator<Item = (&'a T::Axis, Axis)>,
+    ) -> Result<(), BindingError<T>> {
         match axis {
             Axis::Emulated {
                 pos: ref axis_pos,
                 neg: ref axis_neg,
             } => {
-                for (k, a) in self.axes.iter().filter(|(k, _a)| *k != id) {
+                for (k, a) in others {
                     if let Axis::Emulated { pos, neg } = a {
-                        if axis_pos == pos || axis_pos == neg || axis_neg == pos || axis_neg == neg
+                        if *axis_pos == pos
+                            || *axis_pos == neg
+                            || *axis_neg == pos
+                            || *axis_neg == neg
                         {
-                            return Err(BindingError::AxisButtonAlreadyBoundToAxis(
-                                k.clone(),
-                                a.clone(),
-                            ));
+                            return Err(BindingError::AxisButtonAlreadyBoundToAxis(k.clone(), a));
                         }
                     }
                 }
@@ -477,26 +707,26 @@ impl<T: BindingTypes> Bindings<T> {
                 axis: ref input_axis,
                 ..
             } => {
-                for (k, a) in self.axes.iter().filter(|(k, _a)| *k != id) {
+                for (k, a) in others {
                     if let Axis::Controller {
                         controller_id,
                         axis,
                         ..
                     } = a
                     {
-                        if controller_id == input_controller_id && axis == input_axis {
+                        if controller_id == *input_controller_id && axis == *input_axis {
                             return Err(BindingError::ControllerAxisAlreadyBound(k.clone()));
                         }
                     }
                 }
             }
             Axis::Mouse { axis, .. } => {
-                for (k, a) in self.axes.iter().filter(|(k, _a)| *k != id) {
+                for (k, a) in others {
                     if let Axis::Mouse {
                         axis: mouse_axis, ..
                     } = a
                     {
-                        if axis == mouse_axis {
+                        if *axis == mouse_axis {
                             return Err(BindingError::MouseAxisAlreadyBound(k.clone()));
                         }
                     }
@@ -505,9 +735,9 @@ impl<T: BindingTypes> Bindings<T> {
             Axis::MouseWheel {
                 horizontal: ref input_horizontal,
             } => {
-                for (k, a) in self.axes.iter().filter(|(k, _a)| *k != id) {
+                for (k, a) in others {
                     if let Axis::MouseWheel { horizontal } = a {
-                        if input_horizontal == horizontal {
+                        if *input_horizontal == horizontal {
                             return Err(BindingError::MouseWheelAxisAlreadyBound(k.clone()));
                         }
                     }
@@ -518,10 +748,19 @@ impl<T: BindingTypes> Bindings<T> {
     }
 }
 
+/// Returns true if both combinations have the same buttons, in any order.
+fn same_combo(a: &[Button], b: &[Button]) -> bool {
+    a.len() == b.len() && b.iter().all(|b| a.contains(b))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{button::*, controller::ControllerAxis};
+    use crate::{
+        button::*,
+        controller::{ControllerAxis, ControllerStick},
+        DeadZoneShape, ResponseCurve,
+    };
     use winit::{MouseButton, VirtualKeyCode};
 
     #[test]
@@ -766,6 +1005,9 @@ mod tests {
                         axis: ControllerAxis::RightX,
                         invert: false,
                         dead_zone: 0.25,
+                        dead_zone_shape: DeadZoneShape::Axial,
+                        response: ResponseCurve::Linear,
+                        sensitivity: 1.0,
                     },
                 )
                 .unwrap(),
@@ -780,6 +1022,9 @@ mod tests {
                         axis: ControllerAxis::LeftX,
                         invert: false,
                         dead_zone: 0.25,
+                        dead_zone_shape: DeadZoneShape::Axial,
+                        response: ResponseCurve::Linear,
+                        sensitivity: 1.0,
                     },
                 )
                 .unwrap(),
@@ -788,6 +1033,9 @@ mod tests {
                 axis: ControllerAxis::RightX,
                 invert: false,
                 dead_zone: 0.25,
+                dead_zone_shape: DeadZoneShape::Axial,
+                response: ResponseCurve::Linear,
+                sensitivity: 1.0,
             })
         );
         assert_eq!(
@@ -799,6 +1047,9 @@ mod tests {
                         axis: ControllerAxis::LeftX,
                         invert: true,
                         dead_zone: 0.1,
+                        dead_zone_shape: DeadZoneShape::Axial,
+                        response: ResponseCurve::Linear,
+                        sensitivity: 1.0,
                     },
                 )
                 .unwrap_err(),
@@ -864,6 +1115,9 @@ mod tests {
                         axis: ControllerAxis::RightX,
                         invert: false,
                         dead_zone: 0.25,
+                        dead_zone_shape: DeadZoneShape::Axial,
+                        response: ResponseCurve::Linear,
+                        sensitivity: 1.0,
                     },
                 )
                 .unwrap(),
@@ -876,6 +1130,9 @@ mod tests {
                 axis: ControllerAxis::RightX,
                 invert: false,
                 dead_zone: 0.25,
+                dead_zone_shape: DeadZoneShape::Axial,
+                response: ResponseCurve::Linear,
+                sensitivity: 1.0,
             })
         );
         assert_eq!(
@@ -892,4 +1149,260 @@ mod tests {
             Some(Axis::MouseWheel { horizontal: false })
         );
     }
+
+    #[test]
+    fn reports_binding_conflicts() {
+        let mut bindings = Bindings::<StringBindings>::new();
+        bindings
+            .insert_axis(
+                String::from("updown"),
+                Axis::Emulated {
+                    pos: Button::Key(VirtualKeyCode::W),
+                    neg: Button::Key(VirtualKeyCode::S),
+                },
+            )
+            .unwrap();
+        bindings
+            .insert_action_binding(
+                String::from("reload"),
+                [
+                    Button::Key(VirtualKeyCode::LControl),
+                    Button::Key(VirtualKeyCode::R),
+                ]
+                .iter()
+                .cloned(),
+            )
+            .unwrap();
+        bindings
+            .insert_action_binding(
+                String::from("fire"),
+                [Button::Mouse(MouseButton::Left)].iter().cloned(),
+            )
+            .unwrap();
+
+        let jump = String::from("jump");
+        assert_eq!(
+            bindings.action_conflicts(&jump, &[Button::Key(VirtualKeyCode::Space)]),
+            vec![]
+        );
+        assert_eq!(
+            bindings.action_conflicts(&jump, &[Button::Key(VirtualKeyCode::R)]),
+            vec![BindingConflict::Action(
+                String::from("reload"),
+                Button::Key(VirtualKeyCode::R)
+            )]
+        );
+        assert_eq!(
+            bindings.action_conflicts(&jump, &[Button::Mouse(MouseButton::Left)]),
+            vec![BindingConflict::Invalid(BindingError::ComboAlreadyBound(
+                String::from("fire")
+            ))]
+        );
+        assert_eq!(
+            bindings.action_conflicts(
+                &jump,
+                &[
+                    Button::Key(VirtualKeyCode::LControl),
+                    Button::Key(VirtualKeyCode::S)
+                ]
+            ),
+            vec![
+                BindingConflict::Action(
+                    String::from("reload"),
+                    Button::Key(VirtualKeyCode::LControl)
+                ),
+                BindingConflict::Axis(String::from("updown"), Button::Key(VirtualKeyCode::S)),
+            ]
+        );
+        // The bindings of the action being rebound don't conflict.
+        assert_eq!(
+            bindings.action_conflicts(&String::from("reload"), &[Button::Key(VirtualKeyCode::R)]),
+            vec![]
+        );
+
+        let leftright = String::from("leftright");
+        assert_eq!(
+            bindings.axis_conflicts(
+                &leftright,
+                &Axis::Emulated {
+                    pos: Button::Key(VirtualKeyCode::R),
+                    neg: Button::Mouse(MouseButton::Left),
+                }
+            ),
+            vec![
+                BindingConflict::Invalid(BindingError::AxisButtonAlreadyBoundToAction(
+                    String::from("fire"),
+                    Button::Mouse(MouseButton::Left)
+                )),
+                BindingConflict::Action(String::from("reload"), Button::Key(VirtualKeyCode::R)),
+            ]
+        );
+        assert_eq!(
+            bindings.axis_conflicts(
+                &String::from("updown"),
+                &Axis::Emulated {
+                    pos: Button::Key(VirtualKeyCode::Up),
+                    neg: Button::Key(VirtualKeyCode::S),
+                }
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn add_and_remove_axes2() {
+        let mut bindings = Bindings::<StringBindings>::new();
+        let wasd = Axis2::Emulated {
+            up: Button::Key(VirtualKeyCode::W),
+            down: Button::Key(VirtualKeyCode::S),
+            left: Button::Key(VirtualKeyCode::A),
+            right: Button::Key(VirtualKeyCode::D),
+        };
+        let stick = Axis2::Controller {
+            controller_id: 0,
+            stick: ControllerStick::Left,
+            invert_x: false,
+            invert_y: false,
+            dead_zone: 0.1,
+            dead_zone_shape: DeadZoneShape::ScaledRadial,
+            response: ResponseCurve::Linear,
+            sensitivity: 1.0,
+        };
+        assert_eq!(bindings.insert_axis2("move", wasd.clone()), Ok(None));
+        assert_eq!(bindings.insert_axis2("look", stick.clone()), Ok(None));
+        // The same id can be used by an axis.
+        assert_eq!(
+            bindings.insert_axis(
+                "move",
+                Axis::Emulated {
+                    pos: Button::Key(VirtualKeyCode::Up),
+                    neg: Button::Key(VirtualKeyCode::Down),
+                }
+            ),
+            Ok(None)
+        );
+        assert_eq!(bindings.axes2().count(), 2);
+        assert_eq!(bindings.axis2("move"), Some(&wasd));
+
+        let vertical = Axis::Emulated {
+            pos: Button::Key(VirtualKeyCode::W),
+            neg: Button::Key(VirtualKeyCode::S),
+        };
+        assert_eq!(
+            bindings.insert_axis("vertical", vertical),
+            Err(BindingError::AxisButtonAlreadyBoundToAxis(
+                String::from("move"),
+                Axis::Emulated {
+                    pos: Button::Key(VirtualKeyCode::W),
+                    neg: Button::Key(VirtualKeyCode::S),
+                }
+            ))
+        );
+        assert_eq!(
+            bindings.insert_action_binding(
+                String::from("jump"),
+                [Button::Key(VirtualKeyCode::A)].iter().cloned()
+            ),
+            Err(BindingError::ButtonBoundToAxis(
+                String::from("move"),
+                Axis::Emulated {
+                    pos: Button::Key(VirtualKeyCode::D),
+                    neg: Button::Key(VirtualKeyCode::A),
+                }
+            ))
+        );
+        assert_eq!(
+            bindings.insert_axis(
+                "steer",
+                Axis::Controller {
+                    controller_id: 0,
+                    axis: ControllerAxis::LeftY,
+                    invert: false,
+                    dead_zone: 0.1,
+                    dead_zone_shape: DeadZoneShape::Axial,
+                    response: ResponseCurve::Linear,
+                    sensitivity: 1.0,
+                }
+            ),
+            Err(BindingError::ControllerAxisAlreadyBound(String::from(
+                "look"
+            )))
+        );
+        assert_eq!(
+            bindings.insert_axis2(
+                "aim",
+                Axis2::Emulated {
+                    up: Button::Key(VirtualKeyCode::I),
+                    down: Button::Key(VirtualKeyCode::K),
+                    left: Button::Key(VirtualKeyCode::J),
+                    right: Button::Key(VirtualKeyCode::I),
+                }
+            ),
+            Err(BindingError::AxisButtonAlreadyBoundToAxis(
+                String::from("aim"),
+                Axis::Emulated {
+                    pos: Button::Key(VirtualKeyCode::I),
+                    neg: Button::Key(VirtualKeyCode::J),
+                }
+            ))
+        );
+        // Replacing a 2D axis doesn't conflict with itself.
+        assert_eq!(
+            bindings.insert_axis2("look", stick.clone()),
+            Ok(Some(stick.clone()))
+        );
+        assert_eq!(bindings.check_invariants(), Ok(()));
+
+        assert_eq!(bindings.remove_axis2("look"), Some(stick));
+        assert_eq!(bindings.remove_axis2("look"), None);
+        assert_eq!(bindings.axes2().collect::<Vec<_>>(), vec!["move"]);
+    }
+
+    #[test]
+    fn loads_axes2_with_default_settings() {
+        let bindings = Bindings::<StringBindings>::load_bytes(
+            br#"(
+                axes: {},
+                actions: {},
+                axes2: {
+                    "aim": Mouse(over_extendable: false, radius: 10.0),
+                    "move": Controller(
+                        controller_id: 0,
+                        stick: Left,
+                        invert_x: false,
+                        invert_y: true,
+                        dead_zone: 0.1,
+                    ),
+                },
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(
+            bindings.axis2("aim"),
+            Some(&Axis2::Mouse {
+                over_extendable: false,
+                radius: 10.0,
+                sensitivity: 1.0,
+                smoothing: 1,
+                acceleration: 0.0,
+            })
+        );
+        assert_eq!(
+            bindings.axis2("move"),
+            Some(&Axis2::Controller {
+                controller_id: 0,
+                stick: ControllerStick::Left,
+                invert_x: false,
+                invert_y: true,
+                dead_zone: 0.1,
+                dead_zone_shape: DeadZoneShape::ScaledRadial,
+                response: ResponseCurve::Linear,
+                sensitivity: 1.0,
+            })
+        );
+
+        // Bindings without 2D axes are still valid.
+        let bindings = Bindings::<StringBindings>::load_bytes(b"(axes: {}, actions: {})").unwrap();
+        assert_eq!(bindings.axes2().count(), 0);
+    }
 }
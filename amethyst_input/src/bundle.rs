@@ -12,6 +12,8 @@ use std::{error, fmt, path::Path};
 
 #[cfg(feature = "sdl_controller")]
 use crate::sdl_events_system::ControllerMappings;
+#[cfg(feature = "native_controller")]
+use crate::{GamepadEventsSystemDesc, GamepadMappingError, GamepadMappings};
 
 /// Bundle for adding the `InputHandler`.
 ///
@@ -26,7 +28,9 @@ use crate::sdl_events_system::ControllerMappings;
 ///
 /// ## Errors
 ///
-/// No errors returned from this bundle.
+/// With the `native_controller` feature, fails if the gamepad mappings of the
+/// `SDL_GAMECONTROLLERCONFIG_FILE` or `SDL_GAMECONTROLLERCONFIG` environment variables can't be
+/// read.
 ///
 #[derive(Debug, Derivative)]
 #[derivative(Default(bound = ""))]
@@ -34,6 +38,8 @@ pub struct InputBundle<T: BindingTypes> {
     bindings: Option<Bindings<T>>,
     #[cfg(feature = "sdl_controller")]
     controller_mappings: Option<ControllerMappings>,
+    #[cfg(feature = "native_controller")]
+    gamepad_mappings: Option<GamepadMappings>,
 }
 
 impl<T: BindingTypes> InputBundle<T> {
@@ -77,6 +83,26 @@ impl<T: BindingTypes> InputBundle<T> {
         self.controller_mappings = Some(ControllerMappings::FromPath(path_buf));
         self
     }
+
+    /// Use the provided gamepad mappings instead of the default ones with the native gamepad
+    /// backend
+    #[cfg(feature = "native_controller")]
+    pub fn with_gamepad_mappings(mut self, mappings: GamepadMappings) -> Self {
+        self.gamepad_mappings = Some(mappings);
+        self
+    }
+
+    /// Load gamepad mappings from a file, such as the `gamecontrollerdb.txt` of the SDL game
+    /// controller database, on top of those of common gamepads
+    #[cfg(feature = "native_controller")]
+    pub fn with_gamepad_mappings_from_file<P: AsRef<Path>>(
+        mut self,
+        file: P,
+    ) -> Result<Self, GamepadMappingError> {
+        let mut mappings = self.gamepad_mappings.take().unwrap_or_default();
+        mappings.add_mappings_from_file(file)?;
+        Ok(self.with_gamepad_mappings(mappings))
+    }
 }
 
 impl<'a, 'b, T: BindingTypes> SystemBundle<'a, 'b> for InputBundle<T> {
@@ -98,6 +124,16 @@ impl<'a, 'b, T: BindingTypes> SystemBundle<'a, 'b> for InputBundle<T> {
             "input_system",
             &[],
         );
+        #[cfg(feature = "native_controller")]
+        {
+            let mut mappings = self.gamepad_mappings.unwrap_or_default();
+            mappings.add_mappings_from_env().map_err(Error::new)?;
+            builder.add(
+                GamepadEventsSystemDesc::<T>::new(Some(mappings)).build(world),
+                "gamepad_events_system",
+                &["input_system"],
+            );
+        }
         Ok(())
     }
 }
@@ -149,3 +185,52 @@ impl<T: BindingTypes> From<ConfigError> for BindingsFileError<T> {
         BindingsFileError::ConfigError(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::{Axis, Button, StringBindings};
+    use winit::VirtualKeyCode;
+
+    #[test]
+    fn saved_bindings_load_again() {
+        let mut bindings = Bindings::<StringBindings>::new();
+        bindings
+            .insert_axis(
+                String::from("updown"),
+                Axis::Emulated {
+                    pos: Button::Key(VirtualKeyCode::W),
+                    neg: Button::Key(VirtualKeyCode::S),
+                },
+            )
+            .unwrap();
+        bindings
+            .insert_action_binding(
+                String::from("reload"),
+                [
+                    Button::Key(VirtualKeyCode::LControl),
+                    Button::Key(VirtualKeyCode::R),
+                ]
+                .iter()
+                .cloned(),
+            )
+            .unwrap();
+
+        let path = env::temp_dir().join(format!("amethyst_input_{}.ron", std::process::id()));
+        bindings.save(&path).unwrap();
+        let loaded = InputBundle::<StringBindings>::new()
+            .with_bindings_from_file(&path)
+            .unwrap()
+            .bindings
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.axis("updown"), bindings.axis("updown"));
+        assert_eq!(
+            loaded.action_bindings("reload").collect::<Vec<_>>(),
+            bindings.action_bindings("reload").collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use winit::{MouseButton, VirtualKeyCode};
 
-use super::{controller::ControllerButton, scroll_direction::ScrollDirection};
+use super::{controller::ControllerButton, scroll_direction::ScrollDirection, touch::Gesture};
 
 /// A Button is any kind of digital input that the engine supports.
 #[derive(Eq, PartialEq, Debug, Copy, Clone, Hash, Serialize, Deserialize)]
@@ -24,6 +24,13 @@ pub enum Button {
     /// A tuple of sequential controller_id in order of connection
     /// and specific type of used controller button.
     Controller(u32, ControllerButton),
+
+    /// Down while at least one finger touches the screen.
+    Touch,
+
+    /// A touch gesture, pressed and released at once when recognized.
+    /// (Do not use these with an emulated axis.)
+    Gesture(Gesture),
 }
 
 impl From<VirtualKeyCode> for Button {
@@ -0,0 +1,124 @@
+//! Recording of the next input, used by menus letting players rebind actions and axes.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Axis, Button, ControllerAxis, DeadZoneShape, MouseAxis, ResponseCurve, ScrollDirection,
+};
+
+/// Distance a controller axis must move from its rest position to be captured.
+const CONTROLLER_AXIS_THRESHOLD: f32 = 0.5;
+
+/// Distance the mouse must move along an axis to be captured. Also used as the radius of the
+/// captured `Axis::Mouse`.
+const MOUSE_THRESHOLD: f32 = 50.0;
+
+/// Dead zone of a captured `Axis::Controller`.
+const CAPTURED_DEAD_ZONE: f64 = 0.1;
+
+/// The inputs recorded by a capture started with
+/// [start_capture](struct.InputHandler.html#method.start_capture).
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum CaptureKind {
+    /// Records the next pressed button, to be bound to an action or to a side of an emulated axis.
+    Button,
+    /// Records the next controller axis, mouse or mouse wheel movement, to be bound to an axis.
+    Axis,
+    /// Records whichever of a button or an axis comes first. A mouse wheel movement is recorded as
+    /// a `Button::MouseWheel`.
+    Any,
+}
+
+/// An input recorded in capture mode, sent with `InputEvent::InputCaptured`.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum CapturedInput {
+    /// A button was pressed.
+    Button(Button),
+    /// An axis was moved.
+    ///
+    /// A controller axis is inverted if it was moved towards negative values, so that the
+    /// direction the player chose returns positive values.
+    Axis(Axis),
+}
+
+/// State of a capture in progress.
+#[derive(Debug, Clone)]
+pub(crate) struct Capture {
+    kind: CaptureKind,
+    /// Mouse movement since the capture started.
+    mouse_motion: (f32, f32),
+}
+
+impl Capture {
+    pub(crate) fn new(kind: CaptureKind) -> Self {
+        Capture {
+            kind,
+            mouse_motion: (0.0, 0.0),
+        }
+    }
+
+    pub(crate) fn kind(&self) -> CaptureKind {
+        self.kind
+    }
+
+    pub(crate) fn button(&self, button: Button) -> Option<CapturedInput> {
+        match self.kind {
+            CaptureKind::Button | CaptureKind::Any => Some(CapturedInput::Button(button)),
+            CaptureKind::Axis => None,
+        }
+    }
+
+    pub(crate) fn mouse_wheel(&self, direction: ScrollDirection) -> Option<CapturedInput> {
+        match self.kind {
+            CaptureKind::Button | CaptureKind::Any => {
+                Some(CapturedInput::Button(Button::MouseWheel(direction)))
+            }
+            CaptureKind::Axis => Some(CapturedInput::Axis(Axis::MouseWheel {
+                horizontal: match direction {
+                    ScrollDirection::ScrollLeft | ScrollDirection::ScrollRight => true,
+                    ScrollDirection::ScrollUp | ScrollDirection::ScrollDown => false,
+                },
+            })),
+        }
+    }
+
+    pub(crate) fn mouse_motion(&mut self, delta_x: f32, delta_y: f32) -> Option<CapturedInput> {
+        if self.kind == CaptureKind::Button {
+            return None;
+        }
+        self.mouse_motion.0 += delta_x;
+        self.mouse_motion.1 += delta_y;
+        let (x, y) = (self.mouse_motion.0.abs(), self.mouse_motion.1.abs());
+        if x.max(y) < MOUSE_THRESHOLD {
+            return None;
+        }
+        Some(CapturedInput::Axis(Axis::Mouse {
+            axis: if x >= y { MouseAxis::X } else { MouseAxis::Y },
+            over_extendable: false,
+            radius: MOUSE_THRESHOLD,
+            sensitivity: 1.0,
+            smoothing: 1,
+            acceleration: 0.0,
+        }))
+    }
+
+    pub(crate) fn controller_axis(
+        &self,
+        controller_id: u32,
+        axis: ControllerAxis,
+        value: f32,
+    ) -> Option<CapturedInput> {
+        if self.kind == CaptureKind::Button || value.abs() < CONTROLLER_AXIS_THRESHOLD {
+            return None;
+        }
+        Some(CapturedInput::Axis(Axis::Controller {
+            controller_id,
+            axis,
+            invert: value < 0.0,
+            dead_zone: CAPTURED_DEAD_ZONE,
+            dead_zone_shape: DeadZoneShape::default(),
+            response: ResponseCurve::default(),
+            sensitivity: 1.0,
+        }))
+    }
+}
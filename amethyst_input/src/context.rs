@@ -0,0 +1,296 @@
+//! Named sets of bindings which can be stacked on the `InputHandler`.
+
+use std::{borrow::Borrow, hash::Hash, iter};
+
+use derivative::Derivative;
+use fnv::{FnvHashMap, FnvHashSet};
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use super::{Axis, BindingTypes, Bindings, Button};
+
+/// How an input context hides inputs from the contexts below it.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum InputConsumption {
+    /// The contexts below receive every input.
+    Nothing,
+    /// The buttons and axes bound in this context are hidden from the contexts below.
+    Bound,
+    /// The contexts below receive no input, e.g. while a menu or a text field is focused.
+    All,
+}
+
+/// A named set of bindings, such as those of the gameplay, of a vehicle or of a menu.
+///
+/// Contexts are pushed on the [InputHandler](struct.InputHandler.html), usually from
+/// `State::on_start` and `State::on_resume`, and removed from `State::on_stop` and
+/// `State::on_pause`. Actions and axes are looked up in the contexts from the highest priority
+/// to the lowest, then in the bindings of the handler itself. A context of higher priority can
+/// hide inputs from the contexts below, as set by its `consumption`.
+///
+/// Raw input events, such as `InputEvent::KeyPressed`, are always sent.
+///
+/// ```rust,edition2018,no_run,noplaypen
+/// # use amethyst_input::{
+/// #     Bindings, Button, InputConsumption, InputContext, InputHandler, StringBindings,
+/// #     VirtualKeyCode,
+/// # };
+/// let mut bindings = Bindings::<StringBindings>::new();
+/// let confirm = vec![Button::Key(VirtualKeyCode::Return)];
+/// bindings.insert_action_binding("confirm".to_string(), confirm)?;
+///
+/// let mut handler = InputHandler::<StringBindings>::new();
+/// handler.push_context(
+///     InputContext::new("menu", bindings)
+///         .with_priority(10)
+///         .with_consumption(InputConsumption::All),
+/// );
+/// // ... and when the menu is closed:
+/// handler.remove_context("menu");
+/// # Ok::<(), amethyst_input::BindingError<StringBindings>>(())
+/// ```
+#[derive(Derivative, Serialize, Deserialize)]
+#[derivative(Debug(bound = ""), Clone(bound = ""))]
+#[serde(bound(
+    serialize = "T::Axis: Serialize, T::Action: Serialize",
+    deserialize = "T::Axis: Deserialize<'de>, T::Action: Deserialize<'de>",
+))]
+pub struct InputContext<T: BindingTypes> {
+    /// Name identifying the context on the stack.
+    pub name: String,
+    /// Bindings active while the context is on the stack.
+    pub bindings: Bindings<T>,
+    /// Contexts of higher priority are above those of lower priority. Among contexts of the same
+    /// priority, the last one pushed is above.
+    #[serde(default)]
+    pub priority: i32,
+    /// What this context hides from the contexts below it, `InputConsumption::Bound` by default.
+    #[serde(default = "bound")]
+    pub consumption: InputConsumption,
+}
+
+impl<T: BindingTypes> InputContext<T> {
+    /// Creates a context of priority 0 which hides the inputs it binds from the contexts below.
+    pub fn new<N: Into<String>>(name: N, bindings: Bindings<T>) -> Self {
+        InputContext {
+            name: name.into(),
+            bindings,
+            priority: 0,
+            consumption: InputConsumption::Bound,
+        }
+    }
+
+    /// Sets the priority of the context.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets what the context hides from the contexts below it.
+    pub fn with_consumption(mut self, consumption: InputConsumption) -> Self {
+        self.consumption = consumption;
+        self
+    }
+}
+
+fn bound() -> InputConsumption {
+    InputConsumption::Bound
+}
+
+/// Returns the contexts from the highest priority to the lowest, the last pushed first among
+/// contexts of the same priority.
+pub(crate) fn by_priority<T: BindingTypes>(contexts: &[InputContext<T>]) -> Vec<&InputContext<T>> {
+    let mut sorted = contexts.iter().enumerate().collect::<Vec<_>>();
+    sorted.sort_by(|(i, a), (j, b)| b.priority.cmp(&a.priority).then(j.cmp(i)));
+    sorted.into_iter().map(|(_, context)| context).collect()
+}
+
+/// A set of bindings in the order actions and axes are looked up.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""), Clone(bound = ""), Copy(bound = ""))]
+pub(crate) struct Layer<'a, T: BindingTypes> {
+    pub(crate) bindings: &'a Bindings<T>,
+    pub(crate) consumption: InputConsumption,
+}
+
+impl<'a, T: BindingTypes> Layer<'a, T> {
+    /// Returns the layers of the contexts in lookup order, followed by the base bindings. The
+    /// layers below a context consuming all inputs are kept, their inputs being hidden.
+    pub(crate) fn stack(
+        contexts: &'a [InputContext<T>],
+        base: &'a Bindings<T>,
+    ) -> impl Iterator<Item = Layer<'a, T>> {
+        by_priority(contexts)
+            .into_iter()
+            .map(|context| Layer {
+                bindings: &context.bindings,
+                consumption: context.consumption,
+            })
+            .chain(iter::once(Layer {
+                bindings: base,
+                consumption: InputConsumption::Nothing,
+            }))
+    }
+
+    /// Returns the buttons this layer hides from the layers below, unless it hides everything.
+    pub(crate) fn consumed_buttons(&self) -> impl Iterator<Item = Button> + 'a {
+        let bindings = match self.consumption {
+            InputConsumption::Bound => Some(self.bindings),
+            _ => None,
+        };
+        bindings.into_iter().flat_map(|bindings| {
+            bindings
+                .actions
+                .values()
+                .flatten()
+                .flatten()
+                .cloned()
+                .chain(
+                    bindings
+                        .bound_axes()
+                        .filter_map(|(_, axis)| match axis {
+                            Axis::Emulated { pos, neg } => {
+                                Some(iter::once(pos).chain(iter::once(neg)))
+                            }
+                            _ => None,
+                        })
+                        .flatten(),
+                )
+        })
+    }
+
+    /// Returns true if a layer above hides the button.
+    pub(crate) fn button_consumed(above: &[Layer<'_, T>], button: Button) -> bool {
+        above.iter().any(|layer| {
+            layer.consumption == InputConsumption::All
+                || layer.consumed_buttons().any(|consumed| consumed == button)
+        })
+    }
+
+    /// Returns true if a layer above hides the input of the axis.
+    pub(crate) fn axis_consumed(above: &[Layer<'_, T>], axis: &Axis) -> bool {
+        if let Axis::Emulated { pos, neg } = axis {
+            return Self::button_consumed(above, *pos) || Self::button_consumed(above, *neg);
+        }
+        if above
+            .iter()
+            .any(|layer| layer.consumption == InputConsumption::All)
+        {
+            return true;
+        }
+        above
+            .iter()
+            .filter(|layer| layer.consumption == InputConsumption::Bound)
+            .flat_map(|layer| layer.bindings.bound_axes())
+            .any(|(_, other)| match (axis, &other) {
+                (
+                    Axis::Controller {
+                        controller_id,
+                        axis,
+                        ..
+                    },
+                    Axis::Controller {
+                        controller_id: other_id,
+                        axis: other_axis,
+                        ..
+                    },
+                ) => controller_id == other_id && axis == other_axis,
+                (
+                    Axis::Mouse { axis, .. },
+                    Axis::Mouse {
+                        axis: other_axis, ..
+                    },
+                ) => axis == other_axis,
+                (
+                    Axis::MouseWheel { horizontal },
+                    Axis::MouseWheel {
+                        horizontal: other_horizontal,
+                    },
+                ) => horizontal == other_horizontal,
+                _ => false,
+            })
+    }
+}
+
+/// The actions bound in the input contexts and base bindings of an `InputHandler`, with their
+/// combinations which are not hidden by a context above.
+///
+/// The handler keeps them while its contexts and bindings don't change, rather than looking up
+/// the hidden buttons whenever an action is checked.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""), Default(bound = ""))]
+pub(crate) struct VisibleActions<T: BindingTypes> {
+    /// Revisions of the base bindings and of the bindings of the contexts, in the order the
+    /// contexts were pushed.
+    revisions: SmallVec<[u64; 8]>,
+    /// False if a context may have changed since, e.g. its priority.
+    current: bool,
+    /// Every action bound in a layer, even if all its combinations are hidden.
+    combinations: FnvHashMap<T::Action, SmallVec<[SmallVec<[Button; 2]>; 4]>>,
+}
+
+impl<T: BindingTypes> VisibleActions<T> {
+    pub(crate) fn new(contexts: &[InputContext<T>], base: &Bindings<T>) -> Self {
+        let mut combinations = FnvHashMap::<_, SmallVec<[_; 4]>>::default();
+        let mut consumed = FnvHashSet::default();
+        let mut hidden = false;
+        for layer in Layer::stack(contexts, base) {
+            for (action, bound) in layer.bindings.actions.iter() {
+                let visible = combinations.entry(action.clone()).or_default();
+                if !hidden {
+                    visible.extend(
+                        bound
+                            .iter()
+                            .filter(|combination| combination.iter().all(|b| !consumed.contains(b)))
+                            .cloned(),
+                    );
+                }
+            }
+            if layer.consumption == InputConsumption::All {
+                hidden = true;
+            } else if !hidden {
+                consumed.extend(layer.consumed_buttons());
+            }
+        }
+        VisibleActions {
+            revisions: revisions(contexts, base).collect(),
+            current: true,
+            combinations,
+        }
+    }
+
+    /// Returns true if the actions were found in these contexts and bindings.
+    pub(crate) fn is_current(&self, contexts: &[InputContext<T>], base: &Bindings<T>) -> bool {
+        self.current && self.revisions.iter().cloned().eq(revisions(contexts, base))
+    }
+
+    /// Marks the actions as outdated, e.g. when a context may be edited.
+    pub(crate) fn invalidate(&mut self) {
+        self.current = false;
+    }
+
+    /// Returns the visible combinations of the action, or None if no layer binds it.
+    pub(crate) fn get<A>(&self, action: &A) -> Option<&[SmallVec<[Button; 2]>]>
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.combinations.get(action).map(SmallVec::as_slice)
+    }
+
+    /// Returns the visible combinations of all actions.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&T::Action, &[Button])> {
+        self.combinations.iter().flat_map(|(action, combinations)| {
+            combinations
+                .iter()
+                .map(move |combination| (action, combination.as_slice()))
+        })
+    }
+}
+
+fn revisions<'a, T: BindingTypes>(
+    contexts: &'a [InputContext<T>],
+    base: &'a Bindings<T>,
+) -> impl Iterator<Item = u64> + 'a {
+    iter::once(base.revision()).chain(contexts.iter().map(|context| context.bindings.revision()))
+}
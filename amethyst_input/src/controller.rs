@@ -19,6 +19,25 @@ pub enum ControllerAxis {
     RightTrigger,
 }
 
+/// The sticks of a controller, made of two `ControllerAxis`es.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum ControllerStick {
+    /// The left stick, made of the `LeftX` and `LeftY` axes.
+    Left,
+    /// The right stick, made of the `RightX` and `RightY` axes.
+    Right,
+}
+
+impl ControllerStick {
+    /// Returns the X and Y axes of the stick.
+    pub fn axes(self) -> (ControllerAxis, ControllerAxis) {
+        match self {
+            ControllerStick::Left => (ControllerAxis::LeftX, ControllerAxis::LeftY),
+            ControllerStick::Right => (ControllerAxis::RightX, ControllerAxis::RightY),
+        }
+    }
+}
+
 /// Controller buttons matching SDL controller model
 #[derive(Eq, PartialEq, Debug, Copy, Clone, Hash, Serialize, Deserialize)]
 pub enum ControllerButton {
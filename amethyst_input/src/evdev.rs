@@ -0,0 +1,508 @@
+//! Gamepads read through the evdev interface of Linux.
+//!
+//! Buttons, axes and hats are numbered like SDL does, so that they match the mappings of the
+//! SDL game controller database. Rumble effects are played through the force feedback of evdev.
+
+#[cfg(target_os = "linux")]
+pub(crate) use self::linux::{event_devices, EvdevDevice};
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) use self::unsupported::{event_devices, EvdevDevice};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        ffi::CStr,
+        fs::{self, File, OpenOptions},
+        io::{self, Read, Write},
+        mem,
+        os::{
+            raw::{c_char, c_ulong, c_void},
+            unix::{fs::OpenOptionsExt, io::AsRawFd},
+        },
+        path::{Path, PathBuf},
+        ptr, slice,
+        time::Duration,
+    };
+
+    use crate::gamepad_mapping::{guid, RawInput};
+
+    const EV_SYN: u16 = 0x00;
+    const EV_KEY: u16 = 0x01;
+    const EV_ABS: u16 = 0x03;
+    const EV_FF: u16 = 0x15;
+    const SYN_REPORT: u16 = 0x00;
+    const SYN_DROPPED: u16 = 0x03;
+    const KEY_MAX: u16 = 0x2ff;
+    const ABS_MAX: u16 = 0x3f;
+    const ABS_HAT0X: u16 = 0x10;
+    const ABS_HAT3Y: u16 = 0x17;
+    const BTN_JOYSTICK: u16 = 0x120;
+    const BTN_THUMBR: u16 = 0x13e;
+    const BTN_DPAD_UP: u16 = 0x220;
+    const BTN_DPAD_RIGHT: u16 = 0x223;
+    const FF_RUMBLE: u16 = 0x50;
+    const FF_MAX: u16 = 0x7f;
+
+    /// Number of events read at once.
+    const EVENT_BUFFER: usize = 64;
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct InputEvent {
+        time: libc::timeval,
+        kind: u16,
+        code: u16,
+        value: i32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct InputId {
+        bus: u16,
+        vendor: u16,
+        product: u16,
+        version: u16,
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Default, Copy, Clone)]
+    struct AbsInfo {
+        value: i32,
+        minimum: i32,
+        maximum: i32,
+        fuzz: i32,
+        flat: i32,
+        resolution: i32,
+    }
+
+    /// `struct ff_effect`, with the members of its union used here.
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct FfEffect {
+        kind: u16,
+        id: i16,
+        direction: u16,
+        trigger: [u16; 2],
+        /// Length and delay of the effect, in milliseconds.
+        replay: [u16; 2],
+        data: FfEffectData,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    union FfEffectData {
+        /// Strength of the strong and weak motors.
+        rumble: [u16; 2],
+        /// The largest member, which sets the size and alignment of the union.
+        periodic: FfPeriodicEffect,
+    }
+
+    /// `struct ff_periodic_effect`, only used for its size.
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct FfPeriodicEffect {
+        parameters: [u16; 9],
+        custom_len: u32,
+        custom_data: *mut i16,
+    }
+
+    impl AbsInfo {
+        /// Returns the position of the axis between -1 and 1.
+        fn normalize(&self, value: i32) -> f32 {
+            if self.maximum <= self.minimum {
+                return 0.0;
+            }
+            let range = f64::from(self.maximum) - f64::from(self.minimum);
+            let value = (f64::from(value) - f64::from(self.minimum)) / range * 2.0 - 1.0;
+            value.clamp(-1.0, 1.0) as f32
+        }
+    }
+
+    /// Reads data from the device with an evdev ioctl, returning false if it failed.
+    fn ioctl_read<D: ?Sized>(file: &File, number: c_ulong, data: &mut D) -> bool {
+        // `_IOC_READ`
+        ioctl(file, 2, number, data)
+    }
+
+    /// Writes data to the device with an evdev ioctl, returning false if it failed.
+    fn ioctl_write<D: ?Sized>(file: &File, number: c_ulong, data: &mut D) -> bool {
+        // `_IOC_WRITE`
+        ioctl(file, 1, number, data)
+    }
+
+    fn ioctl<D: ?Sized>(file: &File, direction: c_ulong, number: c_ulong, data: &mut D) -> bool {
+        let size = mem::size_of_val(data) as c_ulong;
+        // `_IOC(direction, 'E', number, size)`
+        let request = (direction << 30) | (size << 16) | (c_ulong::from(b'E') << 8) | number;
+        unsafe {
+            libc::ioctl(
+                file.as_raw_fd(),
+                request as _,
+                data as *mut D as *mut c_void,
+            ) >= 0
+        }
+    }
+
+    fn bit(bits: &[u8], code: u16) -> bool {
+        bits.get(usize::from(code / 8))
+            .map(|byte| byte & (1 << (code % 8)) != 0)
+            .unwrap_or(false)
+    }
+
+    /// Returns the bits of the directions of a hat pressed: 1 for up, 2 for right, 4 for down and
+    /// 8 for left.
+    fn hat_bits((x, y): (i32, i32)) -> u8 {
+        let mut bits = 0;
+        if y < 0 {
+            bits |= 1;
+        }
+        if x > 0 {
+            bits |= 2;
+        }
+        if y > 0 {
+            bits |= 4;
+        }
+        if x < 0 {
+            bits |= 8;
+        }
+        bits
+    }
+
+    /// Returns the event devices, some of which may be gamepads.
+    pub(crate) fn event_devices() -> Vec<PathBuf> {
+        fs::read_dir("/dev/input")
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(
+                        |path| match path.file_name().and_then(|name| name.to_str()) {
+                            Some(name) => name.starts_with("event"),
+                            None => false,
+                        },
+                    )
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// An evdev device with gamepad buttons.
+    #[derive(Debug)]
+    pub(crate) struct EvdevDevice {
+        file: File,
+        name: String,
+        guid: String,
+        /// Codes of the buttons, in the order they are numbered.
+        buttons: Vec<u16>,
+        /// Codes and ranges of the axes, in the order they are numbered.
+        axes: Vec<(u16, AbsInfo)>,
+        /// Positions of the hats along their X and Y axes.
+        hats: [(i32, i32); 4],
+        /// True after the kernel dropped events, until the next report.
+        dropped: bool,
+        /// True if the device has rumble motors and could be opened for writing.
+        rumble: bool,
+        /// Id of the rumble effect uploaded to the device, or -1 before the first upload.
+        effect: i16,
+    }
+
+    impl EvdevDevice {
+        /// Opens the device, returning `None` if it is not a gamepad.
+        pub(crate) fn open(path: &Path) -> io::Result<Option<Self>> {
+            let open = |write| {
+                OpenOptions::new()
+                    .read(true)
+                    .write(write)
+                    .custom_flags(libc::O_NONBLOCK)
+                    .open(path)
+            };
+            // Writing is only needed for rumble, so the inputs are still read without it.
+            let (file, writable) = match open(true) {
+                Ok(file) => (file, true),
+                Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                    (open(false)?, false)
+                }
+                Err(err) => return Err(err),
+            };
+
+            let mut key_bits = [0u8; KEY_MAX as usize / 8 + 1];
+            let mut abs_bits = [0u8; ABS_MAX as usize / 8 + 1];
+            if !ioctl_read(&file, 0x20 + c_ulong::from(EV_KEY), &mut key_bits) {
+                return Ok(None);
+            }
+            ioctl_read(&file, 0x20 + c_ulong::from(EV_ABS), &mut abs_bits);
+            let gamepad = (BTN_JOYSTICK..=BTN_THUMBR)
+                .chain(BTN_DPAD_UP..=BTN_DPAD_RIGHT)
+                .any(|code| bit(&key_bits, code));
+            if !gamepad {
+                return Ok(None);
+            }
+
+            let mut ff_bits = [0u8; FF_MAX as usize / 8 + 1];
+            let rumble = writable
+                && ioctl_read(&file, 0x20 + c_ulong::from(EV_FF), &mut ff_bits)
+                && bit(&ff_bits, FF_RUMBLE);
+
+            let mut id = InputId::default();
+            ioctl_read(&file, 0x02, &mut id);
+            let mut name = [0 as c_char; 256];
+            let name = if ioctl_read(&file, 0x06, &mut name[..255]) {
+                unsafe { CStr::from_ptr(name.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned()
+            } else {
+                String::new()
+            };
+
+            let buttons = (BTN_JOYSTICK..KEY_MAX)
+                .chain(0..BTN_JOYSTICK)
+                .filter(|code| bit(&key_bits, *code))
+                .collect();
+            let axes = (0..ABS_MAX)
+                .filter(|code| (*code < ABS_HAT0X || *code > ABS_HAT3Y) && bit(&abs_bits, *code))
+                .map(|code| {
+                    let mut info = AbsInfo::default();
+                    ioctl_read(&file, 0x40 + c_ulong::from(code), &mut info);
+                    (code, info)
+                })
+                .collect();
+            Ok(Some(EvdevDevice {
+                guid: guid(id.bus, id.vendor, id.product, id.version, &name),
+                name,
+                file,
+                buttons,
+                axes,
+                hats: [(0, 0); 4],
+                dropped: false,
+                rumble,
+                effect: -1,
+            }))
+        }
+
+        /// Name of the device.
+        pub(crate) fn name(&self) -> &str {
+            &self.name
+        }
+
+        /// GUID of the device in the SDL game controller database.
+        pub(crate) fn guid(&self) -> &str {
+            &self.guid
+        }
+
+        /// Codes of the buttons, in the order they are numbered.
+        pub(crate) fn buttons(&self) -> &[u16] {
+            &self.buttons
+        }
+
+        /// Codes of the axes, in the order they are numbered.
+        pub(crate) fn axes(&self) -> Vec<u16> {
+            self.axes.iter().map(|(code, _)| *code).collect()
+        }
+
+        /// Sets the strength of the rumble motors, between 0 and 1, for the given time, and starts
+        /// them if `start` is true. Changing the strength of the motors while they rumble restarts
+        /// them for the given time.
+        ///
+        /// Does nothing if the device has no rumble motors. Failures are ignored, as a
+        /// disconnected device is found by `read`.
+        pub(crate) fn rumble(&mut self, strong: f32, weak: f32, length: Duration, start: bool) {
+            if !self.rumble {
+                return;
+            }
+            let magnitude = |strength: f32| (strength.clamp(0.0, 1.0) * f32::from(u16::MAX)) as u16;
+            // A length of 0 would rumble until the effect is stopped.
+            let length = length.as_millis().clamp(1, u128::from(u16::MAX)) as u16;
+            // Zeroed bytes are valid for all the fields, including the padding read by the kernel.
+            let mut effect: FfEffect = unsafe { mem::zeroed() };
+            effect.kind = FF_RUMBLE;
+            effect.id = self.effect;
+            effect.replay = [length, 0];
+            effect.data.rumble = [magnitude(strong), magnitude(weak)];
+            // `EVIOCSFF` uploads a new effect if the id is -1, and sets the id of the effect.
+            if !ioctl_write(&self.file, 0x80, &mut effect) {
+                return;
+            }
+            self.effect = effect.id;
+            if start {
+                self.write_ff(1);
+            }
+        }
+
+        /// Stops the rumble motors.
+        pub(crate) fn stop_rumble(&mut self) {
+            if self.rumble && self.effect >= 0 {
+                self.write_ff(0);
+            }
+        }
+
+        /// Plays the rumble effect the given number of times, or stops it with 0.
+        fn write_ff(&mut self, value: i32) {
+            let event = InputEvent {
+                time: libc::timeval {
+                    tv_sec: 0,
+                    tv_usec: 0,
+                },
+                kind: EV_FF,
+                code: self.effect as u16,
+                value,
+            };
+            let bytes = unsafe {
+                slice::from_raw_parts(
+                    &event as *const InputEvent as *const u8,
+                    mem::size_of::<InputEvent>(),
+                )
+            };
+            let _ = self.file.write(bytes);
+        }
+
+        /// Reads the pending inputs of the device. Fails once the device is disconnected.
+        pub(crate) fn read(&mut self, inputs: &mut Vec<RawInput>) -> io::Result<()> {
+            let size = mem::size_of::<InputEvent>();
+            let mut buffer = [0u8; EVENT_BUFFER * mem::size_of::<InputEvent>()];
+            loop {
+                let read = match self.file.read(&mut buffer) {
+                    Ok(0) => return Ok(()),
+                    Ok(read) => read,
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                };
+                for chunk in buffer[..read - read % size].chunks(size) {
+                    let event = unsafe { ptr::read_unaligned(chunk.as_ptr() as *const InputEvent) };
+                    self.event(event, inputs);
+                }
+            }
+        }
+
+        fn event(&mut self, event: InputEvent, inputs: &mut Vec<RawInput>) {
+            match (event.kind, event.code) {
+                (EV_SYN, SYN_DROPPED) => self.dropped = true,
+                (EV_SYN, SYN_REPORT) if self.dropped => {
+                    self.dropped = false;
+                    self.state(inputs);
+                }
+                _ if self.dropped => {}
+                (EV_KEY, code) => {
+                    if let Some(index) = self.buttons.iter().position(|c| *c == code) {
+                        inputs.push(RawInput::Button(index as u16, event.value != 0));
+                    }
+                }
+                (EV_ABS, code) if (ABS_HAT0X..=ABS_HAT3Y).contains(&code) => {
+                    let hat = (code - ABS_HAT0X) / 2;
+                    let position = &mut self.hats[usize::from(hat)];
+                    match (code - ABS_HAT0X) % 2 {
+                        0 => position.0 = event.value,
+                        _ => position.1 = event.value,
+                    }
+                    inputs.push(RawInput::Hat(hat, hat_bits(*position)));
+                }
+                (EV_ABS, code) => {
+                    if let Some(index) = self.axes.iter().position(|(c, _)| *c == code) {
+                        let value = self.axes[index].1.normalize(event.value);
+                        inputs.push(RawInput::Axis(index as u16, value));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        /// Reads the current state of all the inputs of the device, e.g. after it connected or
+        /// after the kernel dropped events.
+        pub(crate) fn state(&mut self, inputs: &mut Vec<RawInput>) {
+            let mut key_bits = [0u8; KEY_MAX as usize / 8 + 1];
+            if ioctl_read(&self.file, 0x18, &mut key_bits) {
+                for (index, code) in self.buttons.iter().enumerate() {
+                    inputs.push(RawInput::Button(index as u16, bit(&key_bits, *code)));
+                }
+            }
+            for (index, (code, info)) in self.axes.iter_mut().enumerate() {
+                if ioctl_read(&self.file, 0x40 + c_ulong::from(*code), info) {
+                    inputs.push(RawInput::Axis(index as u16, info.normalize(info.value)));
+                }
+            }
+            for hat in 0..4 {
+                let mut position = [AbsInfo::default(); 2];
+                for (axis, info) in position.iter_mut().enumerate() {
+                    let code = ABS_HAT0X + hat * 2 + axis as u16;
+                    ioctl_read(&self.file, 0x40 + c_ulong::from(code), info);
+                }
+                let position = (position[0].value, position[1].value);
+                if position != self.hats[usize::from(hat)] {
+                    self.hats[usize::from(hat)] = position;
+                    inputs.push(RawInput::Hat(hat, hat_bits(position)));
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn ff_effect_has_the_kernel_layout() {
+            assert_eq!(
+                mem::size_of::<FfEffect>(),
+                mem::size_of::<libc::ff_effect>()
+            );
+            assert_eq!(
+                mem::align_of::<FfEffect>(),
+                mem::align_of::<libc::ff_effect>()
+            );
+        }
+    }
+}
+
+/// No gamepads are found on other platforms.
+#[cfg(not(target_os = "linux"))]
+mod unsupported {
+    use std::{io, path::Path, path::PathBuf, time::Duration};
+
+    use crate::gamepad_mapping::RawInput;
+
+    pub(crate) fn event_devices() -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    #[derive(Debug)]
+    pub(crate) enum EvdevDevice {}
+
+    impl EvdevDevice {
+        pub(crate) fn open(_: &Path) -> io::Result<Option<Self>> {
+            Ok(None)
+        }
+
+        pub(crate) fn name(&self) -> &str {
+            match *self {}
+        }
+
+        pub(crate) fn guid(&self) -> &str {
+            match *self {}
+        }
+
+        pub(crate) fn buttons(&self) -> &[u16] {
+            match *self {}
+        }
+
+        pub(crate) fn axes(&self) -> Vec<u16> {
+            match *self {}
+        }
+
+        pub(crate) fn read(&mut self, _: &mut Vec<RawInput>) -> io::Result<()> {
+            match *self {}
+        }
+
+        pub(crate) fn state(&mut self, _: &mut Vec<RawInput>) {
+            match *self {}
+        }
+
+        pub(crate) fn rumble(&mut self, _: f32, _: f32, _: Duration, _: bool) {
+            match *self {}
+        }
+
+        pub(crate) fn stop_rumble(&mut self) {
+            match *self {}
+        }
+    }
+}
@@ -5,8 +5,10 @@ use winit::{MouseButton, VirtualKeyCode};
 use super::{
     bindings::BindingTypes,
     button::Button,
+    capture::CapturedInput,
     controller::{ControllerAxis, ControllerButton},
     scroll_direction::ScrollDirection,
+    touch::SwipeDirection,
 };
 
 /// Events generated by the input system
@@ -60,6 +62,72 @@ where
     },
     /// The mousewheel was moved in either direction
     MouseWheelMoved(ScrollDirection),
+    /// A finger touched the screen.
+    TouchStarted {
+        /// Identifier of the finger, unique among the fingers touching the screen.
+        id: u64,
+        /// Position of the finger in physical pixels.
+        position: (f32, f32),
+    },
+    /// A finger moved on the screen.
+    TouchMoved {
+        /// Identifier of the finger.
+        id: u64,
+        /// Position of the finger in physical pixels.
+        position: (f32, f32),
+    },
+    /// A finger left the screen.
+    TouchEnded {
+        /// Identifier of the finger.
+        id: u64,
+        /// Position of the finger in physical pixels.
+        position: (f32, f32),
+    },
+    /// A touch was cancelled by the system, e.g. when the window lost focus.
+    TouchCancelled {
+        /// Identifier of the finger.
+        id: u64,
+    },
+    /// A finger touched the screen and left it quickly without moving.
+    Tap {
+        /// Position of the tap in physical pixels.
+        position: (f32, f32),
+    },
+    /// A second tap shortly after a first one, sent after the `Tap` of the second tap.
+    DoubleTap {
+        /// Position of the second tap in physical pixels.
+        position: (f32, f32),
+    },
+    /// A finger touched the screen for a while without moving, sent while it is still down.
+    LongPress {
+        /// Position of the finger in physical pixels.
+        position: (f32, f32),
+    },
+    /// A finger moved quickly across the screen and left it.
+    Swipe {
+        /// Main direction of the movement.
+        direction: SwipeDirection,
+        /// Position where the finger touched the screen.
+        start: (f32, f32),
+        /// Position where the finger left the screen.
+        end: (f32, f32),
+    },
+    /// Two fingers moved closer or further apart.
+    Pinch {
+        /// Position between the two fingers.
+        center: (f32, f32),
+        /// Distance between the fingers divided by their distance at the previous event, above 1
+        /// when they move apart.
+        scale: f32,
+    },
+    /// Two fingers turned around each other.
+    Rotate {
+        /// Position between the two fingers.
+        center: (f32, f32),
+        /// Angle of the rotation since the previous event in radians, positive when clockwise on
+        /// the screen.
+        angle: f32,
+    },
     /// An axis value changed.
     ///
     /// Note that this variant is used for `BindingTypes::Axis`, not a `ControllerAxis`.
@@ -116,4 +184,11 @@ where
     ActionReleased(T::Action),
     /// The associated action has its mouse wheel moved.
     ActionWheelMoved(T::Action),
+    /// The sequence of actions of the combo performing the associated action was pressed.
+    ComboPerformed(T::Action),
+    /// An input was recorded by the capture started with
+    /// [start_capture](struct.InputHandler.html#method.start_capture), which is now finished.
+    ///
+    /// This is sent after the events of the input itself.
+    InputCaptured(CapturedInput),
 }
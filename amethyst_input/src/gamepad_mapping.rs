@@ -0,0 +1,752 @@
+//! Gamepad mappings in the format of the SDL game controller database.
+
+// Mappings are only applied by the native gamepad backend.
+#![cfg_attr(not(feature = "native_controller"), allow(dead_code))]
+
+use std::{env, error, fmt, fs, io, path::Path};
+
+use fnv::FnvHashMap as HashMap;
+use smallvec::SmallVec;
+
+use super::{ControllerAxis, ControllerButton, ControllerEvent};
+
+/// Mappings of common gamepads, in the format of the SDL game controller database.
+const STANDARD_MAPPINGS: &str = include_str!("gamecontrollerdb.txt");
+
+/// Environment variable naming a database file to add, as read by SDL.
+const CONFIG_FILE_VAR: &str = "SDL_GAMECONTROLLERCONFIG_FILE";
+
+/// Environment variable with mappings to add, one per line, as read by SDL.
+const CONFIG_VAR: &str = "SDL_GAMECONTROLLERCONFIG";
+
+/// Name of the platform in the `platform` field of the mappings.
+#[cfg(target_os = "linux")]
+const PLATFORM: &str = "Linux";
+#[cfg(target_os = "windows")]
+const PLATFORM: &str = "Windows";
+#[cfg(target_os = "macos")]
+const PLATFORM: &str = "Mac OS X";
+#[cfg(target_os = "android")]
+const PLATFORM: &str = "Android";
+#[cfg(target_os = "ios")]
+const PLATFORM: &str = "iOS";
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "android",
+    target_os = "ios"
+)))]
+const PLATFORM: &str = "";
+
+/// Position past which an axis presses the button it is mapped to.
+const BUTTON_THRESHOLD: f32 = 0.5;
+
+// Codes of the evdev buttons and axes of the standard Linux gamepad layout.
+const BTN_SOUTH: u16 = 0x130;
+const BTN_EAST: u16 = 0x131;
+const BTN_NORTH: u16 = 0x133;
+const BTN_WEST: u16 = 0x134;
+const BTN_TL: u16 = 0x136;
+const BTN_TR: u16 = 0x137;
+const BTN_TL2: u16 = 0x138;
+const BTN_TR2: u16 = 0x139;
+const BTN_SELECT: u16 = 0x13a;
+const BTN_START: u16 = 0x13b;
+const BTN_MODE: u16 = 0x13c;
+const BTN_THUMBL: u16 = 0x13d;
+const BTN_THUMBR: u16 = 0x13e;
+const BTN_DPAD_UP: u16 = 0x220;
+const BTN_DPAD_DOWN: u16 = 0x221;
+const BTN_DPAD_LEFT: u16 = 0x222;
+const BTN_DPAD_RIGHT: u16 = 0x223;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+
+/// An error in a gamepad mapping.
+#[derive(Debug)]
+pub enum GamepadMappingError {
+    /// The mapping has no GUID or no name.
+    Incomplete(String),
+    /// The GUID of the mapping is not made of 32 hexadecimal digits.
+    InvalidGuid(String),
+    /// An element of the mapping, such as `a:b0`, could not be parsed.
+    InvalidElement(String),
+    /// The mapping database could not be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for GamepadMappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            GamepadMappingError::Incomplete(ref mapping) => {
+                write!(f, "Gamepad mapping without GUID or name: {}", mapping)
+            }
+            GamepadMappingError::InvalidGuid(ref guid) => {
+                write!(f, "Invalid gamepad GUID: {}", guid)
+            }
+            GamepadMappingError::InvalidElement(ref element) => {
+                write!(f, "Invalid gamepad mapping element: {}", element)
+            }
+            GamepadMappingError::Io(ref err) => {
+                write!(f, "Failed to read gamepad mappings: {}", err)
+            }
+        }
+    }
+}
+
+impl error::Error for GamepadMappingError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            GamepadMappingError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GamepadMappingError {
+    fn from(err: io::Error) -> Self {
+        GamepadMappingError::Io(err)
+    }
+}
+
+/// Part of the range of an axis.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Range {
+    Full,
+    Positive,
+    Negative,
+}
+
+/// An input of a device, numbered like in the SDL game controller database.
+#[derive(PartialEq, Debug, Copy, Clone)]
+enum Source {
+    Button(u16),
+    Axis {
+        axis: u16,
+        range: Range,
+        invert: bool,
+    },
+    /// A hat, with the bits of the directions pressing the output: 1 for up, 2 for right, 4 for
+    /// down and 8 for left.
+    Hat {
+        hat: u16,
+        mask: u8,
+    },
+}
+
+/// An output of a gamepad.
+#[derive(PartialEq, Debug, Copy, Clone)]
+enum Target {
+    Button(ControllerButton),
+    Axis { axis: ControllerAxis, range: Range },
+}
+
+/// A raw input of a device, numbered like in the SDL game controller database.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub(crate) enum RawInput {
+    /// A button was pressed or released.
+    Button(u16, bool),
+    /// An axis moved, with its position between -1 and 1.
+    Axis(u16, f32),
+    /// A hat moved, with the bits of its pressed directions: 1 for up, 2 for right, 4 for down
+    /// and 8 for left.
+    Hat(u16, u8),
+}
+
+/// The outputs of a gamepad.
+#[derive(Debug, Default)]
+pub(crate) struct GamepadState {
+    buttons: SmallVec<[ControllerButton; 4]>,
+    axes: SmallVec<[(ControllerAxis, f32); 6]>,
+}
+
+/// How the buttons, axes and hats of a gamepad model map to the `ControllerButton`s and
+/// `ControllerAxis`es.
+#[derive(PartialEq, Debug, Clone)]
+pub struct GamepadMapping {
+    /// Name of the gamepad model.
+    pub name: String,
+    elements: Vec<(Source, Target)>,
+}
+
+impl GamepadMapping {
+    /// Parses a mapping of the SDL game controller database, such as
+    /// `030000005e0400008e02000014010000,X360 Controller,a:b0,b:b1,...,platform:Linux,`.
+    ///
+    /// Returns the GUID of the gamepad model with the mapping. Elements with outputs that have
+    /// no `ControllerButton` or `ControllerAxis`, and unknown fields, are ignored.
+    pub fn parse(mapping: &str) -> Result<(String, Self), GamepadMappingError> {
+        let mut fields = mapping.trim().split(',');
+        let (guid, name) = match (fields.next(), fields.next()) {
+            (Some(guid), Some(name)) if !guid.is_empty() && !name.is_empty() => (guid, name),
+            _ => return Err(GamepadMappingError::Incomplete(mapping.to_string())),
+        };
+        if guid.len() != 32 || !guid.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(GamepadMappingError::InvalidGuid(guid.to_string()));
+        }
+        let mut elements = Vec::new();
+        for field in fields.filter(|field| !field.is_empty()) {
+            let invalid = || GamepadMappingError::InvalidElement(field.to_string());
+            let mut parts = field.splitn(2, ':');
+            let (output, input) = match (parts.next(), parts.next()) {
+                (Some(output), Some(input)) => (output, input),
+                _ => return Err(invalid()),
+            };
+            let (output, range) = split_range(output);
+            let target = match parse_target(output, range) {
+                Some(target) => target,
+                None => continue,
+            };
+            elements.push((parse_source(input).ok_or_else(invalid)?, target));
+        }
+        Ok((
+            guid.to_lowercase(),
+            GamepadMapping {
+                name: name.to_string(),
+                elements,
+            },
+        ))
+    }
+
+    /// Returns the platform of a mapping of the SDL game controller database, if it has one.
+    fn platform(mapping: &str) -> Option<&str> {
+        mapping.split(',').find_map(|field| {
+            let mut parts = field.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some("platform"), Some(platform)) => Some(platform.trim()),
+                _ => None,
+            }
+        })
+    }
+
+    /// Builds the mapping of a device following the standard Linux gamepad layout, from the evdev
+    /// codes of its buttons and axes in the order they are numbered.
+    ///
+    /// Returns `None` if the device has no `BTN_SOUTH` button.
+    pub(crate) fn evdev_standard(name: &str, buttons: &[u16], axes: &[u16]) -> Option<Self> {
+        if !buttons.contains(&BTN_SOUTH) {
+            return None;
+        }
+        let button_elements = [
+            (BTN_SOUTH, ControllerButton::A),
+            (BTN_EAST, ControllerButton::B),
+            (BTN_WEST, ControllerButton::X),
+            (BTN_NORTH, ControllerButton::Y),
+            (BTN_TL, ControllerButton::LeftShoulder),
+            (BTN_TR, ControllerButton::RightShoulder),
+            (BTN_SELECT, ControllerButton::Back),
+            (BTN_START, ControllerButton::Start),
+            (BTN_MODE, ControllerButton::Guide),
+            (BTN_THUMBL, ControllerButton::LeftStick),
+            (BTN_THUMBR, ControllerButton::RightStick),
+            (BTN_DPAD_UP, ControllerButton::DPadUp),
+            (BTN_DPAD_DOWN, ControllerButton::DPadDown),
+            (BTN_DPAD_LEFT, ControllerButton::DPadLeft),
+            (BTN_DPAD_RIGHT, ControllerButton::DPadRight),
+        ];
+        let axis_elements = [
+            (ABS_X, ControllerAxis::LeftX),
+            (ABS_Y, ControllerAxis::LeftY),
+            (ABS_RX, ControllerAxis::RightX),
+            (ABS_RY, ControllerAxis::RightY),
+            (ABS_Z, ControllerAxis::LeftTrigger),
+            (ABS_RZ, ControllerAxis::RightTrigger),
+        ];
+        let index = |codes: &[u16], code| codes.iter().position(|c| *c == code);
+        let button = |button| Target::Button(button);
+        let axis = |axis| Target::Axis {
+            axis,
+            range: Range::Full,
+        };
+        let mut elements = button_elements
+            .iter()
+            .filter_map(|&(code, target)| {
+                index(buttons, code).map(|i| (Source::Button(i as u16), button(target)))
+            })
+            .collect::<Vec<_>>();
+        for &(code, target) in axis_elements.iter() {
+            let source = |i: usize| Source::Axis {
+                axis: i as u16,
+                range: Range::Full,
+                invert: false,
+            };
+            elements.extend(index(axes, code).map(|i| (source(i), axis(target))));
+        }
+        // Gamepads without analog triggers report them as buttons.
+        for &(code, target) in &[
+            (BTN_TL2, ControllerAxis::LeftTrigger),
+            (BTN_TR2, ControllerAxis::RightTrigger),
+        ] {
+            if elements.iter().all(|(_, t)| *t != axis(target)) {
+                elements
+                    .extend(index(buttons, code).map(|i| (Source::Button(i as u16), axis(target))));
+            }
+        }
+        // The directional pad is usually the first hat.
+        for &(mask, target) in &[
+            (1, ControllerButton::DPadUp),
+            (2, ControllerButton::DPadRight),
+            (4, ControllerButton::DPadDown),
+            (8, ControllerButton::DPadLeft),
+        ] {
+            if elements.iter().all(|(_, t)| *t != button(target)) {
+                elements.push((Source::Hat { hat: 0, mask }, button(target)));
+            }
+        }
+        Some(GamepadMapping {
+            name: name.to_string(),
+            elements,
+        })
+    }
+
+    /// Updates the state of the gamepad with a raw input, writing the resulting events.
+    pub(crate) fn map(
+        &self,
+        which: u32,
+        input: RawInput,
+        state: &mut GamepadState,
+        events: &mut Vec<ControllerEvent>,
+    ) {
+        for &(source, target) in &self.elements {
+            // Position of the input between -1 and 1, or between 0 and 1 for half axes and buttons.
+            let value = match (source, input) {
+                (Source::Button(b), RawInput::Button(button, pressed)) if b == button => {
+                    if pressed {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                (
+                    Source::Axis {
+                        axis,
+                        range,
+                        invert,
+                    },
+                    RawInput::Axis(a, value),
+                ) if a == axis => {
+                    let value = if invert { -value } else { value };
+                    match range {
+                        Range::Full => value,
+                        Range::Positive => value.max(0.0),
+                        Range::Negative => (-value).max(0.0),
+                    }
+                }
+                (Source::Hat { hat, mask }, RawInput::Hat(h, bits)) if h == hat => {
+                    if bits & mask != 0 {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                _ => continue,
+            };
+            let full_range = match source {
+                Source::Axis { range, .. } => range == Range::Full,
+                _ => false,
+            };
+            match target {
+                Target::Button(button) => {
+                    state.button(which, button, value > BUTTON_THRESHOLD, events);
+                }
+                Target::Axis { axis, range } => {
+                    let trigger = matches!(
+                        axis,
+                        ControllerAxis::LeftTrigger | ControllerAxis::RightTrigger
+                    );
+                    // Scale the input to the range of the output.
+                    let value = match (range, full_range) {
+                        (Range::Full, true) if trigger => (value + 1.0) / 2.0,
+                        (Range::Full, true) => value,
+                        (Range::Full, false) if trigger => value,
+                        (Range::Full, false) => value * 2.0 - 1.0,
+                        (_, true) => (value + 1.0) / 2.0,
+                        (_, false) => value,
+                    };
+                    match range {
+                        Range::Full => state.axis(which, axis, value, events),
+                        // Half axes only reset the axis if it is on their side.
+                        Range::Positive if value > 0.0 || state.axis_value(axis) > 0.0 => {
+                            state.axis(which, axis, value, events)
+                        }
+                        Range::Negative if value > 0.0 || state.axis_value(axis) < 0.0 => {
+                            state.axis(which, axis, -value, events)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GamepadState {
+    fn button(
+        &mut self,
+        which: u32,
+        button: ControllerButton,
+        pressed: bool,
+        events: &mut Vec<ControllerEvent>,
+    ) {
+        let index = self.buttons.iter().position(|b| *b == button);
+        match (index, pressed) {
+            (None, true) => {
+                self.buttons.push(button);
+                events.push(ControllerEvent::ControllerButtonPressed { which, button });
+            }
+            (Some(index), false) => {
+                self.buttons.swap_remove(index);
+                events.push(ControllerEvent::ControllerButtonReleased { which, button });
+            }
+            _ => {}
+        }
+    }
+
+    fn axis_value(&self, axis: ControllerAxis) -> f32 {
+        self.axes
+            .iter()
+            .find(|(a, _)| *a == axis)
+            .map(|(_, value)| *value)
+            .unwrap_or(0.0)
+    }
+
+    fn axis(
+        &mut self,
+        which: u32,
+        axis: ControllerAxis,
+        value: f32,
+        events: &mut Vec<ControllerEvent>,
+    ) {
+        match self.axes.iter_mut().find(|(a, _)| *a == axis) {
+            Some((_, old)) if *old == value => return,
+            Some((_, old)) => *old = value,
+            None => self.axes.push((axis, value)),
+        }
+        events.push(ControllerEvent::ControllerAxisMoved { which, axis, value });
+    }
+}
+
+fn split_range(element: &str) -> (&str, Range) {
+    match element.chars().next() {
+        Some('+') => (&element[1..], Range::Positive),
+        Some('-') => (&element[1..], Range::Negative),
+        _ => (element, Range::Full),
+    }
+}
+
+fn parse_target(output: &str, range: Range) -> Option<Target> {
+    let button = match output {
+        "a" => ControllerButton::A,
+        "b" => ControllerButton::B,
+        "x" => ControllerButton::X,
+        "y" => ControllerButton::Y,
+        "back" => ControllerButton::Back,
+        "guide" => ControllerButton::Guide,
+        "start" => ControllerButton::Start,
+        "leftstick" => ControllerButton::LeftStick,
+        "rightstick" => ControllerButton::RightStick,
+        "leftshoulder" => ControllerButton::LeftShoulder,
+        "rightshoulder" => ControllerButton::RightShoulder,
+        "dpup" => ControllerButton::DPadUp,
+        "dpdown" => ControllerButton::DPadDown,
+        "dpleft" => ControllerButton::DPadLeft,
+        "dpright" => ControllerButton::DPadRight,
+        _ => {
+            let axis = match output {
+                "leftx" => ControllerAxis::LeftX,
+                "lefty" => ControllerAxis::LeftY,
+                "rightx" => ControllerAxis::RightX,
+                "righty" => ControllerAxis::RightY,
+                "lefttrigger" => ControllerAxis::LeftTrigger,
+                "righttrigger" => ControllerAxis::RightTrigger,
+                _ => return None,
+            };
+            return Some(Target::Axis { axis, range });
+        }
+    };
+    Some(Target::Button(button))
+}
+
+fn parse_source(input: &str) -> Option<Source> {
+    let (input, range) = split_range(input);
+    let invert = input.ends_with('~');
+    let input = input.trim_end_matches('~');
+    if input.is_empty() {
+        return None;
+    }
+    let (kind, index) = input.split_at(1);
+    match kind {
+        "b" => index.parse().ok().map(Source::Button),
+        "a" => index.parse().ok().map(|axis| Source::Axis {
+            axis,
+            range,
+            invert,
+        }),
+        "h" => {
+            let mut parts = index.splitn(2, '.');
+            let hat = parts.next()?.parse().ok()?;
+            let mask = parts.next()?.parse().ok()?;
+            Some(Source::Hat { hat, mask })
+        }
+        _ => None,
+    }
+}
+
+/// Returns the GUID identifying a gamepad model in the SDL game controller database.
+pub(crate) fn guid(bus: u16, vendor: u16, product: u16, version: u16, name: &str) -> String {
+    let mut bytes = [0; 16];
+    bytes[0..2].copy_from_slice(&bus.to_le_bytes());
+    if vendor != 0 && product != 0 {
+        bytes[4..6].copy_from_slice(&vendor.to_le_bytes());
+        bytes[8..10].copy_from_slice(&product.to_le_bytes());
+        bytes[12..14].copy_from_slice(&version.to_le_bytes());
+    } else {
+        let name = name.as_bytes();
+        let length = name.len().min(12);
+        bytes[4..4 + length].copy_from_slice(&name[..length]);
+    }
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A database of gamepad mappings, keyed by the GUID of the gamepad models.
+///
+/// The database uses the format of the
+/// [SDL game controller database](https://github.com/gabomdq/SDL_GameControllerDB), one mapping
+/// per line. `GamepadMappings::new` only has the mappings of a few common gamepads, not the whole
+/// SDL database: add the `gamecontrollerdb.txt` of the SDL database, or one of your own, with
+/// `add_mappings_from_file` to map other gamepads. Gamepads without a mapping which follow the
+/// standard Linux gamepad layout work as well.
+///
+/// A mapping replaces those added before it for the same gamepad model. The `InputBundle` uses
+/// the mappings of common gamepads, or those given with `with_gamepad_mappings`, then adds those
+/// of the `SDL_GAMECONTROLLERCONFIG_FILE` and `SDL_GAMECONTROLLERCONFIG` environment variables,
+/// so that players can map their gamepads like they do for SDL games.
+#[derive(Debug, Clone)]
+pub struct GamepadMappings {
+    mappings: HashMap<String, GamepadMapping>,
+}
+
+impl Default for GamepadMappings {
+    fn default() -> Self {
+        let mut mappings = GamepadMappings::empty();
+        mappings
+            .add_mappings(STANDARD_MAPPINGS)
+            .expect("Unreachable: the standard gamepad mappings are valid");
+        mappings
+    }
+}
+
+impl GamepadMappings {
+    /// Creates a database with the mappings of common gamepads.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a database without mappings.
+    pub fn empty() -> Self {
+        GamepadMappings {
+            mappings: HashMap::default(),
+        }
+    }
+
+    /// Adds a mapping, replacing any mapping of the same gamepad model.
+    pub fn add_mapping(&mut self, mapping: &str) -> Result<(), GamepadMappingError> {
+        let (guid, mapping) = GamepadMapping::parse(mapping)?;
+        self.mappings.insert(guid, mapping);
+        Ok(())
+    }
+
+    /// Adds the mappings of a database, one per line, skipping comments and the mappings of other
+    /// platforms. Returns the number of mappings added.
+    pub fn add_mappings(&mut self, mappings: &str) -> Result<usize, GamepadMappingError> {
+        let mut added = 0;
+        for line in mappings.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match GamepadMapping::platform(line) {
+                Some(platform) if platform != PLATFORM => continue,
+                _ => {}
+            }
+            self.add_mapping(line)?;
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Adds the mappings of a database file, such as `gamecontrollerdb.txt`.
+    pub fn add_mappings_from_file<P: AsRef<Path>>(
+        &mut self,
+        file: P,
+    ) -> Result<usize, GamepadMappingError> {
+        self.add_mappings(&fs::read_to_string(file)?)
+    }
+
+    /// Adds the mappings of the database file named by the `SDL_GAMECONTROLLERCONFIG_FILE`
+    /// environment variable, then those of the `SDL_GAMECONTROLLERCONFIG` environment variable,
+    /// one per line, like SDL does. Returns the number of mappings added.
+    pub fn add_mappings_from_env(&mut self) -> Result<usize, GamepadMappingError> {
+        let mut added = 0;
+        if let Some(file) = env::var_os(CONFIG_FILE_VAR) {
+            added += self.add_mappings_from_file(file)?;
+        }
+        if let Ok(mappings) = env::var(CONFIG_VAR) {
+            added += self.add_mappings(&mappings)?;
+        }
+        Ok(added)
+    }
+
+    /// Returns the mapping of the gamepad model with the GUID.
+    ///
+    /// A mapping made for another version of the same model is returned if there is no exact
+    /// match.
+    pub fn mapping(&self, guid: &str) -> Option<&GamepadMapping> {
+        let guid = guid.to_lowercase();
+        self.mappings.get(&guid).or_else(|| {
+            let (model, _) = guid.split_at(guid.len().min(24));
+            self.mappings
+                .iter()
+                .find(|(other, _)| other.starts_with(model))
+                .map(|(_, mapping)| mapping)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAD: &str = "030000005e0400008e02000014010000,Test Pad,a:b0,b:b1,dpup:h0.1,\
+                       dpleft:h0.8,lefttrigger:a2,leftx:a0,lefty:a1~,+rightx:b3,-rightx:b2,\
+                       misc1:b15,platform:Linux,";
+
+    #[test]
+    fn parses_mappings_and_gamepad_guids() {
+        let guid = guid(0x03, 0x045e, 0x028e, 0x0114, "Test Pad");
+        assert_eq!(guid, "030000005e0400008e02000014010000");
+
+        let mut mappings = GamepadMappings::empty();
+        mappings.add_mapping(PAD).unwrap();
+        assert_eq!(mappings.mapping(&guid).unwrap().name, "Test Pad");
+        // Another version of the same model.
+        assert!(mappings
+            .mapping("030000005e0400008e02000010010000")
+            .is_some());
+        assert!(mappings
+            .mapping("030000005e040000ea02000001030000")
+            .is_none());
+
+        assert!(matches!(
+            GamepadMapping::parse("0300,Pad,a:b0"),
+            Err(GamepadMappingError::InvalidGuid(_))
+        ));
+        assert!(matches!(
+            GamepadMapping::parse(&PAD.replace("b:b1", "b:q1")),
+            Err(GamepadMappingError::InvalidElement(_))
+        ));
+        assert!(GamepadMappings::new().mappings.len() > 1);
+    }
+
+    #[test]
+    fn maps_raw_inputs_to_controller_events() {
+        let (_, mapping) = GamepadMapping::parse(PAD).unwrap();
+        let mut state = GamepadState::default();
+        let mut events = Vec::new();
+        let mut map = |input| {
+            events.clear();
+            mapping.map(7, input, &mut state, &mut events);
+            events.clone()
+        };
+        let pressed = |button| ControllerEvent::ControllerButtonPressed { which: 7, button };
+        let released = |button| ControllerEvent::ControllerButtonReleased { which: 7, button };
+        let moved = |axis, value| ControllerEvent::ControllerAxisMoved {
+            which: 7,
+            axis,
+            value,
+        };
+
+        assert_eq!(
+            map(RawInput::Button(0, true)),
+            vec![pressed(ControllerButton::A)]
+        );
+        assert_eq!(map(RawInput::Button(0, true)), vec![]);
+        assert_eq!(
+            map(RawInput::Hat(0, 1 | 8)),
+            vec![
+                pressed(ControllerButton::DPadUp),
+                pressed(ControllerButton::DPadLeft),
+            ]
+        );
+        assert_eq!(
+            map(RawInput::Hat(0, 8)),
+            vec![released(ControllerButton::DPadUp)]
+        );
+        assert_eq!(
+            map(RawInput::Axis(1, 0.5)),
+            vec![moved(ControllerAxis::LeftY, -0.5)]
+        );
+        assert_eq!(
+            map(RawInput::Axis(2, -1.0)),
+            vec![moved(ControllerAxis::LeftTrigger, 0.0)]
+        );
+        assert_eq!(
+            map(RawInput::Button(2, true)),
+            vec![moved(ControllerAxis::RightX, -1.0)]
+        );
+        // Releasing the other side leaves the axis alone.
+        assert_eq!(map(RawInput::Button(3, false)), vec![]);
+        assert_eq!(
+            map(RawInput::Button(2, false)),
+            vec![moved(ControllerAxis::RightX, 0.0)]
+        );
+        assert_eq!(map(RawInput::Button(15, true)), vec![]);
+    }
+
+    #[test]
+    fn standard_linux_layout_is_used_without_mapping() {
+        let buttons = [BTN_SOUTH, BTN_EAST, BTN_NORTH, BTN_WEST, BTN_TL2];
+        let axes = [ABS_X, ABS_Y, ABS_Z, ABS_RX];
+        let mapping = GamepadMapping::evdev_standard("Pad", &buttons, &axes).unwrap();
+        let mut state = GamepadState::default();
+        let mut events = Vec::new();
+        mapping.map(0, RawInput::Button(2, true), &mut state, &mut events);
+        mapping.map(0, RawInput::Axis(2, 1.0), &mut state, &mut events);
+        mapping.map(0, RawInput::Button(4, true), &mut state, &mut events);
+        assert_eq!(
+            events,
+            vec![
+                ControllerEvent::ControllerButtonPressed {
+                    which: 0,
+                    button: ControllerButton::Y,
+                },
+                ControllerEvent::ControllerAxisMoved {
+                    which: 0,
+                    axis: ControllerAxis::LeftTrigger,
+                    value: 1.0,
+                },
+            ]
+        );
+        assert!(GamepadMapping::evdev_standard("Keyboard", &[0x1e], &[]).is_none());
+    }
+
+    #[test]
+    fn mappings_are_added_from_the_environment() {
+        let mut mappings = GamepadMappings::empty();
+        env::set_var(CONFIG_VAR, format!("# Comment\n{}\n", PAD));
+        assert_eq!(mappings.add_mappings_from_env().unwrap(), 1);
+        env::remove_var(CONFIG_VAR);
+        assert_eq!(
+            mappings
+                .mapping("030000005e0400008e02000014010000")
+                .unwrap()
+                .name,
+            "Test Pad"
+        );
+        assert_eq!(mappings.add_mappings_from_env().unwrap(), 0);
+    }
+}
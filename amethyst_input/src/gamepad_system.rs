@@ -0,0 +1,280 @@
+//! Native gamepad system
+use std::{
+    marker::PhantomData,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use derivative::Derivative;
+use derive_new::new;
+
+use crate::{
+    evdev::{event_devices, EvdevDevice},
+    gamepad_mapping::{GamepadMapping, GamepadState, RawInput},
+    BindingTypes, ControllerEvent, GamepadMappings, HapticsBackend, HapticsRequest, InputEvent,
+    InputHandler, InputRecorder, RecordedEvent, RumbleEffect,
+};
+use amethyst_core::{
+    ecs::prelude::{System, SystemData, World, Write},
+    shrev::EventChannel,
+    SystemDesc,
+};
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+/// Time between two searches for connected gamepads.
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Builds a `GamepadEventsSystem`.
+#[derive(Derivative, Debug, new)]
+#[derivative(Default(bound = ""))]
+pub struct GamepadEventsSystemDesc<T>
+where
+    T: BindingTypes,
+{
+    mappings: Option<GamepadMappings>,
+    marker: PhantomData<T>,
+}
+
+impl<'a, 'b, T> SystemDesc<'a, 'b, GamepadEventsSystem<T>> for GamepadEventsSystemDesc<T>
+where
+    T: BindingTypes,
+{
+    fn build(self, world: &mut World) -> GamepadEventsSystem<T> {
+        <GamepadEventsSystem<T> as System<'_>>::SystemData::setup(world);
+
+        GamepadEventsSystem::new(self.mappings.unwrap_or_default())
+    }
+}
+
+/// A `HapticsBackend` rumbling the gamepads read by the `GamepadEventsSystem`, through the
+/// force feedback of evdev on Linux.
+///
+/// Add a `HapticsSystemDesc::<T, GamepadHaptics>` to play the effects of the `Haptics`
+/// resource with it. The effects are played the next time the `GamepadEventsSystem` runs, on the
+/// gamepad of the same id. Gamepads without rumble motors, or which can't be opened for writing,
+/// ignore them.
+#[derive(Debug, Default)]
+pub struct GamepadHaptics {
+    requests: Vec<HapticsRequest>,
+}
+
+impl HapticsBackend for GamepadHaptics {
+    fn play(&mut self, which: u32, effect: &RumbleEffect) {
+        self.requests.push(HapticsRequest::Play {
+            which,
+            effect: *effect,
+        });
+    }
+
+    fn stop(&mut self, which: u32) {
+        self.requests.push(HapticsRequest::Stop { which });
+    }
+}
+
+#[derive(Debug)]
+struct Gamepad {
+    path: PathBuf,
+    which: u32,
+    device: EvdevDevice,
+    mapping: GamepadMapping,
+    state: GamepadState,
+    /// The effect the gamepad rumbles with, when it started and the strength of its motors.
+    rumble: Option<(RumbleEffect, Instant, (f32, f32))>,
+}
+
+/// A system that reads gamepads without SDL and pumps their events into the `amethyst_input`
+/// APIs, like the `SdlEventsSystem` does.
+///
+/// Gamepads are read through evdev on Linux, and none are found on other platforms. They are
+/// mapped to the `ControllerButton`s and `ControllerAxis`es with the mappings of the
+/// `GamepadMappings` database, or with the standard Linux gamepad layout if the database has
+/// no mapping for them. Gamepads connected while the game runs are found within a second, and
+/// their ids are never reused.
+///
+/// Reading gamepads requires permission to read `/dev/input/event*`, which usually comes with
+/// logging in on a seat or with the `input` group.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+pub struct GamepadEventsSystem<T>
+where
+    T: BindingTypes,
+{
+    mappings: GamepadMappings,
+    gamepads: Vec<Gamepad>,
+    /// Devices which are not gamepads.
+    ignored: Vec<PathBuf>,
+    next_id: u32,
+    last_scan: Option<Instant>,
+    marker: PhantomData<T>,
+}
+
+impl<T: BindingTypes> GamepadEventsSystem<T> {
+    /// Creates a new instance of this system with the provided mappings.
+    pub fn new(mappings: GamepadMappings) -> Self {
+        GamepadEventsSystem {
+            mappings,
+            gamepads: Vec::new(),
+            ignored: Vec::new(),
+            next_id: 0,
+            last_scan: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Opens the gamepads connected since the last scan.
+    fn scan(&mut self, events: &mut Vec<ControllerEvent>) {
+        let paths = event_devices();
+        // Forget the devices which are gone, in case their path is reused.
+        self.ignored.retain(|path| paths.contains(path));
+        for path in paths {
+            if self.ignored.contains(&path) || self.gamepads.iter().any(|g| g.path == path) {
+                continue;
+            }
+            let device = match EvdevDevice::open(&path) {
+                Ok(Some(device)) => device,
+                Ok(None) => {
+                    self.ignored.push(path);
+                    continue;
+                }
+                // Permissions are often set right after the device appears, so try again later.
+                Err(_) => continue,
+            };
+            let mapping = match self.mappings.mapping(device.guid()) {
+                Some(mapping) => mapping.clone(),
+                None => match GamepadMapping::evdev_standard(
+                    device.name(),
+                    device.buttons(),
+                    &device.axes(),
+                ) {
+                    Some(mapping) => mapping,
+                    None => {
+                        self.ignored.push(path);
+                        continue;
+                    }
+                },
+            };
+            let which = self.next_id;
+            self.next_id += 1;
+            events.push(ControllerEvent::ControllerConnected { which });
+            let mut gamepad = Gamepad {
+                path,
+                which,
+                mapping,
+                state: GamepadState::default(),
+                device,
+                rumble: None,
+            };
+            let mut inputs = Vec::new();
+            gamepad.device.state(&mut inputs);
+            gamepad.map(&inputs, events);
+            self.gamepads.push(gamepad);
+        }
+    }
+
+    /// Reads the inputs of the gamepads, closing those which are disconnected.
+    fn read(&mut self, events: &mut Vec<ControllerEvent>) {
+        let mut inputs = Vec::new();
+        let mut index = 0;
+        while index < self.gamepads.len() {
+            let gamepad = &mut self.gamepads[index];
+            inputs.clear();
+            match gamepad.device.read(&mut inputs) {
+                Ok(()) => {
+                    gamepad.map(&inputs, events);
+                    index += 1;
+                }
+                Err(_) => {
+                    events.push(ControllerEvent::ControllerDisconnected {
+                        which: gamepad.which,
+                    });
+                    self.gamepads.swap_remove(index);
+                }
+            }
+        }
+    }
+
+    /// Plays the requested rumble effects, and follows the envelopes of those playing.
+    fn rumble(&mut self, requests: Vec<HapticsRequest>) {
+        for request in requests {
+            match request {
+                HapticsRequest::Play { which, effect } => {
+                    if let Some(gamepad) = self.gamepads.iter_mut().find(|g| g.which == which) {
+                        let motors = effect.motors_at(Duration::from_secs(0));
+                        gamepad
+                            .device
+                            .rumble(motors.0, motors.1, effect.duration, true);
+                        gamepad.rumble = Some((effect, Instant::now(), motors));
+                    }
+                }
+                HapticsRequest::Stop { which } => {
+                    if let Some(gamepad) = self.gamepads.iter_mut().find(|g| g.which == which) {
+                        gamepad.device.stop_rumble();
+                        gamepad.rumble = None;
+                    }
+                }
+            }
+        }
+        for gamepad in self.gamepads.iter_mut() {
+            if let Some((effect, start, ref mut motors)) = gamepad.rumble {
+                let elapsed = start.elapsed();
+                if elapsed >= effect.duration {
+                    gamepad.rumble = None;
+                    continue;
+                }
+                let current = effect.motors_at(elapsed);
+                if current != *motors {
+                    *motors = current;
+                    gamepad
+                        .device
+                        .rumble(current.0, current.1, effect.duration - elapsed, false);
+                }
+            }
+        }
+    }
+}
+
+impl Gamepad {
+    fn map(&mut self, inputs: &[RawInput], events: &mut Vec<ControllerEvent>) {
+        for input in inputs {
+            self.mapping
+                .map(self.which, *input, &mut self.state, events);
+        }
+    }
+}
+
+impl<'a, T: BindingTypes> System<'a> for GamepadEventsSystem<T> {
+    type SystemData = (
+        Write<'a, InputHandler<T>>,
+        Write<'a, EventChannel<InputEvent<T>>>,
+        Write<'a, InputRecorder>,
+        Write<'a, GamepadHaptics>,
+    );
+
+    fn run(&mut self, (mut handler, mut output, mut recorder, mut haptics): Self::SystemData) {
+        #[cfg(feature = "profiler")]
+        profile_scope!("gamepad_events_system");
+
+        let mut events = Vec::new();
+        let scan = match self.last_scan {
+            Some(last_scan) => last_scan.elapsed() >= SCAN_INTERVAL,
+            None => true,
+        };
+        if scan {
+            self.scan(&mut events);
+            self.last_scan = Some(Instant::now());
+        }
+        self.read(&mut events);
+        self.rumble(haptics.requests.drain(..).collect());
+
+        if recorder.replaying() {
+            return;
+        }
+        for event in events {
+            // This system runs after the `InputSystem`, which replays the event in the next frame.
+            recorder.record_for_next_frame(RecordedEvent::Controller(event));
+            handler.send_controller_event(&event, &mut output);
+        }
+    }
+}
@@ -0,0 +1,460 @@
+//! Rumble effects played on controllers.
+
+use std::{collections::VecDeque, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// How the strength of a rumble effect rises at its start and falls at its end.
+#[derive(PartialEq, Debug, Copy, Clone, Default, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Time the strength takes to rise from 0 at the start of the effect.
+    pub attack: Duration,
+    /// Time the strength takes to fall to 0 at the end of the effect.
+    pub fade: Duration,
+}
+
+/// A rumble effect, played on a controller through the [Haptics](struct.Haptics.html) resource.
+///
+/// Controllers usually have a low frequency motor, which makes strong and slow vibrations, and a
+/// high frequency motor, which makes weak and fast ones.
+#[derive(PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct RumbleEffect {
+    /// Strength of the low frequency motor, between 0 and 1.
+    pub strong: f32,
+    /// Strength of the high frequency motor, between 0 and 1.
+    pub weak: f32,
+    /// Time the effect plays for, including its envelope.
+    pub duration: Duration,
+    /// Rise and fall of the strength of the effect.
+    #[serde(default)]
+    pub envelope: Envelope,
+}
+
+impl RumbleEffect {
+    /// Creates an effect with the same strength on both motors, without envelope.
+    pub fn new(strength: f32, duration: Duration) -> Self {
+        RumbleEffect {
+            strong: strength,
+            weak: strength,
+            duration,
+            envelope: Envelope::default(),
+        }
+    }
+
+    /// Sets the strength of the low and high frequency motors.
+    pub fn with_motors(mut self, strong: f32, weak: f32) -> Self {
+        self.strong = strong;
+        self.weak = weak;
+        self
+    }
+
+    /// Sets the time the strength takes to rise at the start and to fall at the end of the
+    /// effect.
+    pub fn with_envelope(mut self, attack: Duration, fade: Duration) -> Self {
+        self.envelope = Envelope { attack, fade };
+        self
+    }
+
+    /// Returns the strength of the low and high frequency motors at the given time since the
+    /// start of the effect, for backends which drive the motors themselves.
+    pub fn motors_at(&self, elapsed: Duration) -> (f32, f32) {
+        if elapsed >= self.duration {
+            return (0.0, 0.0);
+        }
+        let ratio = |part: Duration, whole: Duration| {
+            if whole == Duration::from_secs(0) {
+                1.0
+            } else {
+                (part.as_secs_f32() / whole.as_secs_f32()).min(1.0)
+            }
+        };
+        let attack = ratio(elapsed, self.envelope.attack);
+        let fade = ratio(self.duration - elapsed, self.envelope.fade);
+        let level = attack.min(fade);
+        (
+            (self.strong * level).clamp(0.0, 1.0),
+            (self.weak * level).clamp(0.0, 1.0),
+        )
+    }
+}
+
+/// A change of the effect played by a controller, sent by the `HapticsSystem` to the
+/// `HapticsBackend`.
+#[derive(PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum HapticsRequest {
+    /// The controller starts playing the effect, replacing the one it played.
+    Play {
+        /// The controller id, as used by `ControllerEvent`.
+        which: u32,
+        /// The effect.
+        effect: RumbleEffect,
+    },
+    /// The controller stops playing effects.
+    Stop {
+        /// The controller id, as used by `ControllerEvent`.
+        which: u32,
+    },
+}
+
+/// Plays rumble effects on controllers, used by the `HapticsSystem`.
+///
+/// Backends are resources, so that the `HapticsSystem` can be built with `Default` and tests
+/// can read them back.
+pub trait HapticsBackend: Send + Sync + 'static {
+    /// Starts playing an effect on a controller, replacing the effect it played.
+    fn play(&mut self, which: u32, effect: &RumbleEffect);
+
+    /// Stops the effect played by a controller.
+    fn stop(&mut self, which: u32);
+}
+
+/// A backend which doesn't play effects, but records the requests it receives, so that headless
+/// tests can check which effects the game played.
+#[derive(Debug, Default)]
+pub struct RecordingHaptics {
+    requests: Vec<HapticsRequest>,
+}
+
+impl RecordingHaptics {
+    /// Returns the requests received, in order.
+    pub fn requests(&self) -> &[HapticsRequest] {
+        &self.requests
+    }
+
+    /// Returns the effect played by a controller according to the last request it received.
+    pub fn playing(&self, which: u32) -> Option<&RumbleEffect> {
+        self.requests
+            .iter()
+            .rev()
+            .find_map(|request| match request {
+                HapticsRequest::Play { which: id, effect } if *id == which => Some(Some(effect)),
+                HapticsRequest::Stop { which: id } if *id == which => Some(None),
+                _ => None,
+            })
+            .and_then(|effect| effect)
+    }
+
+    /// Forgets the requests received.
+    pub fn clear(&mut self) {
+        self.requests.clear();
+    }
+}
+
+impl HapticsBackend for RecordingHaptics {
+    fn play(&mut self, which: u32, effect: &RumbleEffect) {
+        self.requests.push(HapticsRequest::Play {
+            which,
+            effect: *effect,
+        });
+    }
+
+    fn stop(&mut self, which: u32) {
+        self.requests.push(HapticsRequest::Stop { which });
+    }
+}
+
+#[derive(Debug)]
+struct Playing {
+    effect: RumbleEffect,
+    /// None until the `HapticsSystem` starts the effect.
+    start: Option<Duration>,
+    /// True once the effect was sent to the backend.
+    sent: bool,
+}
+
+impl Playing {
+    fn new(effect: RumbleEffect, start: Option<Duration>) -> Self {
+        Playing {
+            effect,
+            start,
+            sent: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ControllerHaptics {
+    which: u32,
+    playing: Option<Playing>,
+    queue: VecDeque<RumbleEffect>,
+    /// True if the backend plays an effect, which it must be told to stop.
+    active: bool,
+}
+
+/// The rumble effects played on controllers.
+///
+/// Controllers are addressed by the ids used in `ControllerEvent`. Effects are started by the
+/// `HapticsSystem` on the frame after they are requested, and sent to its `HapticsBackend`.
+///
+/// ```rust,edition2018,no_run,noplaypen
+/// # use std::time::Duration;
+/// # use amethyst_input::{Haptics, RumbleEffect};
+/// # let mut haptics = Haptics::default();
+/// let hit = RumbleEffect::new(1.0, Duration::from_millis(200))
+///     .with_envelope(Duration::from_millis(0), Duration::from_millis(150));
+/// haptics.start(0, hit);
+/// // Rumble lightly after the hit.
+/// haptics.queue(0, RumbleEffect::new(0.2, Duration::from_millis(500)).with_motors(0.0, 0.2));
+/// ```
+#[derive(Debug, Default)]
+pub struct Haptics {
+    time: Duration,
+    controllers: Vec<ControllerHaptics>,
+}
+
+impl Haptics {
+    /// Plays an effect on a controller, replacing the effect it plays and those queued.
+    pub fn start(&mut self, which: u32, effect: RumbleEffect) {
+        let controller = self.controller_mut(which);
+        controller.queue.clear();
+        controller.playing = Some(Playing::new(effect, None));
+    }
+
+    /// Plays an effect on a controller once the effects it plays and those queued are finished.
+    pub fn queue(&mut self, which: u32, effect: RumbleEffect) {
+        let controller = self.controller_mut(which);
+        if controller.playing.is_some() {
+            controller.queue.push_back(effect);
+        } else {
+            controller.playing = Some(Playing::new(effect, None));
+        }
+    }
+
+    /// Stops the effect played by a controller and those queued.
+    pub fn stop(&mut self, which: u32) {
+        if let Some(controller) = self.controllers.iter_mut().find(|c| c.which == which) {
+            Self::stop_controller(controller);
+        }
+    }
+
+    /// Stops the effects of all controllers, e.g. when the game is paused.
+    pub fn stop_all(&mut self) {
+        for controller in self.controllers.iter_mut() {
+            Self::stop_controller(controller);
+        }
+    }
+
+    /// Forgets the effects of a controller which was disconnected, without sending any request
+    /// to the backend, as its id may be given to the next controller connected.
+    ///
+    /// The `HapticsSystem` calls this when it reads an `InputEvent::ControllerDisconnected`.
+    pub fn disconnect(&mut self, which: u32) {
+        self.controllers.retain(|c| c.which != which);
+    }
+
+    /// Returns the effect played by a controller, including an effect which is about to start.
+    pub fn playing(&self, which: u32) -> Option<&RumbleEffect> {
+        self.controllers
+            .iter()
+            .find(|c| c.which == which)
+            .and_then(|c| c.playing.as_ref())
+            .map(|playing| &playing.effect)
+    }
+
+    /// Returns the strength of the low and high frequency motors of a controller, as of the last
+    /// update of the `HapticsSystem`.
+    pub fn motors(&self, which: u32) -> (f32, f32) {
+        self.controllers
+            .iter()
+            .find(|c| c.which == which)
+            .and_then(|c| c.playing.as_ref())
+            .and_then(|playing| {
+                let start = playing.start?;
+                Some(playing.effect.motors_at(self.time.checked_sub(start)?))
+            })
+            .unwrap_or((0.0, 0.0))
+    }
+
+    /// Advances the effects to the given time, returning the requests for the backend.
+    ///
+    /// Effects which end before they were sent, e.g. effects of zero duration, are skipped.
+    pub(crate) fn update(&mut self, time: Duration) -> Vec<HapticsRequest> {
+        self.time = time;
+        let mut requests = Vec::new();
+        for controller in self.controllers.iter_mut() {
+            while let Some(ref mut playing) = controller.playing {
+                let start = *playing.start.get_or_insert(time);
+                let end = start + playing.effect.duration;
+                if time < end {
+                    break;
+                }
+                // Chain the queued effect to the end of the finished one.
+                controller.playing = controller
+                    .queue
+                    .pop_front()
+                    .map(|effect| Playing::new(effect, Some(end)));
+            }
+            let which = controller.which;
+            match controller.playing {
+                Some(ref mut playing) if !playing.sent => {
+                    playing.sent = true;
+                    controller.active = true;
+                    requests.push(HapticsRequest::Play {
+                        which,
+                        effect: playing.effect,
+                    });
+                }
+                None if controller.active => {
+                    controller.active = false;
+                    requests.push(HapticsRequest::Stop { which });
+                }
+                _ => {}
+            }
+        }
+        self.controllers
+            .retain(|controller| controller.playing.is_some());
+        requests
+    }
+
+    fn stop_controller(controller: &mut ControllerHaptics) {
+        // The backend is told to stop in the next update if it plays an effect.
+        controller.queue.clear();
+        controller.playing = None;
+    }
+
+    fn controller_mut(&mut self, which: u32) -> &mut ControllerHaptics {
+        match self.controllers.iter().position(|c| c.which == which) {
+            Some(index) => &mut self.controllers[index],
+            None => {
+                self.controllers.push(ControllerHaptics {
+                    which,
+                    playing: None,
+                    queue: VecDeque::new(),
+                    active: false,
+                });
+                self.controllers.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::assert_ulps_eq;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn envelopes() {
+        let effect = RumbleEffect::new(1.0, millis(1000))
+            .with_motors(0.8, 0.4)
+            .with_envelope(millis(200), millis(400));
+        assert_eq!(effect.motors_at(millis(0)), (0.0, 0.0));
+        let (strong, weak) = effect.motors_at(millis(100));
+        assert_ulps_eq!(strong, 0.4);
+        assert_ulps_eq!(weak, 0.2);
+        assert_eq!(effect.motors_at(millis(400)), (0.8, 0.4));
+        let (strong, _) = effect.motors_at(millis(700));
+        assert_ulps_eq!(strong, 0.6);
+        assert_eq!(effect.motors_at(millis(1000)), (0.0, 0.0));
+        assert_eq!(
+            RumbleEffect::new(0.5, millis(10)).motors_at(millis(0)),
+            (0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn effects_are_started_queued_and_stopped() {
+        let mut haptics = Haptics::default();
+        let short = RumbleEffect::new(1.0, millis(100));
+        let long = RumbleEffect::new(0.5, millis(300));
+        haptics.start(0, short);
+        haptics.queue(0, long);
+        haptics.queue(1, long);
+        assert_eq!(
+            haptics.update(millis(1000)),
+            vec![
+                HapticsRequest::Play {
+                    which: 0,
+                    effect: short
+                },
+                HapticsRequest::Play {
+                    which: 1,
+                    effect: long
+                },
+            ]
+        );
+        assert_eq!(haptics.motors(0), (1.0, 1.0));
+        assert_eq!(haptics.update(millis(1050)), vec![]);
+
+        // The queued effect starts when the first one ends.
+        assert_eq!(
+            haptics.update(millis(1150)),
+            vec![HapticsRequest::Play {
+                which: 0,
+                effect: long
+            }]
+        );
+        assert_eq!(haptics.playing(0), Some(&long));
+        haptics.stop(1);
+        assert_eq!(
+            haptics.update(millis(1200)),
+            vec![HapticsRequest::Stop { which: 1 }]
+        );
+        assert_eq!(
+            haptics.update(millis(1400)),
+            vec![HapticsRequest::Stop { which: 0 }]
+        );
+        assert_eq!(haptics.playing(0), None);
+
+        // Effects stopped before they start are never sent.
+        haptics.start(2, short);
+        haptics.stop_all();
+        assert_eq!(haptics.update(millis(1500)), vec![]);
+    }
+
+    #[test]
+    fn effects_ending_before_they_are_sent_are_skipped() {
+        let mut haptics = Haptics::default();
+        let blip = RumbleEffect::new(1.0, millis(0));
+        let short = RumbleEffect::new(1.0, millis(100));
+        haptics.start(0, blip);
+        assert_eq!(haptics.update(millis(1000)), vec![]);
+
+        // A queued effect finishing between two updates isn't sent either.
+        haptics.start(0, short);
+        haptics.queue(0, short);
+        assert_eq!(
+            haptics.update(millis(2000)),
+            vec![HapticsRequest::Play {
+                which: 0,
+                effect: short
+            }]
+        );
+        assert_eq!(
+            haptics.update(millis(2250)),
+            vec![HapticsRequest::Stop { which: 0 }]
+        );
+    }
+
+    #[test]
+    fn disconnected_controllers_are_forgotten() {
+        let mut haptics = Haptics::default();
+        let effect = RumbleEffect::new(1.0, millis(100));
+        haptics.start(0, effect);
+        haptics.queue(0, effect);
+        haptics.update(millis(0));
+        haptics.disconnect(0);
+        assert_eq!(haptics.playing(0), None);
+        // No request is sent to the next controller given the same id.
+        assert_eq!(haptics.update(millis(150)), vec![]);
+    }
+
+    #[test]
+    fn recording_backend_tracks_the_played_effects() {
+        let mut backend = RecordingHaptics::default();
+        let effect = RumbleEffect::new(1.0, millis(100));
+        backend.play(3, &effect);
+        backend.play(4, &effect);
+        backend.stop(4);
+        assert_eq!(backend.playing(3), Some(&effect));
+        assert_eq!(backend.playing(4), None);
+        assert_eq!(backend.requests().len(), 3);
+        backend.clear();
+        assert_eq!(backend.playing(3), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Controller rumble system
+use std::marker::PhantomData;
+
+use derivative::Derivative;
+use derive_new::new;
+
+use crate::{BindingTypes, Haptics, HapticsBackend, HapticsRequest, InputEvent};
+use amethyst_core::{
+    ecs::prelude::{Read, System, SystemData, World, Write},
+    shrev::{EventChannel, ReaderId},
+    timing::Time,
+    SystemDesc,
+};
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+/// Builds a `HapticsSystem`.
+#[derive(Derivative, Debug)]
+#[derivative(Default(bound = ""))]
+pub struct HapticsSystemDesc<T, B>
+where
+    T: BindingTypes,
+    B: HapticsBackend + Default,
+{
+    marker: PhantomData<(T, B)>,
+}
+
+impl<'a, 'b, T, B> SystemDesc<'a, 'b, HapticsSystem<T, B>> for HapticsSystemDesc<T, B>
+where
+    T: BindingTypes,
+    B: HapticsBackend + Default,
+{
+    fn build(self, world: &mut World) -> HapticsSystem<T, B> {
+        <HapticsSystem<T, B> as System<'_>>::SystemData::setup(world);
+
+        let reader = world
+            .fetch_mut::<EventChannel<InputEvent<T>>>()
+            .register_reader();
+
+        HapticsSystem::new(reader)
+    }
+}
+
+/// Controller rumble system
+///
+/// Starts, chains and stops the effects requested through the `Haptics` resource, and plays them
+/// with the `B` resource. Use `RecordingHaptics` as backend to check the effects played in
+/// headless tests. The effects of a controller are forgotten when it is disconnected.
+///
+/// Effects are timed with the real time, so that they end while the game is paused with a time
+/// scale of 0.
+#[derive(Derivative, new)]
+#[derivative(Debug(bound = ""))]
+pub struct HapticsSystem<T, B>
+where
+    T: BindingTypes,
+    B: HapticsBackend + Default,
+{
+    reader: ReaderId<InputEvent<T>>,
+    #[new(default)]
+    #[derivative(Debug = "ignore")]
+    marker: PhantomData<B>,
+}
+
+impl<'a, T, B> System<'a> for HapticsSystem<T, B>
+where
+    T: BindingTypes,
+    B: HapticsBackend + Default,
+{
+    type SystemData = (
+        Read<'a, EventChannel<InputEvent<T>>>,
+        Write<'a, Haptics>,
+        Write<'a, B>,
+        Read<'a, Time>,
+    );
+
+    fn run(&mut self, (input, mut haptics, mut backend, time): Self::SystemData) {
+        #[cfg(feature = "profiler")]
+        profile_scope!("haptics_system");
+
+        for event in input.read(&mut self.reader) {
+            if let InputEvent::ControllerDisconnected { which } = *event {
+                haptics.disconnect(which);
+            }
+        }
+        for request in haptics.update(time.absolute_real_time()) {
+            match request {
+                HapticsRequest::Play { which, effect } => backend.play(which, &effect),
+                HapticsRequest::Stop { which } => backend.stop(which),
+            }
+        }
+    }
+}
@@ -1,18 +1,23 @@
 //! World resource that handles all user input.
 
 use super::{
+    actions::{ActionHistory, Combo},
+    axis::{ControllerResponse, MAX_MOUSE_SMOOTHING},
+    capture::{Capture, CaptureKind, CapturedInput},
+    context::{by_priority, InputContext, Layer, VisibleActions},
     controller::{ControllerButton, ControllerEvent},
     event::InputEvent::{self, *},
     scroll_direction::ScrollDirection,
+    touch::{Gesture, GestureRecognizer, GestureSettings, TouchPoint},
     *,
 };
-use amethyst_core::shrev::EventChannel;
+use amethyst_core::{math::Vector2, shrev::EventChannel};
 use derivative::Derivative;
 use smallvec::SmallVec;
-use std::{borrow::Borrow, hash::Hash};
+use std::{borrow::Borrow, collections::VecDeque, hash::Hash, iter, time::Duration};
 use winit::{
     dpi::LogicalPosition, DeviceEvent, ElementState, Event, KeyboardInput, MouseButton,
-    MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    MouseScrollDelta, Touch, TouchPhase, VirtualKeyCode, WindowEvent,
 };
 
 /// This struct holds state information about input devices.
@@ -25,8 +30,12 @@ pub struct InputHandler<T>
 where
     T: BindingTypes,
 {
-    /// Maps inputs to actions and axes.
+    /// Maps inputs to actions and axes, below all input contexts.
     pub bindings: Bindings<T>,
+    /// Input contexts in the order they were pushed.
+    contexts: Vec<InputContext<T>>,
+    /// Actions of the contexts and bindings, kept while they don't change.
+    visible_actions: VisibleActions<T>,
     /// Encodes the VirtualKeyCode and corresponding scancode.
     pressed_keys: SmallVec<[(VirtualKeyCode, u32); 12]>,
     pressed_mouse_buttons: SmallVec<[MouseButton; 12]>,
@@ -39,8 +48,15 @@ where
     connected_controllers: SmallVec<[(u32, u32); 8]>,
     mouse_last_position: Option<(f32, f32)>,
     mouse_position: Option<(f32, f32)>,
+    /// Movements of the mouse in the previous frames, the last one first.
+    mouse_deltas: VecDeque<(f32, f32)>,
     mouse_wheel_vertical: f32,
     mouse_wheel_horizontal: f32,
+    capture: Option<Capture>,
+    /// Buttons held since their press was captured, whose actions are not pressed nor released.
+    captured_buttons: SmallVec<[Button; 2]>,
+    actions: ActionHistory<T>,
+    gestures: GestureRecognizer,
 }
 
 impl<T> InputHandler<T>
@@ -62,6 +78,7 @@ where
         event_handler: &mut EventChannel<InputEvent<T>>,
         hidpi: f32,
     ) {
+        self.refresh_visible_actions();
         match *event {
             Event::WindowEvent { ref event, .. } => match *event {
                 WindowEvent::ReceivedCharacter(c) => {
@@ -89,19 +106,22 @@ where
                             .cloned(),
                         );
                         self.send_axis_moved_events_key(event_handler, key_code, scancode);
-                        for (action, combinations) in self.bindings.actions.iter() {
-                            for combination in combinations.iter().filter(|c| {
-                                c.contains(&Button::Key(key_code))
-                                    || c.contains(&Button::ScanCode(scancode))
-                            }) {
-                                if combination
-                                    .iter()
-                                    .all(|button| self.button_is_down(*button))
-                                {
-                                    event_handler.single_write(ActionPressed(action.clone()));
-                                }
+                        if self.capture_button(Button::Key(key_code), event_handler) {
+                            return;
+                        }
+                        let mut actions = SmallVec::<[InputEvent<T>; 4]>::new();
+                        for (action, combination) in self.action_combinations().filter(|(_, c)| {
+                            c.contains(&Button::Key(key_code))
+                                || c.contains(&Button::ScanCode(scancode))
+                        }) {
+                            if combination
+                                .iter()
+                                .all(|button| self.button_is_down(*button))
+                            {
+                                actions.push(ActionPressed(action.clone()));
                             }
                         }
+                        self.send_action_events(actions, event_handler);
                     }
                 }
                 WindowEvent::KeyboardInput {
@@ -127,26 +147,29 @@ where
                             .cloned(),
                         );
                         self.send_axis_moved_events_key(event_handler, key_code, scancode);
-                        for (action, combinations) in self.bindings.actions.iter() {
-                            for combination in combinations {
-                                if combination.contains(&Button::Key(key_code))
-                                    && combination
-                                        .iter()
-                                        .filter(|b| b != &&Button::Key(key_code))
-                                        .all(|b| self.button_is_down(*b))
-                                {
-                                    event_handler.single_write(ActionReleased(action.clone()));
-                                }
-                                if combination.contains(&Button::ScanCode(scancode))
-                                    && combination
-                                        .iter()
-                                        .filter(|b| b != &&Button::ScanCode(scancode))
-                                        .all(|b| self.button_is_down(*b))
-                                {
-                                    event_handler.single_write(ActionReleased(action.clone()));
-                                }
+                        if self.release_captured(Button::Key(key_code)) {
+                            return;
+                        }
+                        let mut actions = SmallVec::<[InputEvent<T>; 4]>::new();
+                        for (action, combination) in self.action_combinations() {
+                            if combination.contains(&Button::Key(key_code))
+                                && combination
+                                    .iter()
+                                    .filter(|b| b != &&Button::Key(key_code))
+                                    .all(|b| self.button_is_down(*b))
+                            {
+                                actions.push(ActionReleased(action.clone()));
+                            }
+                            if combination.contains(&Button::ScanCode(scancode))
+                                && combination
+                                    .iter()
+                                    .filter(|b| b != &&Button::ScanCode(scancode))
+                                    .all(|b| self.button_is_down(*b))
+                            {
+                                actions.push(ActionReleased(action.clone()));
                             }
                         }
+                        self.send_action_events(actions, event_handler);
                     }
                 }
                 WindowEvent::MouseInput {
@@ -170,19 +193,22 @@ where
                             .cloned(),
                         );
                         self.send_axis_moved_events_mouse(event_handler, mouse_button);
-                        for (action, combinations) in self.bindings.actions.iter() {
-                            for combination in combinations
+                        if self.capture_button(Button::Mouse(mouse_button), event_handler) {
+                            return;
+                        }
+                        let mut actions = SmallVec::<[InputEvent<T>; 4]>::new();
+                        for (action, combination) in self
+                            .action_combinations()
+                            .filter(|(_, c)| c.contains(&Button::Mouse(mouse_button)))
+                        {
+                            if combination
                                 .iter()
-                                .filter(|c| c.contains(&Button::Mouse(mouse_button)))
+                                .all(|button| self.button_is_down(*button))
                             {
-                                if combination
-                                    .iter()
-                                    .all(|button| self.button_is_down(*button))
-                                {
-                                    event_handler.single_write(ActionPressed(action.clone()));
-                                }
+                                actions.push(ActionPressed(action.clone()));
                             }
                         }
+                        self.send_action_events(actions, event_handler);
                     }
                 }
                 WindowEvent::MouseInput {
@@ -206,18 +232,21 @@ where
                             .cloned(),
                         );
                         self.send_axis_moved_events_mouse(event_handler, mouse_button);
-                        for (action, combinations) in self.bindings.actions.iter() {
-                            for combination in combinations {
-                                if combination.contains(&Button::Mouse(mouse_button))
-                                    && combination
-                                        .iter()
-                                        .filter(|b| b != &&Button::Mouse(mouse_button))
-                                        .all(|b| self.button_is_down(*b))
-                                {
-                                    event_handler.single_write(ActionReleased(action.clone()));
-                                }
+                        if self.release_captured(Button::Mouse(mouse_button)) {
+                            return;
+                        }
+                        let mut actions = SmallVec::<[InputEvent<T>; 4]>::new();
+                        for (action, combination) in self.action_combinations() {
+                            if combination.contains(&Button::Mouse(mouse_button))
+                                && combination
+                                    .iter()
+                                    .filter(|b| b != &&Button::Mouse(mouse_button))
+                                    .all(|b| self.button_is_down(*b))
+                            {
+                                actions.push(ActionReleased(action.clone()));
                             }
                         }
+                        self.send_action_events(actions, event_handler);
                     }
                 }
                 WindowEvent::CursorMoved {
@@ -232,10 +261,24 @@ where
                     }
                     self.mouse_position = Some(((x as f32) * hidpi, (y as f32) * hidpi));
                 }
+                WindowEvent::Touch(Touch {
+                    phase,
+                    location: LogicalPosition { x, y },
+                    id,
+                    ..
+                }) => {
+                    let position = ((x as f32) * hidpi, (y as f32) * hidpi);
+                    self.send_touch(id, phase, position, event_handler);
+                }
                 WindowEvent::Focused(false) => {
+                    self.actions.release_all();
                     self.pressed_keys.clear();
                     self.pressed_mouse_buttons.clear();
+                    self.captured_buttons
+                        .retain(|b| matches!(b, Button::Controller(..)));
                     self.mouse_position = None;
+                    self.mouse_deltas.clear();
+                    self.gestures.clear();
                 }
                 _ => {}
             },
@@ -247,6 +290,9 @@ where
                         delta_x: delta_x as f32,
                         delta_y: delta_y as f32,
                     });
+                    self.capture_input(event_handler, |c| {
+                        c.mouse_motion(delta_x as f32, delta_y as f32)
+                    });
                 }
                 DeviceEvent::MouseWheel {
                     delta: MouseScrollDelta::LineDelta(delta_x, delta_y),
@@ -258,6 +304,7 @@ where
                         self.mouse_wheel_vertical = delta_y.signum();
                     }
                     self.invoke_wheel_moved(delta_x, delta_y, event_handler);
+                    self.capture_mouse_wheel(delta_x, delta_y, event_handler);
                 }
                 DeviceEvent::MouseWheel {
                     delta: MouseScrollDelta::PixelDelta(LogicalPosition { x, y }),
@@ -269,6 +316,7 @@ where
                         self.mouse_wheel_vertical = y.signum() as f32;
                     }
                     self.invoke_wheel_moved(x as f32, y as f32, event_handler);
+                    self.capture_mouse_wheel(x as f32, y as f32, event_handler);
                 }
                 _ => {}
             },
@@ -288,6 +336,7 @@ where
     ) {
         use self::ControllerEvent::*;
 
+        self.refresh_visible_actions();
         match *event {
             ControllerAxisMoved { which, axis, value } => {
                 if let Some(controller_id) = self.controller_idx_to_id(which) {
@@ -299,6 +348,9 @@ where
                             self.controller_axes.push((controller_id, axis, value));
                         });
                     event_handler.single_write(event.into());
+                    self.capture_input(event_handler, |c| {
+                        c.controller_axis(controller_id, axis, value)
+                    });
                 }
             }
             ControllerButtonPressed { which, button } => {
@@ -318,19 +370,23 @@ where
                             .iter()
                             .cloned(),
                         );
-                        for (action, combinations) in self.bindings.actions.iter() {
-                            for combination in combinations
+                        let pressed = Button::Controller(controller_id, button);
+                        if self.capture_button(pressed, event_handler) {
+                            return;
+                        }
+                        let mut actions = SmallVec::<[InputEvent<T>; 4]>::new();
+                        for (action, combination) in self
+                            .action_combinations()
+                            .filter(|(_, c)| c.contains(&pressed))
+                        {
+                            if combination
                                 .iter()
-                                .filter(|c| c.contains(&Button::Controller(controller_id, button)))
+                                .all(|button| self.button_is_down(*button))
                             {
-                                if combination
-                                    .iter()
-                                    .all(|button| self.button_is_down(*button))
-                                {
-                                    event_handler.single_write(ActionPressed(action.clone()));
-                                }
+                                actions.push(ActionPressed(action.clone()));
                             }
                         }
+                        self.send_action_events(actions, event_handler);
                     }
                 }
             }
@@ -350,22 +406,22 @@ where
                             .iter()
                             .cloned(),
                         );
-                        for (action, combinations) in self.bindings.actions.iter() {
-                            for combination in combinations {
-                                if combination.contains(&Button::Controller(controller_id, button))
-                                {
-                                    let down = combination
-                                        .iter()
-                                        .filter(|b| {
-                                            b != &&Button::Controller(controller_id, button)
-                                        })
-                                        .all(|b| self.button_is_down(*b));
-                                    if down {
-                                        event_handler.single_write(ActionReleased(action.clone()));
-                                    }
+                        if self.release_captured(Button::Controller(controller_id, button)) {
+                            return;
+                        }
+                        let mut actions = SmallVec::<[InputEvent<T>; 4]>::new();
+                        for (action, combination) in self.action_combinations() {
+                            if combination.contains(&Button::Controller(controller_id, button)) {
+                                let down = combination
+                                    .iter()
+                                    .filter(|b| b != &&Button::Controller(controller_id, button))
+                                    .all(|b| self.button_is_down(*b));
+                                if down {
+                                    actions.push(ActionReleased(action.clone()));
                                 }
                             }
                         }
+                        self.send_action_events(actions, event_handler);
                     }
                 }
             }
@@ -392,6 +448,9 @@ where
                         self.controller_axes.retain(|a| a.0 != controller_id);
                         self.pressed_controller_buttons
                             .retain(|b| b.0 != controller_id);
+                        self.captured_buttons.retain(
+                            |b| !matches!(b, Button::Controller(id, _) if *id == controller_id),
+                        );
                     }
                 }
             }
@@ -403,9 +462,95 @@ where
     /// The `InputSystem` will call this automatically. If you're using that system, you
     /// don't need to call this function.
     pub fn send_frame_begin(&mut self) {
+        self.refresh_visible_actions();
+        self.actions.frame_begin();
         self.mouse_wheel_vertical = 0.0;
         self.mouse_wheel_horizontal = 0.0;
+        let delta = self.mouse_delta(1);
+        self.mouse_deltas.push_front(delta);
+        self.mouse_deltas.truncate(MAX_MOUSE_SMOOTHING - 1);
         self.mouse_last_position = self.mouse_position;
+        self.gestures.frame_begin();
+    }
+
+    /// Returns the movement of the mouse in this frame, averaged with that of previous frames.
+    fn mouse_delta(&self, frames: u32) -> (f32, f32) {
+        let current_pos = self.mouse_position.unwrap_or((0., 0.));
+        let last_pos = self.mouse_last_position.unwrap_or(current_pos);
+        // These calculations have to be inverses in order to point into the right direction of movement
+        let delta = (last_pos.0 - current_pos.0, last_pos.1 - current_pos.1);
+        let frames = (frames as usize).clamp(1, MAX_MOUSE_SMOOTHING);
+        let deltas = iter::once(delta)
+            .chain(self.mouse_deltas.iter().cloned())
+            .take(frames)
+            .collect::<SmallVec<[(f32, f32); MAX_MOUSE_SMOOTHING]>>();
+        let count = deltas.len() as f32;
+        let (sum_x, sum_y) = deltas
+            .iter()
+            .fold((0.0, 0.0), |(x, y), delta| (x + delta.0, y + delta.1));
+        (sum_x / count, sum_y / count)
+    }
+
+    /// Sets the time of the current frame, used to know how long actions are held and whether
+    /// combos are performed within their window.
+    ///
+    /// The `InputSystem` calls this with `Time::absolute_time` after `send_frame_begin`.
+    pub fn send_frame_time(&mut self, time: Duration) {
+        self.actions.set_time(time);
+    }
+
+    /// Recognizes the gestures made by holding touches rather than by touch events, such as long
+    /// presses.
+    ///
+    /// The `InputSystem` calls this after `send_frame_time`.
+    pub fn send_frame_gestures(&mut self, event_handler: &mut EventChannel<InputEvent<T>>) {
+        self.refresh_visible_actions();
+        let mut events = Vec::new();
+        self.gestures.update(self.actions.time(), &mut events);
+        self.send_touch_events(events, event_handler);
+    }
+
+    /// Sends `ActionReleased` for the held actions whose bindings were hidden since they were
+    /// pressed, e.g. by a context consuming all input pushed when a menu opens.
+    ///
+    /// The `InputSystem` calls this after `send_frame_gestures`.
+    pub fn send_hidden_releases(&mut self, event_handler: &mut EventChannel<InputEvent<T>>) {
+        self.refresh_visible_actions();
+        let released = self
+            .actions
+            .held()
+            .filter(|action| self.action_is_down(*action) != Some(true))
+            .map(|action| ActionReleased(action.clone()))
+            .collect::<SmallVec<[_; 4]>>();
+        self.send_action_events(released, event_handler);
+    }
+
+    /// Returns the fingers touching the screen, and those which left it in this frame.
+    pub fn touches(&self) -> impl Iterator<Item = &TouchPoint> {
+        self.gestures.touches().iter()
+    }
+
+    /// Returns the finger with the given id, if it touches the screen or left it in this frame.
+    pub fn touch(&self, id: u64) -> Option<&TouchPoint> {
+        self.gestures
+            .touches()
+            .iter()
+            .find(|touch| touch.id() == id)
+    }
+
+    /// Returns true if a finger touches the screen.
+    pub fn is_touched(&self) -> bool {
+        self.gestures.active_touches() > 0
+    }
+
+    /// Returns the thresholds used to recognize gestures.
+    pub fn gesture_settings(&self) -> &GestureSettings {
+        &self.gestures.settings
+    }
+
+    /// Sets the thresholds used to recognize gestures.
+    pub fn set_gesture_settings(&mut self, settings: GestureSettings) {
+        self.gestures.settings = settings;
     }
 
     /// Returns an iterator over all keys that are down.
@@ -504,7 +649,16 @@ where
             .iter()
             .map(|&gb| Button::Controller(gb.0, gb.1));
 
-        mouse_buttons.chain(keys).chain(controller_buttons)
+        let touch = if self.is_touched() {
+            Some(Button::Touch)
+        } else {
+            None
+        };
+
+        mouse_buttons
+            .chain(keys)
+            .chain(controller_buttons)
+            .chain(touch)
     }
 
     /// Checks if a button is down.
@@ -514,17 +668,61 @@ where
             Button::Mouse(b) => self.mouse_button_is_down(b),
             Button::ScanCode(s) => self.scan_code_is_down(s),
             Button::Controller(g, b) => self.controller_button_is_down(g, b),
+            Button::Touch => self.is_touched(),
             _ => false,
         }
     }
 
     /// Returns the value of an axis by the id, if the id doesn't exist this returns None.
+    ///
+    /// The axis is looked up in the input contexts from the highest priority to the lowest, then
+    /// in `bindings`. It returns 0 if its input is hidden by a context above, e.g. when the axis
+    /// is bound below a context consuming all input.
     pub fn axis_value<A>(&self, id: &A) -> Option<f32>
     where
         T::Axis: Borrow<A>,
         A: Hash + Eq + ?Sized,
     {
-        self.bindings.axes.get(id).map(|a| match *a {
+        let layers = self.layers();
+        layers.iter().enumerate().find_map(|(i, layer)| {
+            layer.bindings.axes.get(id).map(|axis| {
+                if Layer::axis_consumed(&layers[..i], axis) {
+                    0.0
+                } else {
+                    self.binding_value(axis)
+                }
+            })
+        })
+    }
+
+    /// Returns the value of a 2D axis by the id, if the id doesn't exist this returns None.
+    ///
+    /// The 2D axis is looked up like axes are, and its X or Y value is 0 if the input of this
+    /// axis is hidden by a context above.
+    pub fn axis2_value<A>(&self, id: &A) -> Option<Vector2<f32>>
+    where
+        T::Axis: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        let layers = self.layers();
+        layers.iter().enumerate().find_map(|(i, layer)| {
+            layer.bindings.axes2.get(id).map(|axis2| {
+                let value = |axis: &Axis| {
+                    if Layer::axis_consumed(&layers[..i], axis) {
+                        0.0
+                    } else {
+                        self.binding_value(axis)
+                    }
+                };
+                let (x, y) = axis2.axes();
+                axis2.value(value(&x), value(&y))
+            })
+        })
+    }
+
+    /// Returns the value of an axis binding.
+    pub(crate) fn binding_value(&self, axis: &Axis) -> f32 {
+        match *axis {
             Axis::Emulated { pos, neg, .. } => {
                 match (self.button_is_down(pos), self.button_is_down(neg)) {
                     (true, false) => 1.0,
@@ -537,37 +735,50 @@ where
                 axis,
                 invert,
                 dead_zone,
-                ..
-            } => self
-                .controller_axes
-                .iter()
-                .find(|&&(id, a, _)| id == controller_id && a == axis)
-                .map(|&(_, _, val)| if invert { -val } else { val })
-                .map(|val| {
-                    let dead_zone = dead_zone as f32;
-                    if val < -dead_zone {
-                        (val + dead_zone) / (1.0 - dead_zone)
-                    } else if val > dead_zone {
-                        (val - dead_zone) / (1.0 - dead_zone)
-                    } else {
-                        0.0
+                dead_zone_shape,
+                ref response,
+                sensitivity,
+            } => {
+                let response = ControllerResponse {
+                    axis,
+                    invert,
+                    dead_zone,
+                    dead_zone_shape,
+                    response,
+                    sensitivity,
+                };
+                let position = |axis| {
+                    self.controller_axes
+                        .iter()
+                        .find(|&&(id, a, _)| id == controller_id && a == axis)
+                        .map(|&(_, _, val)| val)
+                };
+                match position(axis) {
+                    Some(val) => {
+                        let paired = response
+                            .paired_axis()
+                            .map(|paired| position(paired).unwrap_or(0.0));
+                        response.value(val, paired)
                     }
-                })
-                .unwrap_or(0.0),
+                    None => 0.0,
+                }
+            }
             Axis::Mouse {
                 axis,
                 over_extendable,
                 radius,
+                sensitivity,
+                smoothing,
+                acceleration,
             } => {
-                let current_pos = self.mouse_position.unwrap_or((0., 0.));
-                let last_pos = self.mouse_last_position.unwrap_or(current_pos);
+                let (delta_x, delta_y) = self.mouse_delta(smoothing);
+                let scale = sensitivity * (1.0 + acceleration * delta_x.hypot(delta_y));
                 let delta = match axis {
-                    // These calculations have to be inverses in order to point into the right direction of movement
-                    MouseAxis::X => last_pos.0 - current_pos.0,
-                    MouseAxis::Y => last_pos.1 - current_pos.1,
+                    MouseAxis::X => delta_x,
+                    MouseAxis::Y => delta_y,
                 };
 
-                let rel_delta = delta / radius;
+                let rel_delta = delta * scale / radius;
 
                 if over_extendable {
                     rel_delta
@@ -580,24 +791,417 @@ where
                 }
             }
             Axis::MouseWheel { horizontal } => self.mouse_wheel_value(horizontal),
-        })
+        }
     }
 
     /// Returns true if any of the actions bindings is down.
     ///
     /// If a binding represents a combination of buttons, all of them need to be down.
+    /// Bindings in all input contexts are checked, except those hidden by a context above, so an
+    /// action bound below a context consuming all input is never down.
     pub fn action_is_down<A>(&self, action: &A) -> Option<bool>
     where
         T::Action: Borrow<A>,
         A: Hash + Eq + ?Sized,
     {
-        self.bindings.actions.get(action).map(|combinations| {
-            combinations.iter().any(|combination| {
-                combination
-                    .iter()
-                    .all(|button| self.button_is_down(*button))
+        let down = |visible: &VisibleActions<T>| {
+            visible.get(action).map(|combinations| {
+                combinations.iter().any(|combination| {
+                    combination
+                        .iter()
+                        .all(|button| self.button_is_down(*button))
+                })
             })
-        })
+        };
+        if self
+            .visible_actions
+            .is_current(&self.contexts, &self.bindings)
+        {
+            down(&self.visible_actions)
+        } else {
+            down(&VisibleActions::new(&self.contexts, &self.bindings))
+        }
+    }
+
+    /// Returns true if the action was pressed during this frame, even if it was released since.
+    pub fn action_just_pressed<A>(&self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.actions.just_pressed(action)
+    }
+
+    /// Returns true if a binding of the action was released during this frame.
+    pub fn action_just_released<A>(&self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.actions.just_released(action)
+    }
+
+    /// Returns true if the action has been held down for at least the given duration, measured
+    /// with the times given to `send_frame_time`.
+    pub fn action_held_for<A>(&self, action: &A, duration: Duration) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        match self.actions.held_for(action) {
+            Some(held) => held >= duration,
+            None => false,
+        }
+    }
+
+    /// Returns true if the action was pressed during the last frames of the input buffer, and the
+    /// press wasn't consumed with `consume_buffered`.
+    ///
+    /// This lets a jump pressed just before landing still be performed when landing.
+    pub fn action_buffered<A>(&self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.actions.buffered(action)
+    }
+
+    /// Consumes the buffered presses of the action, so that they are only acted on once. Returns
+    /// true if the action was buffered.
+    pub fn consume_buffered<A>(&mut self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.actions.consume_buffered(action)
+    }
+
+    /// Returns the number of frames before the current one during which presses are buffered.
+    pub fn input_buffer_frames(&self) -> u64 {
+        self.actions.buffer_frames
+    }
+
+    /// Sets the number of frames before the current one during which presses are buffered,
+    /// 6 by default. With 0, only presses of the current frame are buffered.
+    pub fn set_input_buffer_frames(&mut self, frames: u64) {
+        self.actions.buffer_frames = frames;
+    }
+
+    /// Adds a combo, sent as `InputEvent::ComboPerformed` when its sequence of actions is pressed.
+    pub fn add_combo(&mut self, combo: Combo<T>) {
+        self.actions.add_combo(combo);
+    }
+
+    /// Removes the combo performing the given action.
+    pub fn remove_combo<A>(&mut self, action: &A) -> Option<Combo<T>>
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.actions.remove_combo(action)
+    }
+
+    /// Returns true if the combo performing the action was performed during this frame.
+    pub fn combo_performed<A>(&self, action: &A) -> bool
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.actions.performed(action)
+    }
+
+    /// Adds an input context above the contexts of lower or equal priority.
+    ///
+    /// A context with the same name is replaced.
+    pub fn push_context(&mut self, context: InputContext<T>) {
+        self.contexts.retain(|c| c.name != context.name);
+        self.contexts.push(context);
+        self.refresh_visible_actions();
+    }
+
+    /// Removes the last pushed input context.
+    pub fn pop_context(&mut self) -> Option<InputContext<T>> {
+        let context = self.contexts.pop();
+        self.refresh_visible_actions();
+        context
+    }
+
+    /// Removes the input context with the given name.
+    pub fn remove_context(&mut self, name: &str) -> Option<InputContext<T>> {
+        let index = self.contexts.iter().position(|c| c.name == name)?;
+        let context = self.contexts.remove(index);
+        self.refresh_visible_actions();
+        Some(context)
+    }
+
+    /// Returns the input context with the given name.
+    pub fn context(&self, name: &str) -> Option<&InputContext<T>> {
+        self.contexts.iter().find(|c| c.name == name)
+    }
+
+    /// Returns the input context with the given name, e.g. to edit its bindings.
+    pub fn context_mut(&mut self, name: &str) -> Option<&mut InputContext<T>> {
+        self.visible_actions.invalidate();
+        self.contexts.iter_mut().find(|c| c.name == name)
+    }
+
+    /// Returns the input contexts from the highest priority to the lowest, including those hidden
+    /// by a context consuming all input.
+    pub fn contexts(&self) -> impl Iterator<Item = &InputContext<T>> {
+        by_priority(&self.contexts).into_iter()
+    }
+
+    /// Returns the bindings in which actions and axes are looked up, in order.
+    fn layers(&self) -> SmallVec<[Layer<'_, T>; 8]> {
+        Layer::stack(&self.contexts, &self.bindings).collect()
+    }
+
+    /// Looks up the actions hidden by the contexts again if the contexts or bindings changed.
+    ///
+    /// Called when contexts are added or removed, and by the methods sending events, before they
+    /// use `action_combinations`.
+    fn refresh_visible_actions(&mut self) {
+        if !self
+            .visible_actions
+            .is_current(&self.contexts, &self.bindings)
+        {
+            self.visible_actions = VisibleActions::new(&self.contexts, &self.bindings);
+        }
+    }
+
+    /// Returns the combinations bound to actions, leaving out those hidden by a context above.
+    fn action_combinations(&self) -> impl Iterator<Item = (&T::Action, &[Button])> {
+        debug_assert!(self
+            .visible_actions
+            .is_current(&self.contexts, &self.bindings));
+        self.visible_actions.iter()
+    }
+
+    /// Returns the axes bound in all layers, leaving out those hidden by a context above.
+    fn axis_bindings(&self) -> impl Iterator<Item = (&T::Axis, &Axis)> {
+        let layers = self.layers();
+        let mut axes = SmallVec::<[(&T::Axis, &Axis); 16]>::new();
+        for (i, layer) in layers.iter().enumerate() {
+            axes.extend(
+                layer
+                    .bindings
+                    .axes
+                    .iter()
+                    .filter(|(_, axis)| !Layer::axis_consumed(&layers[..i], axis)),
+            );
+        }
+        axes.into_iter()
+    }
+
+    /// Starts recording the next input of the given kind, to let the player choose a new binding.
+    ///
+    /// The input is sent as `InputEvent::InputCaptured`, which ends the capture. It still sends
+    /// its raw events, such as `KeyPressed` and `ButtonPressed`, but a captured button doesn't
+    /// press or release the actions it is bound to. Buttons which are already down when the
+    /// capture starts are only recorded if they are pressed again.
+    ///
+    /// Check the recorded input with
+    /// [action_conflicts](struct.Bindings.html#method.action_conflicts) or
+    /// [axis_conflicts](struct.Bindings.html#method.axis_conflicts) before binding it.
+    pub fn start_capture(&mut self, kind: CaptureKind) {
+        self.capture = Some(Capture::new(kind));
+    }
+
+    /// Stops the capture in progress without recording anything.
+    pub fn cancel_capture(&mut self) {
+        self.capture = None;
+    }
+
+    /// Returns the kind of the capture in progress, if any.
+    pub fn capturing(&self) -> Option<CaptureKind> {
+        self.capture.as_ref().map(Capture::kind)
+    }
+
+    /// Records the presses and releases of actions, then sends them with the combos they perform.
+    fn send_action_events(
+        &mut self,
+        actions: SmallVec<[InputEvent<T>; 4]>,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) {
+        let mut combos = SmallVec::<[InputEvent<T>; 2]>::new();
+        for event in actions.iter() {
+            match event {
+                ActionPressed(action) => {
+                    combos.extend(self.actions.press(action).into_iter().map(ComboPerformed));
+                }
+                ActionReleased(action) => {
+                    let down = self.action_is_down(action).unwrap_or(false);
+                    self.actions.release(action, down);
+                }
+                _ => {}
+            }
+        }
+        event_handler.iter_write(actions);
+        event_handler.iter_write(combos);
+    }
+
+    fn send_touch(
+        &mut self,
+        id: u64,
+        phase: TouchPhase,
+        position: (f32, f32),
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) {
+        let was_touched = self.is_touched();
+        let mut events = Vec::new();
+        self.gestures
+            .touch(id, phase, position, self.actions.time(), &mut events);
+        self.send_touch_events(events, event_handler);
+
+        let mut actions = SmallVec::<[InputEvent<T>; 4]>::new();
+        match (was_touched, self.is_touched()) {
+            (false, true) => {
+                event_handler.single_write(ButtonPressed(Button::Touch));
+                if self.capture_button(Button::Touch, event_handler) {
+                    return;
+                }
+                for (action, combination) in self
+                    .action_combinations()
+                    .filter(|(_, c)| c.contains(&Button::Touch))
+                {
+                    if combination
+                        .iter()
+                        .all(|button| self.button_is_down(*button))
+                    {
+                        actions.push(ActionPressed(action.clone()));
+                    }
+                }
+                self.send_action_events(actions, event_handler);
+            }
+            (true, false) => {
+                event_handler.single_write(ButtonReleased(Button::Touch));
+                if self.release_captured(Button::Touch) {
+                    return;
+                }
+                for (action, combination) in self.action_combinations() {
+                    if combination.contains(&Button::Touch)
+                        && combination
+                            .iter()
+                            .filter(|b| **b != Button::Touch)
+                            .all(|b| self.button_is_down(*b))
+                    {
+                        actions.push(ActionReleased(action.clone()));
+                    }
+                }
+                self.send_action_events(actions, event_handler);
+            }
+            _ => {}
+        }
+    }
+
+    /// Sends the events of the touches, pressing and releasing the buttons of the gestures.
+    fn send_touch_events(
+        &mut self,
+        events: Vec<InputEvent<T>>,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) {
+        for event in events {
+            let gesture = match event {
+                Tap { .. } => Some(Gesture::Tap),
+                DoubleTap { .. } => Some(Gesture::DoubleTap),
+                LongPress { .. } => Some(Gesture::LongPress),
+                Swipe { direction, .. } => Some(Gesture::Swipe(direction)),
+                _ => None,
+            };
+            event_handler.single_write(event);
+            if let Some(gesture) = gesture {
+                self.send_gesture(gesture, event_handler);
+            }
+        }
+    }
+
+    fn send_gesture(&mut self, gesture: Gesture, event_handler: &mut EventChannel<InputEvent<T>>) {
+        let button = Button::Gesture(gesture);
+        let mut pressed = SmallVec::<[InputEvent<T>; 4]>::new();
+        let mut released = SmallVec::<[InputEvent<T>; 4]>::new();
+        for (action, combination) in self.action_combinations() {
+            if combination.contains(&button)
+                && combination
+                    .iter()
+                    .filter(|b| **b != button)
+                    .all(|b| self.button_is_down(*b))
+            {
+                pressed.push(ActionPressed(action.clone()));
+                released.push(ActionReleased(action.clone()));
+            }
+        }
+        event_handler.single_write(ButtonPressed(button));
+        if self.capture_button(button, event_handler) {
+            self.release_captured(button);
+            event_handler.single_write(ButtonReleased(button));
+            return;
+        }
+        self.send_action_events(pressed, event_handler);
+        event_handler.single_write(ButtonReleased(button));
+        self.send_action_events(released, event_handler);
+    }
+
+    /// Sends the input recorded by the capture in progress, if `record` recorded one, and
+    /// returns true if it did.
+    fn capture_input<F>(
+        &mut self,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+        record: F,
+    ) -> bool
+    where
+        F: FnOnce(&mut Capture) -> Option<CapturedInput>,
+    {
+        match self.capture.as_mut().and_then(record) {
+            Some(captured) => {
+                self.capture = None;
+                event_handler.single_write(InputCaptured(captured));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sends a pressed button to the capture in progress, and returns true if it was captured,
+    /// in which case the actions it is bound to are not pressed.
+    fn capture_button(
+        &mut self,
+        button: Button,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) -> bool {
+        let captured = self.capture_input(event_handler, |c| c.button(button));
+        if captured {
+            self.captured_buttons.push(button);
+        }
+        captured
+    }
+
+    /// Returns true if the press of the released button was captured, in which case the actions
+    /// it is bound to are not released.
+    fn release_captured(&mut self, button: Button) -> bool {
+        let index = self.captured_buttons.iter().position(|b| *b == button);
+        index
+            .map(|i| self.captured_buttons.swap_remove(i))
+            .is_some()
+    }
+
+    fn capture_mouse_wheel(
+        &mut self,
+        delta_x: f32,
+        delta_y: f32,
+        event_handler: &mut EventChannel<InputEvent<T>>,
+    ) {
+        let direction = if delta_y < 0.0 {
+            ScrollDirection::ScrollDown
+        } else if delta_y > 0.0 {
+            ScrollDirection::ScrollUp
+        } else if delta_x > 0.0 {
+            ScrollDirection::ScrollRight
+        } else if delta_x < 0.0 {
+            ScrollDirection::ScrollLeft
+        } else {
+            return;
+        };
+        self.capture_input(event_handler, |c| c.mouse_wheel(direction));
     }
 
     /// Retrieve next free controller number to allocate new controller to
@@ -660,27 +1264,25 @@ where
         };
 
         // check for actions being bound to any invoked mouse wheel
-        for (action, combinations) in self.bindings.actions.iter() {
-            for combination in combinations {
-                if let Some(dir) = dir_x {
-                    if combination.contains(&Button::MouseWheel(dir))
-                        && combination
-                            .iter()
-                            .filter(|b| **b != Button::MouseWheel(dir))
-                            .all(|b| self.button_is_down(*b))
-                    {
-                        events.push(ActionWheelMoved(action.clone()));
-                    }
+        for (action, combination) in self.action_combinations() {
+            if let Some(dir) = dir_x {
+                if combination.contains(&Button::MouseWheel(dir))
+                    && combination
+                        .iter()
+                        .filter(|b| **b != Button::MouseWheel(dir))
+                        .all(|b| self.button_is_down(*b))
+                {
+                    events.push(ActionWheelMoved(action.clone()));
                 }
-                if let Some(dir) = dir_y {
-                    if combination.contains(&Button::MouseWheel(dir))
-                        && combination
-                            .iter()
-                            .filter(|b| **b != Button::MouseWheel(dir))
-                            .all(|b| self.button_is_down(*b))
-                    {
-                        events.push(ActionWheelMoved(action.clone()));
-                    }
+            }
+            if let Some(dir) = dir_y {
+                if combination.contains(&Button::MouseWheel(dir))
+                    && combination
+                        .iter()
+                        .filter(|b| **b != Button::MouseWheel(dir))
+                        .all(|b| self.button_is_down(*b))
+                {
+                    events.push(ActionWheelMoved(action.clone()));
                 }
             }
         }
@@ -695,11 +1297,9 @@ where
         key_code: VirtualKeyCode,
         scancode: u32,
     ) {
-        for (axis, input_axis) in self.bindings.axes.iter() {
+        for (axis, input_axis) in self.axis_bindings() {
             if let Axis::Emulated { pos, neg } = input_axis {
-                let value = self
-                    .axis_value(axis)
-                    .expect("Unreachable: `axis` is from bindings axes.");
+                let value = self.binding_value(input_axis);
                 match *pos {
                     Button::Key(key_code_pos) if key_code_pos == key_code => {
                         event_handler.single_write(AxisMoved {
@@ -740,11 +1340,9 @@ where
         event_handler: &mut EventChannel<InputEvent<T>>,
         mouse_button: MouseButton,
     ) {
-        for (axis, input_axis) in self.bindings.axes.iter() {
+        for (axis, input_axis) in self.axis_bindings() {
             if let Axis::Emulated { pos, neg } = input_axis {
-                let value = self
-                    .axis_value(axis)
-                    .expect("Unreachable: `axis` is from bindings axes.");
+                let value = self.binding_value(input_axis);
                 match *pos {
                     Button::Mouse(mouse_button_pos) if mouse_button_pos == mouse_button => {
                         event_handler.single_write(AxisMoved {
@@ -1228,6 +1826,636 @@ mod tests {
         assert_ulps_eq!(handler.mouse_wheel_value(true), -1.0);
     }
 
+    #[test]
+    fn capture_records_next_input() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        let captured = |events: &EventChannel<_>, reader: &mut _| {
+            events
+                .read(reader)
+                .filter_map(|event| match event {
+                    InputEvent::<StringBindings>::InputCaptured(input) => Some(input.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Buttons are ignored when capturing axes.
+        handler.start_capture(CaptureKind::Axis);
+        handler.send_event(&key_press(104, VirtualKeyCode::Up), &mut events, HIDPI);
+        handler.send_event(&mouse_motion(30.0, -20.0), &mut events, HIDPI);
+        assert_eq!(captured(&events, &mut reader), vec![]);
+        handler.send_event(&mouse_motion(30.0, -20.0), &mut events, HIDPI);
+        assert_eq!(
+            captured(&events, &mut reader),
+            vec![CapturedInput::Axis(Axis::Mouse {
+                axis: MouseAxis::X,
+                over_extendable: false,
+                radius: 50.0,
+                sensitivity: 1.0,
+                smoothing: 1,
+                acceleration: 0.0,
+            })]
+        );
+        assert_eq!(handler.capturing(), None);
+
+        // A button held when the capture starts is only captured when pressed again.
+        handler.start_capture(CaptureKind::Button);
+        assert_eq!(handler.capturing(), Some(CaptureKind::Button));
+        handler.send_event(&key_press(104, VirtualKeyCode::Up), &mut events, HIDPI);
+        handler.send_event(&mouse_wheel(0.0, 5.0), &mut events, HIDPI);
+        handler.send_event(&mouse_press(MouseButton::Left), &mut events, HIDPI);
+        assert_eq!(
+            captured(&events, &mut reader),
+            vec![CapturedInput::Button(Button::MouseWheel(
+                ScrollDirection::ScrollUp
+            ))]
+        );
+
+        handler.start_capture(CaptureKind::Any);
+        handler.cancel_capture();
+        handler.send_event(&key_release(104, VirtualKeyCode::Up), &mut events, HIDPI);
+        handler.send_event(&key_press(104, VirtualKeyCode::Up), &mut events, HIDPI);
+        assert_eq!(captured(&events, &mut reader), vec![]);
+    }
+
+    #[test]
+    fn captured_buttons_do_not_press_their_actions() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        handler
+            .bindings
+            .insert_action_binding(
+                String::from("confirm"),
+                [Button::Key(VirtualKeyCode::Return)].iter().cloned(),
+            )
+            .unwrap();
+        let actions = |events: &EventChannel<_>, reader: &mut _| {
+            events
+                .read(reader)
+                .filter(|event| {
+                    matches!(
+                        event,
+                        InputEvent::<StringBindings>::ActionPressed(_)
+                            | InputEvent::ActionReleased(_)
+                            | InputEvent::ComboPerformed(_)
+                    )
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        handler.start_capture(CaptureKind::Button);
+        handler.send_event(&key_press(28, VirtualKeyCode::Return), &mut events, HIDPI);
+        assert_eq!(handler.capturing(), None);
+        assert_eq!(actions(&events, &mut reader), vec![]);
+        assert!(!handler.action_just_pressed("confirm"));
+        handler.send_event(&key_release(28, VirtualKeyCode::Return), &mut events, HIDPI);
+        assert_eq!(actions(&events, &mut reader), vec![]);
+
+        handler.send_event(&key_press(28, VirtualKeyCode::Return), &mut events, HIDPI);
+        assert_eq!(
+            actions(&events, &mut reader),
+            vec![InputEvent::ActionPressed(String::from("confirm"))]
+        );
+    }
+
+    #[test]
+    fn capture_records_controller_axes() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        handler.send_controller_event(
+            &ControllerEvent::ControllerConnected { which: 7 },
+            &mut events,
+        );
+        handler.start_capture(CaptureKind::Any);
+        for value in &[-0.2, -0.8] {
+            handler.send_controller_event(
+                &ControllerEvent::ControllerAxisMoved {
+                    which: 7,
+                    axis: ControllerAxis::LeftY,
+                    value: *value,
+                },
+                &mut events,
+            );
+        }
+        let event_vec = events.read(&mut reader).cloned().collect::<Vec<_>>();
+        assert_eq!(
+            event_vec.last(),
+            Some(&InputEvent::InputCaptured(CapturedInput::Axis(
+                Axis::Controller {
+                    controller_id: 0,
+                    axis: ControllerAxis::LeftY,
+                    invert: true,
+                    dead_zone: 0.1,
+                    dead_zone_shape: DeadZoneShape::Axial,
+                    response: ResponseCurve::Linear,
+                    sensitivity: 1.0,
+                }
+            )))
+        );
+        assert_eq!(
+            event_vec
+                .iter()
+                .filter(|e| matches!(e, InputEvent::InputCaptured(..)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn mouse_axis_smoothing_and_acceleration() {
+        use approx::assert_ulps_eq;
+
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mouse_axis = |axis, smoothing, acceleration| Axis::Mouse {
+            axis,
+            over_extendable: true,
+            radius: 10.0,
+            sensitivity: 2.0,
+            smoothing,
+            acceleration,
+        };
+        handler
+            .bindings
+            .insert_axis("smooth", mouse_axis(MouseAxis::X, 3, 0.0))
+            .unwrap();
+        handler
+            .bindings
+            .insert_axis("accelerated", mouse_axis(MouseAxis::Y, 1, 0.1))
+            .unwrap();
+
+        handler.send_event(&cursor_moved(50.0, 50.0), &mut events, HIDPI);
+        handler.send_frame_begin();
+        handler.send_event(&cursor_moved(20.0, 10.0), &mut events, HIDPI);
+        // Averaged with the frame before, in which the mouse didn't move.
+        assert_ulps_eq!(handler.axis_value("smooth").unwrap(), 3.0);
+        assert_ulps_eq!(handler.axis_value("accelerated").unwrap(), 48.0);
+        handler.send_frame_begin();
+        assert_ulps_eq!(handler.axis_value("smooth").unwrap(), 2.0);
+        assert_ulps_eq!(handler.axis_value("accelerated").unwrap(), 0.0);
+    }
+
+    #[test]
+    fn axis2_values_are_normalized() {
+        use approx::assert_ulps_eq;
+
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        handler
+            .bindings
+            .insert_axis2(
+                "move",
+                Axis2::Emulated {
+                    up: Button::Key(VirtualKeyCode::W),
+                    down: Button::Key(VirtualKeyCode::S),
+                    left: Button::Key(VirtualKeyCode::A),
+                    right: Button::Key(VirtualKeyCode::D),
+                },
+            )
+            .unwrap();
+        handler
+            .bindings
+            .insert_axis2(
+                "look",
+                Axis2::Controller {
+                    controller_id: 0,
+                    stick: ControllerStick::Right,
+                    invert_x: false,
+                    invert_y: false,
+                    dead_zone: 0.2,
+                    dead_zone_shape: DeadZoneShape::ScaledRadial,
+                    response: ResponseCurve::Linear,
+                    sensitivity: 2.0,
+                },
+            )
+            .unwrap();
+        assert_eq!(handler.axis2_value("move"), Some(Vector2::new(0.0, 0.0)));
+        assert_eq!(handler.axis2_value("other"), None);
+
+        handler.send_event(&key_press(17, VirtualKeyCode::W), &mut events, HIDPI);
+        assert_eq!(handler.axis2_value("move"), Some(Vector2::new(0.0, 1.0)));
+        handler.send_event(&key_press(30, VirtualKeyCode::A), &mut events, HIDPI);
+        let diagonal = handler.axis2_value("move").unwrap();
+        assert_ulps_eq!(diagonal.norm(), 1.0);
+        assert_ulps_eq!(diagonal.x, -diagonal.y);
+
+        handler.send_controller_event(
+            &ControllerEvent::ControllerConnected { which: 3 },
+            &mut events,
+        );
+        for (axis, value) in &[
+            (ControllerAxis::RightX, 0.36),
+            (ControllerAxis::RightY, 0.48),
+        ] {
+            handler.send_controller_event(
+                &ControllerEvent::ControllerAxisMoved {
+                    which: 3,
+                    axis: *axis,
+                    value: *value,
+                },
+                &mut events,
+            );
+        }
+        // The stick is at 0.6 from the center, 0.5 past the dead zone, doubled by the sensitivity.
+        let look = handler.axis2_value("look").unwrap();
+        assert_ulps_eq!(look.x, 0.6);
+        assert_ulps_eq!(look.y, -0.8);
+    }
+
+    #[test]
+    fn touches_and_gestures_trigger_actions() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        handler
+            .bindings
+            .insert_action_binding(String::from("aim"), [Button::Touch].iter().cloned())
+            .unwrap();
+        handler
+            .bindings
+            .insert_action_binding(
+                String::from("jump"),
+                [Button::Gesture(Gesture::Swipe(SwipeDirection::Up))]
+                    .iter()
+                    .cloned(),
+            )
+            .unwrap();
+
+        handler.send_event(
+            &touch(TouchPhase::Started, 5, 100.0, 300.0),
+            &mut events,
+            2.0,
+        );
+        assert!(handler.is_touched());
+        assert_eq!(handler.touch(5).unwrap().position(), (200.0, 600.0));
+        assert_eq!(handler.action_is_down("aim"), Some(true));
+        handler.send_frame_begin();
+        handler.send_frame_time(Duration::from_millis(100));
+        handler.send_event(&touch(TouchPhase::Moved, 5, 100.0, 200.0), &mut events, 2.0);
+        handler.send_event(&touch(TouchPhase::Ended, 5, 100.0, 200.0), &mut events, 2.0);
+        assert!(!handler.is_touched());
+        assert_eq!(handler.touch(5).unwrap().phase(), TouchPhase::Ended);
+        assert_eq!(handler.action_is_down("jump"), Some(false));
+        assert!(handler.action_just_pressed("jump"));
+
+        let event_vec = events.read(&mut reader).cloned().collect::<Vec<_>>();
+        assert_eq!(
+            event_vec,
+            vec![
+                TouchStarted {
+                    id: 5,
+                    position: (200.0, 600.0),
+                },
+                ButtonPressed(Button::Touch),
+                ActionPressed(String::from("aim")),
+                TouchMoved {
+                    id: 5,
+                    position: (200.0, 400.0),
+                },
+                TouchEnded {
+                    id: 5,
+                    position: (200.0, 400.0),
+                },
+                Swipe {
+                    direction: SwipeDirection::Up,
+                    start: (200.0, 600.0),
+                    end: (200.0, 400.0),
+                },
+                ButtonPressed(Button::Gesture(Gesture::Swipe(SwipeDirection::Up))),
+                ActionPressed(String::from("jump")),
+                ButtonReleased(Button::Gesture(Gesture::Swipe(SwipeDirection::Up))),
+                ActionReleased(String::from("jump")),
+                ButtonReleased(Button::Touch),
+                ActionReleased(String::from("aim")),
+            ]
+        );
+
+        handler.send_frame_begin();
+        assert_eq!(handler.touches().count(), 0);
+    }
+
+    #[test]
+    fn contexts_hide_lower_bindings() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        let actions = |events: &EventChannel<_>, reader: &mut _| {
+            events
+                .read(reader)
+                .filter_map(|event| match event {
+                    InputEvent::<StringBindings>::ActionPressed(action) => Some(action.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let updown = Axis::Emulated {
+            pos: Button::Key(VirtualKeyCode::W),
+            neg: Button::Key(VirtualKeyCode::S),
+        };
+        handler
+            .bindings
+            .insert_axis(String::from("updown"), updown.clone())
+            .unwrap();
+        handler
+            .bindings
+            .insert_action_binding(
+                String::from("jump"),
+                [Button::Key(VirtualKeyCode::Space)].iter().cloned(),
+            )
+            .unwrap();
+        handler
+            .bindings
+            .insert_action_binding(
+                String::from("fire"),
+                [Button::Mouse(MouseButton::Left)].iter().cloned(),
+            )
+            .unwrap();
+
+        let mut vehicle = Bindings::new();
+        vehicle
+            .insert_axis(String::from("throttle"), updown)
+            .unwrap();
+        handler.push_context(InputContext::new("vehicle", vehicle));
+        let mut menu = Bindings::new();
+        menu.insert_action_binding(
+            String::from("confirm"),
+            [Button::Key(VirtualKeyCode::Space)].iter().cloned(),
+        )
+        .unwrap();
+        handler.push_context(
+            InputContext::new("menu", menu)
+                .with_priority(10)
+                .with_consumption(InputConsumption::All),
+        );
+        handler.push_context(InputContext::new("hud", Bindings::new()).with_priority(-1));
+        assert_eq!(
+            handler
+                .contexts()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["menu", "vehicle", "hud"]
+        );
+
+        // The menu consumes all input.
+        handler.send_event(&key_press(57, VirtualKeyCode::Space), &mut events, HIDPI);
+        handler.send_event(&mouse_press(MouseButton::Left), &mut events, HIDPI);
+        handler.send_event(&key_press(17, VirtualKeyCode::W), &mut events, HIDPI);
+        assert_eq!(actions(&events, &mut reader), vec![String::from("confirm")]);
+        assert_eq!(handler.action_is_down("confirm"), Some(true));
+        assert_eq!(handler.action_is_down("jump"), Some(false));
+        assert_eq!(handler.axis_value("throttle"), Some(0.0));
+        assert_eq!(handler.action_is_down("missing"), None);
+        handler.send_event(&key_release(57, VirtualKeyCode::Space), &mut events, HIDPI);
+        handler.send_event(&mouse_release(MouseButton::Left), &mut events, HIDPI);
+
+        // The vehicle only hides the buttons of its throttle.
+        assert_eq!(handler.remove_context("menu").unwrap().name, "menu");
+        assert_eq!(handler.axis_value("throttle"), Some(1.0));
+        assert_eq!(handler.axis_value("updown"), Some(0.0));
+        assert_eq!(handler.action_is_down("jump"), Some(false));
+        handler.send_event(&key_press(57, VirtualKeyCode::Space), &mut events, HIDPI);
+        assert_eq!(actions(&events, &mut reader), vec![String::from("jump")]);
+
+        handler.context_mut("vehicle").unwrap().consumption = InputConsumption::Nothing;
+        assert_eq!(handler.axis_value("updown"), Some(1.0));
+        handler
+            .context_mut("vehicle")
+            .unwrap()
+            .bindings
+            .insert_action_binding(
+                String::from("horn"),
+                [Button::Key(VirtualKeyCode::H)].iter().cloned(),
+            )
+            .unwrap();
+        assert_eq!(handler.action_is_down("horn"), Some(false));
+        handler.send_event(&key_press(35, VirtualKeyCode::H), &mut events, HIDPI);
+        assert_eq!(actions(&events, &mut reader), vec![String::from("horn")]);
+        assert_eq!(handler.pop_context().unwrap().name, "hud");
+        assert_eq!(handler.pop_context().unwrap().name, "vehicle");
+        assert!(handler.context("vehicle").is_none());
+    }
+
+    #[test]
+    fn hiding_held_actions_releases_them() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        handler
+            .bindings
+            .insert_action_binding(
+                String::from("jump"),
+                [Button::Key(VirtualKeyCode::Space)].iter().cloned(),
+            )
+            .unwrap();
+        handler.send_event(&key_press(57, VirtualKeyCode::Space), &mut events, HIDPI);
+        handler.send_hidden_releases(&mut events);
+        assert!(handler.action_held_for("jump", Duration::from_secs(0)));
+
+        handler.push_context(
+            InputContext::new("menu", Bindings::new()).with_consumption(InputConsumption::All),
+        );
+        handler.send_frame_begin();
+        handler.send_hidden_releases(&mut events);
+        assert!(handler.action_just_released("jump"));
+        assert!(!handler.action_held_for("jump", Duration::from_secs(0)));
+        let actions = events
+            .read(&mut reader)
+            .filter(|event| matches!(event, ActionPressed(_) | ActionReleased(_)))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![
+                ActionPressed(String::from("jump")),
+                ActionReleased(String::from("jump")),
+            ]
+        );
+    }
+
+    #[test]
+    fn action_edges_and_held_time() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        handler
+            .bindings
+            .insert_action_binding(
+                String::from("charge"),
+                [Button::Key(VirtualKeyCode::C)].iter().cloned(),
+            )
+            .unwrap();
+        let frame = |handler: &mut InputHandler<StringBindings>, millis| {
+            handler.send_frame_begin();
+            handler.send_frame_time(Duration::from_millis(millis));
+        };
+
+        frame(&mut handler, 0);
+        handler.send_event(&key_press(46, VirtualKeyCode::C), &mut events, HIDPI);
+        assert!(handler.action_just_pressed("charge"));
+        assert!(!handler.action_just_released("charge"));
+        assert!(handler.action_held_for("charge", Duration::from_millis(0)));
+        assert!(!handler.action_held_for("charge", Duration::from_millis(500)));
+
+        frame(&mut handler, 600);
+        assert!(!handler.action_just_pressed("charge"));
+        assert!(handler.action_held_for("charge", Duration::from_millis(500)));
+        handler.send_event(&key_release(46, VirtualKeyCode::C), &mut events, HIDPI);
+        assert!(handler.action_just_released("charge"));
+        assert!(!handler.action_held_for("charge", Duration::from_millis(0)));
+
+        // A tap within a frame is both pressed and released.
+        frame(&mut handler, 700);
+        handler.send_event(&key_press(46, VirtualKeyCode::C), &mut events, HIDPI);
+        handler.send_event(&key_release(46, VirtualKeyCode::C), &mut events, HIDPI);
+        assert!(handler.action_just_pressed("charge"));
+        assert!(handler.action_just_released("charge"));
+    }
+
+    #[test]
+    fn input_buffer_remembers_presses() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        handler
+            .bindings
+            .insert_action_binding(
+                String::from("jump"),
+                [Button::Key(VirtualKeyCode::Space)].iter().cloned(),
+            )
+            .unwrap();
+        handler.set_input_buffer_frames(3);
+        let tap = |handler: &mut InputHandler<StringBindings>, events: &mut _| {
+            handler.send_event(&key_press(57, VirtualKeyCode::Space), events, HIDPI);
+            handler.send_event(&key_release(57, VirtualKeyCode::Space), events, HIDPI);
+        };
+
+        tap(&mut handler, &mut events);
+        for _ in 0..3 {
+            handler.send_frame_begin();
+            assert!(handler.action_buffered("jump"));
+        }
+        handler.send_frame_begin();
+        assert!(!handler.action_buffered("jump"));
+
+        tap(&mut handler, &mut events);
+        handler.send_frame_begin();
+        assert!(handler.consume_buffered("jump"));
+        assert!(!handler.action_buffered("jump"));
+        assert!(!handler.consume_buffered("jump"));
+    }
+
+    #[test]
+    fn combos_are_performed_in_order_within_their_window() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let mut reader = events.register_reader();
+        for (action, buttons) in &[
+            ("down", vec![VirtualKeyCode::Down]),
+            ("forward", vec![VirtualKeyCode::Right]),
+            (
+                "down_forward",
+                vec![VirtualKeyCode::Down, VirtualKeyCode::Right],
+            ),
+            ("punch", vec![VirtualKeyCode::P]),
+        ] {
+            handler
+                .bindings
+                .insert_action_binding(action.to_string(), buttons.iter().map(|&k| k.into()))
+                .unwrap();
+        }
+        handler.add_combo(Combo::new(
+            String::from("fireball"),
+            ["down", "down_forward", "forward", "punch"]
+                .iter()
+                .map(|action| action.to_string())
+                .collect(),
+            Duration::from_millis(400),
+        ));
+        let mut millis = 0;
+        let mut step = |handler: &mut InputHandler<StringBindings>, events: &mut _, event| {
+            millis += 100;
+            handler.send_frame_begin();
+            handler.send_frame_time(Duration::from_millis(millis));
+            handler.send_event(&event, events, HIDPI);
+        };
+        let combos = |events: &EventChannel<_>, reader: &mut _| {
+            events
+                .read(reader)
+                .filter(|event| matches!(event, InputEvent::<StringBindings>::ComboPerformed(..)))
+                .count()
+        };
+
+        step(
+            &mut handler,
+            &mut events,
+            key_press(108, VirtualKeyCode::Down),
+        );
+        step(
+            &mut handler,
+            &mut events,
+            key_press(106, VirtualKeyCode::Right),
+        );
+        step(
+            &mut handler,
+            &mut events,
+            key_release(108, VirtualKeyCode::Down),
+        );
+        step(&mut handler, &mut events, key_press(25, VirtualKeyCode::P));
+        assert!(handler.combo_performed("fireball"));
+        assert_eq!(combos(&events, &mut reader), 1);
+
+        // Pressing the last action again doesn't reuse the sequence.
+        step(
+            &mut handler,
+            &mut events,
+            key_release(25, VirtualKeyCode::P),
+        );
+        step(&mut handler, &mut events, key_press(25, VirtualKeyCode::P));
+        assert!(!handler.combo_performed("fireball"));
+
+        // Too slow.
+        step(
+            &mut handler,
+            &mut events,
+            key_release(106, VirtualKeyCode::Right),
+        );
+        step(
+            &mut handler,
+            &mut events,
+            key_release(25, VirtualKeyCode::P),
+        );
+        step(
+            &mut handler,
+            &mut events,
+            key_press(108, VirtualKeyCode::Down),
+        );
+        step(
+            &mut handler,
+            &mut events,
+            key_press(106, VirtualKeyCode::Right),
+        );
+        step(
+            &mut handler,
+            &mut events,
+            key_release(108, VirtualKeyCode::Down),
+        );
+        step(
+            &mut handler,
+            &mut events,
+            key_release(106, VirtualKeyCode::Right),
+        );
+        step(
+            &mut handler,
+            &mut events,
+            key_press(106, VirtualKeyCode::Right),
+        );
+        step(&mut handler, &mut events, key_press(25, VirtualKeyCode::P));
+        assert_eq!(combos(&events, &mut reader), 0);
+    }
+
     /// Compares two sets for equality, but not the order
     fn sets_are_equal<T>(a: &[T], b: &[T])
     where
@@ -1313,6 +2541,36 @@ right: `{:?}`",
         }
     }
 
+    fn cursor_moved(x: f64, y: f64) -> Event {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event: WindowEvent::CursorMoved {
+                device_id: unsafe { DeviceId::dummy() },
+                position: LogicalPosition::new(x, y),
+                modifiers: ModifiersState::default(),
+            },
+        }
+    }
+
+    fn touch(phase: TouchPhase, id: u64, x: f64, y: f64) -> Event {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event: WindowEvent::Touch(Touch {
+                device_id: unsafe { DeviceId::dummy() },
+                phase,
+                location: LogicalPosition::new(x, y),
+                id,
+            }),
+        }
+    }
+
+    fn mouse_motion(x: f64, y: f64) -> Event {
+        Event::DeviceEvent {
+            device_id: unsafe { DeviceId::dummy() },
+            event: DeviceEvent::MouseMotion { delta: (x, y) },
+        }
+    }
+
     fn mouse_wheel(x: f32, y: f32) -> Event {
         Event::DeviceEvent {
             device_id: unsafe { DeviceId::dummy() },
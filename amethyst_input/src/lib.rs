@@ -9,42 +9,68 @@
 #![warn(clippy::all)]
 #![allow(clippy::new_without_default)]
 
+#[cfg(feature = "native_controller")]
+pub use self::gamepad_system::{GamepadEventsSystem, GamepadEventsSystemDesc, GamepadHaptics};
 #[cfg(feature = "sdl_controller")]
 pub use self::sdl_events_system::SdlEventsSystem;
 pub use self::{
-    axis::Axis,
-    bindings::{BindingError, BindingTypes, Bindings, StringBindings},
+    actions::Combo,
+    axis::{Axis, Axis2, DeadZoneShape, ResponseCurve},
+    bindings::{BindingConflict, BindingError, BindingTypes, Bindings, StringBindings},
     bundle::{BindingsFileError, InputBundle},
     button::Button,
-    controller::{ControllerAxis, ControllerButton, ControllerEvent},
+    capture::{CaptureKind, CapturedInput},
+    context::{InputConsumption, InputContext},
+    controller::{ControllerAxis, ControllerButton, ControllerEvent, ControllerStick},
     event::InputEvent,
+    gamepad_mapping::{GamepadMapping, GamepadMappingError, GamepadMappings},
+    haptics::{Envelope, Haptics, HapticsBackend, HapticsRequest, RecordingHaptics, RumbleEffect},
+    haptics_system::{HapticsSystem, HapticsSystemDesc},
     input_handler::InputHandler,
     mouse::MouseAxis,
+    player::{InputDevice, PlayerInput, PlayerInputEvent, PlayerInputs},
+    player_system::{PlayerInputSystem, PlayerInputSystemDesc},
+    recording::{InputRecorder, InputRecording, RecordedEvent, RecordedFrame},
     scroll_direction::ScrollDirection,
     system::{InputSystem, InputSystemDesc},
+    touch::{Gesture, GestureSettings, SwipeDirection, TouchPoint},
     util::{
         get_input_axis_simple, get_key, get_mouse_button, is_close_requested, is_key_down,
         is_key_up, is_mouse_button_down,
     },
 };
-pub use winit::{ElementState, VirtualKeyCode};
+pub use winit::{ElementState, TouchPhase, VirtualKeyCode};
 
 use std::iter::Iterator;
 
 use winit;
 
+mod actions;
 mod axis;
 mod bindings;
 mod bundle;
 mod button;
+mod capture;
+mod context;
 mod controller;
 mod event;
+mod gamepad_mapping;
+mod haptics;
+mod haptics_system;
 mod input_handler;
 mod mouse;
+mod player;
+mod player_system;
+mod recording;
 mod scroll_direction;
 mod system;
+mod touch;
 mod util;
 
+#[cfg(feature = "native_controller")]
+mod evdev;
+#[cfg(feature = "native_controller")]
+mod gamepad_system;
 #[cfg(feature = "sdl_controller")]
 mod sdl_events_system;
 
@@ -0,0 +1,573 @@
+//! Input of the players of a local multiplayer game, each using their own devices.
+
+use std::{borrow::Borrow, hash::Hash};
+
+use amethyst_core::math::Vector2;
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use winit::VirtualKeyCode;
+
+use super::{Axis, BindingTypes, Bindings, Button, ControllerButton, InputHandler};
+
+/// A device which can be assigned to a player.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash, Serialize, Deserialize)]
+pub enum InputDevice {
+    /// The keyboard and the mouse, which are shared by all keyboards and mice plugged in.
+    KeyboardMouse,
+    /// The controller with the given id, as assigned by the `InputHandler`.
+    Controller(u32),
+}
+
+impl InputDevice {
+    /// Returns the device a button belongs to.
+    pub fn of(button: Button) -> Self {
+        match button {
+            Button::Controller(controller_id, _) => InputDevice::Controller(controller_id),
+            _ => InputDevice::KeyboardMouse,
+        }
+    }
+}
+
+/// Sent by the `PlayerInputSystem` when players join or their devices are unplugged.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum PlayerInputEvent {
+    /// A player joined by pressing a join button on a device no player was using.
+    Joined {
+        /// Index of the player.
+        player: usize,
+        /// Device the player joined with.
+        device: InputDevice,
+    },
+    /// A controller assigned to a player was disconnected. It stays assigned to the player, so
+    /// that it is used again when reconnected.
+    DeviceDisconnected {
+        /// Index of the player.
+        player: usize,
+        /// The disconnected device.
+        device: InputDevice,
+    },
+    /// A controller assigned to a player was reconnected.
+    DeviceReconnected {
+        /// Index of the player.
+        player: usize,
+        /// The reconnected device.
+        device: InputDevice,
+    },
+}
+
+/// The bindings and devices of a player.
+///
+/// Controller ids in the bindings of a player refer to the controllers assigned to the player,
+/// in the order they were assigned, so that `Button::Controller(0, ControllerButton::A)` is the
+/// A button of the first controller of the player. Keyboard and mouse bindings only apply if the
+/// player was assigned `InputDevice::KeyboardMouse`.
+///
+/// Input contexts of the `InputHandler` don't apply to players.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""), Clone(bound = ""))]
+pub struct PlayerInput<T: BindingTypes> {
+    /// Maps the inputs of the devices of the player to actions and axes.
+    pub bindings: Bindings<T>,
+    devices: SmallVec<[InputDevice; 2]>,
+    disconnected: SmallVec<[u32; 2]>,
+}
+
+impl<T: BindingTypes> PlayerInput<T> {
+    /// Creates a player without devices.
+    pub fn new(bindings: Bindings<T>) -> Self {
+        PlayerInput {
+            bindings,
+            devices: SmallVec::new(),
+            disconnected: SmallVec::new(),
+        }
+    }
+
+    /// Returns the devices assigned to the player.
+    pub fn devices(&self) -> &[InputDevice] {
+        &self.devices
+    }
+
+    /// Returns true if the device is assigned to the player.
+    pub fn has_device(&self, device: InputDevice) -> bool {
+        self.devices.contains(&device)
+    }
+
+    /// Returns true if any of the bindings of the action is down on the devices of the player.
+    ///
+    /// If a binding represents a combination of buttons, all of them need to be down.
+    pub fn action_is_down<A>(&self, handler: &InputHandler<T>, action: &A) -> Option<bool>
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.bindings.actions.get(action).map(|combinations| {
+            combinations.iter().any(|combination| {
+                combination
+                    .iter()
+                    .all(|button| match self.device_button(*button) {
+                        Some(button) => handler.button_is_down(button),
+                        None => false,
+                    })
+            })
+        })
+    }
+
+    /// Returns the value of an axis on the devices of the player, if the id doesn't exist this
+    /// returns None.
+    pub fn axis_value<A>(&self, handler: &InputHandler<T>, id: &A) -> Option<f32>
+    where
+        T::Axis: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.bindings
+            .axes
+            .get(id)
+            .map(|axis| match self.device_axis(axis) {
+                Some(axis) => handler.binding_value(&axis),
+                None => 0.0,
+            })
+    }
+
+    /// Returns the value of a 2D axis on the devices of the player, if the id doesn't exist this
+    /// returns None.
+    pub fn axis2_value<A>(&self, handler: &InputHandler<T>, id: &A) -> Option<Vector2<f32>>
+    where
+        T::Axis: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        self.bindings.axes2.get(id).map(|axis2| {
+            let value = |axis| match self.device_axis(&axis) {
+                Some(axis) => handler.binding_value(&axis),
+                None => 0.0,
+            };
+            let (x, y) = axis2.axes();
+            axis2.value(value(x), value(y))
+        })
+    }
+
+    /// Assigns a device to the player. Returns false if it was already assigned.
+    pub(crate) fn assign(&mut self, device: InputDevice) -> bool {
+        if self.has_device(device) {
+            return false;
+        }
+        self.devices.push(device);
+        true
+    }
+
+    /// Returns the button on the devices of the player a button of the bindings refers to.
+    fn device_button(&self, button: Button) -> Option<Button> {
+        match button {
+            Button::Controller(slot, controller_button) => self
+                .controller(slot)
+                .map(|controller_id| Button::Controller(controller_id, controller_button)),
+            _ if self.has_device(InputDevice::KeyboardMouse) => Some(button),
+            _ => None,
+        }
+    }
+
+    /// Returns the axis on the devices of the player an axis of the bindings refers to.
+    fn device_axis(&self, axis: &Axis) -> Option<Axis> {
+        match *axis {
+            Axis::Emulated { pos, neg } => Some(Axis::Emulated {
+                pos: self.device_button(pos)?,
+                neg: self.device_button(neg)?,
+            }),
+            Axis::Controller { controller_id, .. } => {
+                let mut device_axis = axis.clone();
+                if let Axis::Controller {
+                    controller_id: ref mut slot,
+                    ..
+                } = device_axis
+                {
+                    *slot = self.controller(controller_id)?;
+                }
+                Some(device_axis)
+            }
+            Axis::Mouse { .. } | Axis::MouseWheel { .. } => {
+                if self.has_device(InputDevice::KeyboardMouse) {
+                    Some(axis.clone())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Returns the id of the controller assigned to the player at the given position.
+    fn controller(&self, slot: u32) -> Option<u32> {
+        self.devices
+            .iter()
+            .filter_map(|device| match device {
+                InputDevice::Controller(controller_id) => Some(*controller_id),
+                InputDevice::KeyboardMouse => None,
+            })
+            .nth(slot as usize)
+    }
+}
+
+/// The players of a local multiplayer game, updated by the `PlayerInputSystem`.
+///
+/// While joining is allowed, pressing one of the join buttons on a device which isn't assigned
+/// to a player makes a new player join with this device, using a copy of the bindings of this
+/// resource. Players keep their index until they leave, and new players take the lowest free
+/// index.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+pub struct PlayerInputs<T: BindingTypes> {
+    /// Bindings given to players who join.
+    pub bindings: Bindings<T>,
+    players: Vec<Option<PlayerInput<T>>>,
+    join_buttons: Vec<Button>,
+    joining: bool,
+    max_players: usize,
+}
+
+impl<T: BindingTypes> Default for PlayerInputs<T> {
+    fn default() -> Self {
+        PlayerInputs::new(Bindings::new())
+    }
+}
+
+impl<T: BindingTypes> PlayerInputs<T> {
+    /// Creates the resource without players, allowing up to 4 players to join by pressing
+    /// Start on a controller or Return on the keyboard.
+    pub fn new(bindings: Bindings<T>) -> Self {
+        PlayerInputs {
+            bindings,
+            players: Vec::new(),
+            join_buttons: vec![
+                Button::Controller(0, ControllerButton::Start),
+                Button::Key(VirtualKeyCode::Return),
+            ],
+            joining: true,
+            max_players: 4,
+        }
+    }
+
+    /// Sets the buttons which make a new player join. The controller id of controller buttons is
+    /// ignored, as they can be pressed on any controller.
+    pub fn set_join_buttons(&mut self, buttons: Vec<Button>) {
+        self.join_buttons = buttons;
+    }
+
+    /// Returns true if new players can join.
+    pub fn joining(&self) -> bool {
+        self.joining
+    }
+
+    /// Allows or forbids new players to join, e.g. to only let them join in a lobby.
+    pub fn set_joining(&mut self, joining: bool) {
+        self.joining = joining;
+    }
+
+    /// Sets the largest number of players, 4 by default.
+    pub fn set_max_players(&mut self, max_players: usize) {
+        self.max_players = max_players;
+    }
+
+    /// Returns the player with the given index.
+    pub fn player(&self, player: usize) -> Option<&PlayerInput<T>> {
+        self.players.get(player).and_then(Option::as_ref)
+    }
+
+    /// Returns the player with the given index, e.g. to rebind its actions.
+    pub fn player_mut(&mut self, player: usize) -> Option<&mut PlayerInput<T>> {
+        self.players.get_mut(player).and_then(Option::as_mut)
+    }
+
+    /// Returns the players with their indices.
+    pub fn players(&self) -> impl Iterator<Item = (usize, &PlayerInput<T>)> {
+        self.players
+            .iter()
+            .enumerate()
+            .filter_map(|(index, player)| player.as_ref().map(|player| (index, player)))
+    }
+
+    /// Returns the index of the player the device is assigned to.
+    pub fn player_of(&self, device: InputDevice) -> Option<usize> {
+        self.players()
+            .find(|(_, player)| player.has_device(device))
+            .map(|(index, _)| index)
+    }
+
+    /// Adds a player using the device, regardless of whether joining is allowed. Returns the index
+    /// of the player, or None if the device is already assigned or there are too many players.
+    pub fn join(&mut self, device: InputDevice) -> Option<usize> {
+        if self.player_of(device).is_some() || self.players().count() >= self.max_players {
+            return None;
+        }
+        let mut player = PlayerInput::new(self.bindings.clone());
+        player.assign(device);
+        match self.players.iter().position(Option::is_none) {
+            Some(index) => {
+                self.players[index] = Some(player);
+                Some(index)
+            }
+            None => {
+                self.players.push(Some(player));
+                Some(self.players.len() - 1)
+            }
+        }
+    }
+
+    /// Removes a player, freeing its devices.
+    pub fn leave(&mut self, player: usize) -> Option<PlayerInput<T>> {
+        let removed = self.players.get_mut(player).and_then(Option::take);
+        while let Some(None) = self.players.last() {
+            self.players.pop();
+        }
+        removed
+    }
+
+    /// Assigns another device to a player, e.g. a second controller. Returns false if the player
+    /// doesn't exist or the device is already assigned.
+    pub fn assign(&mut self, player: usize, device: InputDevice) -> bool {
+        if self.player_of(device).is_some() {
+            return false;
+        }
+        match self.player_mut(player) {
+            Some(player) => player.assign(device),
+            None => false,
+        }
+    }
+
+    /// Removes a device from the player it is assigned to.
+    pub fn unassign(&mut self, device: InputDevice) {
+        for player in self.players.iter_mut().flatten() {
+            player.devices.retain(|d| *d != device);
+            if let InputDevice::Controller(controller_id) = device {
+                player.disconnected.retain(|id| *id != controller_id);
+            }
+        }
+    }
+
+    /// Makes a new player join if the button is a join button pressed on a free device.
+    pub(crate) fn button_pressed(&mut self, button: Button) -> Option<PlayerInputEvent> {
+        let device = InputDevice::of(button);
+        let join = self.join_buttons.iter().any(|join| match (join, button) {
+            (Button::Controller(_, join), Button::Controller(_, pressed)) => *join == pressed,
+            (join, pressed) => *join == pressed,
+        });
+        if !self.joining || !join {
+            return None;
+        }
+        self.join(device)
+            .map(|player| PlayerInputEvent::Joined { player, device })
+    }
+
+    /// Tracks the connection of the controllers assigned to players, returning the changes.
+    pub(crate) fn update_connections(
+        &mut self,
+        handler: &InputHandler<T>,
+    ) -> Vec<PlayerInputEvent> {
+        let mut events = Vec::new();
+        for (index, player) in self.players.iter_mut().enumerate() {
+            let player = match player {
+                Some(player) => player,
+                None => continue,
+            };
+            for device in player.devices.iter() {
+                let controller_id = match device {
+                    InputDevice::Controller(controller_id) => *controller_id,
+                    InputDevice::KeyboardMouse => continue,
+                };
+                let connected = handler.is_controller_connected(controller_id);
+                let was_disconnected = player.disconnected.contains(&controller_id);
+                if connected && was_disconnected {
+                    player.disconnected.retain(|id| *id != controller_id);
+                    events.push(PlayerInputEvent::DeviceReconnected {
+                        player: index,
+                        device: *device,
+                    });
+                } else if !connected && !was_disconnected {
+                    player.disconnected.push(controller_id);
+                    events.push(PlayerInputEvent::DeviceDisconnected {
+                        player: index,
+                        device: *device,
+                    });
+                }
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ControllerAxis, ControllerEvent, DeadZoneShape, InputEvent, ResponseCurve, StringBindings,
+    };
+    use amethyst_core::shrev::EventChannel;
+    use winit::{
+        DeviceId, ElementState, Event, KeyboardInput, ModifiersState, WindowEvent, WindowId,
+    };
+
+    fn bindings() -> Bindings<StringBindings> {
+        let mut bindings = Bindings::new();
+        bindings
+            .insert_action_binding(
+                String::from("jump"),
+                [Button::Controller(0, ControllerButton::A)].iter().cloned(),
+            )
+            .unwrap();
+        bindings
+            .insert_action_binding(
+                String::from("jump"),
+                [Button::Key(VirtualKeyCode::Space)].iter().cloned(),
+            )
+            .unwrap();
+        bindings
+            .insert_axis(
+                String::from("steer"),
+                Axis::Controller {
+                    controller_id: 0,
+                    axis: ControllerAxis::LeftX,
+                    invert: false,
+                    dead_zone: 0.0,
+                    dead_zone_shape: DeadZoneShape::Axial,
+                    response: ResponseCurve::Linear,
+                    sensitivity: 1.0,
+                },
+            )
+            .unwrap();
+        bindings
+    }
+
+    fn key_press(key: VirtualKeyCode) -> Event {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event: WindowEvent::KeyboardInput {
+                device_id: unsafe { DeviceId::dummy() },
+                input: KeyboardInput {
+                    scancode: 0,
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    modifiers: ModifiersState::default(),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn join_buttons_claim_free_devices() {
+        let mut players = PlayerInputs::<StringBindings>::new(bindings());
+        assert_eq!(
+            players.button_pressed(Button::Controller(1, ControllerButton::Start)),
+            Some(PlayerInputEvent::Joined {
+                player: 0,
+                device: InputDevice::Controller(1),
+            })
+        );
+        assert_eq!(
+            players.button_pressed(Button::Controller(1, ControllerButton::Start)),
+            None
+        );
+        assert_eq!(
+            players.button_pressed(Button::Controller(0, ControllerButton::A)),
+            None
+        );
+        assert_eq!(
+            players.button_pressed(Button::Key(VirtualKeyCode::Return)),
+            Some(PlayerInputEvent::Joined {
+                player: 1,
+                device: InputDevice::KeyboardMouse,
+            })
+        );
+        assert_eq!(players.player_of(InputDevice::KeyboardMouse), Some(1));
+
+        assert!(players.leave(0).is_some());
+        players.set_joining(false);
+        assert_eq!(
+            players.button_pressed(Button::Controller(0, ControllerButton::Start)),
+            None
+        );
+        players.set_joining(true);
+        players.set_max_players(2);
+        assert_eq!(players.join(InputDevice::Controller(0)), Some(0));
+        assert_eq!(players.join(InputDevice::Controller(2)), None);
+        assert!(players.assign(1, InputDevice::Controller(2)));
+        assert!(!players.assign(0, InputDevice::Controller(2)));
+        assert_eq!(
+            players
+                .players()
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn players_only_read_their_devices() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        for which in &[10, 11] {
+            handler.send_controller_event(
+                &ControllerEvent::ControllerConnected { which: *which },
+                &mut events,
+            );
+        }
+        let mut players = PlayerInputs::<StringBindings>::new(bindings());
+        players.join(InputDevice::Controller(1));
+        players.join(InputDevice::KeyboardMouse);
+        let (pad, keyboard) = (players.player(0).unwrap(), players.player(1).unwrap());
+
+        handler.send_controller_event(
+            &ControllerEvent::ControllerButtonPressed {
+                which: 11,
+                button: ControllerButton::A,
+            },
+            &mut events,
+        );
+        handler.send_controller_event(
+            &ControllerEvent::ControllerAxisMoved {
+                which: 11,
+                axis: ControllerAxis::LeftX,
+                value: 0.5,
+            },
+            &mut events,
+        );
+        assert_eq!(pad.action_is_down(&handler, "jump"), Some(true));
+        assert_eq!(keyboard.action_is_down(&handler, "jump"), Some(false));
+        assert_eq!(pad.axis_value(&handler, "steer"), Some(0.5));
+        assert_eq!(keyboard.axis_value(&handler, "steer"), Some(0.0));
+        assert_eq!(pad.axis_value(&handler, "throttle"), None);
+
+        handler.send_event(&key_press(VirtualKeyCode::Space), &mut events, 1.0);
+        assert_eq!(keyboard.action_is_down(&handler, "jump"), Some(true));
+    }
+
+    #[test]
+    fn tracks_controller_connections() {
+        let mut handler = InputHandler::<StringBindings>::new();
+        let mut events = EventChannel::<InputEvent<StringBindings>>::new();
+        let connect = |handler: &mut InputHandler<_>, events: &mut _, which| {
+            handler.send_controller_event(&ControllerEvent::ControllerConnected { which }, events);
+        };
+        connect(&mut handler, &mut events, 10);
+        let mut players = PlayerInputs::<StringBindings>::new(bindings());
+        players.join(InputDevice::Controller(0));
+        assert_eq!(players.update_connections(&handler), vec![]);
+
+        handler.send_controller_event(
+            &ControllerEvent::ControllerDisconnected { which: 10 },
+            &mut events,
+        );
+        let disconnected = PlayerInputEvent::DeviceDisconnected {
+            player: 0,
+            device: InputDevice::Controller(0),
+        };
+        assert_eq!(players.update_connections(&handler), vec![disconnected]);
+        assert_eq!(players.update_connections(&handler), vec![]);
+
+        connect(&mut handler, &mut events, 12);
+        assert_eq!(
+            players.update_connections(&handler),
+            vec![PlayerInputEvent::DeviceReconnected {
+                player: 0,
+                device: InputDevice::Controller(0),
+            }]
+        );
+    }
+}
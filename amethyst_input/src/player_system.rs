@@ -0,0 +1,79 @@
+//! Player joining system
+use std::marker::PhantomData;
+
+use derivative::Derivative;
+use derive_new::new;
+
+use crate::{BindingTypes, InputEvent, InputHandler, PlayerInputEvent, PlayerInputs};
+use amethyst_core::{
+    ecs::{
+        prelude::{Read, System, World, Write},
+        SystemData,
+    },
+    shrev::{EventChannel, ReaderId},
+    SystemDesc,
+};
+
+#[cfg(feature = "profiler")]
+use thread_profiler::profile_scope;
+
+/// Builds a `PlayerInputSystem`.
+#[derive(Derivative, Debug)]
+#[derivative(Default(bound = ""))]
+pub struct PlayerInputSystemDesc<T>
+where
+    T: BindingTypes,
+{
+    marker: PhantomData<T>,
+}
+
+impl<'a, 'b, T> SystemDesc<'a, 'b, PlayerInputSystem<T>> for PlayerInputSystemDesc<T>
+where
+    T: BindingTypes,
+{
+    fn build(self, world: &mut World) -> PlayerInputSystem<T> {
+        <PlayerInputSystem<T> as System<'_>>::SystemData::setup(world);
+
+        let reader = world
+            .fetch_mut::<EventChannel<InputEvent<T>>>()
+            .register_reader();
+
+        PlayerInputSystem::new(reader)
+    }
+}
+
+/// Player input system
+///
+/// Makes players join the `PlayerInputs` resource when a join button is pressed on a free
+/// device, and tracks the connection of their controllers. Changes are sent as
+/// `PlayerInputEvent`s. It should run after the `InputSystem`.
+#[derive(Debug, new)]
+pub struct PlayerInputSystem<T>
+where
+    T: BindingTypes,
+{
+    reader: ReaderId<InputEvent<T>>,
+}
+
+impl<'a, T: BindingTypes> System<'a> for PlayerInputSystem<T> {
+    type SystemData = (
+        Read<'a, EventChannel<InputEvent<T>>>,
+        Read<'a, InputHandler<T>>,
+        Write<'a, PlayerInputs<T>>,
+        Write<'a, EventChannel<PlayerInputEvent>>,
+    );
+
+    fn run(&mut self, (input, handler, mut players, mut output): Self::SystemData) {
+        #[cfg(feature = "profiler")]
+        profile_scope!("player_input_system");
+
+        for event in input.read(&mut self.reader) {
+            if let InputEvent::ButtonPressed(button) = *event {
+                if let Some(joined) = players.button_pressed(button) {
+                    output.single_write(joined);
+                }
+            }
+        }
+        output.iter_write(players.update_connections(&handler));
+    }
+}
@@ -0,0 +1,421 @@
+//! Recording of the input read by the `InputSystem`, and its replay in place of live input.
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::LogicalPosition, DeviceEvent, DeviceId, ElementState, Event, KeyboardInput,
+    ModifiersState, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent, WindowId,
+};
+
+use super::ControllerEvent;
+
+/// An input event of a recording.
+///
+/// Holds the parts of the `winit::Event`s read by the [InputHandler](struct.InputHandler.html),
+/// and the `ControllerEvent`s sent to it.
+#[derive(PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    /// A character was typed.
+    ReceivedCharacter(char),
+    /// A key was pressed or released.
+    KeyboardInput(KeyboardInput),
+    /// A mouse button was pressed or released.
+    MouseInput {
+        /// The button.
+        button: MouseButton,
+        /// Whether the button was pressed or released.
+        state: ElementState,
+    },
+    /// The cursor moved in the window, in logical coordinates.
+    CursorMoved(LogicalPosition),
+    /// The window gained or lost focus.
+    Focused(bool),
+    /// A finger touched, moved on or left the screen.
+    Touch {
+        /// Identifier of the finger.
+        id: u64,
+        /// Phase of the touch.
+        phase: TouchPhase,
+        /// Position of the finger in the window, in logical coordinates.
+        location: LogicalPosition,
+    },
+    /// The mouse moved.
+    MouseMotion {
+        /// Movement along the horizontal axis.
+        delta_x: f64,
+        /// Movement along the vertical axis.
+        delta_y: f64,
+    },
+    /// The mouse wheel moved.
+    MouseWheel(MouseScrollDelta),
+    /// A controller event.
+    Controller(ControllerEvent),
+}
+
+impl RecordedEvent {
+    /// Returns the recorded part of the event, or `None` if it is not read by the `InputHandler`.
+    pub fn from_event(event: &Event) -> Option<Self> {
+        match *event {
+            Event::WindowEvent { ref event, .. } => match *event {
+                WindowEvent::ReceivedCharacter(c) => Some(RecordedEvent::ReceivedCharacter(c)),
+                WindowEvent::KeyboardInput { input, .. } => {
+                    Some(RecordedEvent::KeyboardInput(input))
+                }
+                WindowEvent::MouseInput { button, state, .. } => {
+                    Some(RecordedEvent::MouseInput { button, state })
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    Some(RecordedEvent::CursorMoved(position))
+                }
+                WindowEvent::Focused(focused) => Some(RecordedEvent::Focused(focused)),
+                WindowEvent::Touch(Touch {
+                    id,
+                    phase,
+                    location,
+                    ..
+                }) => Some(RecordedEvent::Touch {
+                    id,
+                    phase,
+                    location,
+                }),
+                _ => None,
+            },
+            Event::DeviceEvent { ref event, .. } => match *event {
+                DeviceEvent::MouseMotion {
+                    delta: (delta_x, delta_y),
+                } => Some(RecordedEvent::MouseMotion { delta_x, delta_y }),
+                DeviceEvent::MouseWheel { delta } => Some(RecordedEvent::MouseWheel(delta)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the `winit::Event` to send to the `InputHandler`, or `None` for controller events.
+    ///
+    /// The window and device ids of the event are dummies, which the `InputHandler` ignores.
+    pub fn to_event(&self) -> Option<Event> {
+        // The dummy ids are only compared with each other, and the handler does not look at them.
+        let window_id = unsafe { WindowId::dummy() };
+        let device_id = unsafe { DeviceId::dummy() };
+        let event = match *self {
+            RecordedEvent::ReceivedCharacter(c) => WindowEvent::ReceivedCharacter(c),
+            RecordedEvent::KeyboardInput(input) => WindowEvent::KeyboardInput { device_id, input },
+            RecordedEvent::MouseInput { button, state } => WindowEvent::MouseInput {
+                device_id,
+                state,
+                button,
+                modifiers: ModifiersState::default(),
+            },
+            RecordedEvent::CursorMoved(position) => WindowEvent::CursorMoved {
+                device_id,
+                position,
+                modifiers: ModifiersState::default(),
+            },
+            RecordedEvent::Focused(focused) => WindowEvent::Focused(focused),
+            RecordedEvent::Touch {
+                id,
+                phase,
+                location,
+            } => WindowEvent::Touch(Touch {
+                device_id,
+                phase,
+                location,
+                id,
+            }),
+            RecordedEvent::MouseMotion { delta_x, delta_y } => {
+                return Some(Event::DeviceEvent {
+                    device_id,
+                    event: DeviceEvent::MouseMotion {
+                        delta: (delta_x, delta_y),
+                    },
+                })
+            }
+            RecordedEvent::MouseWheel(delta) => {
+                return Some(Event::DeviceEvent {
+                    device_id,
+                    event: DeviceEvent::MouseWheel { delta },
+                })
+            }
+            RecordedEvent::Controller(_) => return None,
+        };
+        Some(Event::WindowEvent { window_id, event })
+    }
+}
+
+/// The events read in one frame of a recording.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Number of the frame, counted from the frame the recording started in.
+    pub frame: u64,
+    /// The events, in the order they were read.
+    pub events: Vec<RecordedEvent>,
+}
+
+/// A recording of input events, keyed by frame.
+///
+/// Recordings are made and replayed with the [InputRecorder](struct.InputRecorder.html), and
+/// can be saved to and loaded from RON files with `amethyst_config::Config`, for example to
+/// attach them to a bug report or to replay them in a test.
+///
+/// Frames are counted with `Time::frame_number`, so a replay matches the recording as long as
+/// the game does the same work each frame, e.g. with a fixed time step.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InputRecording {
+    /// The frames with events, in order.
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl InputRecording {
+    /// Creates an empty recording.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds an event read in the given frame, after the events already recorded in that frame.
+    pub fn push(&mut self, frame: u64, event: RecordedEvent) {
+        // Events are mostly recorded in order, so the last frames are checked first.
+        let index = self.frames.len()
+            - self
+                .frames
+                .iter()
+                .rev()
+                .take_while(|f| f.frame > frame)
+                .count();
+        match self.frames[..index].last_mut() {
+            Some(last) if last.frame == frame => last.events.push(event),
+            _ => self.frames.insert(
+                index,
+                RecordedFrame {
+                    frame,
+                    events: vec![event],
+                },
+            ),
+        }
+    }
+
+    /// Returns the events of the frame.
+    pub fn events(&self, frame: u64) -> &[RecordedEvent] {
+        match self.frames.binary_search_by_key(&frame, |f| f.frame) {
+            Ok(index) => &self.frames[index].events,
+            Err(_) => &[],
+        }
+    }
+
+    /// Returns the number of frames from the first frame of the recording to its last event.
+    pub fn len(&self) -> u64 {
+        self.frames.last().map(|f| f.frame + 1).unwrap_or(0)
+    }
+
+    /// Returns true if the recording has no events.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Mode {
+    Live,
+    Recording(InputRecording),
+    Replaying(InputRecording),
+}
+
+/// Records the input events read by the `InputSystem`, or replays a recording in their place.
+///
+/// While replaying, live keyboard, mouse and controller input is ignored and the events of the
+/// recording are sent to the `InputHandler` at the start of their frame. This makes it possible
+/// to reproduce a bug from a recording, to play attract mode demos, or to drive automated
+/// gameplay tests without a window.
+///
+/// Controller backends record their `ControllerEvent`s here and skip them while replaying.
+#[derive(Debug)]
+pub struct InputRecorder {
+    mode: Mode,
+    /// Frame number of the first frame of the recording or replay, once it started.
+    start: Option<u64>,
+    /// Frame number of the current frame.
+    frame: u64,
+}
+
+impl Default for InputRecorder {
+    fn default() -> Self {
+        InputRecorder {
+            mode: Mode::Live,
+            start: None,
+            frame: 0,
+        }
+    }
+}
+
+impl InputRecorder {
+    /// Creates a recorder sending live input.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Starts recording from the next frame, discarding any recording or replay in progress.
+    pub fn start_recording(&mut self) {
+        self.mode = Mode::Recording(InputRecording::new());
+        self.start = None;
+    }
+
+    /// Stops recording and returns the recording, or `None` if not recording.
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        match std::mem::replace(&mut self.mode, Mode::Live) {
+            Mode::Recording(recording) => Some(recording),
+            mode => {
+                self.mode = mode;
+                None
+            }
+        }
+    }
+
+    /// Returns true while recording.
+    pub fn recording(&self) -> bool {
+        matches!(self.mode, Mode::Recording(_))
+    }
+
+    /// Replays the recording from the next frame in place of live input, discarding any recording
+    /// or replay in progress. The `InputHandler` forgets the inputs held when the replay starts.
+    ///
+    /// Live input stays ignored when the replay is finished, until `stop_replay` is called.
+    pub fn replay(&mut self, recording: InputRecording) {
+        self.mode = Mode::Replaying(recording);
+        self.start = None;
+    }
+
+    /// Stops replaying and returns the recording, or `None` if not replaying.
+    pub fn stop_replay(&mut self) -> Option<InputRecording> {
+        match std::mem::replace(&mut self.mode, Mode::Live) {
+            Mode::Replaying(recording) => Some(recording),
+            mode => {
+                self.mode = mode;
+                None
+            }
+        }
+    }
+
+    /// Returns true while replaying, including after the end of the recording.
+    pub fn replaying(&self) -> bool {
+        matches!(self.mode, Mode::Replaying(_))
+    }
+
+    /// Returns true if the replay passed the last event of its recording.
+    pub fn replay_finished(&self) -> bool {
+        match (&self.mode, self.start) {
+            (Mode::Replaying(recording), Some(start)) => self.frame - start >= recording.len(),
+            _ => false,
+        }
+    }
+
+    /// Records an event of the current frame if recording.
+    ///
+    /// Backends sending their events to the `InputHandler` after the `InputSystem` ran in the
+    /// frame, like the `SdlEventsSystem`, should use `record_for_next_frame` instead, since the
+    /// replay sends them from the `InputSystem`.
+    pub fn record(&mut self, event: RecordedEvent) {
+        self.record_at(0, event);
+    }
+
+    /// Records an event as read at the start of the next frame if recording.
+    pub fn record_for_next_frame(&mut self, event: RecordedEvent) {
+        self.record_at(1, event);
+    }
+
+    fn record_at(&mut self, offset: u64, event: RecordedEvent) {
+        if let (Mode::Recording(recording), Some(start)) = (&mut self.mode, self.start) {
+            recording.push(self.frame - start + offset, event);
+        }
+    }
+
+    /// Starts the frame of the given number, returning true if a replay starts with it.
+    pub(crate) fn frame_begin(&mut self, frame: u64) -> bool {
+        self.frame = frame;
+        match self.mode {
+            Mode::Live => false,
+            Mode::Recording(_) | Mode::Replaying(_) if self.start.is_some() => false,
+            Mode::Recording(_) => {
+                self.start = Some(frame);
+                false
+            }
+            Mode::Replaying(_) => {
+                self.start = Some(frame);
+                true
+            }
+        }
+    }
+
+    /// Returns the recorded events of the current frame if replaying.
+    pub(crate) fn replayed(&self) -> &[RecordedEvent] {
+        match (&self.mode, self.start) {
+            (Mode::Replaying(recording), Some(start)) => recording.events(self.frame - start),
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ControllerButton, ControllerEvent};
+
+    #[test]
+    fn records_and_replays_frames_from_their_start() {
+        let press = RecordedEvent::Controller(ControllerEvent::ControllerButtonPressed {
+            which: 0,
+            button: ControllerButton::A,
+        });
+        let mut recorder = InputRecorder::new();
+        recorder.start_recording();
+        recorder.record(press);
+        assert!(!recorder.frame_begin(10));
+        recorder.record(press);
+        recorder.record(press);
+        recorder.record_for_next_frame(press);
+        recorder.frame_begin(12);
+        recorder.record(RecordedEvent::Focused(false));
+        let recording = recorder.stop_recording().unwrap();
+        assert_eq!(recording.events(0), &[press, press]);
+        assert_eq!(recording.events(1), &[press]);
+        assert_eq!(recording.events(2), &[RecordedEvent::Focused(false)]);
+        assert_eq!(recording.len(), 3);
+
+        recorder.replay(recording);
+        assert!(recorder.frame_begin(40));
+        assert_eq!(recorder.replayed(), &[press, press]);
+        recorder.frame_begin(42);
+        assert!(!recorder.replay_finished());
+        assert_eq!(recorder.replayed(), &[RecordedEvent::Focused(false)]);
+        recorder.frame_begin(43);
+        assert!(recorder.replay_finished());
+        assert!(recorder.replayed().is_empty());
+        assert!(recorder.stop_replay().is_some());
+        assert!(!recorder.replaying());
+    }
+
+    #[test]
+    fn events_for_the_next_frame_stay_in_order() {
+        let mut recorder = InputRecorder::new();
+        recorder.start_recording();
+        assert!(!recorder.frame_begin(0));
+        recorder.record_for_next_frame(RecordedEvent::Focused(false));
+        recorder.record(RecordedEvent::Focused(true));
+        recorder.record_for_next_frame(RecordedEvent::Focused(true));
+        let recording = recorder.stop_recording().unwrap();
+        assert_eq!(recording.events(0), &[RecordedEvent::Focused(true)]);
+        assert_eq!(
+            recording.events(1),
+            &[RecordedEvent::Focused(false), RecordedEvent::Focused(true)]
+        );
+        assert_eq!(recording.len(), 2);
+    }
+
+    #[test]
+    fn recorded_events_are_sent_back_as_winit_events() {
+        let event = RecordedEvent::MouseInput {
+            button: MouseButton::Left,
+            state: ElementState::Pressed,
+        };
+        let winit_event = event.to_event().unwrap();
+        assert_eq!(RecordedEvent::from_event(&winit_event), Some(event));
+    }
+}
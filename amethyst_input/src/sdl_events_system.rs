@@ -17,7 +17,7 @@ use amethyst_core::{
 
 use super::{
     controller::{ControllerAxis, ControllerButton, ControllerEvent},
-    BindingTypes, InputEvent, InputHandler,
+    BindingTypes, InputEvent, InputHandler, InputRecorder, RecordedEvent,
 };
 
 /// A collection of errors that can occur in the SDL system.
@@ -92,19 +92,20 @@ pub struct SdlEventsSystem<T: BindingTypes> {
 type SdlEventsData<'a, T> = (
     Write<'a, InputHandler<T>>,
     Write<'a, EventChannel<InputEvent<T>>>,
+    Write<'a, InputRecorder>,
 );
 
 impl<'a, T: BindingTypes> System<'a> for SdlEventsSystem<T> {
     type SystemData = SdlEventsData<'a, T>;
 
-    fn run(&mut self, (mut handler, mut output): Self::SystemData) {
+    fn run(&mut self, (mut handler, mut output, mut recorder): Self::SystemData) {
         let mut event_pump = self
             .event_pump
             .take()
             .expect("Unreachable: `event_pump` is always reinserted after `take`");
         for event in event_pump.poll_iter() {
             // handle appropriate events locally
-            self.handle_sdl_event(&event, &mut handler, &mut output);
+            self.handle_sdl_event(&event, &mut handler, &mut output, &mut recorder);
         }
         self.event_pump = Some(event_pump);
     }
@@ -147,7 +148,7 @@ impl<T: BindingTypes> SdlEventsSystem<T> {
             opened_controllers: vec![],
             marker: PhantomData,
         };
-        let (mut handler, mut output) = SdlEventsData::fetch(world);
+        let (mut handler, mut output, _) = SdlEventsData::fetch(world);
         sys.initialize_controllers(&mut handler, &mut output);
         Ok(sys)
     }
@@ -157,6 +158,7 @@ impl<T: BindingTypes> SdlEventsSystem<T> {
         event: &Event,
         handler: &mut InputHandler<T>,
         output: &mut EventChannel<InputEvent<T>>,
+        recorder: &mut InputRecorder,
     ) {
         use self::ControllerEvent::*;
 
@@ -164,7 +166,7 @@ impl<T: BindingTypes> SdlEventsSystem<T> {
             Event::ControllerAxisMotion {
                 which, axis, value, ..
             } => {
-                handler.send_controller_event(
+                Self::send_controller_event(
                     &ControllerAxisMoved {
                         which: which as u32,
                         axis: axis.into(),
@@ -174,45 +176,73 @@ impl<T: BindingTypes> SdlEventsSystem<T> {
                             f32::from(value) / 32768.0
                         },
                     },
+                    handler,
                     output,
+                    recorder,
                 );
             }
             Event::ControllerButtonDown { which, button, .. } => {
-                handler.send_controller_event(
+                Self::send_controller_event(
                     &ControllerButtonPressed {
                         which: which as u32,
                         button: button.into(),
                     },
+                    handler,
                     output,
+                    recorder,
                 );
             }
             Event::ControllerButtonUp { which, button, .. } => {
-                handler.send_controller_event(
+                Self::send_controller_event(
                     &ControllerButtonReleased {
                         which: which as u32,
                         button: button.into(),
                     },
+                    handler,
                     output,
+                    recorder,
                 );
             }
             Event::ControllerDeviceRemoved { which, .. } => {
                 self.close_controller(which as u32);
-                handler.send_controller_event(
+                Self::send_controller_event(
                     &ControllerDisconnected {
                         which: which as u32,
                     },
+                    handler,
                     output,
+                    recorder,
                 );
             }
             Event::ControllerDeviceAdded { which, .. } => {
                 if let Some(idx) = self.open_controller(which) {
-                    handler.send_controller_event(&ControllerConnected { which: idx }, output);
+                    Self::send_controller_event(
+                        &ControllerConnected { which: idx },
+                        handler,
+                        output,
+                        recorder,
+                    );
                 }
             }
             _ => {}
         }
     }
 
+    /// Sends the event to the handler and records it, unless a recording is replayed instead.
+    fn send_controller_event(
+        event: &ControllerEvent,
+        handler: &mut InputHandler<T>,
+        output: &mut EventChannel<InputEvent<T>>,
+        recorder: &mut InputRecorder,
+    ) {
+        if recorder.replaying() {
+            return;
+        }
+        // This system runs after the `InputSystem`, which replays the event in the next frame.
+        recorder.record_for_next_frame(RecordedEvent::Controller(*event));
+        handler.send_controller_event(event, output);
+    }
+
     fn open_controller(&mut self, which: u32) -> Option<u32> {
         if self.controller_subsystem.is_game_controller(which) {
             self.controller_subsystem.open(which).ok().map(|c| {
@@ -2,13 +2,14 @@
 use derive_new::new;
 use winit::Event;
 
-use crate::{BindingTypes, Bindings, InputEvent, InputHandler};
+use crate::{BindingTypes, Bindings, InputEvent, InputHandler, InputRecorder, RecordedEvent};
 use amethyst_core::{
     ecs::{
-        prelude::{Read, ReadExpect, System, World, Write},
+        prelude::{Read, System, World, Write},
         SystemData,
     },
     shrev::{EventChannel, ReaderId},
+    timing::Time,
     SystemDesc,
 };
 use amethyst_window::ScreenDimensions;
@@ -45,6 +46,10 @@ where
 ///
 /// Will read `winit::Event` from `EventHandler<winit::Event>`, process them with `InputHandler`,
 /// and push the results in `EventHandler<InputEvent>`.
+///
+/// The events are recorded by the `InputRecorder` while it records, and replaced by the events of
+/// its recording while it replays. A hidpi factor of 1 is used if there is no `ScreenDimensions`,
+/// e.g. when replaying without a window.
 #[derive(Debug)]
 pub struct InputSystem<T>
 where
@@ -68,6 +73,19 @@ impl<T: BindingTypes> InputSystem<T> {
     ) {
         handler.send_event(event, output, hidpi as f32);
     }
+
+    fn replay_event(
+        event: &RecordedEvent,
+        handler: &mut InputHandler<T>,
+        output: &mut EventChannel<InputEvent<T>>,
+        hidpi: f32,
+    ) {
+        match (event, event.to_event()) {
+            (RecordedEvent::Controller(event), _) => handler.send_controller_event(event, output),
+            (_, Some(event)) => Self::process_event(&event, handler, output, hidpi),
+            (_, None) => {}
+        }
+    }
 }
 
 impl<'a, T: BindingTypes> System<'a> for InputSystem<T> {
@@ -75,21 +93,47 @@ impl<'a, T: BindingTypes> System<'a> for InputSystem<T> {
         Read<'a, EventChannel<Event>>,
         Write<'a, InputHandler<T>>,
         Write<'a, EventChannel<InputEvent<T>>>,
-        ReadExpect<'a, ScreenDimensions>,
+        Option<Read<'a, ScreenDimensions>>,
+        Read<'a, Time>,
+        Write<'a, InputRecorder>,
     );
 
-    fn run(&mut self, (input, mut handler, mut output, screen_dimensions): Self::SystemData) {
+    fn run(
+        &mut self,
+        (input, mut handler, mut output, screen_dimensions, time, mut recorder): Self::SystemData,
+    ) {
         #[cfg(feature = "profiler")]
         profile_scope!("input_system");
 
+        let hidpi = screen_dimensions
+            .map(|screen_dimensions| screen_dimensions.hidpi_factor() as f32)
+            .unwrap_or(1.0);
         handler.send_frame_begin();
-        for event in input.read(&mut self.reader) {
-            Self::process_event(
-                event,
+        handler.send_frame_time(time.absolute_time());
+        handler.send_frame_gestures(&mut output);
+        handler.send_hidden_releases(&mut output);
+        if recorder.frame_begin(time.frame_number()) {
+            // Forget the live inputs held when the replay starts.
+            Self::replay_event(
+                &RecordedEvent::Focused(false),
                 &mut *handler,
                 &mut *output,
-                screen_dimensions.hidpi_factor() as f32,
+                hidpi,
             );
         }
+        // Reading the live events also drops them while replaying.
+        let live = input.read(&mut self.reader);
+        if recorder.replaying() {
+            for event in recorder.replayed() {
+                Self::replay_event(event, &mut *handler, &mut *output, hidpi);
+            }
+            return;
+        }
+        for event in live {
+            if let Some(recorded) = RecordedEvent::from_event(event) {
+                recorder.record(recorded);
+            }
+            Self::process_event(event, &mut *handler, &mut *output, hidpi);
+        }
     }
 }
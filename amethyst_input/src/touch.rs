@@ -0,0 +1,533 @@
+//! Touches on a touch screen and the gestures they make.
+
+use std::{f32::consts::PI, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use winit::TouchPhase;
+
+use super::{BindingTypes, InputEvent};
+
+/// Direction of a swipe on the screen.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash, Serialize, Deserialize)]
+pub enum SwipeDirection {
+    /// Towards the top of the screen.
+    Up,
+    /// Towards the bottom of the screen.
+    Down,
+    /// Towards the left of the screen.
+    Left,
+    /// Towards the right of the screen.
+    Right,
+}
+
+/// A gesture which can be bound to an action with `Button::Gesture`.
+///
+/// Gestures are pressed and released at once when they are recognized, so they trigger
+/// `ActionPressed` and `ActionReleased` events and
+/// [action_just_pressed](struct.InputHandler.html#method.action_just_pressed), but never keep
+/// an action down.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Hash, Serialize, Deserialize)]
+pub enum Gesture {
+    /// A finger touched the screen and left it quickly without moving.
+    Tap,
+    /// A second tap shortly after a first one, at the same place. Both taps are also sent as
+    /// `Tap`.
+    DoubleTap,
+    /// A finger touched the screen for a while without moving.
+    LongPress,
+    /// A finger moved quickly across the screen and left it.
+    Swipe(SwipeDirection),
+}
+
+/// Thresholds used to recognize gestures, set with
+/// [set_gesture_settings](struct.InputHandler.html#method.set_gesture_settings).
+///
+/// Distances are in physical pixels, like the positions of touches.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GestureSettings {
+    /// Longest time a finger can touch the screen for a tap.
+    pub tap_time: Duration,
+    /// Farthest a finger can move for a tap or a long press.
+    pub tap_distance: f32,
+    /// Longest time between two taps of a double tap.
+    pub double_tap_time: Duration,
+    /// Farthest two taps of a double tap can be from each other.
+    pub double_tap_distance: f32,
+    /// Time a finger must touch the screen for a long press.
+    pub long_press_time: Duration,
+    /// Shortest distance a finger must move for a swipe.
+    pub swipe_distance: f32,
+    /// Longest time a swipe can take.
+    pub swipe_time: Duration,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        GestureSettings {
+            tap_time: Duration::from_millis(300),
+            tap_distance: 10.0,
+            double_tap_time: Duration::from_millis(300),
+            double_tap_distance: 40.0,
+            long_press_time: Duration::from_millis(500),
+            swipe_distance: 50.0,
+            swipe_time: Duration::from_millis(500),
+        }
+    }
+}
+
+/// A finger touching the screen, returned by
+/// [touches](struct.InputHandler.html#method.touches).
+#[derive(PartialEq, Debug, Clone)]
+pub struct TouchPoint {
+    id: u64,
+    phase: TouchPhase,
+    position: (f32, f32),
+    start_position: (f32, f32),
+    start_time: Duration,
+    long_pressed: bool,
+}
+
+impl TouchPoint {
+    /// Identifier of the finger, unique among the fingers touching the screen.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Phase of the last event of the touch. Touches are kept until the frame after they ended
+    /// or were cancelled.
+    pub fn phase(&self) -> TouchPhase {
+        self.phase
+    }
+
+    /// Position of the finger, in physical pixels.
+    pub fn position(&self) -> (f32, f32) {
+        self.position
+    }
+
+    /// Position where the finger touched the screen.
+    pub fn start_position(&self) -> (f32, f32) {
+        self.start_position
+    }
+
+    /// Time when the finger touched the screen, as set by
+    /// [send_frame_time](struct.InputHandler.html#method.send_frame_time).
+    pub fn start_time(&self) -> Duration {
+        self.start_time
+    }
+
+    /// Returns true until the touch ended or was cancelled.
+    pub fn is_active(&self) -> bool {
+        matches!(self.phase, TouchPhase::Started | TouchPhase::Moved)
+    }
+
+    fn distance_moved(&self) -> f32 {
+        distance(self.start_position, self.position)
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+/// Tracks the touches and recognizes the gestures they make.
+#[derive(Debug, Default)]
+pub(crate) struct GestureRecognizer {
+    pub(crate) settings: GestureSettings,
+    touches: SmallVec<[TouchPoint; 4]>,
+    /// True once more than one finger touched the screen, until they all left it.
+    multi_touch: bool,
+    /// Time and position of the last tap which can start a double tap.
+    last_tap: Option<(Duration, (f32, f32))>,
+    /// Distance and angle between the two fingers of a pinch or rotation.
+    pair: Option<(f32, f32)>,
+}
+
+impl GestureRecognizer {
+    pub(crate) fn touches(&self) -> &[TouchPoint] {
+        &self.touches
+    }
+
+    pub(crate) fn active_touches(&self) -> usize {
+        self.touches
+            .iter()
+            .filter(|touch| touch.is_active())
+            .count()
+    }
+
+    /// Forgets the touches which ended in the previous frame.
+    pub(crate) fn frame_begin(&mut self) {
+        self.touches.retain(|touch| touch.is_active());
+    }
+
+    /// Forgets all touches, e.g. when the window loses focus.
+    pub(crate) fn clear(&mut self) {
+        self.touches.clear();
+        self.multi_touch = false;
+        self.pair = None;
+    }
+
+    /// Updates a touch, sending the touch events and the gestures it makes.
+    pub(crate) fn touch<T: BindingTypes>(
+        &mut self,
+        id: u64,
+        phase: TouchPhase,
+        position: (f32, f32),
+        time: Duration,
+        events: &mut Vec<InputEvent<T>>,
+    ) {
+        let index = self
+            .touches
+            .iter()
+            .position(|touch| touch.id == id && touch.is_active());
+        match (phase, index) {
+            (TouchPhase::Started, _) => {
+                // Finish the touch if its end was missed, and drop it if it ended in this frame.
+                self.touches.retain(|touch| touch.id != id);
+                self.touches.push(TouchPoint {
+                    id,
+                    phase,
+                    position,
+                    start_position: position,
+                    start_time: time,
+                    long_pressed: false,
+                });
+                events.push(InputEvent::TouchStarted { id, position });
+                let active = self.active_touches();
+                self.multi_touch |= active > 1;
+                self.pair = if active == 2 { self.pair_state() } else { None };
+            }
+            (TouchPhase::Moved, Some(index)) => {
+                let touch = &mut self.touches[index];
+                if touch.position == position {
+                    return;
+                }
+                touch.phase = phase;
+                touch.position = position;
+                events.push(InputEvent::TouchMoved { id, position });
+                self.pinch_and_rotate(events);
+            }
+            (TouchPhase::Ended, Some(index)) => {
+                let touch = &mut self.touches[index];
+                touch.phase = phase;
+                touch.position = position;
+                events.push(InputEvent::TouchEnded { id, position });
+                let touch = touch.clone();
+                self.touch_ended(&touch, time, events);
+                self.touch_left();
+            }
+            (TouchPhase::Cancelled, Some(index)) => {
+                self.touches[index].phase = phase;
+                events.push(InputEvent::TouchCancelled { id });
+                self.touch_left();
+            }
+            (_, None) => {}
+        }
+    }
+
+    /// Sends the long presses of the touches held long enough.
+    pub(crate) fn update<T: BindingTypes>(
+        &mut self,
+        time: Duration,
+        events: &mut Vec<InputEvent<T>>,
+    ) {
+        if self.multi_touch {
+            return;
+        }
+        let settings = &self.settings;
+        for touch in self.touches.iter_mut().filter(|touch| touch.is_active()) {
+            if !touch.long_pressed
+                && touch.distance_moved() <= settings.tap_distance
+                && time >= touch.start_time + settings.long_press_time
+            {
+                touch.long_pressed = true;
+                self.last_tap = None;
+                events.push(InputEvent::LongPress {
+                    position: touch.position,
+                });
+            }
+        }
+    }
+
+    fn touch_ended<T: BindingTypes>(
+        &mut self,
+        touch: &TouchPoint,
+        time: Duration,
+        events: &mut Vec<InputEvent<T>>,
+    ) {
+        if self.multi_touch || touch.long_pressed {
+            return;
+        }
+        let duration = time.checked_sub(touch.start_time).unwrap_or_default();
+        let moved = touch.distance_moved();
+        let settings = &self.settings;
+        if duration <= settings.tap_time && moved <= settings.tap_distance {
+            events.push(InputEvent::Tap {
+                position: touch.position,
+            });
+            let double_tap = match self.last_tap {
+                Some((last_time, last_position)) => {
+                    time <= last_time + settings.double_tap_time
+                        && distance(last_position, touch.position) <= settings.double_tap_distance
+                }
+                None => false,
+            };
+            if double_tap {
+                self.last_tap = None;
+                events.push(InputEvent::DoubleTap {
+                    position: touch.position,
+                });
+            } else {
+                self.last_tap = Some((time, touch.position));
+            }
+        } else if duration <= settings.swipe_time && moved >= settings.swipe_distance {
+            let (start, end) = (touch.start_position, touch.position);
+            let (delta_x, delta_y) = (end.0 - start.0, end.1 - start.1);
+            // Positions go down the screen.
+            let direction = if delta_x.abs() >= delta_y.abs() {
+                if delta_x > 0.0 {
+                    SwipeDirection::Right
+                } else {
+                    SwipeDirection::Left
+                }
+            } else if delta_y > 0.0 {
+                SwipeDirection::Down
+            } else {
+                SwipeDirection::Up
+            };
+            events.push(InputEvent::Swipe {
+                direction,
+                start,
+                end,
+            });
+        }
+    }
+
+    /// Starts a new gesture once no finger touches the screen.
+    fn touch_left(&mut self) {
+        let active = self.active_touches();
+        if active == 0 {
+            self.multi_touch = false;
+        }
+        self.pair = if active == 2 { self.pair_state() } else { None };
+    }
+
+    /// Returns the center, distance and angle of the two fingers touching the screen.
+    fn pair_geometry(&self) -> Option<((f32, f32), f32, f32)> {
+        let mut active = self.touches.iter().filter(|touch| touch.is_active());
+        let (a, b) = (active.next()?.position, active.next()?.position);
+        let center = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        Some((center, distance(a, b), (b.1 - a.1).atan2(b.0 - a.0)))
+    }
+
+    fn pair_state(&self) -> Option<(f32, f32)> {
+        self.pair_geometry()
+            .map(|(_, distance, angle)| (distance, angle))
+    }
+
+    fn pinch_and_rotate<T: BindingTypes>(&mut self, events: &mut Vec<InputEvent<T>>) {
+        let (last_distance, last_angle) = match self.pair {
+            Some(pair) => pair,
+            None => return,
+        };
+        let (center, distance, angle) = match self.pair_geometry() {
+            Some(geometry) => geometry,
+            None => return,
+        };
+        if last_distance > 0.0 && (distance - last_distance).abs() > f32::EPSILON {
+            events.push(InputEvent::Pinch {
+                center,
+                scale: distance / last_distance,
+            });
+        }
+        let mut rotation = angle - last_angle;
+        if rotation > PI {
+            rotation -= 2.0 * PI;
+        } else if rotation <= -PI {
+            rotation += 2.0 * PI;
+        }
+        if rotation.abs() > f32::EPSILON {
+            events.push(InputEvent::Rotate {
+                center,
+                angle: rotation,
+            });
+        }
+        self.pair = Some((distance, angle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::assert_ulps_eq;
+
+    use crate::StringBindings;
+
+    type Events = Vec<InputEvent<StringBindings>>;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn gestures(events: &mut Events) -> Events {
+        let gestures = events
+            .iter()
+            .filter(|event| {
+                !matches!(
+                    event,
+                    InputEvent::TouchStarted { .. }
+                        | InputEvent::TouchMoved { .. }
+                        | InputEvent::TouchEnded { .. }
+                        | InputEvent::TouchCancelled { .. }
+                )
+            })
+            .cloned()
+            .collect();
+        events.clear();
+        gestures
+    }
+
+    #[test]
+    fn taps_and_long_presses() {
+        let mut recognizer = GestureRecognizer::default();
+        let mut events = Events::new();
+        recognizer.touch(1, TouchPhase::Started, (10.0, 10.0), millis(0), &mut events);
+        assert_eq!(recognizer.active_touches(), 1);
+        recognizer.touch(1, TouchPhase::Moved, (15.0, 10.0), millis(50), &mut events);
+        recognizer.touch(1, TouchPhase::Ended, (15.0, 10.0), millis(100), &mut events);
+        assert_eq!(
+            gestures(&mut events),
+            vec![InputEvent::Tap {
+                position: (15.0, 10.0)
+            }]
+        );
+        assert_eq!(recognizer.touches()[0].phase(), TouchPhase::Ended);
+        recognizer.frame_begin();
+        assert!(recognizer.touches().is_empty());
+
+        recognizer.touch(
+            2,
+            TouchPhase::Started,
+            (20.0, 10.0),
+            millis(200),
+            &mut events,
+        );
+        recognizer.touch(2, TouchPhase::Ended, (20.0, 10.0), millis(250), &mut events);
+        assert_eq!(
+            gestures(&mut events),
+            vec![
+                InputEvent::Tap {
+                    position: (20.0, 10.0)
+                },
+                InputEvent::DoubleTap {
+                    position: (20.0, 10.0)
+                },
+            ]
+        );
+
+        recognizer.touch(
+            3,
+            TouchPhase::Started,
+            (20.0, 10.0),
+            millis(1000),
+            &mut events,
+        );
+        recognizer.update(millis(1400), &mut events);
+        assert_eq!(gestures(&mut events), vec![]);
+        recognizer.update(millis(1500), &mut events);
+        recognizer.update(millis(1600), &mut events);
+        assert_eq!(
+            gestures(&mut events),
+            vec![InputEvent::LongPress {
+                position: (20.0, 10.0)
+            }]
+        );
+        // A long press isn't a tap.
+        recognizer.touch(
+            3,
+            TouchPhase::Ended,
+            (20.0, 10.0),
+            millis(1700),
+            &mut events,
+        );
+        assert_eq!(gestures(&mut events), vec![]);
+    }
+
+    #[test]
+    fn swipes() {
+        let mut recognizer = GestureRecognizer::default();
+        let mut events = Events::new();
+        recognizer.touch(
+            1,
+            TouchPhase::Started,
+            (100.0, 100.0),
+            millis(0),
+            &mut events,
+        );
+        recognizer.touch(1, TouchPhase::Moved, (90.0, 40.0), millis(100), &mut events);
+        recognizer.touch(1, TouchPhase::Ended, (80.0, 20.0), millis(200), &mut events);
+        assert_eq!(
+            gestures(&mut events),
+            vec![InputEvent::Swipe {
+                direction: SwipeDirection::Up,
+                start: (100.0, 100.0),
+                end: (80.0, 20.0),
+            }]
+        );
+
+        // Too slow for a swipe.
+        recognizer.touch(
+            1,
+            TouchPhase::Started,
+            (100.0, 100.0),
+            millis(1000),
+            &mut events,
+        );
+        recognizer.touch(
+            1,
+            TouchPhase::Ended,
+            (200.0, 100.0),
+            millis(2000),
+            &mut events,
+        );
+        assert_eq!(gestures(&mut events), vec![]);
+    }
+
+    #[test]
+    fn pinches_and_rotations() {
+        let mut recognizer = GestureRecognizer::default();
+        let mut events = Events::new();
+        recognizer.touch(1, TouchPhase::Started, (0.0, 0.0), millis(0), &mut events);
+        recognizer.touch(2, TouchPhase::Started, (10.0, 0.0), millis(0), &mut events);
+        recognizer.touch(2, TouchPhase::Moved, (20.0, 0.0), millis(10), &mut events);
+        assert_eq!(
+            gestures(&mut events),
+            vec![InputEvent::Pinch {
+                center: (10.0, 0.0),
+                scale: 2.0,
+            }]
+        );
+        recognizer.touch(2, TouchPhase::Moved, (0.0, 20.0), millis(20), &mut events);
+        match gestures(&mut events).as_slice() {
+            [InputEvent::Rotate { center, angle }] => {
+                assert_eq!(*center, (0.0, 10.0));
+                assert_ulps_eq!(*angle, PI / 2.0);
+            }
+            events => panic!("Expected a rotation, got {:?}", events),
+        }
+
+        // Fingers lifted after a pinch don't tap.
+        recognizer.touch(1, TouchPhase::Ended, (0.0, 0.0), millis(30), &mut events);
+        recognizer.touch(
+            2,
+            TouchPhase::Cancelled,
+            (0.0, 20.0),
+            millis(30),
+            &mut events,
+        );
+        assert_eq!(gestures(&mut events), vec![]);
+        assert_eq!(recognizer.active_touches(), 0);
+    }
+}
@@ -2,14 +2,25 @@
 //! more utilities to make their way into this module. e.g. "Component synchronization",
 //! "Matchmaking", etc.
 
+mod encryption;
 mod events;
 mod message;
 mod requirements;
+mod rollback;
+mod stats;
 mod timing;
 mod transport;
 
+pub use encryption::{EncryptionError, OpenedPacket, PacketEncryption, KEY_LEN};
 pub use events::NetworkSimulationEvent;
 pub use message::Message;
 pub use requirements::{DeliveryRequirement, UrgencyRequirement};
+pub use rollback::{
+    decode_inputs, encode_inputs, RollbackDispatcher, RollbackDispatcherBuilder, RollbackInput,
+    RollbackInputs, RollbackSession, MAX_ROLLBACK_ACTIONS, ROLLBACK_MESSAGE_TAG,
+};
+pub use stats::{
+    NetworkStats, NetworkStatsSystem, NetworkStatsSystemDesc, PeerStats, RTT_PROBE_TAG,
+};
 pub use timing::{NetworkSimulationTime, NetworkSimulationTimeSystem};
-pub use transport::{laminar, tcp, udp, TransportResource};
+pub use transport::{laminar, tcp, udp, websocket, TransportResource};
@@ -0,0 +1,782 @@
+//! Optional authenticated encryption of the payloads sent by the datagram based transports.
+//!
+//! Payloads are encrypted and authenticated using either a key shared by all peers ahead of
+//! time, a per peer key agreed on with an X25519 key exchange, or both. When both are
+//! configured the exchanged key is mixed with the shared key, which authenticates the exchange.
+//! Packets which fail to decrypt are reported as `NetworkSimulationEvent::RecvError`, so spoofed
+//! or tampered packets never reach the game.
+//!
+//! Payloads are sealed with XChaCha20-Poly1305, whose 192-bit nonces are made of a random 128-bit
+//! id of the sending session and a counter. The session id is also authenticated as associated
+//! data. Every receiver keeps a window of the counters it saw from each session of each address,
+//! so replayed packets are rejected as well. Once a key is known for a peer, handshakes can't
+//! replace it; the peer has to be removed first.
+//!
+//! The UDP and Laminar transports use the `PacketEncryption` resource when it is enabled, see
+//! `UdpNetworkBundle::with_encryption` and `LaminarNetworkBundle::with_encryption`.
+
+use crate::simulation::message::Message;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    error, fmt, io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Length of the symmetric keys in bytes.
+pub const KEY_LEN: usize = 32;
+
+const PACKET_HANDSHAKE: u8 = 0;
+const PACKET_SEALED: u8 = 1;
+const PACKET_HANDSHAKE_REPLY: u8 = 2;
+const SESSION_ID_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+const KEY_DERIVATION_CONTEXT: &[u8] = b"amethyst_network packet encryption";
+const HANDSHAKE_CONTEXT: &[u8] = b"amethyst_network handshake";
+
+/// Time after which the handshake of an unanswered key exchange is sent again.
+const HANDSHAKE_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Number of counters below the highest one seen which are still accepted out of order.
+const REPLAY_WINDOW: u64 = 64;
+
+/// Errors which can occur while sealing or opening a packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncryptionError {
+    /// No key is known for the peer.
+    MissingKey(SocketAddr),
+    /// The packet was too short or of an unknown kind.
+    MalformedPacket(SocketAddr),
+    /// The packet failed authentication; it was tampered with or sealed with another key.
+    AuthenticationFailed(SocketAddr),
+    /// The packet was already received, or is too old to tell.
+    Replayed(SocketAddr),
+    /// A handshake tried to replace the key of a peer which already has one.
+    ///
+    /// Without a shared key handshakes are not authenticated, so whoever spoofs the address of a
+    /// peer and completes the first handshake with it keeps the real peer out with this error,
+    /// until `PacketEncryption::remove_peer` is called.
+    UnexpectedHandshake(SocketAddr),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::MissingKey(addr) => write!(f, "No encryption key for {}", addr),
+            EncryptionError::MalformedPacket(addr) => {
+                write!(f, "Malformed encrypted packet from {}", addr)
+            }
+            EncryptionError::AuthenticationFailed(addr) => {
+                write!(f, "Packet from {} failed authentication", addr)
+            }
+            EncryptionError::Replayed(addr) => write!(f, "Replayed packet from {}", addr),
+            EncryptionError::UnexpectedHandshake(addr) => {
+                write!(f, "Handshake from {} which already has a key", addr)
+            }
+        }
+    }
+}
+
+impl error::Error for EncryptionError {}
+
+impl From<EncryptionError> for io::Error {
+    fn from(error: EncryptionError) -> Self {
+        let kind = match error {
+            EncryptionError::MissingKey(_) => io::ErrorKind::NotFound,
+            EncryptionError::MalformedPacket(_)
+            | EncryptionError::AuthenticationFailed(_)
+            | EncryptionError::Replayed(_)
+            | EncryptionError::UnexpectedHandshake(_) => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, error)
+    }
+}
+
+/// What an incoming packet turned out to be after `PacketEncryption::open`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum OpenedPacket {
+    /// A payload sent by the game.
+    Payload(Vec<u8>),
+    /// A key exchange message, handled internally. Nothing needs to be delivered to the game.
+    Handshake,
+}
+
+/// Key of a single peer.
+struct PeerKey {
+    key: Key,
+    /// Public key the peer sent in its handshake, if the key was exchanged.
+    public: Option<[u8; KEY_LEN]>,
+}
+
+/// Counters received from a session of a sender, to detect replayed packets.
+#[derive(Debug, Default)]
+struct ReplayWindow {
+    highest: Option<u64>,
+    /// Bit `n` is set if the counter `highest - n` was received.
+    seen: u64,
+}
+
+impl ReplayWindow {
+    fn is_replay(&self, counter: u64) -> bool {
+        match self.highest {
+            Some(highest) if counter <= highest => {
+                let age = highest - counter;
+                age >= REPLAY_WINDOW || self.seen & (1 << age) != 0
+            }
+            _ => false,
+        }
+    }
+
+    fn mark(&mut self, counter: u64) {
+        match self.highest {
+            Some(highest) if counter <= highest => self.seen |= 1 << (highest - counter),
+            _ => {
+                let shift = self
+                    .highest
+                    .map_or(REPLAY_WINDOW, |highest| counter - highest);
+                self.seen = if shift >= REPLAY_WINDOW {
+                    0
+                } else {
+                    self.seen << shift
+                };
+                self.seen |= 1;
+                self.highest = Some(counter);
+            }
+        }
+    }
+}
+
+/// Resource holding the keys used to encrypt and authenticate packets.
+///
+/// By default encryption is disabled and payloads are sent as they are.
+pub struct PacketEncryption {
+    shared_key: Option<Key>,
+    secret: Option<StaticSecret>,
+    peer_keys: HashMap<SocketAddr, PeerKey>,
+    /// Peers a key exchange was started with, and when their handshake was last sent.
+    pending_exchanges: HashMap<SocketAddr, Instant>,
+    outgoing_handshakes: Vec<(SocketAddr, Vec<u8>)>,
+    /// Random id of this session, the first part of the nonces.
+    session_id: [u8; SESSION_ID_LEN],
+    /// Counter of the sealed packets, the second part of the nonces.
+    send_counter: u64,
+    replay_windows: HashMap<(SocketAddr, [u8; SESSION_ID_LEN]), ReplayWindow>,
+}
+
+impl PacketEncryption {
+    /// Creates a `PacketEncryption` which leaves payloads unencrypted.
+    pub fn disabled() -> Self {
+        Self {
+            shared_key: None,
+            secret: None,
+            peer_keys: HashMap::new(),
+            pending_exchanges: HashMap::new(),
+            outgoing_handshakes: Vec::new(),
+            session_id: {
+                let mut session_id = [0; SESSION_ID_LEN];
+                OsRng.fill_bytes(&mut session_id);
+                session_id
+            },
+            send_counter: 0,
+            replay_windows: HashMap::new(),
+        }
+    }
+
+    /// Creates a `PacketEncryption` sealing every packet with a key all peers know in advance.
+    pub fn with_shared_key(key: [u8; KEY_LEN]) -> Self {
+        Self {
+            shared_key: Some(key.into()),
+            ..Self::disabled()
+        }
+    }
+
+    /// Creates a `PacketEncryption` agreeing on a key with every peer through an X25519 key
+    /// exchange. Without a shared key the exchange is not authenticated, so it only protects
+    /// against passive eavesdroppers. It also lets anyone spoofing the address of a peer send
+    /// the first handshake for it, after which the handshakes of the real peer are rejected
+    /// with `EncryptionError::UnexpectedHandshake`.
+    pub fn with_key_exchange() -> Self {
+        Self::disabled().and_key_exchange()
+    }
+
+    /// Enables the X25519 key exchange in addition to the shared key. Handshakes are
+    /// authenticated with the shared key and the exchanged keys are derived from both, so only
+    /// peers knowing the shared key can complete the exchange.
+    pub fn and_key_exchange(mut self) -> Self {
+        self.secret = Some(StaticSecret::random_from_rng(OsRng));
+        self
+    }
+
+    /// Returns true if payloads are encrypted.
+    pub fn is_enabled(&self) -> bool {
+        self.shared_key.is_some() || self.secret.is_some()
+    }
+
+    /// Returns true if a key for the peer is known.
+    pub fn has_key(&self, addr: SocketAddr) -> bool {
+        self.key_for(addr).is_some()
+    }
+
+    /// Returns true if messages to the peer may be sent now, i.e. if encryption is disabled or a
+    /// key for the peer is known.
+    pub fn can_send_to(&self, addr: SocketAddr) -> bool {
+        !self.is_enabled() || self.has_key(addr)
+    }
+
+    /// Returns true if a key exchange has to be started before messages can be sent to the peer.
+    pub fn needs_key_exchange(&self, addr: SocketAddr) -> bool {
+        self.secret.is_some()
+            && !self.peer_keys.contains_key(&addr)
+            && !self.pending_exchanges.contains_key(&addr)
+    }
+
+    /// Sets the key used for a single peer, e.g. one agreed on by the game's own login
+    /// handshake.
+    pub fn set_peer_key(&mut self, addr: SocketAddr, key: [u8; KEY_LEN]) {
+        self.peer_keys.insert(
+            addr,
+            PeerKey {
+                key: key.into(),
+                public: None,
+            },
+        );
+        self.pending_exchanges.remove(&addr);
+    }
+
+    /// Forgets the key of a peer, e.g. after it disconnected, so that it can exchange a new one.
+    pub fn remove_peer(&mut self, addr: SocketAddr) {
+        self.peer_keys.remove(&addr);
+        self.pending_exchanges.remove(&addr);
+    }
+
+    /// Starts a key exchange with the peer. Messages to the peer are held back until the
+    /// exchange completed. Does nothing if the key exchange is not enabled.
+    pub fn begin_key_exchange(&mut self, addr: SocketAddr) {
+        if let Some(handshake) = self.handshake_packet(PACKET_HANDSHAKE) {
+            self.peer_keys.remove(&addr);
+            self.pending_exchanges.insert(addr, Instant::now());
+            self.outgoing_handshakes.push((addr, handshake));
+        }
+    }
+
+    /// Starts key exchanges with all peers messages are queued for but no key is known yet.
+    /// Handshakes of exchanges still in progress are sent again every `HANDSHAKE_RETRY_INTERVAL`,
+    /// as they may have been lost.
+    pub(crate) fn begin_pending_key_exchanges<'a>(
+        &mut self,
+        messages: impl IntoIterator<Item = &'a Message>,
+        now: Instant,
+    ) {
+        let handshake = match self.handshake_packet(PACKET_HANDSHAKE) {
+            Some(handshake) => handshake,
+            None => return,
+        };
+        let mut destinations = HashSet::new();
+        for message in messages {
+            if !self.peer_keys.contains_key(&message.destination) {
+                destinations.insert(message.destination);
+            }
+        }
+        for addr in destinations {
+            let due = match self.pending_exchanges.get(&addr) {
+                Some(sent) => now.saturating_duration_since(*sent) >= HANDSHAKE_RETRY_INTERVAL,
+                None => true,
+            };
+            if due {
+                self.pending_exchanges.insert(addr, now);
+                self.outgoing_handshakes.push((addr, handshake.clone()));
+            }
+        }
+    }
+
+    /// Returns the key exchange messages which need to be sent. This should be called by a
+    /// transport implementation.
+    pub fn drain_handshakes(&mut self) -> Vec<(SocketAddr, Vec<u8>)> {
+        self.outgoing_handshakes.drain(..).collect()
+    }
+
+    /// Encrypts and authenticates a payload for the peer. This should be called by a transport
+    /// implementation.
+    pub fn seal(&mut self, addr: SocketAddr, payload: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        if !self.is_enabled() {
+            return Ok(payload.to_vec());
+        }
+        let key = self
+            .key_for(addr)
+            .ok_or(EncryptionError::MissingKey(addr))?;
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..SESSION_ID_LEN].copy_from_slice(&self.session_id);
+        nonce[SESSION_ID_LEN..].copy_from_slice(&self.send_counter.to_le_bytes());
+        let aad = [&[PACKET_SEALED][..], &self.session_id].concat();
+        let ciphertext = XChaCha20Poly1305::new(key)
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: payload,
+                    aad: &aad,
+                },
+            )
+            .expect("Unreachable: encryption is infallible for in-memory buffers");
+
+        self.send_counter += 1;
+
+        let mut packet = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+        packet.push(PACKET_SEALED);
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&ciphertext);
+        Ok(packet)
+    }
+
+    /// Verifies and decrypts a packet received from the peer, or handles it if it is part of a
+    /// key exchange. This should be called by a transport implementation.
+    pub fn open(
+        &mut self,
+        addr: SocketAddr,
+        packet: &[u8],
+    ) -> Result<OpenedPacket, EncryptionError> {
+        if !self.is_enabled() {
+            return Ok(OpenedPacket::Payload(packet.to_vec()));
+        }
+        match packet.split_first() {
+            Some((&PACKET_SEALED, sealed)) if sealed.len() >= NONCE_LEN + TAG_LEN => {
+                let key = self
+                    .key_for(addr)
+                    .ok_or(EncryptionError::MissingKey(addr))?;
+                let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+                let mut session_id = [0; SESSION_ID_LEN];
+                session_id.copy_from_slice(&nonce[..SESSION_ID_LEN]);
+                let mut counter = [0; 8];
+                counter.copy_from_slice(&nonce[SESSION_ID_LEN..]);
+                let counter = u64::from_le_bytes(counter);
+                if let Some(window) = self.replay_windows.get(&(addr, session_id)) {
+                    if window.is_replay(counter) {
+                        return Err(EncryptionError::Replayed(addr));
+                    }
+                }
+
+                let aad = [&[PACKET_SEALED][..], &session_id].concat();
+                let payload = XChaCha20Poly1305::new(key)
+                    .decrypt(
+                        XNonce::from_slice(nonce),
+                        Payload {
+                            msg: ciphertext,
+                            aad: &aad,
+                        },
+                    )
+                    .map_err(|_| EncryptionError::AuthenticationFailed(addr))?;
+                // Only authenticated packets may move the window.
+                self.replay_windows
+                    .entry((addr, session_id))
+                    .or_default()
+                    .mark(counter);
+                Ok(OpenedPacket::Payload(payload))
+            }
+            Some((&kind, handshake))
+                if (kind == PACKET_HANDSHAKE || kind == PACKET_HANDSHAKE_REPLY)
+                    && handshake.len() == self.handshake_len() =>
+            {
+                self.complete_key_exchange(addr, kind, handshake)?;
+                Ok(OpenedPacket::Handshake)
+            }
+            _ => Err(EncryptionError::MalformedPacket(addr)),
+        }
+    }
+
+    fn complete_key_exchange(
+        &mut self,
+        addr: SocketAddr,
+        kind: u8,
+        handshake: &[u8],
+    ) -> Result<(), EncryptionError> {
+        let secret = self
+            .secret
+            .as_ref()
+            .ok_or(EncryptionError::MalformedPacket(addr))?;
+        let (public, authentication) = handshake.split_at(KEY_LEN);
+        if let Some(cipher) = self.handshake_cipher() {
+            let (nonce, tag) = authentication.split_at(NONCE_LEN);
+            let aad = [&[kind][..], public].concat();
+            cipher
+                .decrypt(
+                    XNonce::from_slice(nonce),
+                    Payload {
+                        msg: tag,
+                        aad: &aad,
+                    },
+                )
+                .map_err(|_| EncryptionError::AuthenticationFailed(addr))?;
+        }
+        let mut peer_public = [0; KEY_LEN];
+        peer_public.copy_from_slice(public);
+
+        match self.peer_keys.get(&addr) {
+            // The peer didn't get our reply, or the handshake is replayed. Either way the key
+            // stays the same.
+            Some(peer) if peer.public == Some(peer_public) => {
+                if kind == PACKET_HANDSHAKE {
+                    self.queue_handshake(addr, PACKET_HANDSHAKE_REPLY);
+                }
+                return Ok(());
+            }
+            Some(_) => return Err(EncryptionError::UnexpectedHandshake(addr)),
+            None => {}
+        }
+        let shared_secret = secret.diffie_hellman(&PublicKey::from(peer_public));
+
+        let mut hasher = Sha256::new();
+        hasher.update(KEY_DERIVATION_CONTEXT);
+        if let Some(shared_key) = &self.shared_key {
+            hasher.update(shared_key);
+        }
+        hasher.update(shared_secret.as_bytes());
+        let key = hasher.finalize();
+
+        // Answer exchanges started by the peer, so it can derive the same key.
+        self.pending_exchanges.remove(&addr);
+        if kind == PACKET_HANDSHAKE {
+            self.queue_handshake(addr, PACKET_HANDSHAKE_REPLY);
+        }
+        self.peer_keys.insert(
+            addr,
+            PeerKey {
+                key: *Key::from_slice(&key),
+                public: Some(peer_public),
+            },
+        );
+        Ok(())
+    }
+
+    fn queue_handshake(&mut self, addr: SocketAddr, kind: u8) {
+        if let Some(handshake) = self.handshake_packet(kind) {
+            self.outgoing_handshakes.push((addr, handshake));
+        }
+    }
+
+    /// Returns the length of handshakes without their kind.
+    fn handshake_len(&self) -> usize {
+        match self.shared_key {
+            Some(_) => KEY_LEN + NONCE_LEN + TAG_LEN,
+            None => KEY_LEN,
+        }
+    }
+
+    /// Returns the cipher authenticating the handshakes, if a shared key is known.
+    fn handshake_cipher(&self) -> Option<XChaCha20Poly1305> {
+        self.shared_key.as_ref().map(|shared_key| {
+            let mut hasher = Sha256::new();
+            hasher.update(HANDSHAKE_CONTEXT);
+            hasher.update(shared_key);
+            XChaCha20Poly1305::new(Key::from_slice(&hasher.finalize()))
+        })
+    }
+
+    fn handshake_packet(&self, kind: u8) -> Option<Vec<u8>> {
+        let secret = self.secret.as_ref()?;
+        let mut packet = Vec::with_capacity(1 + self.handshake_len());
+        packet.push(kind);
+        packet.extend_from_slice(PublicKey::from(secret).as_bytes());
+        if let Some(cipher) = self.handshake_cipher() {
+            let mut nonce = [0; NONCE_LEN];
+            OsRng.fill_bytes(&mut nonce);
+            let tag = cipher
+                .encrypt(
+                    XNonce::from_slice(&nonce),
+                    Payload {
+                        msg: &[],
+                        aad: &packet,
+                    },
+                )
+                .expect("Unreachable: encryption is infallible for in-memory buffers");
+            packet.extend_from_slice(&nonce);
+            packet.extend_from_slice(&tag);
+        }
+        Some(packet)
+    }
+
+    fn key_for(&self, addr: SocketAddr) -> Option<&Key> {
+        match self.peer_keys.get(&addr) {
+            Some(peer) => Some(&peer.key),
+            // With the key exchange enabled the shared key only authenticates the exchange.
+            None if self.secret.is_none() => self.shared_key.as_ref(),
+            None => None,
+        }
+    }
+}
+
+impl Default for PacketEncryption {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl fmt::Debug for PacketEncryption {
+    // Keys are deliberately left out.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PacketEncryption")
+            .field("shared_key", &self.shared_key.is_some())
+            .field("key_exchange", &self.secret.is_some())
+            .field("peers", &self.peer_keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::requirements::{DeliveryRequirement, UrgencyRequirement};
+
+    fn alice() -> SocketAddr {
+        "127.0.0.1:3000".parse().unwrap()
+    }
+
+    fn bob() -> SocketAddr {
+        "127.0.0.1:3001".parse().unwrap()
+    }
+
+    /// Delivers all pending handshakes of `from` to `to`.
+    fn deliver_handshakes(
+        from: &mut PacketEncryption,
+        from_addr: SocketAddr,
+        to: &mut PacketEncryption,
+    ) {
+        for (_, handshake) in from.drain_handshakes() {
+            assert_eq!(to.open(from_addr, &handshake), Ok(OpenedPacket::Handshake));
+        }
+    }
+
+    #[test]
+    fn test_disabled_passes_payloads_through() {
+        let mut encryption = PacketEncryption::disabled();
+        assert_eq!(encryption.seal(alice(), b"test"), Ok(b"test".to_vec()));
+        assert_eq!(
+            encryption.open(alice(), b"test"),
+            Ok(OpenedPacket::Payload(b"test".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_shared_key_round_trip() {
+        let mut sender = PacketEncryption::with_shared_key([7; KEY_LEN]);
+        let mut receiver = PacketEncryption::with_shared_key([7; KEY_LEN]);
+
+        let packet = sender.seal(bob(), b"test").unwrap();
+        assert_ne!(&packet[1 + NONCE_LEN..], b"test");
+        assert_eq!(
+            receiver.open(alice(), &packet),
+            Ok(OpenedPacket::Payload(b"test".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_tampered_packet_fails_authentication() {
+        let mut sender = PacketEncryption::with_shared_key([7; KEY_LEN]);
+        let mut receiver = PacketEncryption::with_shared_key([7; KEY_LEN]);
+
+        let mut packet = sender.seal(bob(), b"test").unwrap();
+        let last = packet.len() - 1;
+        packet[last] ^= 1;
+        assert_eq!(
+            receiver.open(alice(), &packet),
+            Err(EncryptionError::AuthenticationFailed(alice()))
+        );
+    }
+
+    #[test]
+    fn test_wrong_key_fails_authentication() {
+        let mut sender = PacketEncryption::with_shared_key([7; KEY_LEN]);
+        let mut receiver = PacketEncryption::with_shared_key([8; KEY_LEN]);
+
+        let packet = sender.seal(bob(), b"test").unwrap();
+        assert_eq!(
+            receiver.open(alice(), &packet),
+            Err(EncryptionError::AuthenticationFailed(alice()))
+        );
+    }
+
+    #[test]
+    fn test_replayed_packet_is_rejected() {
+        let mut sender = PacketEncryption::with_shared_key([7; KEY_LEN]);
+        let mut receiver = PacketEncryption::with_shared_key([7; KEY_LEN]);
+
+        let first = sender.seal(bob(), b"first").unwrap();
+        let second = sender.seal(bob(), b"second").unwrap();
+        // Packets may arrive out of order, but only once.
+        assert!(receiver.open(alice(), &second).is_ok());
+        assert!(receiver.open(alice(), &first).is_ok());
+        assert_eq!(
+            receiver.open(alice(), &first),
+            Err(EncryptionError::Replayed(alice()))
+        );
+        assert_eq!(
+            receiver.open(alice(), &second),
+            Err(EncryptionError::Replayed(alice()))
+        );
+
+        // Packets older than the window are rejected as well.
+        let old = sender.seal(bob(), b"old").unwrap();
+        for _ in 0..REPLAY_WINDOW {
+            let packet = sender.seal(bob(), b"new").unwrap();
+            assert!(receiver.open(alice(), &packet).is_ok());
+        }
+        assert_eq!(
+            receiver.open(alice(), &old),
+            Err(EncryptionError::Replayed(alice()))
+        );
+    }
+
+    #[test]
+    fn test_replay_windows_are_kept_per_address() {
+        let mut first = PacketEncryption::with_shared_key([7; KEY_LEN]);
+        let mut second = PacketEncryption::with_shared_key([7; KEY_LEN]);
+        second.session_id = first.session_id;
+        let mut receiver = PacketEncryption::with_shared_key([7; KEY_LEN]);
+
+        // The same session id and counter from two addresses are two different packets.
+        let packet = first.seal(bob(), b"first").unwrap();
+        assert!(receiver.open(alice(), &packet).is_ok());
+        let packet = second.seal(bob(), b"second").unwrap();
+        assert!(receiver
+            .open("127.0.0.1:3002".parse().unwrap(), &packet)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_session_id_is_authenticated() {
+        let mut sender = PacketEncryption::with_shared_key([7; KEY_LEN]);
+        let mut receiver = PacketEncryption::with_shared_key([7; KEY_LEN]);
+
+        let mut packet = sender.seal(bob(), b"test").unwrap();
+        packet[1] ^= 1;
+        assert_eq!(
+            receiver.open(alice(), &packet),
+            Err(EncryptionError::AuthenticationFailed(alice()))
+        );
+    }
+
+    #[test]
+    fn test_malformed_packet() {
+        let mut receiver = PacketEncryption::with_shared_key([7; KEY_LEN]);
+        assert_eq!(
+            receiver.open(alice(), &[PACKET_SEALED, 1, 2, 3]),
+            Err(EncryptionError::MalformedPacket(alice()))
+        );
+    }
+
+    #[test]
+    fn test_key_exchange() {
+        let mut a = PacketEncryption::with_key_exchange();
+        let mut b = PacketEncryption::with_key_exchange();
+
+        assert!(a.needs_key_exchange(bob()));
+        assert!(!a.can_send_to(bob()));
+        a.begin_key_exchange(bob());
+        assert!(!a.needs_key_exchange(bob()));
+        assert_eq!(
+            a.seal(bob(), b"test"),
+            Err(EncryptionError::MissingKey(bob()))
+        );
+
+        deliver_handshakes(&mut a, alice(), &mut b);
+        assert!(b.has_key(alice()));
+        deliver_handshakes(&mut b, bob(), &mut a);
+        assert!(a.can_send_to(bob()));
+        assert!(a.drain_handshakes().is_empty());
+
+        let packet = a.seal(bob(), b"test").unwrap();
+        assert_eq!(
+            b.open(alice(), &packet),
+            Ok(OpenedPacket::Payload(b"test".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_key_exchange_is_authenticated_by_shared_key() {
+        let mut a = PacketEncryption::with_shared_key([1; KEY_LEN]).and_key_exchange();
+        let mut b = PacketEncryption::with_shared_key([2; KEY_LEN]).and_key_exchange();
+
+        a.begin_key_exchange(bob());
+        let (_, handshake) = a.drain_handshakes().remove(0);
+        assert_eq!(
+            b.open(alice(), &handshake),
+            Err(EncryptionError::AuthenticationFailed(alice()))
+        );
+        assert!(!b.has_key(alice()));
+        assert!(b.drain_handshakes().is_empty());
+    }
+
+    #[test]
+    fn test_handshake_without_shared_key_is_rejected() {
+        let mut a = PacketEncryption::with_key_exchange();
+        let mut b = PacketEncryption::with_shared_key([1; KEY_LEN]).and_key_exchange();
+
+        a.begin_key_exchange(bob());
+        let (_, mut handshake) = a.drain_handshakes().remove(0);
+        handshake.extend_from_slice(&[0; NONCE_LEN + TAG_LEN]);
+        assert_eq!(
+            b.open(alice(), &handshake),
+            Err(EncryptionError::AuthenticationFailed(alice()))
+        );
+        assert!(!b.has_key(alice()));
+    }
+
+    #[test]
+    fn test_established_key_is_not_replaced() {
+        let mut a = PacketEncryption::with_key_exchange();
+        let mut b = PacketEncryption::with_key_exchange();
+        let mut mallory = PacketEncryption::with_key_exchange();
+
+        a.begin_key_exchange(bob());
+        deliver_handshakes(&mut a, alice(), &mut b);
+        deliver_handshakes(&mut b, bob(), &mut a);
+
+        // A spoofed handshake is rejected and the key of the peer keeps working.
+        mallory.begin_key_exchange(bob());
+        let (_, spoofed) = mallory.drain_handshakes().remove(0);
+        assert_eq!(
+            b.open(alice(), &spoofed),
+            Err(EncryptionError::UnexpectedHandshake(alice()))
+        );
+        let packet = a.seal(bob(), b"test").unwrap();
+        assert_eq!(
+            b.open(alice(), &packet),
+            Ok(OpenedPacket::Payload(b"test".to_vec()))
+        );
+
+        // A handshake sent again because the reply was lost is answered without a new key.
+        a.begin_key_exchange(bob());
+        deliver_handshakes(&mut a, alice(), &mut b);
+        deliver_handshakes(&mut b, bob(), &mut a);
+        assert!(a.drain_handshakes().is_empty());
+        let packet = a.seal(bob(), b"again").unwrap();
+        assert_eq!(
+            b.open(alice(), &packet),
+            Ok(OpenedPacket::Payload(b"again".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_handshakes_are_retried_after_interval() {
+        let mut a = PacketEncryption::with_key_exchange();
+        let messages = vec![Message::new(
+            bob(),
+            b"test",
+            DeliveryRequirement::Unreliable,
+            UrgencyRequirement::OnTick,
+        )];
+        let now = Instant::now();
+
+        a.begin_pending_key_exchanges(&messages, now);
+        assert_eq!(a.drain_handshakes().len(), 1);
+        a.begin_pending_key_exchanges(&messages, now + Duration::from_millis(100));
+        assert!(a.drain_handshakes().is_empty());
+        a.begin_pending_key_exchanges(&messages, now + HANDSHAKE_RETRY_INTERVAL);
+        assert_eq!(a.drain_handshakes().len(), 1);
+    }
+}
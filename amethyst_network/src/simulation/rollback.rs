@@ -0,0 +1,888 @@
+//! Deterministic lockstep simulation with rollback (GGPO-style) on misprediction.
+//!
+//! Every peer runs the same fixed-update dispatcher once per simulation frame. The state of each
+//! action of a `RollbackInput` resource is packed into a bitmask and exchanged with the other
+//! peers for every frame. When the input of a remote player has not arrived yet it is predicted by repeating the
+//! last known input. Once the real input arrives and differs from the prediction, the designated
+//! ECS state is restored to the mispredicted frame and the dispatcher re-simulates every frame
+//! up to the present.
+//!
+//! The `RollbackDispatcher` is meant to be driven from `State::fixed_update`, so that one fixed
+//! step of `CoreApplication` advances the simulation by exactly one frame. It keeps the
+//! `NetworkSimulationTime` frame number in sync with the simulation, so it should not be combined
+//! with a running `NetworkSimulationTimeSystem`.
+
+use crate::simulation::{
+    events::NetworkSimulationEvent,
+    requirements::{DeliveryRequirement, UrgencyRequirement},
+    timing::NetworkSimulationTime,
+    transport::TransportResource,
+};
+use amethyst_core::{
+    ecs::{
+        shred::Resource, Component, Dispatcher, DispatcherBuilder, Entity, Join, System, World,
+        WorldExt,
+    },
+    shrev::{EventChannel, ReaderId},
+};
+#[cfg(feature = "input")]
+use amethyst_input::{BindingTypes, InputHandler};
+use derivative::Derivative;
+use log::warn;
+use std::{
+    any::Any,
+    borrow::Borrow,
+    collections::{BTreeMap, VecDeque},
+    fmt::Debug,
+    hash::Hash,
+    marker::PhantomData,
+    net::SocketAddr,
+};
+
+/// First byte of every message carrying rollback inputs. Messages starting with any other byte
+/// are left alone, so the game can keep using the same transport for its own messages.
+pub const ROLLBACK_MESSAGE_TAG: u8 = 0xAB;
+
+/// Maximum number of actions that can be exchanged, one bit of the input bitmask each.
+pub const MAX_ROLLBACK_ACTIONS: usize = 64;
+
+/// Number of bytes an encoded `(frame, input)` pair takes on the wire.
+const ENCODED_INPUT_LEN: usize = 12;
+
+const DEFAULT_INPUT_DELAY: u32 = 2;
+const DEFAULT_MAX_PREDICTION_FRAMES: u32 = 8;
+const DEFAULT_INPUT_REDUNDANCY: usize = 8;
+
+/// Encodes a list of `(frame, input)` pairs into a rollback message payload.
+pub fn encode_inputs(inputs: &[(u32, u64)]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(2 + inputs.len() * ENCODED_INPUT_LEN);
+    payload.push(ROLLBACK_MESSAGE_TAG);
+    payload.push(inputs.len() as u8);
+    for (frame, input) in inputs {
+        payload.extend_from_slice(&frame.to_be_bytes());
+        payload.extend_from_slice(&input.to_be_bytes());
+    }
+    payload
+}
+
+/// Decodes a rollback message payload. Returns `None` if the payload is not a well formed
+/// rollback message.
+pub fn decode_inputs(payload: &[u8]) -> Option<Vec<(u32, u64)>> {
+    match payload {
+        [ROLLBACK_MESSAGE_TAG, count, rest @ ..]
+            if rest.len() == *count as usize * ENCODED_INPUT_LEN =>
+        {
+            Some(
+                rest.chunks(ENCODED_INPUT_LEN)
+                    .map(|chunk| {
+                        let mut frame = [0; 4];
+                        let mut input = [0; 8];
+                        frame.copy_from_slice(&chunk[..4]);
+                        input.copy_from_slice(&chunk[4..]);
+                        (u32::from_be_bytes(frame), u64::from_be_bytes(input))
+                    })
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Input history of a single player.
+#[derive(Debug, Default)]
+struct PlayerInputs {
+    /// Inputs known to be correct, keyed by frame.
+    confirmed: BTreeMap<u32, u64>,
+    /// Inputs the simulation actually ran with, keyed by frame.
+    simulated: BTreeMap<u32, u64>,
+    /// Every frame up to and including this one has a confirmed input.
+    last_confirmed_frame: Option<u32>,
+}
+
+impl PlayerInputs {
+    fn confirm(&mut self, frame: u32, input: u64) {
+        self.confirmed.insert(frame, input);
+        let mut next = self.last_confirmed_frame.map_or(0, |f| f + 1);
+        while self.confirmed.contains_key(&next) {
+            self.last_confirmed_frame = Some(next);
+            next += 1;
+        }
+    }
+
+    /// Returns the confirmed input for the frame, or a prediction repeating the most recent
+    /// confirmed input before it.
+    fn input_for(&self, frame: u32) -> u64 {
+        self.confirmed
+            .range(..=frame)
+            .next_back()
+            .map_or(0, |(_, input)| *input)
+    }
+
+    fn prune_before(&mut self, frame: u32) {
+        // Always keep the newest confirmed input around, it is the base of every prediction.
+        let keep_from = self
+            .last_confirmed_frame
+            .map_or(frame, |confirmed| frame.min(confirmed));
+        self.confirmed = self.confirmed.split_off(&keep_from);
+        self.simulated = self.simulated.split_off(&frame);
+    }
+}
+
+/// Bookkeeping of the inputs of all players in a rollback session.
+///
+/// This type does not touch the `World`; it only decides which inputs each frame is simulated
+/// with, whether the simulation may advance, and from which frame it has to be re-simulated.
+#[derive(Debug)]
+pub struct RollbackSession {
+    local_player: usize,
+    remote_players: Vec<(usize, SocketAddr)>,
+    players: Vec<PlayerInputs>,
+    input_delay: u32,
+    max_prediction_frames: u32,
+    input_redundancy: usize,
+    current_frame: u32,
+    rollback_frame: Option<u32>,
+}
+
+impl RollbackSession {
+    /// Creates a session where the local player has the given index and remote players are
+    /// reached through the given addresses.
+    pub fn new(
+        local_player: usize,
+        remote_players: Vec<(usize, SocketAddr)>,
+        input_delay: u32,
+        max_prediction_frames: u32,
+    ) -> Self {
+        let player_count = remote_players
+            .iter()
+            .map(|(player, _)| *player)
+            .chain(Some(local_player))
+            .max()
+            .map_or(1, |max| max + 1);
+        let mut players = Vec::with_capacity(player_count);
+        players.resize_with(player_count, PlayerInputs::default);
+        // Nobody can provide input for the frames hidden by the input delay, so every peer
+        // agrees they are empty.
+        for player in players.iter_mut() {
+            for frame in 0..input_delay {
+                player.confirm(frame, 0);
+            }
+        }
+        Self {
+            local_player,
+            remote_players,
+            players,
+            input_delay,
+            max_prediction_frames,
+            input_redundancy: DEFAULT_INPUT_REDUNDANCY,
+            current_frame: 0,
+            rollback_frame: None,
+        }
+    }
+
+    /// Returns the index of the local player.
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    /// Returns the number of players in the session.
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Returns the frame that will be simulated next.
+    pub fn current_frame(&self) -> u32 {
+        self.current_frame
+    }
+
+    /// Returns the number of frames between sampling a local input and simulating it.
+    pub fn input_delay(&self) -> u32 {
+        self.input_delay
+    }
+
+    /// Returns how many frames the simulation may run ahead of the last confirmed remote input.
+    pub fn max_prediction_frames(&self) -> u32 {
+        self.max_prediction_frames
+    }
+
+    /// Sets how many of the most recent local inputs are sent in every message, so that a lost
+    /// packet does not stall the remote peers.
+    pub fn set_input_redundancy(&mut self, redundancy: usize) {
+        self.input_redundancy = redundancy.clamp(1, u8::MAX as usize);
+    }
+
+    /// Returns the player index belonging to a remote address.
+    pub fn player_for_addr(&self, addr: SocketAddr) -> Option<usize> {
+        self.remote_players
+            .iter()
+            .find(|(_, remote)| *remote == addr)
+            .map(|(player, _)| *player)
+    }
+
+    /// Returns the addresses of all remote players.
+    pub fn remote_addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.remote_players.iter().map(|(_, addr)| *addr)
+    }
+
+    /// Records the local input sampled in the current frame. It applies to the frame
+    /// `current_frame + input_delay`.
+    pub fn add_local_input(&mut self, input: u64) {
+        let frame = self.current_frame + self.input_delay;
+        self.add_input(self.local_player, frame, input);
+    }
+
+    /// Records an input received from a remote player.
+    pub fn add_remote_input(&mut self, player: usize, frame: u32, input: u64) {
+        if player != self.local_player {
+            self.add_input(player, frame, input);
+        }
+    }
+
+    fn add_input(&mut self, player: usize, frame: u32, input: u64) {
+        let inputs = match self.players.get_mut(player) {
+            Some(inputs) => inputs,
+            None => return,
+        };
+        if inputs.confirmed.contains_key(&frame) {
+            return;
+        }
+        inputs.confirm(frame, input);
+        // Every simulated frame from this one up to the next confirmed input used the
+        // prediction, so check all of them.
+        let mispredicted = inputs
+            .simulated
+            .range(frame..)
+            .take_while(|(f, _)| **f == frame || !inputs.confirmed.contains_key(f))
+            .find(|(f, simulated)| inputs.input_for(**f) != **simulated)
+            .map(|(f, _)| *f);
+        if let Some(mispredicted) = mispredicted {
+            self.rollback_frame = Some(
+                self.rollback_frame
+                    .map_or(mispredicted, |f| f.min(mispredicted)),
+            );
+        }
+    }
+
+    /// Returns the most recent local inputs, to be sent to the remote players.
+    pub fn local_inputs_to_send(&self) -> Vec<(u32, u64)> {
+        let confirmed = &self.players[self.local_player].confirmed;
+        let mut inputs = confirmed
+            .iter()
+            .rev()
+            .take(self.input_redundancy)
+            .map(|(frame, input)| (*frame, *input))
+            .collect::<Vec<_>>();
+        inputs.reverse();
+        inputs
+    }
+
+    /// Returns the last frame for which the inputs of all players are confirmed.
+    pub fn last_confirmed_frame(&self) -> Option<u32> {
+        self.players
+            .iter()
+            .map(|player| player.last_confirmed_frame)
+            .min()
+            .flatten()
+    }
+
+    /// Returns true if the current frame may be simulated without exceeding the prediction
+    /// window.
+    pub fn can_advance(&self) -> bool {
+        let predicted_from = self.last_confirmed_frame().map_or(0, |f| f + 1);
+        self.current_frame < predicted_from + self.max_prediction_frames
+    }
+
+    /// Returns and clears the earliest frame that was simulated with a wrong prediction.
+    pub fn take_rollback_frame(&mut self) -> Option<u32> {
+        self.rollback_frame.take()
+    }
+
+    /// Returns the inputs of all players for the frame and remembers them as the inputs the
+    /// frame was simulated with.
+    pub fn inputs_for_frame(&mut self, frame: u32) -> Vec<u64> {
+        self.players
+            .iter_mut()
+            .map(|player| {
+                let input = player.input_for(frame);
+                player.simulated.insert(frame, input);
+                input
+            })
+            .collect()
+    }
+
+    /// Moves on to the next frame, forgetting inputs that can no longer be rolled back to.
+    pub fn advance_frame(&mut self) {
+        self.current_frame += 1;
+        if let Some(oldest) = self
+            .current_frame
+            .checked_sub(self.max_prediction_frames + 1)
+        {
+            for player in self.players.iter_mut() {
+                player.prune_before(oldest);
+            }
+        }
+    }
+}
+
+/// A resource the local input of a `RollbackDispatcher` is sampled from.
+///
+/// With the `input` feature, it is implemented for the `InputHandler` of `amethyst_input`.
+pub trait RollbackInput: Send + Sync + 'static {
+    /// Type of the actions exchanged with the other peers.
+    type Action: Clone + Debug + PartialEq + Send + Sync + 'static;
+
+    /// Returns true if the action is down.
+    fn is_down(&self, action: &Self::Action) -> bool;
+}
+
+#[cfg(feature = "input")]
+impl<T: BindingTypes> RollbackInput for InputHandler<T> {
+    type Action = T::Action;
+
+    fn is_down(&self, action: &T::Action) -> bool {
+        self.action_is_down(action).unwrap_or(false)
+    }
+}
+
+/// Resource holding the inputs of every player for the frame being simulated. Systems run by a
+/// `RollbackDispatcher` should read their input from here rather than from the `RollbackInput`.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""), Default(bound = ""))]
+pub struct RollbackInputs<T: RollbackInput> {
+    actions: Vec<T::Action>,
+    frame: u32,
+    inputs: Vec<u64>,
+    resimulating: bool,
+}
+
+impl<T: RollbackInput> RollbackInputs<T> {
+    /// Returns the frame being simulated.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Returns true if the frame is being re-simulated after a misprediction. Systems with side
+    /// effects outside of the rolled back state, like playing sounds, may want to skip them.
+    pub fn is_resimulating(&self) -> bool {
+        self.resimulating
+    }
+
+    /// Returns the number of players in the session.
+    pub fn player_count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Returns the raw input bitmask of a player.
+    pub fn raw_input(&self, player: usize) -> Option<u64> {
+        self.inputs.get(player).cloned()
+    }
+
+    /// Returns true if the action is down for the player. Returns `None` if either the player or
+    /// the action is not part of the session.
+    pub fn action_is_down<A>(&self, player: usize, action: &A) -> Option<bool>
+    where
+        T::Action: Borrow<A>,
+        A: Hash + Eq + ?Sized,
+    {
+        let bit = self.actions.iter().position(|a| a.borrow() == action)?;
+        self.raw_input(player).map(|input| input & (1 << bit) != 0)
+    }
+}
+
+/// Saves and restores one piece of designated ECS state.
+trait StateSnapshot: Send + Sync {
+    fn save(&self, world: &World) -> Box<dyn Any + Send + Sync>;
+
+    fn restore(&self, world: &World, snapshot: &(dyn Any + Send + Sync));
+}
+
+struct ComponentSnapshot<C>(PhantomData<C>);
+
+impl<C> StateSnapshot for ComponentSnapshot<C>
+where
+    C: Component + Clone + Send + Sync,
+{
+    fn save(&self, world: &World) -> Box<dyn Any + Send + Sync> {
+        let entities = world.entities();
+        let storage = world.read_storage::<C>();
+        Box::new(
+            (&entities, &storage)
+                .join()
+                .map(|(entity, component)| (entity, component.clone()))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn restore(&self, world: &World, snapshot: &(dyn Any + Send + Sync)) {
+        let saved = snapshot
+            .downcast_ref::<Vec<(Entity, C)>>()
+            .expect("Unreachable: snapshot was saved by the same type");
+        let mut storage = world.write_storage::<C>();
+        storage.clear();
+        for (entity, component) in saved {
+            if storage.insert(*entity, component.clone()).is_err() {
+                warn!(
+                    "Entity {:?} was deleted during rollback, its state can't be restored",
+                    entity
+                );
+            }
+        }
+    }
+}
+
+struct ResourceSnapshot<R>(PhantomData<R>);
+
+impl<R> StateSnapshot for ResourceSnapshot<R>
+where
+    R: Resource + Clone,
+{
+    fn save(&self, world: &World) -> Box<dyn Any + Send + Sync> {
+        Box::new(R::clone(&world.fetch::<R>()))
+    }
+
+    fn restore(&self, world: &World, snapshot: &(dyn Any + Send + Sync)) {
+        let saved = snapshot
+            .downcast_ref::<R>()
+            .expect("Unreachable: snapshot was saved by the same type");
+        *world.fetch_mut::<R>() = saved.clone();
+    }
+}
+
+/// Builder for a `RollbackDispatcher`.
+#[allow(missing_debug_implementations)]
+pub struct RollbackDispatcherBuilder<'a, 'b, T: RollbackInput> {
+    actions: Vec<T::Action>,
+    local_player: usize,
+    remote_players: Vec<(usize, SocketAddr)>,
+    input_delay: u32,
+    max_prediction_frames: u32,
+    input_redundancy: usize,
+    snapshots: Vec<Box<dyn StateSnapshot>>,
+    dispatcher_builder: DispatcherBuilder<'a, 'b>,
+}
+
+impl<'a, 'b, T: RollbackInput> RollbackDispatcherBuilder<'a, 'b, T> {
+    /// Creates a builder exchanging the given actions. Every peer must list the actions in the
+    /// same order, as each action is sent as one bit of the input bitmask.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `MAX_ROLLBACK_ACTIONS` actions are given.
+    pub fn new(actions: Vec<T::Action>) -> Self {
+        assert!(
+            actions.len() <= MAX_ROLLBACK_ACTIONS,
+            "At most {} actions can be exchanged, {} were given.",
+            MAX_ROLLBACK_ACTIONS,
+            actions.len()
+        );
+        Self {
+            actions,
+            local_player: 0,
+            remote_players: Vec::new(),
+            input_delay: DEFAULT_INPUT_DELAY,
+            max_prediction_frames: DEFAULT_MAX_PREDICTION_FRAMES,
+            input_redundancy: DEFAULT_INPUT_REDUNDANCY,
+            snapshots: Vec::new(),
+            dispatcher_builder: DispatcherBuilder::new(),
+        }
+    }
+
+    /// Sets the player index of the local player. Defaults to 0.
+    pub fn with_local_player(mut self, player: usize) -> Self {
+        self.local_player = player;
+        self
+    }
+
+    /// Adds a remote player with the given index, reachable at the given address.
+    pub fn with_remote_player(mut self, player: usize, addr: SocketAddr) -> Self {
+        self.remote_players.push((player, addr));
+        self
+    }
+
+    /// Sets the number of frames between sampling a local input and simulating it. A larger
+    /// delay means fewer rollbacks at the cost of responsiveness. Defaults to 2.
+    pub fn with_input_delay(mut self, frames: u32) -> Self {
+        self.input_delay = frames;
+        self
+    }
+
+    /// Sets how many frames the simulation may run ahead of the remote players before it
+    /// stalls. Defaults to 8.
+    pub fn with_max_prediction_frames(mut self, frames: u32) -> Self {
+        self.max_prediction_frames = frames.max(1);
+        self
+    }
+
+    /// Sets how many of the most recent local inputs are resent in every message. Defaults to 8.
+    pub fn with_input_redundancy(mut self, redundancy: usize) -> Self {
+        self.input_redundancy = redundancy;
+        self
+    }
+
+    /// Saves and restores all components of type `C` on rollback.
+    pub fn with_component<C>(mut self) -> Self
+    where
+        C: Component + Clone + Send + Sync,
+    {
+        self.snapshots
+            .push(Box::new(ComponentSnapshot::<C>(PhantomData)));
+        self
+    }
+
+    /// Saves and restores the resource `R` on rollback.
+    pub fn with_resource<R>(mut self) -> Self
+    where
+        R: Resource + Clone,
+    {
+        self.snapshots
+            .push(Box::new(ResourceSnapshot::<R>(PhantomData)));
+        self
+    }
+
+    /// Adds a system to the dispatcher that is run for every simulated frame.
+    pub fn with<S>(mut self, system: S, name: &str, dependencies: &[&str]) -> Self
+    where
+        S: for<'c> System<'c> + Send + 'a,
+    {
+        self.dispatcher_builder.add(system, name, dependencies);
+        self
+    }
+
+    /// Builds the `RollbackDispatcher`, setting up its systems and resources in the `World`.
+    pub fn build(self, world: &mut World) -> RollbackDispatcher<'a, 'b, T> {
+        let mut dispatcher = self.dispatcher_builder.build();
+        dispatcher.setup(world);
+
+        world
+            .entry::<TransportResource>()
+            .or_insert_with(Default::default);
+        world
+            .entry::<NetworkSimulationTime>()
+            .or_insert_with(Default::default);
+        world.insert(RollbackInputs::<T> {
+            actions: self.actions.clone(),
+            ..Default::default()
+        });
+        let reader = world
+            .entry::<EventChannel<NetworkSimulationEvent>>()
+            .or_insert_with(EventChannel::new)
+            .register_reader();
+
+        let mut session = RollbackSession::new(
+            self.local_player,
+            self.remote_players,
+            self.input_delay,
+            self.max_prediction_frames,
+        );
+        session.set_input_redundancy(self.input_redundancy);
+
+        RollbackDispatcher {
+            actions: self.actions,
+            session,
+            reader,
+            snapshots: self.snapshots,
+            saved_frames: VecDeque::new(),
+            dispatcher,
+        }
+    }
+}
+
+/// Runs a fixed-update dispatcher in lockstep with the remote players, rolling back and
+/// re-simulating on misprediction. Call `run_frame` from `State::fixed_update`.
+#[allow(missing_debug_implementations)]
+pub struct RollbackDispatcher<'a, 'b, T: RollbackInput> {
+    actions: Vec<T::Action>,
+    session: RollbackSession,
+    reader: ReaderId<NetworkSimulationEvent>,
+    snapshots: Vec<Box<dyn StateSnapshot>>,
+    saved_frames: VecDeque<(u32, Vec<Box<dyn Any + Send + Sync>>)>,
+    dispatcher: Dispatcher<'a, 'b>,
+}
+
+impl<'a, 'b, T: RollbackInput> RollbackDispatcher<'a, 'b, T> {
+    /// Returns the session bookkeeping the inputs of all players.
+    pub fn session(&self) -> &RollbackSession {
+        &self.session
+    }
+
+    /// Receives remote inputs, re-simulates mispredicted frames and then simulates the current
+    /// frame. Returns false if the simulation stalled because the remote players are too far
+    /// behind.
+    pub fn run_frame(&mut self, world: &mut World) -> bool {
+        self.receive_inputs(world);
+        self.rollback(world);
+
+        if !self.session.can_advance() {
+            self.send_inputs(world);
+            return false;
+        }
+
+        let input = self.sample_local_input(world);
+        self.session.add_local_input(input);
+        self.send_inputs(world);
+
+        let frame = self.session.current_frame();
+        self.simulate(world, frame, false);
+        self.session.advance_frame();
+        world
+            .write_resource::<NetworkSimulationTime>()
+            .set_frame_number(self.session.current_frame());
+        true
+    }
+
+    fn receive_inputs(&mut self, world: &World) {
+        let channel = world.read_resource::<EventChannel<NetworkSimulationEvent>>();
+        for event in channel.read(&mut self.reader) {
+            if let NetworkSimulationEvent::Message(addr, payload) = event {
+                let player = match self.session.player_for_addr(*addr) {
+                    Some(player) => player,
+                    None => continue,
+                };
+                if let Some(inputs) = decode_inputs(payload) {
+                    for (frame, input) in inputs {
+                        self.session.add_remote_input(player, frame, input);
+                    }
+                }
+            }
+        }
+    }
+
+    fn rollback(&mut self, world: &mut World) {
+        let frame = match self.session.take_rollback_frame() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let index = match self.saved_frames.iter().position(|(f, _)| *f == frame) {
+            Some(index) => index,
+            None => {
+                warn!(
+                    "Frame {} mispredicted but is outside the rollback window, can't correct it",
+                    frame
+                );
+                return;
+            }
+        };
+        let (_, saved) = &self.saved_frames[index];
+        for (snapshot, state) in self.snapshots.iter().zip(saved) {
+            snapshot.restore(world, state.as_ref());
+        }
+        self.saved_frames.truncate(index);
+        for frame in frame..self.session.current_frame() {
+            self.simulate(world, frame, true);
+        }
+    }
+
+    fn simulate(&mut self, world: &mut World, frame: u32, resimulating: bool) {
+        let saved = self
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.save(world))
+            .collect();
+        self.saved_frames.push_back((frame, saved));
+        while self.saved_frames.len() > self.session.max_prediction_frames() as usize + 1 {
+            self.saved_frames.pop_front();
+        }
+
+        let inputs = self.session.inputs_for_frame(frame);
+        {
+            let mut rollback_inputs = world.write_resource::<RollbackInputs<T>>();
+            rollback_inputs.frame = frame;
+            rollback_inputs.inputs = inputs;
+            rollback_inputs.resimulating = resimulating;
+        }
+        self.dispatcher.dispatch(world);
+    }
+
+    fn sample_local_input(&self, world: &World) -> u64 {
+        let input = match world.try_fetch::<T>() {
+            Some(input) => input,
+            None => return 0,
+        };
+        self.actions
+            .iter()
+            .enumerate()
+            .filter(|(_, action)| input.is_down(action))
+            .fold(0, |input, (bit, _)| input | 1 << bit)
+    }
+
+    fn send_inputs(&self, world: &World) {
+        let payload = encode_inputs(&self.session.local_inputs_to_send());
+        let mut transport = world.write_resource::<TransportResource>();
+        for addr in self.session.remote_addrs() {
+            transport.send_with_requirements(
+                addr,
+                &payload,
+                DeliveryRequirement::Unreliable,
+                UrgencyRequirement::Immediate,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst_core::ecs::{Builder, DenseVecStorage, Read, WriteStorage};
+    use bytes::Bytes;
+
+    /// Local input holding no action.
+    #[derive(Default)]
+    struct NoInput;
+
+    impl RollbackInput for NoInput {
+        type Action = String;
+
+        fn is_down(&self, _: &String) -> bool {
+            false
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Position(i32);
+
+    impl Component for Position {
+        type Storage = DenseVecStorage<Self>;
+    }
+
+    /// Moves every `Position` by one for each player holding "right".
+    struct MoveSystem;
+
+    impl<'s> System<'s> for MoveSystem {
+        type SystemData = (
+            Read<'s, RollbackInputs<NoInput>>,
+            WriteStorage<'s, Position>,
+        );
+
+        fn run(&mut self, (inputs, mut positions): Self::SystemData) {
+            let steps = (0..inputs.player_count())
+                .filter(|player| inputs.action_is_down(*player, "right").unwrap())
+                .count() as i32;
+            for position in (&mut positions).join() {
+                position.0 += steps;
+            }
+        }
+    }
+
+    fn remote_addr() -> SocketAddr {
+        "127.0.0.1:3000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let inputs = vec![(1, 0), (2, 5), (u32::MAX, u64::MAX)];
+        assert_eq!(decode_inputs(&encode_inputs(&inputs)), Some(inputs));
+    }
+
+    #[test]
+    fn test_decode_rejects_foreign_messages() {
+        assert_eq!(decode_inputs(b"hello"), None);
+        assert_eq!(decode_inputs(&[ROLLBACK_MESSAGE_TAG, 1, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_remote_input_is_predicted_from_last_confirmed() {
+        let mut session = RollbackSession::new(0, vec![(1, remote_addr())], 0, 8);
+        session.add_remote_input(1, 0, 3);
+        assert_eq!(session.inputs_for_frame(0), vec![0, 3]);
+        assert_eq!(session.inputs_for_frame(1), vec![0, 3]);
+    }
+
+    #[test]
+    fn test_misprediction_requests_rollback() {
+        let mut session = RollbackSession::new(0, vec![(1, remote_addr())], 0, 8);
+        session.add_remote_input(1, 0, 1);
+        for frame in 0..4 {
+            session.inputs_for_frame(frame);
+            session.advance_frame();
+        }
+
+        // Correct prediction.
+        session.add_remote_input(1, 1, 1);
+        assert_eq!(session.take_rollback_frame(), None);
+
+        // Misprediction.
+        session.add_remote_input(1, 2, 0);
+        assert_eq!(session.take_rollback_frame(), Some(2));
+        assert_eq!(session.take_rollback_frame(), None);
+    }
+
+    #[test]
+    fn test_session_stalls_outside_prediction_window() {
+        let mut session = RollbackSession::new(0, vec![(1, remote_addr())], 0, 2);
+        for _ in 0..2 {
+            assert!(session.can_advance());
+            session.add_local_input(0);
+            session.advance_frame();
+        }
+        assert!(!session.can_advance());
+
+        session.add_remote_input(1, 0, 0);
+        assert!(session.can_advance());
+    }
+
+    #[test]
+    fn test_input_delay_frames_are_confirmed() {
+        let session = RollbackSession::new(0, vec![(1, remote_addr())], 3, 8);
+        assert_eq!(session.last_confirmed_frame(), Some(2));
+    }
+
+    #[test]
+    fn test_local_inputs_are_delayed() {
+        let mut session = RollbackSession::new(0, vec![(1, remote_addr())], 2, 8);
+        session.add_local_input(7);
+        assert_eq!(session.local_inputs_to_send(), vec![(0, 0), (1, 0), (2, 7)]);
+    }
+
+    #[test]
+    fn test_rollback_dispatcher_resimulates_on_misprediction() {
+        let mut world = World::new();
+        let mut rollback = RollbackDispatcherBuilder::<NoInput>::new(vec![
+            "left".to_string(),
+            "right".to_string(),
+        ])
+        .with_local_player(0)
+        .with_remote_player(1, remote_addr())
+        .with_input_delay(0)
+        .with_component::<Position>()
+        .with(MoveSystem, "move", &[])
+        .build(&mut world);
+        let entity = world.create_entity().with(Position(0)).build();
+
+        // Remote player holds nothing for frame 0, which is then predicted for frames 1 and 2.
+        world
+            .write_resource::<EventChannel<NetworkSimulationEvent>>()
+            .single_write(NetworkSimulationEvent::Message(
+                remote_addr(),
+                Bytes::from(encode_inputs(&[(0, 0)])),
+            ));
+        for _ in 0..3 {
+            assert!(rollback.run_frame(&mut world));
+        }
+        assert_eq!(
+            world.read_storage::<Position>().get(entity),
+            Some(&Position(0))
+        );
+
+        // It turns out the remote player held "right" since frame 1.
+        world
+            .write_resource::<EventChannel<NetworkSimulationEvent>>()
+            .single_write(NetworkSimulationEvent::Message(
+                remote_addr(),
+                Bytes::from(encode_inputs(&[(1, 0b10), (2, 0b10), (3, 0b10)])),
+            ));
+        assert!(rollback.run_frame(&mut world));
+
+        // Frames 1, 2 and 3 are now simulated with the remote player moving right.
+        assert_eq!(
+            world.read_storage::<Position>().get(entity),
+            Some(&Position(3))
+        );
+        assert_eq!(
+            world
+                .read_resource::<NetworkSimulationTime>()
+                .frame_number(),
+            4
+        );
+        assert!(world.read_resource::<TransportResource>().has_messages());
+    }
+}
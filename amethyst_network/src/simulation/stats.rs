@@ -0,0 +1,574 @@
+//! Bandwidth and reliability statistics of the network simulation.
+//!
+//! Every transport records the messages it sends and receives in the `NetworkStats` resource.
+//! The `NetworkStatsSystem` turns these into per-second rates, optionally measures the round trip
+//! time to each peer with small probe messages and can periodically write the statistics to the
+//! logger. `NetworkStats` implements `Display`, so its summary can also be shown in an on-screen
+//! debug overlay.
+
+use crate::simulation::{
+    events::NetworkSimulationEvent,
+    requirements::{DeliveryRequirement, UrgencyRequirement},
+    transport::TransportResource,
+};
+use amethyst_core::{
+    ecs::{Read, System, SystemData, World, Write},
+    shrev::{EventChannel, ReaderId},
+    timing::Time,
+    SystemDesc,
+};
+use log::info;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// First byte of the probe messages used to measure round trip times. Messages starting with
+/// this byte are answered by the `NetworkStatsSystem` and should be ignored by the game.
+pub const RTT_PROBE_TAG: u8 = 0xAC;
+
+const PROBE_PING: u8 = 0;
+const PROBE_PONG: u8 = 1;
+const PROBE_LEN: usize = 10;
+
+/// Weight of a new sample in the smoothed round trip time, as recommended by RFC 6298.
+const RTT_SMOOTHING: f64 = 0.125;
+
+/// Default duration over which per-second rates are averaged.
+const DEFAULT_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Counters accumulated during the current rate window.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Counters {
+    bytes_sent: u64,
+    bytes_received: u64,
+    packets_sent: u64,
+    packets_received: u64,
+}
+
+/// Statistics of the traffic exchanged with a single peer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PeerStats {
+    total: Counters,
+    window: Counters,
+    bytes_sent_per_sec: f32,
+    bytes_received_per_sec: f32,
+    packets_sent_per_sec: f32,
+    packets_received_per_sec: f32,
+    rtt: Option<Duration>,
+    resent_packets: u64,
+    dropped_packets: u64,
+    oversized_packets: u64,
+}
+
+impl PeerStats {
+    /// Returns the total number of payload bytes sent to the peer.
+    pub fn bytes_sent(&self) -> u64 {
+        self.total.bytes_sent
+    }
+
+    /// Returns the total number of payload bytes received from the peer.
+    pub fn bytes_received(&self) -> u64 {
+        self.total.bytes_received
+    }
+
+    /// Returns the total number of packets sent to the peer.
+    pub fn packets_sent(&self) -> u64 {
+        self.total.packets_sent
+    }
+
+    /// Returns the total number of packets received from the peer.
+    pub fn packets_received(&self) -> u64 {
+        self.total.packets_received
+    }
+
+    /// Returns the number of bytes sent per second, averaged over the last rate window.
+    pub fn bytes_sent_per_sec(&self) -> f32 {
+        self.bytes_sent_per_sec
+    }
+
+    /// Returns the number of bytes received per second, averaged over the last rate window.
+    pub fn bytes_received_per_sec(&self) -> f32 {
+        self.bytes_received_per_sec
+    }
+
+    /// Returns the number of packets sent per second, averaged over the last rate window.
+    pub fn packets_sent_per_sec(&self) -> f32 {
+        self.packets_sent_per_sec
+    }
+
+    /// Returns the number of packets received per second, averaged over the last rate window.
+    pub fn packets_received_per_sec(&self) -> f32 {
+        self.packets_received_per_sec
+    }
+
+    /// Returns the smoothed round trip time estimate, if it has been measured.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Returns the number of packets the transport had to send again.
+    ///
+    /// None of the bundled transports report resends: Laminar 0.3 resends its reliable packets
+    /// internally without telling, so this stays 0 unless a custom transport calls
+    /// `NetworkStats::record_resend`.
+    pub fn resent_packets(&self) -> u64 {
+        self.resent_packets
+    }
+
+    /// Returns the number of packets which could not be sent or received.
+    pub fn dropped_packets(&self) -> u64 {
+        self.dropped_packets
+    }
+
+    /// Returns the number of packets which were too large for the transport.
+    pub fn oversized_packets(&self) -> u64 {
+        self.oversized_packets
+    }
+
+    fn roll_window(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f32();
+        self.bytes_sent_per_sec = self.window.bytes_sent as f32 / secs;
+        self.bytes_received_per_sec = self.window.bytes_received as f32 / secs;
+        self.packets_sent_per_sec = self.window.packets_sent as f32 / secs;
+        self.packets_received_per_sec = self.window.packets_received as f32 / secs;
+        self.window = Counters::default();
+    }
+}
+
+impl fmt::Display for PeerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "out {:.0} B/s ({:.0} pkt/s), in {:.0} B/s ({:.0} pkt/s), rtt ",
+            self.bytes_sent_per_sec,
+            self.packets_sent_per_sec,
+            self.bytes_received_per_sec,
+            self.packets_received_per_sec,
+        )?;
+        match self.rtt {
+            Some(rtt) => write!(f, "{:.1} ms", rtt.as_secs_f64() * 1000.0)?,
+            None => write!(f, "n/a")?,
+        }
+        write!(
+            f,
+            ", resent {}, dropped {}, oversized {}",
+            self.resent_packets, self.dropped_packets, self.oversized_packets
+        )
+    }
+}
+
+/// Resource holding the traffic statistics of every peer. Transports record into it, the
+/// `NetworkStatsSystem` keeps the per-second rates up to date.
+///
+/// Peers are only added once a packet from them has been authenticated by the
+/// `PacketEncryption`, so that datagrams with a spoofed source address neither create entries
+/// nor receive round trip time probes. Datagrams failing authentication are only counted by
+/// `rejected_packets`.
+///
+/// The resent packet counts stay 0 with the bundled transports, since Laminar 0.3 does not
+/// report the packets it resends.
+#[derive(Clone, Debug)]
+pub struct NetworkStats {
+    peers: HashMap<SocketAddr, PeerStats>,
+    rejected_packets: u64,
+    rate_window: Duration,
+    window_elapsed: Duration,
+    rtt_probe_interval: Option<Duration>,
+    log_interval: Option<Duration>,
+}
+
+impl NetworkStats {
+    /// Creates an empty `NetworkStats`.
+    pub fn new() -> Self {
+        Self {
+            peers: HashMap::new(),
+            rejected_packets: 0,
+            rate_window: DEFAULT_RATE_WINDOW,
+            window_elapsed: Duration::from_secs(0),
+            rtt_probe_interval: None,
+            log_interval: None,
+        }
+    }
+
+    /// Returns the statistics of a single peer.
+    pub fn peer(&self, addr: SocketAddr) -> Option<&PeerStats> {
+        self.peers.get(&addr)
+    }
+
+    /// Returns an iterator over the statistics of every known peer.
+    pub fn peers(&self) -> impl Iterator<Item = (&SocketAddr, &PeerStats)> {
+        self.peers.iter()
+    }
+
+    /// Forgets the statistics of a peer, e.g. after it disconnected.
+    pub fn remove_peer(&mut self, addr: SocketAddr) -> Option<PeerStats> {
+        self.peers.remove(&addr)
+    }
+
+    /// Records a packet of `bytes` bytes sent to the peer. This should be called by a transport
+    /// implementation.
+    pub fn record_sent(&mut self, addr: SocketAddr, bytes: usize) {
+        let peer = self.peers.entry(addr).or_default();
+        for counters in &mut [&mut peer.total, &mut peer.window] {
+            counters.bytes_sent += bytes as u64;
+            counters.packets_sent += 1;
+        }
+    }
+
+    /// Records a packet of `bytes` bytes received from the peer. This should be called by a
+    /// transport implementation, once the packet has been authenticated.
+    pub fn record_received(&mut self, addr: SocketAddr, bytes: usize) {
+        let peer = self.peers.entry(addr).or_default();
+        for counters in &mut [&mut peer.total, &mut peer.window] {
+            counters.bytes_received += bytes as u64;
+            counters.packets_received += 1;
+        }
+    }
+
+    /// Records a packet the transport had to send again. This should be called by a transport
+    /// implementation.
+    pub fn record_resend(&mut self, addr: SocketAddr) {
+        self.peers.entry(addr).or_default().resent_packets += 1;
+    }
+
+    /// Records a packet which could not be sent or received. This should be called by a
+    /// transport implementation.
+    pub fn record_dropped(&mut self, addr: SocketAddr) {
+        self.peers.entry(addr).or_default().dropped_packets += 1;
+    }
+
+    /// Records a packet which was too large for the transport. This should be called by a
+    /// transport implementation.
+    pub fn record_oversized(&mut self, addr: SocketAddr) {
+        self.peers.entry(addr).or_default().oversized_packets += 1;
+    }
+
+    /// Records a received datagram which failed authentication. This should be called by a
+    /// transport implementation. Its source address can't be trusted, so no peer is added for it.
+    pub fn record_rejected(&mut self) {
+        self.rejected_packets += 1;
+    }
+
+    /// Returns the number of received datagrams which failed authentication.
+    pub fn rejected_packets(&self) -> u64 {
+        self.rejected_packets
+    }
+
+    /// Feeds a round trip time measurement into the smoothed estimate of the peer.
+    pub fn record_rtt_sample(&mut self, addr: SocketAddr, sample: Duration) {
+        let peer = self.peers.entry(addr).or_default();
+        peer.rtt = Some(match peer.rtt {
+            Some(rtt) => rtt.mul_f64(1.0 - RTT_SMOOTHING) + sample.mul_f64(RTT_SMOOTHING),
+            None => sample,
+        });
+    }
+
+    /// Returns the mean round trip time over all peers it has been measured for.
+    pub fn mean_rtt(&self) -> Option<Duration> {
+        let rtts = self.peers.values().filter_map(|peer| peer.rtt);
+        let (count, sum) = rtts.fold((0, Duration::from_secs(0)), |(count, sum), rtt| {
+            (count + 1, sum + rtt)
+        });
+        if count > 0 {
+            Some(sum / count)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the duration over which per-second rates are averaged.
+    pub fn rate_window(&self) -> Duration {
+        self.rate_window
+    }
+
+    /// Sets the duration over which per-second rates are averaged. Defaults to one second.
+    pub fn set_rate_window(&mut self, window: Duration) {
+        self.rate_window = window;
+    }
+
+    /// Returns how often the round trip time to each peer is measured.
+    pub fn rtt_probe_interval(&self) -> Option<Duration> {
+        self.rtt_probe_interval
+    }
+
+    /// Sets how often the round trip time to each peer is measured. `None`, the default,
+    /// disables the probes. Probes are small messages tagged with `RTT_PROBE_TAG`; both peers
+    /// need a `NetworkStatsSystem` for them to be answered.
+    pub fn set_rtt_probe_interval(&mut self, interval: Option<Duration>) {
+        self.rtt_probe_interval = interval;
+    }
+
+    /// Returns how often the statistics are written to the logger.
+    pub fn log_interval(&self) -> Option<Duration> {
+        self.log_interval
+    }
+
+    /// Sets how often the statistics are written to the logger. `None`, the default, disables
+    /// logging.
+    pub fn set_log_interval(&mut self, interval: Option<Duration>) {
+        self.log_interval = interval;
+    }
+
+    /// Advances the rate window, recomputing the per-second rates once it is full.
+    pub fn update(&mut self, elapsed: Duration) {
+        self.window_elapsed += elapsed;
+        if self.window_elapsed >= self.rate_window && self.window_elapsed > Duration::from_secs(0) {
+            let window = self.window_elapsed;
+            for peer in self.peers.values_mut() {
+                peer.roll_window(window);
+            }
+            self.window_elapsed = Duration::from_secs(0);
+        }
+    }
+}
+
+impl Default for NetworkStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for NetworkStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.peers.is_empty() {
+            write!(f, "no peers")?;
+        }
+        let mut peers = self.peers.iter().collect::<Vec<_>>();
+        peers.sort_by_key(|(addr, _)| **addr);
+        for (i, (addr, peer)) in peers.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", addr, peer)?;
+        }
+        if self.rejected_packets > 0 {
+            write!(f, "\nrejected {}", self.rejected_packets)?;
+        }
+        Ok(())
+    }
+}
+
+fn encode_probe(kind: u8, nanos: u64) -> [u8; PROBE_LEN] {
+    let mut probe = [0; PROBE_LEN];
+    probe[0] = RTT_PROBE_TAG;
+    probe[1] = kind;
+    probe[2..].copy_from_slice(&nanos.to_be_bytes());
+    probe
+}
+
+fn decode_probe(payload: &[u8]) -> Option<(u8, u64)> {
+    if payload.len() != PROBE_LEN || payload[0] != RTT_PROBE_TAG {
+        return None;
+    }
+    let mut nanos = [0; 8];
+    nanos.copy_from_slice(&payload[2..]);
+    Some((payload[1], u64::from_be_bytes(nanos)))
+}
+
+/// Builds a `NetworkStatsSystem`.
+#[derive(Debug, Default)]
+pub struct NetworkStatsSystemDesc;
+
+impl<'a, 'b> SystemDesc<'a, 'b, NetworkStatsSystem> for NetworkStatsSystemDesc {
+    fn build(self, world: &mut World) -> NetworkStatsSystem {
+        <NetworkStatsSystem as System<'_>>::SystemData::setup(world);
+
+        let reader = world
+            .fetch_mut::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        NetworkStatsSystem::new(reader)
+    }
+}
+
+/// System keeping the per-second rates of `NetworkStats` up to date. It also answers and sends
+/// round trip time probes and logs the statistics if configured to.
+pub struct NetworkStatsSystem {
+    reader: ReaderId<NetworkSimulationEvent>,
+    started: Instant,
+    since_probe: Duration,
+    since_log: Duration,
+}
+
+impl NetworkStatsSystem {
+    /// Creates a new `NetworkStatsSystem`. Needs a reader id for
+    /// `EventChannel<NetworkSimulationEvent>`.
+    pub fn new(reader: ReaderId<NetworkSimulationEvent>) -> Self {
+        Self {
+            reader,
+            started: Instant::now(),
+            since_probe: Duration::from_secs(0),
+            since_log: Duration::from_secs(0),
+        }
+    }
+
+    fn now_nanos(&self) -> u64 {
+        self.started.elapsed().as_nanos() as u64
+    }
+}
+
+impl<'s> System<'s> for NetworkStatsSystem {
+    type SystemData = (
+        Write<'s, NetworkStats>,
+        Write<'s, TransportResource>,
+        Read<'s, EventChannel<NetworkSimulationEvent>>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (mut stats, mut transport, channel, time): Self::SystemData) {
+        for event in channel.read(&mut self.reader) {
+            match event {
+                NetworkSimulationEvent::Message(addr, payload) => match decode_probe(payload) {
+                    Some((PROBE_PING, nanos)) => {
+                        transport.send_with_requirements(
+                            *addr,
+                            &encode_probe(PROBE_PONG, nanos),
+                            DeliveryRequirement::Default,
+                            UrgencyRequirement::Immediate,
+                        );
+                    }
+                    Some((PROBE_PONG, nanos)) => {
+                        let sample = self.now_nanos().saturating_sub(nanos);
+                        stats.record_rtt_sample(*addr, Duration::from_nanos(sample));
+                    }
+                    _ => {}
+                },
+                NetworkSimulationEvent::Disconnect(addr) => {
+                    stats.remove_peer(*addr);
+                }
+                _ => {}
+            }
+        }
+        if let Some(rtt) = stats.mean_rtt() {
+            transport.set_latency_nanos(rtt.as_nanos() as i64);
+        }
+
+        let delta = time.delta_real_time();
+        stats.update(delta);
+
+        if let Some(interval) = stats.rtt_probe_interval() {
+            self.since_probe += delta;
+            if self.since_probe >= interval {
+                self.since_probe = Duration::from_secs(0);
+                let ping = encode_probe(PROBE_PING, self.now_nanos());
+                for addr in stats.peers.keys() {
+                    transport.send_with_requirements(
+                        *addr,
+                        &ping,
+                        DeliveryRequirement::Default,
+                        UrgencyRequirement::Immediate,
+                    );
+                }
+            }
+        }
+
+        if let Some(interval) = stats.log_interval() {
+            self.since_log += delta;
+            if self.since_log >= interval {
+                self.since_log = Duration::from_secs(0);
+                info!("Network statistics:\n{}", *stats);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:3000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_rates_are_computed_per_window() {
+        let mut stats = NetworkStats::new();
+        stats.set_rate_window(Duration::from_secs(2));
+        stats.record_sent(addr(), 100);
+        stats.record_sent(addr(), 300);
+        stats.record_received(addr(), 50);
+
+        stats.update(Duration::from_secs(1));
+        assert_eq!(stats.peer(addr()).unwrap().bytes_sent_per_sec(), 0.0);
+
+        stats.update(Duration::from_secs(1));
+        let peer = stats.peer(addr()).unwrap();
+        assert_eq!(peer.bytes_sent_per_sec(), 200.0);
+        assert_eq!(peer.packets_sent_per_sec(), 1.0);
+        assert_eq!(peer.bytes_received_per_sec(), 25.0);
+        assert_eq!(peer.packets_received_per_sec(), 0.5);
+        assert_eq!(peer.bytes_sent(), 400);
+        assert_eq!(peer.packets_received(), 1);
+
+        // The next window starts from scratch, totals are kept.
+        stats.update(Duration::from_secs(2));
+        let peer = stats.peer(addr()).unwrap();
+        assert_eq!(peer.bytes_sent_per_sec(), 0.0);
+        assert_eq!(peer.bytes_sent(), 400);
+    }
+
+    #[test]
+    fn test_rtt_is_smoothed() {
+        let mut stats = NetworkStats::new();
+        stats.record_rtt_sample(addr(), Duration::from_millis(100));
+        assert_eq!(
+            stats.peer(addr()).unwrap().rtt(),
+            Some(Duration::from_millis(100))
+        );
+
+        stats.record_rtt_sample(addr(), Duration::from_millis(180));
+        assert_eq!(
+            stats.peer(addr()).unwrap().rtt(),
+            Some(Duration::from_millis(110))
+        );
+        assert_eq!(stats.mean_rtt(), Some(Duration::from_millis(110)));
+    }
+
+    #[test]
+    fn test_reliability_counters() {
+        let mut stats = NetworkStats::new();
+        stats.record_resend(addr());
+        stats.record_dropped(addr());
+        stats.record_dropped(addr());
+        stats.record_oversized(addr());
+
+        let peer = stats.peer(addr()).unwrap();
+        assert_eq!(peer.resent_packets(), 1);
+        assert_eq!(peer.dropped_packets(), 2);
+        assert_eq!(peer.oversized_packets(), 1);
+    }
+
+    #[test]
+    fn test_rejected_packets_add_no_peer() {
+        let mut stats = NetworkStats::new();
+        stats.record_rejected();
+        stats.record_rejected();
+
+        assert_eq!(stats.rejected_packets(), 2);
+        assert_eq!(stats.peers().count(), 0);
+        assert_eq!(stats.to_string(), "no peers\nrejected 2");
+    }
+
+    #[test]
+    fn test_probe_round_trip() {
+        let probe = encode_probe(PROBE_PONG, 1234);
+        assert_eq!(decode_probe(&probe), Some((PROBE_PONG, 1234)));
+        assert_eq!(decode_probe(b"not a probe"), None);
+    }
+
+    #[test]
+    fn test_display_lists_peers() {
+        let mut stats = NetworkStats::new();
+        assert_eq!(stats.to_string(), "no peers");
+
+        stats.record_dropped(addr());
+        assert_eq!(
+            stats.to_string(),
+            "127.0.0.1:3000: out 0 B/s (0 pkt/s), in 0 B/s (0 pkt/s), rtt n/a, \
+             resent 0, dropped 1, oversized 0"
+        );
+    }
+}
@@ -5,11 +5,13 @@
 pub mod laminar;
 pub mod tcp;
 pub mod udp;
+pub mod websocket;
 
 const NETWORK_SIM_TIME_SYSTEM_NAME: &str = "simulation_time";
 const NETWORK_SEND_SYSTEM_NAME: &str = "network_send";
 const NETWORK_RECV_SYSTEM_NAME: &str = "network_recv";
 const NETWORK_POLL_SYSTEM_NAME: &str = "network_poll";
+const NETWORK_STATS_SYSTEM_NAME: &str = "network_stats";
 
 use crate::simulation::{
     message::Message,
@@ -1,18 +1,21 @@
 //! Network systems implementation backed by the Laminar network protocol.
 
 use crate::simulation::{
+    encryption::{OpenedPacket, PacketEncryption},
     events::NetworkSimulationEvent,
     requirements::DeliveryRequirement,
+    stats::{NetworkStats, NetworkStatsSystemDesc},
     timing::{NetworkSimulationTime, NetworkSimulationTimeSystem},
     transport::{
         TransportResource, NETWORK_POLL_SYSTEM_NAME, NETWORK_RECV_SYSTEM_NAME,
-        NETWORK_SEND_SYSTEM_NAME, NETWORK_SIM_TIME_SYSTEM_NAME,
+        NETWORK_SEND_SYSTEM_NAME, NETWORK_SIM_TIME_SYSTEM_NAME, NETWORK_STATS_SYSTEM_NAME,
     },
 };
 use amethyst_core::{
     bundle::SystemBundle,
     ecs::{DispatcherBuilder, Read, System, World, Write},
     shrev::EventChannel,
+    SystemDesc,
 };
 use amethyst_error::Error;
 pub use laminar::{Config as LaminarConfig, ErrorKind, Socket as LaminarSocket};
@@ -25,11 +28,21 @@ use std::time::Instant;
 /// Use this network bundle to add the laminar transport layer to your game.
 pub struct LaminarNetworkBundle {
     socket: Option<LaminarSocket>,
+    encryption: PacketEncryption,
 }
 
 impl LaminarNetworkBundle {
     pub fn new(socket: Option<LaminarSocket>) -> Self {
-        Self { socket }
+        Self {
+            socket,
+            encryption: PacketEncryption::default(),
+        }
+    }
+
+    /// Encrypts and authenticates all packets using the given `PacketEncryption`.
+    pub fn with_encryption(mut self, encryption: PacketEncryption) -> Self {
+        self.encryption = encryption;
+        self
     }
 }
 
@@ -62,7 +75,14 @@ impl<'a, 'b> SystemBundle<'a, 'b> for LaminarNetworkBundle {
             &[NETWORK_POLL_SYSTEM_NAME],
         );
 
+        builder.add(
+            NetworkStatsSystemDesc.build(world),
+            NETWORK_STATS_SYSTEM_NAME,
+            &[NETWORK_SEND_SYSTEM_NAME, NETWORK_RECV_SYSTEM_NAME],
+        );
+
         world.insert(LaminarSocketResource::new(self.socket));
+        world.insert(self.encryption);
         Ok(())
     }
 }
@@ -75,54 +95,78 @@ impl<'s> System<'s> for LaminarNetworkSendSystem {
         Write<'s, LaminarSocketResource>,
         Read<'s, NetworkSimulationTime>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
+        Write<'s, NetworkStats>,
+        Write<'s, PacketEncryption>,
     );
 
-    fn run(&mut self, (mut transport, mut socket, sim_time, mut event_channel): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut transport, mut socket, sim_time, mut event_channel, mut stats, mut encryption): Self::SystemData,
+    ) {
         if let Some(socket) = socket.get_mut() {
-            let messages = transport.drain_messages_to_send(|_| sim_time.should_send_message_now());
+            if sim_time.should_send_message_now() {
+                encryption.begin_pending_key_exchanges(transport.get_messages(), Instant::now());
+            }
+            for (addr, handshake) in encryption.drain_handshakes() {
+                let len = handshake.len();
+                match socket.send(Packet::unreliable(addr, handshake)) {
+                    Ok(_) => stats.record_sent(addr, len),
+                    Err(_) => stats.record_dropped(addr),
+                }
+            }
+
+            let messages = transport.drain_messages_to_send(|message| {
+                sim_time.should_send_message_now() && encryption.can_send_to(message.destination)
+            });
 
             for message in messages {
+                let payload = match encryption.seal(message.destination, &message.payload) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        stats.record_dropped(message.destination);
+                        event_channel
+                            .single_write(NetworkSimulationEvent::SendError(e.into(), message));
+                        continue;
+                    }
+                };
+                let payload_len = payload.len();
                 let packet = match message.delivery {
                     DeliveryRequirement::Unreliable => {
-                        Packet::unreliable(message.destination, message.payload.to_vec())
+                        Packet::unreliable(message.destination, payload)
                     }
                     DeliveryRequirement::UnreliableSequenced(stream_id) => {
-                        Packet::unreliable_sequenced(
-                            message.destination,
-                            message.payload.to_vec(),
-                            stream_id,
-                        )
+                        Packet::unreliable_sequenced(message.destination, payload, stream_id)
                     }
                     DeliveryRequirement::Reliable => {
-                        Packet::reliable_unordered(message.destination, message.payload.to_vec())
+                        Packet::reliable_unordered(message.destination, payload)
                     }
                     DeliveryRequirement::ReliableSequenced(stream_id) => {
-                        Packet::reliable_sequenced(
-                            message.destination,
-                            message.payload.to_vec(),
-                            stream_id,
-                        )
+                        Packet::reliable_sequenced(message.destination, payload, stream_id)
+                    }
+                    DeliveryRequirement::ReliableOrdered(stream_id) => {
+                        Packet::reliable_ordered(message.destination, payload, stream_id)
+                    }
+                    DeliveryRequirement::Default => {
+                        Packet::reliable_ordered(message.destination, payload, None)
                     }
-                    DeliveryRequirement::ReliableOrdered(stream_id) => Packet::reliable_ordered(
-                        message.destination,
-                        message.payload.to_vec(),
-                        stream_id,
-                    ),
-                    DeliveryRequirement::Default => Packet::reliable_ordered(
-                        message.destination,
-                        message.payload.to_vec(),
-                        None,
-                    ),
                 };
 
+                // Laminar resends lost reliable packets internally without telling us, so only
+                // the initial send is recorded here.
                 match socket.send(packet) {
                     Err(ErrorKind::IOError(e)) => {
+                        stats.record_dropped(message.destination);
                         event_channel.single_write(NetworkSimulationEvent::SendError(e, message));
                     }
+                    Err(e @ ErrorKind::PacketError(_)) | Err(e @ ErrorKind::FragmentError(_)) => {
+                        stats.record_oversized(message.destination);
+                        error!("Error sending message: {:?}", e);
+                    }
                     Err(e) => {
+                        stats.record_dropped(message.destination);
                         error!("Error sending message: {:?}", e);
                     }
-                    Ok(_) => {}
+                    Ok(_) => stats.record_sent(message.destination, payload_len),
                 }
             }
         }
@@ -147,18 +191,39 @@ impl<'s> System<'s> for LaminarNetworkRecvSystem {
     type SystemData = (
         Write<'s, LaminarSocketResource>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
+        Write<'s, NetworkStats>,
+        Write<'s, PacketEncryption>,
     );
 
-    fn run(&mut self, (mut socket, mut event_channel): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut socket, mut event_channel, mut stats, mut encryption): Self::SystemData,
+    ) {
         if let Some(socket) = socket.get_mut() {
             while let Some(event) = socket.recv() {
                 let event = match event {
-                    SocketEvent::Packet(packet) => NetworkSimulationEvent::Message(
-                        packet.addr(),
-                        Bytes::copy_from_slice(packet.payload()),
-                    ),
+                    SocketEvent::Packet(packet) => {
+                        let opened = encryption.open(packet.addr(), packet.payload());
+                        // Only authenticated packets are attributed to their source address.
+                        if opened.is_ok() {
+                            stats.record_received(packet.addr(), packet.payload().len());
+                        }
+                        match opened {
+                            Ok(OpenedPacket::Payload(payload)) => {
+                                NetworkSimulationEvent::Message(packet.addr(), Bytes::from(payload))
+                            }
+                            Ok(OpenedPacket::Handshake) => continue,
+                            Err(e) => {
+                                stats.record_rejected();
+                                NetworkSimulationEvent::RecvError(e.into())
+                            }
+                        }
+                    }
                     SocketEvent::Connect(addr) => NetworkSimulationEvent::Connect(addr),
-                    SocketEvent::Timeout(addr) => NetworkSimulationEvent::Disconnect(addr),
+                    SocketEvent::Timeout(addr) => {
+                        encryption.remove_peer(addr);
+                        NetworkSimulationEvent::Disconnect(addr)
+                    }
                 };
                 event_channel.single_write(event);
             }
@@ -4,16 +4,18 @@ use crate::simulation::{
     events::NetworkSimulationEvent,
     message::Message,
     requirements::DeliveryRequirement,
+    stats::{NetworkStats, NetworkStatsSystemDesc},
     timing::{NetworkSimulationTime, NetworkSimulationTimeSystem},
     transport::{
         TransportResource, NETWORK_RECV_SYSTEM_NAME, NETWORK_SEND_SYSTEM_NAME,
-        NETWORK_SIM_TIME_SYSTEM_NAME,
+        NETWORK_SIM_TIME_SYSTEM_NAME, NETWORK_STATS_SYSTEM_NAME,
     },
 };
 use amethyst_core::{
     bundle::SystemBundle,
     ecs::{DispatcherBuilder, Read, System, World, Write},
     shrev::EventChannel,
+    SystemDesc,
 };
 use amethyst_error::Error;
 use bytes::Bytes;
@@ -89,6 +91,12 @@ impl<'a, 'b> SystemBundle<'a, 'b> for TcpNetworkBundle {
             ],
         );
 
+        builder.add(
+            NetworkStatsSystemDesc.build(world),
+            NETWORK_STATS_SYSTEM_NAME,
+            &[NETWORK_SEND_SYSTEM_NAME, NETWORK_RECV_SYSTEM_NAME],
+        );
+
         world.insert(TcpNetworkResource::new(
             self.listener,
             self.recv_buffer_size_bytes,
@@ -185,18 +193,22 @@ impl<'s> System<'s> for TcpNetworkSendSystem {
         Write<'s, TcpNetworkResource>,
         Read<'s, NetworkSimulationTime>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
+        Write<'s, NetworkStats>,
     );
 
-    fn run(&mut self, (mut transport, mut net, sim_time, mut channel): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut transport, mut net, sim_time, mut channel, mut stats): Self::SystemData,
+    ) {
         let messages = transport.drain_messages_to_send(|_| sim_time.should_send_message_now());
         for message in messages {
             match message.delivery {
                 DeliveryRequirement::ReliableOrdered(Some(_)) => {
                     warn!("Streams are not supported by TCP and will be ignored.");
-                    write_message(message, &mut net, &mut channel);
+                    write_message(message, &mut net, &mut channel, &mut stats);
                 }
                 DeliveryRequirement::ReliableOrdered(_) | DeliveryRequirement::Default => {
-                    write_message(message, &mut net, &mut channel);
+                    write_message(message, &mut net, &mut channel, &mut stats);
                 }
                 delivery => panic!(
                     "{:?} is unsupported. TCP only supports ReliableOrdered by design.",
@@ -211,10 +223,15 @@ fn write_message(
     message: Message,
     net: &mut TcpNetworkResource,
     channel: &mut EventChannel<NetworkSimulationEvent>,
+    stats: &mut NetworkStats,
 ) {
     if let Some((_, stream)) = net.get_stream(message.destination) {
-        if let Err(e) = stream.write(&message.payload) {
-            channel.single_write(NetworkSimulationEvent::SendError(e, message));
+        match stream.write(&message.payload) {
+            Ok(sent_len) => stats.record_sent(message.destination, sent_len),
+            Err(e) => {
+                stats.record_dropped(message.destination);
+                channel.single_write(NetworkSimulationEvent::SendError(e, message));
+            }
         }
     }
 }
@@ -226,9 +243,10 @@ impl<'s> System<'s> for TcpNetworkRecvSystem {
     type SystemData = (
         Write<'s, TcpNetworkResource>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
+        Write<'s, NetworkStats>,
     );
 
-    fn run(&mut self, (mut net, mut event_channel): Self::SystemData) {
+    fn run(&mut self, (mut net, mut event_channel, mut stats): Self::SystemData) {
         let resource = net.deref_mut();
         for (_, (active, stream)) in resource.streams.iter_mut() {
             // If we can't get a peer_addr, there is likely something pretty wrong with the
@@ -246,6 +264,8 @@ impl<'s> System<'s> for TcpNetworkRecvSystem {
                 match stream.read(&mut resource.recv_buffer) {
                     Ok(recv_len) => {
                         if recv_len > 0 {
+                            // The TCP handshake already proved that the peer owns its address.
+                            stats.record_received(peer_addr, recv_len);
                             let event = NetworkSimulationEvent::Message(
                                 peer_addr,
                                 Bytes::copy_from_slice(&resource.recv_buffer[..recv_len]),
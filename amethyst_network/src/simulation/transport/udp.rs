@@ -1,27 +1,34 @@
 //! Network systems implementation backed by the UDP network protocol.
 
 use crate::simulation::{
+    encryption::{OpenedPacket, PacketEncryption},
     events::NetworkSimulationEvent,
     requirements::DeliveryRequirement,
+    stats::{NetworkStats, NetworkStatsSystemDesc},
     timing::{NetworkSimulationTime, NetworkSimulationTimeSystem},
     transport::{
         TransportResource, NETWORK_RECV_SYSTEM_NAME, NETWORK_SEND_SYSTEM_NAME,
-        NETWORK_SIM_TIME_SYSTEM_NAME,
+        NETWORK_SIM_TIME_SYSTEM_NAME, NETWORK_STATS_SYSTEM_NAME,
     },
 };
 use amethyst_core::{
     bundle::SystemBundle,
     ecs::{DispatcherBuilder, Read, System, World, Write},
     shrev::EventChannel,
+    SystemDesc,
 };
 use amethyst_error::Error;
 use bytes::Bytes;
-use std::{io, net::UdpSocket};
+use std::{io, net::UdpSocket, time::Instant};
+
+/// Largest payload that fits into a single UDP datagram.
+const MAX_UDP_PAYLOAD_BYTES: usize = 65_507;
 
 /// Use this network bundle to add the UDP transport layer to your game.
 pub struct UdpNetworkBundle {
     socket: Option<UdpSocket>,
     recv_buffer_size_bytes: usize,
+    encryption: PacketEncryption,
 }
 
 impl UdpNetworkBundle {
@@ -29,8 +36,15 @@ impl UdpNetworkBundle {
         Self {
             socket,
             recv_buffer_size_bytes,
+            encryption: PacketEncryption::default(),
         }
     }
+
+    /// Encrypts and authenticates all packets using the given `PacketEncryption`.
+    pub fn with_encryption(mut self, encryption: PacketEncryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
 }
 
 impl<'a, 'b> SystemBundle<'a, 'b> for UdpNetworkBundle {
@@ -55,7 +69,14 @@ impl<'a, 'b> SystemBundle<'a, 'b> for UdpNetworkBundle {
             &[NETWORK_SIM_TIME_SYSTEM_NAME],
         );
 
+        builder.add(
+            NetworkStatsSystemDesc.build(world),
+            NETWORK_STATS_SYSTEM_NAME,
+            &[NETWORK_SEND_SYSTEM_NAME, NETWORK_RECV_SYSTEM_NAME],
+        );
+
         world.insert(UdpSocketResource::new(self.socket));
+        world.insert(self.encryption);
         Ok(())
     }
 }
@@ -68,16 +89,52 @@ impl<'s> System<'s> for UdpNetworkSendSystem {
         Write<'s, UdpSocketResource>,
         Read<'s, NetworkSimulationTime>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
+        Write<'s, NetworkStats>,
+        Write<'s, PacketEncryption>,
     );
 
-    fn run(&mut self, (mut transport, mut socket, sim_time, mut channel): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut transport, mut socket, sim_time, mut channel, mut stats, mut encryption): Self::SystemData,
+    ) {
         if let Some(socket) = socket.get_mut() {
-            let messages = transport.drain_messages_to_send(|_| sim_time.should_send_message_now());
+            if sim_time.should_send_message_now() {
+                encryption.begin_pending_key_exchanges(transport.get_messages(), Instant::now());
+            }
+            for (addr, handshake) in encryption.drain_handshakes() {
+                match socket.send_to(&handshake, addr) {
+                    Ok(sent_len) => stats.record_sent(addr, sent_len),
+                    Err(_) => stats.record_dropped(addr),
+                }
+            }
+
+            let messages = transport.drain_messages_to_send(|message| {
+                sim_time.should_send_message_now() && encryption.can_send_to(message.destination)
+            });
             for message in messages {
                 match message.delivery {
                     DeliveryRequirement::Unreliable | DeliveryRequirement::Default => {
-                        if let Err(e) = socket.send_to(&message.payload, message.destination) {
-                            channel.single_write(NetworkSimulationEvent::SendError(e, message));
+                        let packet = match encryption.seal(message.destination, &message.payload) {
+                            Ok(packet) => packet,
+                            Err(e) => {
+                                stats.record_dropped(message.destination);
+                                channel.single_write(NetworkSimulationEvent::SendError(
+                                    e.into(),
+                                    message,
+                                ));
+                                continue;
+                            }
+                        };
+                        match socket.send_to(&packet, message.destination) {
+                            Ok(sent_len) => stats.record_sent(message.destination, sent_len),
+                            Err(e) => {
+                                if packet.len() > MAX_UDP_PAYLOAD_BYTES {
+                                    stats.record_oversized(message.destination);
+                                } else {
+                                    stats.record_dropped(message.destination);
+                                }
+                                channel.single_write(NetworkSimulationEvent::SendError(e, message));
+                            }
                         }
                     }
                     delivery => panic!(
@@ -107,17 +164,38 @@ impl<'s> System<'s> for UdpNetworkRecvSystem {
     type SystemData = (
         Write<'s, UdpSocketResource>,
         Write<'s, EventChannel<NetworkSimulationEvent>>,
+        Write<'s, NetworkStats>,
+        Write<'s, PacketEncryption>,
     );
 
-    fn run(&mut self, (mut socket, mut event_channel): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut socket, mut event_channel, mut stats, mut encryption): Self::SystemData,
+    ) {
         if let Some(socket) = socket.get_mut() {
             loop {
                 match socket.recv_from(&mut self.recv_buffer) {
                     Ok((recv_len, address)) => {
-                        let event = NetworkSimulationEvent::Message(
-                            address,
-                            Bytes::copy_from_slice(&self.recv_buffer[..recv_len]),
-                        );
+                        let opened = encryption.open(address, &self.recv_buffer[..recv_len]);
+                        // Only authenticated datagrams are attributed to their source address.
+                        if opened.is_ok() {
+                            // Datagrams larger than the buffer are silently truncated, a full
+                            // buffer is the only hint we get.
+                            if recv_len == self.recv_buffer.len() {
+                                stats.record_oversized(address);
+                            }
+                            stats.record_received(address, recv_len);
+                        }
+                        let event = match opened {
+                            Ok(OpenedPacket::Payload(payload)) => {
+                                NetworkSimulationEvent::Message(address, Bytes::from(payload))
+                            }
+                            Ok(OpenedPacket::Handshake) => continue,
+                            Err(e) => {
+                                stats.record_rejected();
+                                NetworkSimulationEvent::RecvError(e.into())
+                            }
+                        };
                         // TODO: Handle other types of events.
                         event_channel.single_write(event);
                     }
@@ -0,0 +1,582 @@
+//! Network systems implementation backed by the WebSocket protocol.
+//!
+//! Every message is sent as a single binary WebSocket frame over a TCP connection. Servers accept
+//! connections through a `TcpListener` and perform the HTTP upgrade handshake, clients connect
+//! to `ws://<destination>/` the first time a message is sent to a destination. This makes it
+//! possible for browser based tools to talk to a game server through the regular message API.
+
+use crate::simulation::{
+    events::NetworkSimulationEvent,
+    message::Message,
+    requirements::{DeliveryRequirement, UrgencyRequirement},
+    stats::{NetworkStats, NetworkStatsSystemDesc},
+    timing::{NetworkSimulationTime, NetworkSimulationTimeSystem},
+    transport::{
+        TransportResource, NETWORK_RECV_SYSTEM_NAME, NETWORK_SEND_SYSTEM_NAME,
+        NETWORK_SIM_TIME_SYSTEM_NAME, NETWORK_STATS_SYSTEM_NAME,
+    },
+};
+use amethyst_core::{
+    bundle::SystemBundle,
+    ecs::{DispatcherBuilder, Read, System, World, Write},
+    shrev::EventChannel,
+    SystemDesc,
+};
+use amethyst_error::Error;
+use bytes::Bytes;
+use log::warn;
+use std::{
+    collections::HashMap,
+    io, mem,
+    net::{SocketAddr, TcpListener, TcpStream},
+    ops::DerefMut,
+};
+use tungstenite::{
+    handshake::{
+        client::ClientHandshake,
+        server::{NoCallback, ServerHandshake},
+        MidHandshake,
+    },
+    Error as WebSocketError, HandshakeError, Message as WebSocketMessage, WebSocket,
+};
+
+const CONNECTION_LISTENER_SYSTEM_NAME: &str = "connection_listener";
+const STREAM_MANAGEMENT_SYSTEM_NAME: &str = "stream_management";
+
+/// Use this network bundle to add the WebSocket transport layer to your game.
+pub struct WebSocketNetworkBundle {
+    listener: Option<TcpListener>,
+}
+
+impl WebSocketNetworkBundle {
+    pub fn new(listener: Option<TcpListener>) -> Self {
+        Self { listener }
+    }
+}
+
+impl<'a, 'b> SystemBundle<'a, 'b> for WebSocketNetworkBundle {
+    fn build(
+        self,
+        world: &mut World,
+        builder: &mut DispatcherBuilder<'_, '_>,
+    ) -> Result<(), Error> {
+        // NetworkSimulationTime should run first
+        // followed by WebSocketConnectionListenerSystem and WebSocketStreamManagementSystem
+        // then WebSocketNetworkSendSystem and WebSocketNetworkRecvSystem
+
+        builder.add(
+            NetworkSimulationTimeSystem,
+            NETWORK_SIM_TIME_SYSTEM_NAME,
+            &[],
+        );
+
+        builder.add(
+            WebSocketConnectionListenerSystem,
+            CONNECTION_LISTENER_SYSTEM_NAME,
+            &[NETWORK_SIM_TIME_SYSTEM_NAME],
+        );
+
+        builder.add(
+            WebSocketStreamManagementSystem,
+            STREAM_MANAGEMENT_SYSTEM_NAME,
+            &[NETWORK_SIM_TIME_SYSTEM_NAME],
+        );
+
+        builder.add(
+            WebSocketNetworkSendSystem,
+            NETWORK_SEND_SYSTEM_NAME,
+            &[
+                STREAM_MANAGEMENT_SYSTEM_NAME,
+                CONNECTION_LISTENER_SYSTEM_NAME,
+            ],
+        );
+
+        builder.add(
+            WebSocketNetworkRecvSystem,
+            NETWORK_RECV_SYSTEM_NAME,
+            &[
+                STREAM_MANAGEMENT_SYSTEM_NAME,
+                CONNECTION_LISTENER_SYSTEM_NAME,
+            ],
+        );
+
+        builder.add(
+            NetworkStatsSystemDesc.build(world),
+            NETWORK_STATS_SYSTEM_NAME,
+            &[NETWORK_SEND_SYSTEM_NAME, NETWORK_RECV_SYSTEM_NAME],
+        );
+
+        world.insert(WebSocketNetworkResource::new(self.listener));
+        Ok(())
+    }
+}
+
+/// State of a single WebSocket connection.
+pub enum WebSocketConnection {
+    /// We accepted the connection and are waiting for the upgrade request to complete.
+    ServerHandshake(MidHandshake<ServerHandshake<TcpStream, NoCallback>>),
+    /// We opened the connection and are waiting for the upgrade response to complete.
+    ClientHandshake(MidHandshake<ClientHandshake<TcpStream>>),
+    /// The handshake is done, messages can be exchanged.
+    Open(WebSocket<TcpStream>),
+    /// The handshake failed. The connection will be dropped without a `Disconnect` event.
+    Failed,
+    /// The connection was open and has been closed. It will be dropped with a `Disconnect` event.
+    Closed,
+}
+
+impl WebSocketConnection {
+    /// Returns true if messages can be exchanged over this connection.
+    pub fn is_open(&self) -> bool {
+        matches!(self, WebSocketConnection::Open(_))
+    }
+
+    /// Drives a pending handshake forward, emitting a `Connect` event once it completes.
+    fn continue_handshake(
+        self,
+        addr: SocketAddr,
+        event_channel: &mut EventChannel<NetworkSimulationEvent>,
+    ) -> Self {
+        match self {
+            WebSocketConnection::ServerHandshake(mid) => {
+                handle_server_handshake(mid.handshake(), addr, event_channel)
+            }
+            WebSocketConnection::ClientHandshake(mid) => {
+                handle_client_handshake(mid.handshake(), addr, event_channel)
+            }
+            connection => connection,
+        }
+    }
+}
+
+fn handle_server_handshake(
+    result: Result<WebSocket<TcpStream>, HandshakeError<ServerHandshake<TcpStream, NoCallback>>>,
+    addr: SocketAddr,
+    event_channel: &mut EventChannel<NetworkSimulationEvent>,
+) -> WebSocketConnection {
+    match result {
+        Ok(socket) => {
+            event_channel.single_write(NetworkSimulationEvent::Connect(addr));
+            WebSocketConnection::Open(socket)
+        }
+        Err(HandshakeError::Interrupted(mid)) => WebSocketConnection::ServerHandshake(mid),
+        Err(HandshakeError::Failure(e)) => {
+            event_channel.single_write(NetworkSimulationEvent::ConnectionError(
+                into_io_error(e),
+                Some(addr),
+            ));
+            WebSocketConnection::Failed
+        }
+    }
+}
+
+fn handle_client_handshake<R>(
+    result: Result<(WebSocket<TcpStream>, R), HandshakeError<ClientHandshake<TcpStream>>>,
+    addr: SocketAddr,
+    event_channel: &mut EventChannel<NetworkSimulationEvent>,
+) -> WebSocketConnection {
+    match result {
+        Ok((socket, _)) => {
+            event_channel.single_write(NetworkSimulationEvent::Connect(addr));
+            WebSocketConnection::Open(socket)
+        }
+        Err(HandshakeError::Interrupted(mid)) => WebSocketConnection::ClientHandshake(mid),
+        Err(HandshakeError::Failure(e)) => {
+            event_channel.single_write(NetworkSimulationEvent::ConnectionError(
+                into_io_error(e),
+                Some(addr),
+            ));
+            WebSocketConnection::Failed
+        }
+    }
+}
+
+/// Converts a WebSocket error into the `io::Error` carried by `NetworkSimulationEvent`s.
+fn into_io_error(error: WebSocketError) -> io::Error {
+    match error {
+        WebSocketError::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+/// System to open connections to new destinations, drive pending handshakes and drop closed
+/// connections.
+pub struct WebSocketStreamManagementSystem;
+
+impl<'s> System<'s> for WebSocketStreamManagementSystem {
+    type SystemData = (
+        Write<'s, WebSocketNetworkResource>,
+        Write<'s, TransportResource>,
+        Write<'s, EventChannel<NetworkSimulationEvent>>,
+    );
+
+    fn run(&mut self, (mut net, mut transport, mut event_channel): Self::SystemData) {
+        // Make connections for each message in the channel if one hasn't yet been established
+        let mut failed = Vec::new();
+        for message in transport.get_messages() {
+            let addr = message.destination;
+            if net.connections.contains_key(&addr) || failed.contains(&addr) {
+                continue;
+            }
+            let connection = TcpStream::connect(addr).and_then(|s| {
+                s.set_nonblocking(true)?;
+                s.set_nodelay(true)?;
+                Ok(s)
+            });
+            match connection {
+                Ok(stream) => {
+                    let result = tungstenite::client(format!("ws://{}/", addr), stream);
+                    let connection = handle_client_handshake(result, addr, &mut event_channel);
+                    net.connections.insert(addr, connection);
+                }
+                Err(e) => {
+                    event_channel
+                        .single_write(NetworkSimulationEvent::ConnectionError(e, Some(addr)));
+                    failed.push(addr);
+                }
+            }
+        }
+
+        // Messages to unreachable destinations would otherwise wait forever
+        for addr in failed {
+            transport.drain_messages(|message| message.destination == addr);
+        }
+
+        for (addr, connection) in net.connections.iter_mut() {
+            let pending = mem::replace(connection, WebSocketConnection::Failed);
+            *connection = pending.continue_handshake(*addr, &mut event_channel);
+        }
+
+        // Remove failed and closed connections
+        net.connections.retain(|addr, connection| match connection {
+            WebSocketConnection::Failed => false,
+            WebSocketConnection::Closed => {
+                event_channel.single_write(NetworkSimulationEvent::Disconnect(*addr));
+                false
+            }
+            _ => true,
+        });
+    }
+}
+
+/// System to accept incoming connections and start their handshakes.
+pub struct WebSocketConnectionListenerSystem;
+
+impl<'s> System<'s> for WebSocketConnectionListenerSystem {
+    type SystemData = (
+        Write<'s, WebSocketNetworkResource>,
+        Write<'s, EventChannel<NetworkSimulationEvent>>,
+    );
+
+    fn run(&mut self, (mut net, mut event_channel): Self::SystemData) {
+        let resource = net.deref_mut();
+        if let Some(ref listener) = resource.listener {
+            loop {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        stream
+                            .set_nonblocking(true)
+                            .expect("Setting nonblocking mode");
+                        stream.set_nodelay(true).expect("Setting nodelay");
+                        let connection = handle_server_handshake(
+                            tungstenite::accept(stream),
+                            addr,
+                            &mut event_channel,
+                        );
+                        resource.connections.insert(addr, connection);
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        break;
+                    }
+                    Err(e) => {
+                        event_channel
+                            .single_write(NetworkSimulationEvent::ConnectionError(e, None));
+                        break;
+                    }
+                };
+            }
+        }
+    }
+}
+
+/// System to send messages to open WebSocket connections.
+pub struct WebSocketNetworkSendSystem;
+
+impl<'s> System<'s> for WebSocketNetworkSendSystem {
+    type SystemData = (
+        Write<'s, TransportResource>,
+        Write<'s, WebSocketNetworkResource>,
+        Read<'s, NetworkSimulationTime>,
+        Write<'s, EventChannel<NetworkSimulationEvent>>,
+        Write<'s, NetworkStats>,
+    );
+
+    fn run(
+        &mut self,
+        (mut transport, mut net, sim_time, mut channel, mut stats): Self::SystemData,
+    ) {
+        // Messages to connections which are still handshaking are kept until they are open.
+        let messages = transport.drain_messages(|message| {
+            net.is_open(message.destination)
+                && (message.urgency == UrgencyRequirement::Immediate
+                    || sim_time.should_send_message_now())
+        });
+        for message in messages {
+            match message.delivery {
+                DeliveryRequirement::ReliableOrdered(Some(_)) => {
+                    warn!("Streams are not supported by WebSockets and will be ignored.");
+                    write_message(message, &mut net, &mut channel, &mut stats);
+                }
+                DeliveryRequirement::ReliableOrdered(_) | DeliveryRequirement::Default => {
+                    write_message(message, &mut net, &mut channel, &mut stats);
+                }
+                delivery => panic!(
+                    "{:?} is unsupported. WebSockets only support ReliableOrdered by design.",
+                    delivery
+                ),
+            }
+        }
+
+        // Flush frames that didn't fit in the socket buffer on previous frames.
+        for connection in net.connections.values_mut() {
+            if let WebSocketConnection::Open(socket) = connection {
+                match socket.write_pending() {
+                    Err(WebSocketError::ConnectionClosed) | Err(WebSocketError::AlreadyClosed) => {
+                        *connection = WebSocketConnection::Closed;
+                    }
+                    Err(WebSocketError::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => {
+                        warn!("Encountered an error flushing a WebSocket: {:?}", e);
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }
+    }
+}
+
+fn write_message(
+    message: Message,
+    net: &mut WebSocketNetworkResource,
+    channel: &mut EventChannel<NetworkSimulationEvent>,
+    stats: &mut NetworkStats,
+) {
+    let addr = message.destination;
+    if let Some(connection) = net.get_connection(addr) {
+        if let WebSocketConnection::Open(socket) = connection {
+            match socket.write_message(WebSocketMessage::Binary(message.payload.to_vec())) {
+                // The frame is queued and will be flushed later.
+                Err(WebSocketError::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    stats.record_sent(addr, message.payload.len());
+                }
+                Err(WebSocketError::ConnectionClosed) | Err(WebSocketError::AlreadyClosed) => {
+                    stats.record_dropped(addr);
+                    *connection = WebSocketConnection::Closed;
+                }
+                Err(e) => {
+                    if let WebSocketError::Capacity(_) = e {
+                        stats.record_oversized(addr);
+                    } else {
+                        stats.record_dropped(addr);
+                    }
+                    channel
+                        .single_write(NetworkSimulationEvent::SendError(into_io_error(e), message));
+                }
+                Ok(_) => stats.record_sent(addr, message.payload.len()),
+            }
+        }
+    }
+}
+
+/// System to receive messages from all open WebSocket connections.
+pub struct WebSocketNetworkRecvSystem;
+
+impl<'s> System<'s> for WebSocketNetworkRecvSystem {
+    type SystemData = (
+        Write<'s, WebSocketNetworkResource>,
+        Write<'s, EventChannel<NetworkSimulationEvent>>,
+        Write<'s, NetworkStats>,
+    );
+
+    fn run(&mut self, (mut net, mut event_channel, mut stats): Self::SystemData) {
+        for (addr, connection) in net.connections.iter_mut() {
+            let socket = match connection {
+                WebSocketConnection::Open(socket) => socket,
+                _ => continue,
+            };
+
+            loop {
+                match socket.read_message() {
+                    Ok(WebSocketMessage::Binary(payload)) => {
+                        stats.record_received(*addr, payload.len());
+                        event_channel
+                            .single_write(NetworkSimulationEvent::Message(*addr, payload.into()));
+                    }
+                    Ok(WebSocketMessage::Text(text)) => {
+                        stats.record_received(*addr, text.len());
+                        event_channel.single_write(NetworkSimulationEvent::Message(
+                            *addr,
+                            Bytes::from(text),
+                        ));
+                    }
+                    // Pings are answered and close frames are acknowledged by tungstenite, we
+                    // only need to keep reading until the connection is closed.
+                    Ok(WebSocketMessage::Ping(_))
+                    | Ok(WebSocketMessage::Pong(_))
+                    | Ok(WebSocketMessage::Close(_)) => {}
+                    Err(WebSocketError::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                        break;
+                    }
+                    Err(WebSocketError::ConnectionClosed) | Err(WebSocketError::AlreadyClosed) => {
+                        *connection = WebSocketConnection::Closed;
+                        break;
+                    }
+                    Err(WebSocketError::Io(ref e))
+                        if e.kind() == io::ErrorKind::ConnectionReset =>
+                    {
+                        *connection = WebSocketConnection::Closed;
+                        break;
+                    }
+                    Err(e) => {
+                        if let WebSocketError::Capacity(_) = e {
+                            stats.record_oversized(*addr);
+                        } else {
+                            stats.record_dropped(*addr);
+                        }
+                        event_channel
+                            .single_write(NetworkSimulationEvent::RecvError(into_io_error(e)));
+                        *connection = WebSocketConnection::Closed;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Resource that owns the listener and all WebSocket connections.
+#[derive(Default)]
+pub struct WebSocketNetworkResource {
+    listener: Option<TcpListener>,
+    connections: HashMap<SocketAddr, WebSocketConnection>,
+}
+
+impl WebSocketNetworkResource {
+    pub fn new(listener: Option<TcpListener>) -> Self {
+        Self {
+            listener,
+            connections: HashMap::new(),
+        }
+    }
+
+    /// Returns an immutable reference to the listener if there is one configured.
+    pub fn get(&self) -> Option<&TcpListener> {
+        self.listener.as_ref()
+    }
+
+    /// Returns a mutable reference to the listener if there is one configured.
+    pub fn get_mut(&mut self) -> Option<&mut TcpListener> {
+        self.listener.as_mut()
+    }
+
+    /// Sets the bound listener to the `WebSocketNetworkResource`.
+    pub fn set_listener(&mut self, listener: TcpListener) {
+        self.listener = Some(listener);
+    }
+
+    /// Drops the listener from the `WebSocketNetworkResource`.
+    pub fn drop_listener(&mut self) {
+        self.listener = None;
+    }
+
+    /// Returns true if there is an open connection to the given address.
+    pub fn is_open(&self, addr: SocketAddr) -> bool {
+        self.connections
+            .get(&addr)
+            .is_some_and(WebSocketConnection::is_open)
+    }
+
+    /// Returns the connection to the given address, if there is one.
+    pub fn get_connection(&mut self, addr: SocketAddr) -> Option<&mut WebSocketConnection> {
+        self.connections.get_mut(&addr)
+    }
+
+    /// Starts the close handshake with the given address. A `Disconnect` event is emitted once
+    /// the peer acknowledged it.
+    pub fn close(&mut self, addr: SocketAddr) {
+        if let Some(connection) = self.connections.get_mut(&addr) {
+            match connection {
+                WebSocketConnection::Open(socket) => {
+                    if let Err(e) = socket.close(None) {
+                        match e {
+                            WebSocketError::Io(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                            _ => *connection = WebSocketConnection::Closed,
+                        }
+                    }
+                }
+                _ => *connection = WebSocketConnection::Failed,
+            }
+        }
+    }
+
+    /// Drops the connection with the given `SocketAddr` without a close handshake.
+    pub fn drop_connection(&mut self, addr: SocketAddr) -> Option<WebSocketConnection> {
+        self.connections.remove(&addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst_core::ecs::{Dispatcher, WorldExt};
+    use std::{thread, time::Duration};
+
+    fn setup(listener: Option<TcpListener>) -> (World, Dispatcher<'static, 'static>) {
+        let mut world = World::new();
+        let mut builder = DispatcherBuilder::new();
+        WebSocketNetworkBundle::new(listener)
+            .build(&mut world, &mut builder)
+            .unwrap();
+        let mut dispatcher = builder.build();
+        dispatcher.setup(&mut world);
+        (world, dispatcher)
+    }
+
+    #[test]
+    fn test_client_and_server_exchange_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let (server, mut server_dispatcher) = setup(Some(listener));
+        let (client, mut client_dispatcher) = setup(None);
+        let mut server_reader = server
+            .write_resource::<EventChannel<NetworkSimulationEvent>>()
+            .register_reader();
+
+        client
+            .write_resource::<TransportResource>()
+            .send_immediate(server_addr, b"hello");
+
+        let mut received = None;
+        for _ in 0..500 {
+            client_dispatcher.dispatch(&client);
+            server_dispatcher.dispatch(&server);
+            let channel = server.read_resource::<EventChannel<NetworkSimulationEvent>>();
+            for event in channel.read(&mut server_reader) {
+                if let NetworkSimulationEvent::Message(_, payload) = event {
+                    received = Some(payload.clone());
+                }
+            }
+            if received.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        assert_eq!(received, Some(Bytes::from_static(b"hello")));
+        assert!(client
+            .read_resource::<WebSocketNetworkResource>()
+            .is_open(server_addr));
+    }
+}
@@ -5,7 +5,7 @@ use amethyst::{
     core::{transform::TransformBundle, EventReader, RunNowDesc, SystemBundle, SystemDesc},
     ecs::prelude::*,
     error::Error,
-    input::{BindingTypes, InputBundle},
+    input::{BindingTypes, InputBundle, InputRecorder, InputRecording},
     prelude::*,
     shred::Resource,
     ui::UiBundle,
@@ -381,6 +381,21 @@ where
             .with_bundle(UiBundle::<B>::new())
     }
 
+    /// Replays an input recording in place of live input, starting from the first frame.
+    ///
+    /// The `InputSystem`, e.g. from `with_ui_bundles`, sends the recorded events to the
+    /// `InputHandler` as they were read when recording, which lets gameplay be tested without a
+    /// window.
+    ///
+    /// # Parameters
+    ///
+    /// * `recording`: Recording to replay.
+    pub fn with_input_replay(self, recording: InputRecording) -> Self {
+        let mut recorder = InputRecorder::new();
+        recorder.replay(recording);
+        self.with_resource(recorder)
+    }
+
     /// Adds a resource to the `World`.
     ///
     /// # Parameters
@@ -596,9 +611,14 @@ mod test {
         derive::SystemDesc,
         ecs::prelude::*,
         error::Error,
+        input::{
+            InputBundle, InputHandler, InputRecording, RecordedEvent, StringBindings,
+            VirtualKeyCode,
+        },
         prelude::*,
         ui::FontAsset,
         window::ScreenDimensions,
+        winit::{ElementState, KeyboardInput, ModifiersState},
     };
 
     use super::AmethystApplication;
@@ -868,6 +888,29 @@ mod test {
             .run()
     }
 
+    #[test]
+    fn with_input_replay_sends_recorded_events_to_the_input_handler() -> Result<(), Error> {
+        let mut recording = InputRecording::new();
+        recording.push(
+            0,
+            RecordedEvent::KeyboardInput(KeyboardInput {
+                scancode: 104,
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::Up),
+                modifiers: ModifiersState::default(),
+            }),
+        );
+
+        AmethystApplication::blank()
+            .with_bundle(InputBundle::<StringBindings>::new())
+            .with_input_replay(recording)
+            .with_assertion(|world| {
+                let handler = world.read_resource::<InputHandler<StringBindings>>();
+                assert!(handler.key_is_down(VirtualKeyCode::Up));
+            })
+            .run()
+    }
+
     #[test]
     fn with_effect_invoked_twice_should_run_in_the_specified_order() -> Result<(), Error> {
         AmethystApplication::blank()
@@ -1006,6 +1049,44 @@ mod test {
         }
     }
 
+    /// Audio can be tested without a sound card by playing on a recording `Output`.
+    mod audio_recording_test {
+        use std::time::Duration;
+
+        use amethyst::{
+            audio::{output::Output, AudioBundle, Source},
+            ecs::WorldExt,
+            error::Error,
+        };
+
+        use super::AmethystApplication;
+
+        #[test]
+        fn audio_recording_records_played_sources() -> Result<(), Error> {
+            let source = Source {
+                bytes: include_bytes!("../../amethyst_audio/tests/sound_test.wav").to_vec(),
+            };
+            let played = source.clone();
+            AmethystApplication::blank()
+                .with_bundle(AudioBundle::with_output(Output::new_recording(44_100)))
+                .with_effect(move |world| {
+                    world.read_resource::<Output>().play_once(&played, 0.5);
+                })
+                .with_assertion(move |world| {
+                    let output = world.read_resource::<Output>();
+                    let recording = output.recording().expect("Expected a recording output.");
+                    recording.advance(Duration::from_secs(10));
+
+                    let sounds = recording.sounds();
+                    assert_eq!(1, sounds.len());
+                    assert!(sounds[0].played(&source));
+                    assert!(sounds[0].ended);
+                    assert_eq!(0.0, sounds[0].balance());
+                })
+                .run()
+        }
+    }
+
     // === Resources === //
     #[derive(Debug, Default)]
     struct ApplicationResource;
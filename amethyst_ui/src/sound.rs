@@ -1,5 +1,5 @@
 use amethyst_assets::AssetStorage;
-use amethyst_audio::{output::Output, Source, SourceHandle};
+use amethyst_audio::{output::Output, AudioMixer, Bus, Source, SourceHandle};
 use amethyst_core::{
     ecs::{
         prelude::{Component, DenseVecStorage},
@@ -71,7 +71,7 @@ impl EventRetrigger for UiSoundRetrigger {
 }
 
 /// Handles any dispatches `UiPlaySoundAction`s and plays the received
-/// sounds through the set `Output`.
+/// sounds through the set `Output`, on the `Bus::Ui` mixer bus.
 #[derive(Debug, SystemDesc)]
 #[system_desc(name(UiSoundSystemDesc))]
 pub struct UiSoundSystem {
@@ -93,9 +93,10 @@ impl<'s> System<'s> for UiSoundSystem {
         Write<'s, EventChannel<UiPlaySoundAction>>,
         Read<'s, AssetStorage<Source>>,
         Option<Read<'s, Output>>,
+        Write<'s, AudioMixer>,
     );
 
-    fn run(&mut self, (sound_events, audio_storage, audio_output): Self::SystemData) {
+    fn run(&mut self, (sound_events, audio_storage, audio_output, mut mixer): Self::SystemData) {
         #[cfg(feature = "profiler")]
         profile_scope!("ui_sound_system");
 
@@ -104,7 +105,7 @@ impl<'s> System<'s> for UiSoundSystem {
         for event in sound_events.read(event_reader) {
             if let Some(output) = audio_output.as_ref() {
                 if let Some(sound) = audio_storage.get(&event.0) {
-                    output.play_once(sound, 1.0);
+                    output.play_once_on_bus(sound, 1.0, &mixer.gain(&Bus::Ui));
                 }
             }
         }
@@ -30,6 +30,9 @@ use crate::{
     ui::UiEvent,
 };
 
+#[cfg(feature = "server")]
+use crate::server::ShutdownSignal;
+
 /// `CoreApplication` is the application implementation for the game engine. This is fully generic
 /// over the state type and event type.
 ///
@@ -268,8 +271,19 @@ where
             .expect("Tried to start state machine without any states present");
     }
 
-    // React to window close events
+    // React to window close events and shutdown requests
     fn should_close(&mut self) -> bool {
+        #[cfg(feature = "server")]
+        {
+            if self
+                .world
+                .try_fetch::<ShutdownSignal>()
+                .is_some_and(|signal| signal.is_requested())
+            {
+                return true;
+            }
+        }
+
         if self.ignore_window_close {
             false
         } else {
@@ -854,6 +868,31 @@ where
         self
     }
 
+    /// Configures the application to run as a headless dedicated server.
+    ///
+    /// The game loop and the fixed updates run at `ticks_per_second`, window close events are
+    /// ignored and a `ShutdownSignal` resource is added which stops the application gracefully
+    /// on SIGINT or SIGTERM. Don't add the window and rendering bundles to the game data of a
+    /// server.
+    ///
+    /// # Parameters
+    ///
+    /// `ticks_per_second`: The number of simulation ticks per second.
+    ///
+    /// # Returns
+    ///
+    /// This function returns the ApplicationBuilder after modifying it.
+    #[cfg(feature = "server")]
+    pub fn headless(self, ticks_per_second: u32) -> Self {
+        self.with_frame_limit(
+            FrameRateLimitStrategy::SleepAndYield(Duration::from_millis(1)),
+            ticks_per_second,
+        )
+        .with_fixed_step_length(Duration::from_secs(1) / ticks_per_second)
+        .with_resource(ShutdownSignal::with_signal_handler())
+        .ignore_window_close(true)
+    }
+
     /// Build an `Application` object using the `ApplicationBuilder` as configured.
     ///
     /// # Returns
@@ -109,6 +109,8 @@ pub use self::{
 pub type Result<T> = std::result::Result<T, error::Error>;
 
 pub mod prelude;
+#[cfg(feature = "server")]
+pub mod server;
 
 mod app;
 mod callback_queue;
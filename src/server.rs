@@ -0,0 +1,252 @@
+//! Utilities to run the game as a headless dedicated server.
+//!
+//! A dedicated server is an ordinary `Application` built without the window and rendering
+//! bundles. Use `ApplicationBuilder::headless` to run it at a fixed tick rate and to shut it down
+//! gracefully on SIGINT or SIGTERM, and `ServerBundle` to read commands from an admin console on
+//! stdin.
+
+use std::{
+    io::{self, BufRead},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Once,
+    },
+    thread,
+};
+
+use crossbeam_channel::Receiver;
+use log::{error, info, warn};
+
+use crate::{
+    core::{
+        ecs::{DispatcherBuilder, Read, System, SystemData, World, Write},
+        shrev::EventChannel,
+        SystemBundle, SystemDesc,
+    },
+    error::Error,
+};
+
+/// Set by the signal handler. Shared by all `ShutdownSignal`s as there is only one handler per
+/// process.
+static SIGNAL_RECEIVED: AtomicBool = AtomicBool::new(false);
+static INSTALL_SIGNAL_HANDLER: Once = Once::new();
+
+/// Admin console commands which shut the server down.
+const QUIT_COMMANDS: &[&str] = &["quit", "exit", "shutdown"];
+
+/// Resource which requests the application to stop.
+///
+/// When a shutdown is requested, the application stops all states, so `State::on_stop` can save
+/// the game or notify connected clients, and then leaves the game loop.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownSignal {
+    requested: Arc<AtomicBool>,
+    handle_signals: bool,
+}
+
+impl ShutdownSignal {
+    /// Creates a `ShutdownSignal` which also triggers on SIGINT and SIGTERM (Ctrl-C and
+    /// Ctrl-Break on Windows).
+    pub fn with_signal_handler() -> Self {
+        INSTALL_SIGNAL_HANDLER.call_once(|| {
+            if let Err(e) = ctrlc::set_handler(|| SIGNAL_RECEIVED.store(true, Ordering::SeqCst)) {
+                warn!("Failed to install the shutdown signal handler: {}", e);
+            }
+        });
+        Self {
+            requested: Arc::default(),
+            handle_signals: true,
+        }
+    }
+
+    /// Requests the application to shut down at the start of the next frame.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if a shutdown has been requested.
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+            || (self.handle_signals && SIGNAL_RECEIVED.load(Ordering::SeqCst))
+    }
+}
+
+/// A command entered on the admin console. Each non empty line of input is one command, the first
+/// word being the command name and the remaining words its arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdminCommand {
+    /// The name of the command.
+    pub name: String,
+    /// The arguments of the command.
+    pub args: Vec<String>,
+}
+
+impl AdminCommand {
+    /// Parses a line of input, returns `None` if it contains no command.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace().map(str::to_owned);
+        words.next().map(|name| AdminCommand {
+            name,
+            args: words.collect(),
+        })
+    }
+}
+
+/// Adds the systems of a dedicated server.
+///
+/// With the admin console enabled, lines read from stdin are published as `AdminCommand`s in an
+/// `EventChannel<AdminCommand>`. The commands `quit`, `exit` and `shutdown` are handled by the
+/// console itself and request a graceful shutdown.
+#[derive(Debug, Default)]
+pub struct ServerBundle {
+    admin_console: bool,
+}
+
+impl ServerBundle {
+    /// Creates a new `ServerBundle` without admin console.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables or disables the admin console on stdin.
+    pub fn with_admin_console(mut self, enabled: bool) -> Self {
+        self.admin_console = enabled;
+        self
+    }
+}
+
+impl<'a, 'b> SystemBundle<'a, 'b> for ServerBundle {
+    fn build(
+        self,
+        world: &mut World,
+        builder: &mut DispatcherBuilder<'a, 'b>,
+    ) -> Result<(), Error> {
+        if self.admin_console {
+            builder.add(
+                AdminConsoleSystemDesc.build(world),
+                "admin_console_system",
+                &[],
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Builds an `AdminConsoleSystem` and spawns the thread reading stdin.
+#[derive(Debug, Default)]
+pub struct AdminConsoleSystemDesc;
+
+impl<'a, 'b> SystemDesc<'a, 'b, AdminConsoleSystem> for AdminConsoleSystemDesc {
+    fn build(self, world: &mut World) -> AdminConsoleSystem {
+        <AdminConsoleSystem as System<'_>>::SystemData::setup(world);
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let spawned = thread::Builder::new()
+            .name("admin_console".into())
+            .spawn(move || {
+                for line in io::stdin().lock().lines() {
+                    match line {
+                        Ok(line) => {
+                            if sender.send(line).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed to read from the admin console: {}", e);
+                            break;
+                        }
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            error!("Failed to start the admin console: {}", e);
+        }
+
+        AdminConsoleSystem::new(receiver)
+    }
+}
+
+/// Publishes the commands entered on the admin console.
+#[derive(Debug)]
+pub struct AdminConsoleSystem {
+    lines: Receiver<String>,
+}
+
+impl AdminConsoleSystem {
+    /// Creates a new `AdminConsoleSystem` reading lines from the given channel.
+    pub fn new(lines: Receiver<String>) -> Self {
+        Self { lines }
+    }
+}
+
+impl<'s> System<'s> for AdminConsoleSystem {
+    type SystemData = (
+        Write<'s, EventChannel<AdminCommand>>,
+        Read<'s, ShutdownSignal>,
+    );
+
+    fn run(&mut self, (mut commands, shutdown): Self::SystemData) {
+        while let Ok(line) = self.lines.try_recv() {
+            if let Some(command) = AdminCommand::parse(&line) {
+                if QUIT_COMMANDS.contains(&command.name.as_str()) {
+                    info!("Shutdown requested from the admin console");
+                    shutdown.request();
+                }
+                commands.single_write(command);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::{RunNow, WorldExt};
+
+    #[test]
+    fn parse_admin_command() {
+        assert_eq!(AdminCommand::parse("   "), None);
+        assert_eq!(
+            AdminCommand::parse(" kick  player1 cheating "),
+            Some(AdminCommand {
+                name: "kick".into(),
+                args: vec!["player1".into(), "cheating".into()],
+            })
+        );
+    }
+
+    #[test]
+    fn shutdown_signal_is_shared_by_clones() {
+        let signal = ShutdownSignal::default();
+        let clone = signal.clone();
+        assert!(!clone.is_requested());
+        signal.request();
+        assert!(clone.is_requested());
+    }
+
+    #[test]
+    fn admin_console_publishes_commands_and_requests_shutdown() {
+        let mut world = World::new();
+        <AdminConsoleSystem as System<'_>>::SystemData::setup(&mut world);
+        let mut reader = world
+            .write_resource::<EventChannel<AdminCommand>>()
+            .register_reader();
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut system = AdminConsoleSystem::new(receiver);
+        sender.send("status".into()).unwrap();
+        system.run_now(&world);
+        assert!(!world.read_resource::<ShutdownSignal>().is_requested());
+
+        sender.send("quit".into()).unwrap();
+        system.run_now(&world);
+        assert!(world.read_resource::<ShutdownSignal>().is_requested());
+
+        let names = world
+            .read_resource::<EventChannel<AdminCommand>>()
+            .read(&mut reader)
+            .map(|command| command.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["status".to_string(), "quit".to_string()]);
+    }
+}